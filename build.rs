@@ -13,12 +13,98 @@ fn sanitize_name(name: &str) -> String {
     name.replace(".", "_")
 }
 
+/// The MISC-MEM major opcode (FENCE and FENCE.I)
+const MISC_MEM: u32 = 0b0001111;
+
+/// An instruction definition, as given by a line of a riscv-opcodes
+/// extension file; e.g.:
+///
+/// ```text
+/// add     rd rs1 rs2 31..25=0  14..12=0 6..2=0x0C 1..0=3
+/// ```
+struct Def {
+    name: String,
+    args: Vec<String>,
+    /// The fixed bits of the encoding
+    value: u32,
+}
+
+impl Def {
+    /// Parses the definitions in the riscv-opcodes extension file `filename`;
+    /// pseudo-ops are only included if the extension they alias an
+    /// instruction from isn't one of `extensions`.
+    fn parse_file(filename: &str, extensions: &[&str]) -> Vec<Def> {
+        let mut defs = vec![];
+        for line in read_to_string(filename).unwrap().lines() {
+            let line = line.split('#').next().unwrap();
+            let mut tokens = line.split_whitespace().peekable();
+            match tokens.peek() {
+                None => continue,
+                Some(&"$pseudo_op") => {
+                    let (ext, _) = tokens.nth(1).unwrap().split_once("::").unwrap();
+                    if extensions.iter().any(|e| e.rsplit('/').next() == Some(ext)) {
+                        continue;
+                    }
+                }
+                Some(token) if token.starts_with('$') => panic!("unsupported directive: {}", line),
+                _ => {}
+            }
+
+            let mut def = Def {
+                name: tokens.next().unwrap().to_string(),
+                args: vec![],
+                value: 0,
+            };
+            for token in tokens {
+                let Some((bits, value)) = token.split_once('=') else {
+                    def.args.push(token.to_string());
+                    continue;
+                };
+                let (hi, lo) = bits.split_once("..").unwrap_or((bits, bits));
+                let (hi, lo): (u32, u32) = (hi.parse().unwrap(), lo.parse().unwrap());
+                let value = match value.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => value.parse(),
+                }
+                .unwrap();
+                assert!(
+                    hi >= lo && hi < 32 && value >> (hi - lo) <= 1,
+                    "bad field: {} ({})",
+                    token,
+                    def.name
+                );
+                def.value |= value << lo;
+            }
+            defs.push(def);
+        }
+        defs
+    }
+
+    fn has(&self, arg: &str) -> bool {
+        self.args.iter().any(|a| a == arg)
+    }
+
+    fn opcode(&self) -> u32 {
+        self.value & 0x7f
+    }
+
+    fn funct3(&self) -> u32 {
+        (self.value >> 12) & 0x7
+    }
+
+    fn funct7(&self) -> u32 {
+        self.value >> 25
+    }
+}
+
 fn main() {
     let out_dir = env::var_os("OUT_DIR").unwrap();
     let decode_path = Path::new(&out_dir).join("decode.rs");
     let encode_path = Path::new(&out_dir).join("encode.rs");
     let enum_path = Path::new(&out_dir).join("enum.rs");
-    let exec_path = Path::new(&out_dir).join("exec.rs");
+    let dispatch_path = Path::new(&out_dir).join("dispatch.rs");
+    let roundtrip_path = Path::new(&out_dir).join("roundtrip.rs");
+    let kinds_path = Path::new(&out_dir).join("kinds.rs");
 
     let mut variants: Vec<TokenStream> = vec![];
 
@@ -27,60 +113,107 @@ fn main() {
     let mut shamt: HashMap<u32, HashMap<u32, HashMap<u32, Ident>>> = HashMap::new();
     let mut rtype: HashMap<u32, HashMap<u32, HashMap<u32, Ident>>> = HashMap::new();
     let mut stype: HashMap<u32, HashMap<u32, Ident>> = HashMap::new();
+    let mut fence: HashMap<u32, HashMap<u32, Ident>> = HashMap::new();
+    let mut system: HashMap<u32, Vec<TokenStream>> = HashMap::new();
 
     let mut opcode_matches: Vec<TokenStream> = vec![];
-    let mut exec_matches: Vec<TokenStream> = vec![];
+    let mut dispatch_matches: Vec<TokenStream> = vec![];
+    let mut dest_matches: Vec<TokenStream> = vec![];
+    let mut source_matches: Vec<TokenStream> = vec![];
+    let mut mnemonic_matches: Vec<TokenStream> = vec![];
+    let mut mnemonics: Vec<String> = vec![];
     let mut encode_matches: Vec<TokenStream> = vec![];
+    let mut roundtrip_tests: Vec<TokenStream> = vec![];
+    // (mask, value) of the fixed bits of each instruction that decodes
+    let mut kinds: Vec<TokenStream> = vec![];
+
+    // NB in the order they're listed in, which is the order of the variants
+    let mut extensions: Vec<&str> = vec![
+        "src/opcodes/rv_i",
+        "src/opcodes/rv32_i",
+        "src/opcodes/rv_zifencei",
+        "src/opcodes/rv_zicsr",
+    ];
+
+    if cfg!(feature = "rv32m") {
+        extensions.push("src/opcodes/rv_m");
+    }
 
-    let mut tables: Vec<&str> = vec!["src/rv32i.tab"];
-
-    #[cfg(feature = "rv32m")]
-    tables.push("src/rv32m.tab");
-
-    for filename in tables {
-        for line in read_to_string(filename).unwrap().lines() {
-            let pieces: Vec<&str> = line.split(&[' ', '\t', '\r', '\n']).collect();
-
-            let opname = format_ident!("{}", sanitize_name(pieces[pieces.len() - 1]));
-            let lcname = sanitize_name(pieces[pieces.len() - 1]).to_lowercase();
+    for filename in &extensions {
+        for def in Def::parse_file(filename, &extensions) {
+            let opname = format_ident!("{}", sanitize_name(&def.name).to_uppercase());
+            let lcname = sanitize_name(&def.name);
             let funname = format_ident!("{}", lcname);
-            let opcode = u32::from_str_radix(pieces[pieces.len() - 2], 2).unwrap();
-
-            // TODO this will work for now, but could use refinement/refactoring
-            match pieces[0] {
-                // B-Type: imm[12|10:5] rs2 rs1 000 imm[4:1|11] 1100011 BEQ
-                "imm[12|10:5]" => {
+            let opcode = def.opcode();
+            let testname = format_ident!("roundtrip_{}", lcname);
+            let mnemonic = def.name.clone();
+            let mnemonic_variant = opname.clone();
+            let mut unit = false;
+
+            // the format follows from the arguments (and for fences, the opcode)
+            match &def {
+                // B-Type: beq bimm12hi rs1 rs2 bimm12lo 14..12=0 6..2=0x18 1..0=3
+                def if def.has("bimm12hi") => {
                     variants.push(quote! {#opname{rs1: Reg, rs2: Reg, imm: i32}});
-                    exec_matches.push(
-                        quote! {Inst::#opname{rs1, rs2, imm} => em.#funname(*rs1, *rs2, *imm)},
-                    );
+                    source_matches
+                        .push(quote! {Inst::#opname{rs1, rs2, ..} => (Some(*rs1), Some(*rs2))});
+                    dispatch_matches.push(quote! {Inst::#opname{rs1, rs2, imm} => Op { cost: 1,
+                        handler: |em, o| em.#funname(o.rs1, o.rs2, o.imm).into_result(),
+                        operands: Operands { rd: Reg::zero, rs1: *rs1, rs2: *rs2, imm: *imm },
+                    }});
 
-                    let funct3 = u32::from_str_radix(pieces[3], 2).unwrap();
+                    let funct3 = def.funct3();
                     encode_matches.push(quote! {Inst::#opname{rs1, rs2, imm} => {
                         Inst::b_type(#opcode, #funct3, rs1, rs2, imm)
                     }});
 
+                    let value = opcode | (funct3 << 12);
+                    kinds.push(quote! {(0x707f, #value)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0x707f, #value, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
+
                     let funct3s = btype.entry(opcode).or_default();
                     funct3s.insert(funct3, opname);
                 }
-                // I-Type: imm[11:0] rs1 000 rd 0010011 ADDI
-                "imm[11:0]" => {
+                // I-Type: addi rd rs1 imm12 14..12=0 6..2=0x04 1..0=3
+                def if def.has("imm12") && def.opcode() != MISC_MEM => {
                     variants.push(quote! {#opname{rd: Reg, rs1: Reg, imm: i32}});
-                    exec_matches
-                        .push(quote! {Inst::#opname{rd, rs1, imm} => em.#funname(*rd, *rs1, *imm)});
+                    dest_matches.push(quote! {Inst::#opname{rd, ..} => Some(*rd)});
+                    source_matches.push(quote! {Inst::#opname{rs1, ..} => (Some(*rs1), None)});
+                    dispatch_matches.push(quote! {Inst::#opname{rd, rs1, imm} => Op { cost: 1,
+                        handler: |em, o| em.#funname(o.rd, o.rs1, o.imm).into_result(),
+                        operands: Operands { rd: *rd, rs1: *rs1, rs2: Reg::zero, imm: *imm },
+                    }});
 
-                    let funct3 = u32::from_str_radix(pieces[2], 2).unwrap();
+                    let funct3 = def.funct3();
                     encode_matches.push(quote! {Inst::#opname{rd, rs1, imm} => {
                         Inst::i_type(#opcode, #funct3, rd, rs1, imm)
                     }});
 
+                    let value = opcode | (funct3 << 12);
+                    kinds.push(quote! {(0x707f, #value)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0x707f, #value, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
+
                     let funct3s = itype.entry(opcode).or_default();
                     funct3s.insert(funct3, opname);
                 }
-                // J-Type: imm[20|10:1|11|19:12] rd 1101111 JAL
-                "imm[20|10:1|11|19:12]" => {
+                // J-Type: jal rd jimm20 6..2=0x1b 1..0=3
+                def if def.has("jimm20") => {
                     variants.push(quote! {#opname{rd: Reg,  imm: i32}});
-                    exec_matches.push(quote! {Inst::#opname{rd, imm} => em.#funname(*rd, *imm)});
+                    dest_matches.push(quote! {Inst::#opname{rd, ..} => Some(*rd)});
+                    dispatch_matches.push(quote! {Inst::#opname{rd, imm} => Op { cost: 1,
+                        handler: |em, o| em.#funname(o.rd, o.imm).into_result(),
+                        operands: Operands { rd: *rd, rs1: Reg::zero, rs2: Reg::zero, imm: *imm },
+                    }});
 
                     opcode_matches.push(quote! {
                         #opcode => Ok(Inst::#opname{rd: Inst::rd(inst), imm: Inst::imm_j(inst)})
@@ -89,15 +222,38 @@ fn main() {
                     encode_matches.push(quote! {Inst::#opname{rd, imm} => {
                         Inst::j_type(#opcode, rd, imm)
                     }});
+
+                    kinds.push(quote! {(0x7f, #opcode)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0x7f, #opcode, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
                 }
-                // R-Type: 0000000 rs2 rs1 000 rd 0110011 ADD
-                "0000000" | "0000001" | "0100000" => {
-                    let funct3 = u32::from_str_radix(pieces[3], 2).unwrap();
-                    let funct7 = u32::from_str_radix(pieces[0], 2).unwrap();
-                    // shamt (special case): 0000000 shamt rs1 001 rd 0010011 SLLI
-                    if pieces[1] == "shamt" {
+                // R-Type: add rd rs1 rs2 31..25=0 14..12=0 6..2=0x0C 1..0=3
+                def if def.has("rd") && (def.has("rs2") || def.has("shamtw")) => {
+                    let funct3 = def.funct3();
+                    let funct7 = def.funct7();
+
+                    let value = opcode | (funct3 << 12) | (funct7 << 25);
+                    kinds.push(quote! {(0xfe00707f, #value)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0xfe00707f, #value, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
+
+                    // shamt (special case): slli rd rs1 shamtw 31..25=0 14..12=1 6..2=0x04 1..0=3
+                    if def.has("shamtw") {
                         variants.push(quote! {#opname{rd: Reg, rs1: Reg, shamt: u32}});
-                        exec_matches.push(quote!{Inst::#opname{rd, rs1, shamt} => em.#funname(*rd, *rs1, *shamt)});
+                        dest_matches.push(quote! {Inst::#opname{rd, ..} => Some(*rd)});
+                        source_matches.push(quote! {Inst::#opname{rs1, ..} => (Some(*rs1), None)});
+                        dispatch_matches.push(quote! {Inst::#opname{rd, rs1, shamt} => Op { cost: 1,
+                            handler: |em, o| em.#funname(o.rd, o.rs1, o.imm as u32).into_result(),
+                            operands: Operands { rd: *rd, rs1: *rs1, rs2: Reg::zero, imm: *shamt as i32 },
+                        }});
 
                         encode_matches.push(quote! {Inst::#opname{rd, rs1, shamt} => {
                             Inst::i_type_shamt(#opcode, #funct3, #funct7, rd, rs1, shamt)
@@ -107,11 +263,14 @@ fn main() {
                         let funct7s = funct3s.entry(funct3).or_default();
                         funct7s.insert(funct7, opname);
                     } else {
-                        // 0000000 rs2 rs1 000 rd 0110011 ADD
                         variants.push(quote! {#opname{rd: Reg, rs1: Reg, rs2: Reg}});
-                        exec_matches.push(
-                            quote! {Inst::#opname{rd, rs1, rs2} => em.#funname(*rd, *rs1, *rs2)},
-                        );
+                        dest_matches.push(quote! {Inst::#opname{rd, ..} => Some(*rd)});
+                        source_matches
+                            .push(quote! {Inst::#opname{rs1, rs2, ..} => (Some(*rs1), Some(*rs2))});
+                        dispatch_matches.push(quote! {Inst::#opname{rd, rs1, rs2} => Op { cost: 1,
+                            handler: |em, o| em.#funname(o.rd, o.rs1, o.rs2).into_result(),
+                            operands: Operands { rd: *rd, rs1: *rs1, rs2: *rs2, imm: 0 },
+                        }});
 
                         encode_matches.push(quote! {Inst::#opname{rd, rs1, rs2} => {
                             Inst::r_type(#opcode, #funct3, #funct7, rd, rs1, rs2)
@@ -122,25 +281,82 @@ fn main() {
                         funct7s.insert(funct7, opname);
                     }
                 }
-                // S-Type: imm[11:5] rs2 rs1 000 imm[4:0] 0100011 SB
-                "imm[11:5]" => {
+                // S-Type: sb imm12hi rs1 rs2 imm12lo 14..12=0 6..2=0x08 1..0=3
+                def if def.has("imm12hi") => {
                     variants.push(quote! {#opname{rs1: Reg, rs2: Reg, imm: i32}});
-                    exec_matches.push(
-                        quote! {Inst::#opname{rs1, rs2, imm} => em.#funname(*rs1, *rs2, *imm)},
-                    );
+                    source_matches
+                        .push(quote! {Inst::#opname{rs1, rs2, ..} => (Some(*rs1), Some(*rs2))});
+                    dispatch_matches.push(quote! {Inst::#opname{rs1, rs2, imm} => Op { cost: 1,
+                        handler: |em, o| em.#funname(o.rs1, o.rs2, o.imm).into_result(),
+                        operands: Operands { rd: Reg::zero, rs1: *rs1, rs2: *rs2, imm: *imm },
+                    }});
 
-                    let funct3 = u32::from_str_radix(pieces[3], 2).unwrap();
+                    let funct3 = def.funct3();
                     encode_matches.push(quote! {Inst::#opname{rs1, rs2, imm} => {
                         Inst::s_type(#opcode, #funct3, rs1, rs2, imm)
                     }});
 
+                    let value = opcode | (funct3 << 12);
+                    kinds.push(quote! {(0x707f, #value)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0x707f, #value, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
+
                     let funct3s = stype.entry(opcode).or_default();
                     funct3s.insert(funct3, opname);
                 }
-                // U-Type: imm[31:12] rd 0110111 LUI
-                "imm[31:12]" => {
+                // CSR: csrrw rd rs1 csr 14..12=1 6..2=0x1C 1..0=3 / csrrwi rd zimm csr ...
+                def if def.has("csr") => {
+                    let funct3 = def.funct3();
+                    dest_matches.push(quote! {Inst::#opname{rd, ..} => Some(*rd)});
+                    if def.has("zimm") {
+                        variants.push(quote! {#opname{rd: Reg, zimm: u32, csr: u32}});
+                        // NB zimm is packed above the CSR number
+                        dispatch_matches.push(quote! {Inst::#opname{rd, zimm, csr} => Op { cost: 1,
+                            handler: |em, o| em.#funname(o.rd, o.imm as u32 >> 12, o.imm as u32 & 0xfff).into_result(),
+                            operands: Operands { rd: *rd, rs1: Reg::zero, rs2: Reg::zero, imm: (*zimm << 12 | *csr) as i32 },
+                        }});
+                        system.entry(opcode).or_default().push(quote! {
+                            #funct3 => Ok(Inst::#opname{rd: Inst::rd(inst), zimm: Inst::zimm(inst), csr: Inst::csr(inst)})
+                        });
+                        encode_matches.push(quote! {Inst::#opname{rd, zimm, csr} => {
+                            Inst::i_type(#opcode, #funct3, rd, Reg::from_bits(zimm), csr as i32)
+                        }});
+                    } else {
+                        variants.push(quote! {#opname{rd: Reg, rs1: Reg, csr: u32}});
+                        source_matches.push(quote! {Inst::#opname{rs1, ..} => (Some(*rs1), None)});
+                        dispatch_matches.push(quote! {Inst::#opname{rd, rs1, csr} => Op { cost: 1,
+                            handler: |em, o| em.#funname(o.rd, o.rs1, o.imm as u32).into_result(),
+                            operands: Operands { rd: *rd, rs1: *rs1, rs2: Reg::zero, imm: *csr as i32 },
+                        }});
+                        system.entry(opcode).or_default().push(quote! {
+                            #funct3 => Ok(Inst::#opname{rd: Inst::rd(inst), rs1: Inst::rs1(inst), csr: Inst::csr(inst)})
+                        });
+                        encode_matches.push(quote! {Inst::#opname{rd, rs1, csr} => {
+                            Inst::i_type(#opcode, #funct3, rd, rs1, csr as i32)
+                        }});
+                    }
+
+                    let value = opcode | (funct3 << 12);
+                    kinds.push(quote! {(0x707f, #value)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0x707f, #value, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
+                }
+                // U-Type: lui rd imm20 6..2=0x0D 1..0=3
+                def if def.has("imm20") => {
                     variants.push(quote! {#opname{rd: Reg, imm: i32}});
-                    exec_matches.push(quote! {Inst::#opname{rd, imm} => em.#funname(*rd, *imm)});
+                    dest_matches.push(quote! {Inst::#opname{rd, ..} => Some(*rd)});
+                    dispatch_matches.push(quote! {Inst::#opname{rd, imm} => Op { cost: 1,
+                        handler: |em, o| em.#funname(o.rd, o.imm).into_result(),
+                        operands: Operands { rd: *rd, rs1: Reg::zero, rs2: Reg::zero, imm: *imm },
+                    }});
 
                     opcode_matches.push(quote! {
                         #opcode => Ok(Inst::#opname{rd: Inst::rd(inst), imm: Inst::imm_u(inst)})
@@ -149,26 +365,87 @@ fn main() {
                     encode_matches.push(quote! {Inst::#opname{rd, imm} => {
                         Inst::u_type(#opcode, rd, imm)
                     }});
+
+                    kinds.push(quote! {(0x7f, #opcode)});
+                    roundtrip_tests.push(quote! {
+                        #[test]
+                        fn #testname() {
+                            check_roundtrip(0x7f, #opcode, |inst| matches!(inst, Inst::#opname{..}));
+                        }
+                    });
                 }
-                _ => {
+                def => {
+                    unit = true;
                     if opname == "ECALL" {
                         variants.push(quote! {#opname});
-                        opcode_matches.push(quote! {
-                            #opcode => Ok(Inst::#opname)
+                        system.entry(opcode).or_default().push(quote! {
+                            0 => match Inst::csr(inst) {
+                                0 => Ok(Inst::ECALL),
+                                funct12 => Err(EmulatorError::InstructionDecode(format!("unknown/unimplemented opcode+funct3+funct12 {:07b} {:03b} {:012b}", opcode, 0, funct12))),
+                            }
                         });
-                        exec_matches.push(quote! {Inst::ECALL => em.ecall()});
+                        dispatch_matches.push(quote! {Inst::ECALL => Op { cost: 1,
+                            handler: |em, _| em.ecall().into_result(),
+                            operands: Operands::default(),
+                        }});
 
                         encode_matches.push(quote! {Inst::ECALL => #opcode});
+
+                        kinds.push(quote! {(0xfff0707f, #opcode)});
+                        roundtrip_tests.push(quote! {
+                            #[test]
+                            fn #testname() {
+                                check_roundtrip(0xfff0707f, #opcode, |inst| matches!(inst, Inst::ECALL));
+                            }
+                        });
+                    } else if opname == "FENCE" || opname == "FENCE_I" {
+                        // FENCE: fence fm pred succ rs1 14..12=0 rd 6..2=0x03 1..0=3
+                        let funct3 = def.funct3();
+                        variants.push(quote! {#opname});
+                        dispatch_matches.push(quote! {Inst::#opname => Op { cost: 1,
+                            handler: |em, _| em.#funname().into_result(),
+                            operands: Operands::default(),
+                        }});
+
+                        // NB pred/succ aren't retained, so FENCE encodes as the most conservative fence
+                        let word = if opname == "FENCE" {
+                            (0b1111_1111 << 20) | opcode
+                        } else {
+                            (funct3 << 12) | opcode
+                        };
+                        encode_matches.push(quote! {Inst::#opname => #word});
+
+                        let value = opcode | (funct3 << 12);
+                        kinds.push(quote! {(0x707f, #value)});
+                        roundtrip_tests.push(quote! {
+                            #[test]
+                            fn #testname() {
+                                check_roundtrip(0x707f, #value, |inst| matches!(inst, Inst::#opname));
+                            }
+                        });
+
+                        let funct3s = fence.entry(opcode).or_default();
+                        funct3s.insert(funct3, opname);
                     } else {
                         variants.push(quote! {
                             // keep the compiler from griping about unused variants
                             #[allow(dead_code)]
                             #opname
                         });
-                        exec_matches.push(quote! {Inst::#opname => em.nop()});
+                        dispatch_matches.push(quote! {Inst::#opname => Op { cost: 1,
+                            handler: |em, _| em.nop().into_result(),
+                            operands: Operands::default(),
+                        }});
                     }
                 }
             }
+
+            if unit {
+                mnemonic_matches.push(quote! {Inst::#mnemonic_variant => #mnemonic});
+            } else {
+                mnemonic_matches.push(quote! {Inst::#mnemonic_variant{..} => #mnemonic});
+            }
+            mnemonics.push(mnemonic);
         }
     }
 
@@ -281,8 +558,40 @@ fn main() {
         })
     }
 
+    // SYSTEM (ECALL, CSRs)
+    for (opcode, funct3_matches) in system {
+        opcode_matches.push(quote! {
+            #opcode => {
+                let funct3 = Inst::funct3(inst);
+                match funct3 {
+                    #(#funct3_matches,)*
+                    _ => { Err(EmulatorError::InstructionDecode(format!("unknown/unimplemented opcode+funct3 {:07b} {:03b}", opcode, funct3))) }
+                }
+            }
+        })
+    }
+
+    // FENCE
+    for (opcode, funct3s) in fence {
+        let mut funct3_matches: Vec<TokenStream> = vec![];
+        for (funct3, opname) in funct3s {
+            funct3_matches.push(quote! {
+                #funct3 => Ok(Inst::#opname)
+            });
+        }
+        opcode_matches.push(quote! {
+            #opcode => {
+                let funct3 = Inst::funct3(inst);
+                match funct3 {
+                    #(#funct3_matches,)*
+                    _ => { Err(EmulatorError::InstructionDecode(format!("unknown/unimplemented opcode+funct3 {:07b} {:03b}", opcode, funct3))) }
+                }
+            }
+        })
+    }
+
     let enum_output = quote! {
-        #[derive(Debug)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
         #[allow(non_camel_case_types)] // to keep the compiler from griping about FENCE_I
         /// Enumeration of all known instruction types.
         pub enum Inst {
@@ -293,19 +602,49 @@ fn main() {
     let formatted = prettyplease::unparse(&syntax_tree);
     fs::write(&enum_path, formatted).unwrap();
 
-    let exec_output = quote! {
+    let dispatch_output = quote! {
         impl Inst {
-            /// Executes a single instruction.
-            pub(crate) fn execute(&self, em: &mut Emulator) {
+            /// Resolves an instruction into a handler pointer and packed operands.
+            // NB most handlers return (), which into_result() turns into Ok(())
+            #[allow(clippy::unit_arg)]
+            pub(crate) fn resolve(&self) -> Op {
+                match self {
+                    #(#dispatch_matches),*
+                }
+            }
+
+            /// Returns the instruction's mnemonic (e.g., "addi").
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    #(#mnemonic_matches,)*
+                }
+            }
+
+            /// Returns the mnemonics of all known instructions.
+            pub fn mnemonics() -> impl Iterator<Item = &'static str> {
+                [#(#mnemonics),*].into_iter()
+            }
+
+            /// Returns the destination register for instructions that have one.
+            pub fn dest(&self) -> Option<Reg> {
                 match self {
-                    #(#exec_matches),*
+                    #(#dest_matches,)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the source registers read by the instruction, if any.
+            pub fn sources(&self) -> (Option<Reg>, Option<Reg>) {
+                match self {
+                    #(#source_matches,)*
+                    _ => (None, None),
                 }
             }
         }
     };
-    let syntax_tree = syn::parse2(exec_output).unwrap();
+    let syntax_tree = syn::parse2(dispatch_output).unwrap();
     let formatted = prettyplease::unparse(&syntax_tree);
-    fs::write(&exec_path, formatted).unwrap();
+    fs::write(&dispatch_path, formatted).unwrap();
 
     let decode_output = quote! {
         impl TryFrom<u32> for Inst {
@@ -339,8 +678,23 @@ fn main() {
     let formatted = prettyplease::unparse(&syntax_tree);
     fs::write(&encode_path, formatted).unwrap();
 
+    let roundtrip_output = quote! {
+        #(#roundtrip_tests)*
+    };
+    let syntax_tree = syn::parse2(roundtrip_output).unwrap();
+    let formatted = prettyplease::unparse(&syntax_tree);
+    fs::write(&roundtrip_path, formatted).unwrap();
+
+    let kinds_output = quote! {
+        /// The fixed bits (under the mask) of each kind of instruction that
+        /// decodes, as (mask, value); any other bits are operands.
+        const KINDS: &[(u32, u32)] = &[#(#kinds),*];
+    };
+    let syntax_tree = syn::parse2(kinds_output).unwrap();
+    let formatted = prettyplease::unparse(&syntax_tree);
+    fs::write(&kinds_path, formatted).unwrap();
+
     println!("cargo::rerun-if-changed=src/lib.rs");
     println!("cargo::rerun-if-changed=src/inst.rs");
-    println!("cargo::rerun-if-changed=src/rv32i.tab");
-    println!("cargo::rerun-if-changed=src/rv32m.tab");
+    println!("cargo::rerun-if-changed=src/opcodes");
 }
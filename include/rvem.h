@@ -0,0 +1,46 @@
+/*
+ * C bindings for rvem, a RISC-V emulator. Build the shared library with
+ * `make ffi` (it ends up in target/release) and link against it.
+ *
+ * Functions returning int return 0 on success and -1 on failure, in which
+ * case rvem_last_error() describes what went wrong.
+ */
+#ifndef RVEM_H
+#define RVEM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Emulator rvem_t;
+
+/* description of the last error on this thread, or NULL */
+const char *rvem_last_error(void);
+
+/* a new emulator with `memory` bytes of memory (0 for the default) */
+rvem_t *rvem_new(size_t memory);
+void rvem_free(rvem_t *em);
+
+int rvem_load(rvem_t *em, const char *path);
+int rvem_load_bytes(rvem_t *em, const uint8_t *buf, size_t len);
+
+/* execute one instruction; returns 1 once the program has exited */
+int rvem_step(rvem_t *em);
+/* run until the program exits, storing its exit code (if exit_code != NULL) */
+int rvem_run(rvem_t *em, int *exit_code);
+
+uint32_t rvem_pc(const rvem_t *em);
+uint32_t rvem_get_reg(const rvem_t *em, uint32_t n);
+int rvem_set_reg(rvem_t *em, uint32_t n, uint32_t value);
+
+int rvem_read_mem(const rvem_t *em, size_t addr, uint8_t *buf, size_t len);
+int rvem_write_mem(rvem_t *em, size_t addr, const uint8_t *buf, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RVEM_H */
@@ -0,0 +1,182 @@
+use crate::golden::{FNV_OFFSET, FNV_PRIME};
+use crate::{csr, Emulator, Reg};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+/// The CSRs captured in an [ArchState]: the counters, but for `time`, which
+/// follows the host's clock (unless virtual time is on; see
+/// [Emulator::set_virtual_time]) and so would never compare equal
+const CSRS: &[u32] = &[csr::CYCLE, csr::INSTRET, csr::CYCLEH, csr::INSTRETH];
+
+/// The architectural state of an emulator - its PC, registers, CSRs, and
+/// (optionally) a hash of memory - as captured by [Emulator::arch_state],
+/// for differential testing: comparing rvem against a reference model, or
+/// against another version of itself, at chosen sync points. Unlike a
+/// [crate::Snapshot], it's small enough to capture often, and (with the
+/// `serialize` feature) to save and compare across builds, the memory hash
+/// being stable across them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ArchState {
+    pub pc: usize,
+    /// x0-x31
+    pub regs: [u32; 32],
+    /// (CSR number, value) pairs, in order of number
+    pub csrs: Vec<(u32, u32)>,
+    /// A 64-bit FNV-1a hash of all of memory, if captured
+    pub mem_hash: Option<u64>,
+}
+
+/// The differences between two [ArchState]s, as returned by
+/// [ArchState::diff].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchDiff {
+    /// The two program counters, if they differ
+    pub pc: Option<(usize, usize)>,
+    /// Registers that differ, with their values in each state
+    pub registers: Vec<(Reg, u32, u32)>,
+    /// CSRs that differ (by number), with their values in each state
+    pub csrs: Vec<(u32, u32, u32)>,
+    /// The two memory hashes, if both states have one and they differ
+    pub memory: Option<(u64, u64)>,
+}
+
+impl Emulator {
+    /// Captures the emulator's architectural state (see [ArchState]),
+    /// hashing all of memory too if `memory` is set.
+    ///
+    /// ```
+    /// # use rvem::Emulator;
+    /// let mut a = Emulator::load_from("tests/data/fac", None).unwrap();
+    /// let mut b = Emulator::load_from("tests/data/fac", None).unwrap();
+    /// a.set_stdout(std::io::sink());
+    /// b.set_stdout(std::io::sink());
+    /// a.run().unwrap();
+    /// b.run().unwrap();
+    /// assert_eq!(a.arch_state(true), b.arch_state(true));
+    /// ```
+    pub fn arch_state(&self, memory: bool) -> ArchState {
+        let mut regs = [0; 32];
+        for reg in Reg::iter() {
+            regs[reg as usize] = self[reg];
+        }
+        let csrs = (CSRS.iter())
+            .map(|csr| (*csr, self.read_csr(*csr).unwrap_or_default()))
+            .collect();
+        let mem_hash = memory.then(|| {
+            (self.mem.iter()).fold(FNV_OFFSET, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+            })
+        });
+        ArchState {
+            pc: self.pc,
+            regs,
+            csrs,
+            mem_hash,
+        }
+    }
+}
+
+impl ArchState {
+    /// Returns the differences between this state and `other`. Memory is
+    /// only compared if both have a hash of it, and CSRs only if both have
+    /// them.
+    pub fn diff(&self, other: &ArchState) -> ArchDiff {
+        let mut diff = ArchDiff::default();
+        if self.pc != other.pc {
+            diff.pc = Some((self.pc, other.pc));
+        }
+        for reg in Reg::iter() {
+            let (a, b) = (self.regs[reg as usize], other.regs[reg as usize]);
+            if a != b {
+                diff.registers.push((reg, a, b));
+            }
+        }
+        for (csr, a) in &self.csrs {
+            if let Some((_, b)) = other.csrs.iter().find(|(other, _)| other == csr) {
+                if a != b {
+                    diff.csrs.push((*csr, *a, *b));
+                }
+            }
+        }
+        if let (Some(a), Some(b)) = (self.mem_hash, other.mem_hash) {
+            if a != b {
+                diff.memory = Some((a, b));
+            }
+        }
+        diff
+    }
+}
+
+impl ArchDiff {
+    /// Returns whether the two states are the same.
+    pub fn is_empty(&self) -> bool {
+        *self == ArchDiff::default()
+    }
+}
+
+impl std::fmt::Display for ArchDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((a, b)) = self.pc {
+            writeln!(f, "pc: {:08x} -> {:08x}", a, b)?;
+        }
+        for (reg, a, b) in &self.registers {
+            writeln!(f, "{}: 0x{:08x} -> 0x{:08x}", reg, a, b)?;
+        }
+        for (csr, a, b) in &self.csrs {
+            writeln!(f, "{}: 0x{:08x} -> 0x{:08x}", csr::Name(*csr), a, b)?;
+        }
+        if let Some((a, b)) = self.memory {
+            writeln!(f, "memory hash: {:016x} -> {:016x}", a, b)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, NoTrace, Reg};
+
+    #[test]
+    fn test_arch_state() {
+        let mut a = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut b = Emulator::load_from("tests/data/fac", None).unwrap();
+        a.init().unwrap();
+        b.init().unwrap();
+        // in lockstep, comparing at every instruction
+        while a.exit_code().is_none() {
+            a.step_with(&mut NoTrace).unwrap();
+            b.step_with(&mut NoTrace).unwrap();
+            let diff = a.arch_state(true).diff(&b.arch_state(true));
+            assert!(diff.is_empty(), "{}", diff);
+        }
+
+        let before = a.arch_state(true);
+        a.set_reg("a0", 6).unwrap();
+        a[0x80004] = 0xff;
+        let after = a.arch_state(true);
+        let diff = before.diff(&after);
+        assert_eq!(diff.registers, [(Reg::a0, 120, 6)]);
+        assert!(diff.memory.is_some());
+        assert_eq!(
+            diff.to_string().lines().next(),
+            Some("a0: 0x00000078 -> 0x00000006")
+        );
+
+        // memory's only compared if both states have a hash of it
+        let diff = a.arch_state(false).diff(&after);
+        assert!(diff.is_empty());
+        assert_ne!(a.arch_state(false), after);
+
+        // and a run against the start of one
+        let mut c = Emulator::load_from("tests/data/fac", None).unwrap();
+        c.init().unwrap();
+        let diff = c.arch_state(false).diff(&a.arch_state(false));
+        assert!(diff.pc.is_some());
+        assert!(diff
+            .csrs
+            .iter()
+            .any(|(csr, _, _)| *csr == crate::csr::INSTRET));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{Emulator, Inst, Reg, Retired, TraceSink};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+/// A [TraceSink] that reports the machine state to `W` whenever execution
+/// reaches one of a set of breakpoints, and (unless told to continue) then
+/// halts execution.
+///
+/// Each report has the registers, followed by (with some
+/// [Breakpoints::context]) the disassembly of the instructions starting at
+/// the breakpoint and the words at the top of the stack, e.g.:
+///
+/// ```text
+/// breakpoint at 00010074 <fact> at tests/data/fac.s:4
+///      4 |     addi   sp, sp, -8      # reserve our stack area
+/// PC: 0x10074  zero: 0x0 ra: 0x100b4 sp: 0x80000 ...
+///   10074: ff810113 addi sp, sp, -8
+///   10078: 00112023 sw ra, 0(sp)
+/// stack:
+///   00080000: 00000000
+///   00080004: 00000000
+/// ```
+///
+/// The source file and line after the address (and, if the file can be
+/// read, the line itself below it) are only there for a program with source
+/// line information.
+///
+/// Since sinks can't fail, the first write error (if any) is held onto and
+/// reported by [Breakpoints::finish].
+pub struct Breakpoints<W: Write> {
+    out: W,
+    addrs: BTreeSet<usize>,
+    stop: bool,
+    context: usize,
+    hits: Vec<usize>,
+    started: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Breakpoints<W> {
+    /// Returns a new set of breakpoints at `addrs` that reports to `out`,
+    /// halting at the first one reached if `stop` is set.
+    pub fn new<I: IntoIterator<Item = usize>>(out: W, addrs: I, stop: bool) -> Breakpoints<W> {
+        Breakpoints {
+            out,
+            addrs: addrs.into_iter().collect(),
+            stop,
+            context: 0,
+            hits: Vec::new(),
+            started: false,
+            error: None,
+        }
+    }
+
+    /// Also reports `n` instructions and `n` words of stack at each
+    /// breakpoint.
+    pub fn context(mut self, n: usize) -> Breakpoints<W> {
+        self.context = n;
+        self
+    }
+
+    /// Returns the breakpoints reached so far, in order.
+    pub fn hits(&self) -> &[usize] {
+        &self.hits
+    }
+
+    /// Flushes the reports, returning the first error encountered while
+    /// writing them (if any).
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    /// Reports on `em`, which has reached the breakpoint at `pc`.
+    fn hit(&mut self, em: &Emulator, pc: usize) {
+        self.hits.push(pc);
+        if self.error.is_none() {
+            if let Err(e) = self.report(em, pc) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn report(&mut self, em: &Emulator, pc: usize) -> io::Result<()> {
+        let out = &mut self.out;
+        write!(
+            out,
+            "breakpoint at {:08x} <{}>",
+            pc,
+            em.symbols().symbolize(pc)
+        )?;
+        match em.lines().lookup(pc) {
+            Some((file, line)) => {
+                writeln!(out, " at {}:{}", file, line)?;
+                if let Some(text) = source_line(file, line) {
+                    writeln!(out, "{:>6} | {}", line, text)?;
+                }
+            }
+            None => writeln!(out)?,
+        }
+        writeln!(out, "{:?}", em)?;
+        if self.context == 0 {
+            return Ok(());
+        }
+        for addr in (pc..).step_by(4).take(self.context) {
+            let Ok(word) = em.read_u32(addr) else {
+                break;
+            };
+            match Inst::try_from(word) {
+                Ok(inst) => writeln!(out, "  {:x}: {:08x} {}", addr, word, inst.at(addr))?,
+                Err(_) => writeln!(out, "  {:x}: {:08x} <unknown>", addr, word)?,
+            }
+        }
+        writeln!(out, "stack:")?;
+        let sp = em[Reg::sp] as usize;
+        for addr in (sp..).step_by(4).take(self.context) {
+            let Ok(word) = em.read_u32(addr) else {
+                break;
+            };
+            writeln!(out, "  {:08x}: {:08x}", addr, word)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the text of `line` of the source file at `path`, if it can be
+/// read.
+fn source_line(path: &str, line: u32) -> Option<String> {
+    let source = std::fs::read_to_string(path).ok()?;
+    let text = source.lines().nth(line.checked_sub(1)? as usize)?;
+    Some(text.trim_end().to_string())
+}
+
+impl<W: Write> TraceSink for Breakpoints<W> {
+    fn before(&mut self, em: &Emulator, pc: usize, _word: u32, _inst: &Inst) {
+        // NB breakpoints are otherwise checked as they're reached, on
+        // retiring the instruction before
+        if !self.started {
+            self.started = true;
+            if self.addrs.contains(&pc) {
+                self.hit(em, pc);
+            }
+        }
+    }
+
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        if self.addrs.contains(&retired.next_pc) && em.exit_code().is_none() {
+            self.hit(em, retired.next_pc);
+        }
+    }
+
+    fn halt(&self) -> bool {
+        self.stop && !self.hits.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmulatorError;
+
+    #[test]
+    fn test_breakpoints() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let fact = em.addr_of("fact").unwrap();
+        let mut breakpoints = Breakpoints::new(Vec::new(), [fact], true).context(2);
+        assert!(matches!(
+            em.run_with(&mut breakpoints),
+            Err(EmulatorError::Halted(pc)) if pc == fact
+        ));
+        assert_eq!(breakpoints.hits(), [fact]);
+        let report = String::from_utf8(breakpoints.out.clone()).unwrap();
+        assert!(report.starts_with(&format!("breakpoint at {:08x} <fact>\nPC: ", fact)));
+        assert_eq!(report.lines().count(), 7);
+
+        // fact is called 5 times, and _start is where it all begins
+        let start = em.addr_of("_start").unwrap();
+        let mut breakpoints = Breakpoints::new(io::sink(), [fact, start], false);
+        assert_eq!(em.run_with(&mut breakpoints).unwrap(), 0);
+        assert_eq!(breakpoints.hits().len(), 6);
+        assert_eq!(breakpoints.hits()[0], start);
+        breakpoints.finish().unwrap();
+    }
+
+    #[test]
+    fn test_source_breakpoint() {
+        let mut em = Emulator::load_from("tests/data/cover", None).unwrap();
+        let sum = em.lookup("cover.s:18").unwrap();
+        assert_eq!(Some(sum), em.addr_of("sum"));
+        let mut breakpoints = Breakpoints::new(Vec::new(), [sum], true);
+        assert!(em.run_with(&mut breakpoints).is_err());
+        let report = String::from_utf8(breakpoints.out.clone()).unwrap();
+        let (header, _) = report.split_once('\n').unwrap();
+        assert!(header.starts_with(&format!("breakpoint at {:08x} <sum> at ", sum)));
+        assert!(header.ends_with("cover.s:18"));
+        let (path, _) = em.lines().lookup(sum).unwrap();
+        if std::path::Path::new(path).exists() {
+            assert!(report.contains("\n    18 |     mv     t0, a0\n"));
+        }
+    }
+}
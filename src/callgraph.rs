@@ -0,0 +1,87 @@
+use crate::{Emulator, Inst, Reg, Retired, TraceSink};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Returns whether `inst` is a call, i.e., a jump that links through `ra`.
+pub(crate) fn is_call(inst: &Inst) -> bool {
+    matches!(
+        inst,
+        Inst::JAL { rd: Reg::ra, .. } | Inst::JALR { rd: Reg::ra, .. }
+    )
+}
+
+/// Returns whether `inst` is a return, i.e., a jump through `ra` that doesn't
+/// link.
+pub(crate) fn is_return(inst: &Inst) -> bool {
+    matches!(
+        inst,
+        Inst::JALR {
+            rd: Reg::zero,
+            rs1: Reg::ra,
+            ..
+        }
+    )
+}
+
+/// A [TraceSink] that records the dynamic call graph of a program: which
+/// functions called which, and how many times.
+///
+/// Its [Display](std::fmt::Display) implementation renders the graph in
+/// graphviz DOT format.
+#[derive(Clone, Debug, Default)]
+pub struct CallGraph {
+    /// Every function seen, whether or not it called or was called
+    functions: BTreeSet<String>,
+    /// Call counts by (caller, callee)
+    edges: BTreeMap<(String, String), u64>,
+}
+
+impl CallGraph {
+    /// Returns a new, empty call graph.
+    pub fn new() -> CallGraph {
+        Default::default()
+    }
+
+    /// Returns the number of calls from `caller` to `callee`.
+    pub fn calls(&self, caller: &str, callee: &str) -> u64 {
+        self.edges
+            .get(&(caller.to_string(), callee.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns an iterator over the edges of the graph as (caller, callee,
+    /// count) triples.
+    pub fn edges(&self) -> impl Iterator<Item = (&str, &str, u64)> {
+        self.edges
+            .iter()
+            .map(|((caller, callee), count)| (caller.as_str(), callee.as_str(), *count))
+    }
+}
+
+impl TraceSink for CallGraph {
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        if self.functions.is_empty() {
+            self.functions
+                .insert(em.symbols().symbolize_function(retired.pc));
+        }
+        if is_call(&retired.inst) {
+            let caller = em.symbols().symbolize_function(retired.pc);
+            let callee = em.symbols().symbolize_function(retired.next_pc);
+            self.functions.insert(callee.clone());
+            *self.edges.entry((caller, callee)).or_default() += 1;
+        }
+    }
+}
+
+impl std::fmt::Display for CallGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "digraph calls {{")?;
+        for function in &self.functions {
+            writeln!(f, "    {:?};", function)?;
+        }
+        for ((caller, callee), count) in &self.edges {
+            writeln!(f, "    {:?} -> {:?} [label=\"{}\"];", caller, callee, count)?;
+        }
+        writeln!(f, "}}")
+    }
+}
@@ -0,0 +1,246 @@
+use crate::fuzz::PAGE_SIZE;
+use crate::{Emulator, EmulatorError, NoTrace};
+use std::collections::VecDeque;
+
+/// The state of the machine at a checkpoint, but for memory, of which only
+/// the pages written since the checkpoint before are kept.
+struct Checkpoint {
+    instret: u64,
+    cycles: u64,
+    pc: usize,
+    reg: [u32; 32],
+    brk: usize,
+    mmap_top: usize,
+    exit_code: Option<i32>,
+    rng: u64,
+    /// The pages (by number) written since the previous checkpoint, as they
+    /// were at this one
+    pages: Vec<(usize, Vec<u8>)>,
+}
+
+/// A ring buffer of the most recent checkpoints taken by an [Emulator] (see
+/// [Emulator::set_checkpoints]).
+pub(crate) struct Checkpoints {
+    /// Instructions between checkpoints
+    pub(crate) every: u64,
+    /// Most checkpoints to keep
+    keep: usize,
+    /// The whole of memory as of the oldest checkpoint
+    base: Vec<u8>,
+    /// The checkpoints, oldest first
+    ring: VecDeque<Checkpoint>,
+}
+
+impl Emulator {
+    /// Has the emulator checkpoint the program's state every `every`
+    /// instructions (or so: at the end of the basic block in which the count
+    /// comes due) as it runs, keeping the most recent `keep`, so that it can
+    /// be wound back to shortly before some point of interest - e.g., a
+    /// crash - with [Emulator::rewind]. A checkpoint is taken straight away,
+    /// and whenever a program is started or a [crate::Snapshot] restored.
+    ///
+    /// Only the oldest checkpoint has a copy of all of memory; the rest have
+    /// just the pages written since the one before, so many can be kept of a
+    /// program that writes to little memory at a time.
+    pub fn set_checkpoints(&mut self, every: u64, keep: usize) {
+        self.checkpoints = Some(Checkpoints {
+            every: every.max(1),
+            keep: keep.max(1),
+            base: Vec::new(),
+            ring: VecDeque::new(),
+        });
+        self.reset_checkpoints();
+    }
+
+    /// Stops taking checkpoints, and forgets the ones taken.
+    pub fn clear_checkpoints(&mut self) {
+        self.checkpoints = None;
+        self.checkpoint_dirty = Vec::new();
+        self.next_checkpoint = u64::MAX;
+    }
+
+    /// Returns the instruction count and program counter at each of the
+    /// checkpoints kept, oldest first.
+    pub fn checkpoints(&self) -> Vec<(u64, usize)> {
+        (self.checkpoints.iter())
+            .flat_map(|checkpoints| &checkpoints.ring)
+            .map(|checkpoint| (checkpoint.instret, checkpoint.pc))
+            .collect()
+    }
+
+    /// Forgets any checkpoints taken and takes a fresh one, e.g., when a
+    /// program starts over.
+    pub(crate) fn reset_checkpoints(&mut self) {
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.ring.clear();
+            self.checkpoint_dirty = vec![false; self.mem.len().div_ceil(PAGE_SIZE)];
+            self.checkpoint();
+        }
+    }
+
+    /// Takes a checkpoint, dropping the oldest if there are too many.
+    pub(crate) fn checkpoint(&mut self) {
+        let Some(mut checkpoints) = self.checkpoints.take() else {
+            self.next_checkpoint = u64::MAX;
+            return;
+        };
+        let mut pages = Vec::new();
+        if checkpoints.ring.is_empty() {
+            checkpoints.base.clone_from(&self.mem);
+        } else {
+            for (page, dirty) in self.checkpoint_dirty.iter().enumerate() {
+                if *dirty {
+                    let start = page * PAGE_SIZE;
+                    let end = (start + PAGE_SIZE).min(self.mem.len());
+                    pages.push((page, self.mem[start..end].to_vec()));
+                }
+            }
+        }
+        self.checkpoint_dirty.fill(false);
+        checkpoints.ring.push_back(Checkpoint {
+            instret: self.instret,
+            cycles: self.cycles,
+            pc: self.pc,
+            reg: self.reg,
+            brk: self.brk,
+            mmap_top: self.mmap_top,
+            exit_code: self.exit_code,
+            rng: self.rng,
+            pages,
+        });
+
+        // NB the next oldest becomes the base, by folding its pages in
+        if checkpoints.ring.len() > checkpoints.keep {
+            checkpoints.ring.pop_front();
+            if let Some(oldest) = checkpoints.ring.front_mut() {
+                for (page, bytes) in oldest.pages.drain(..) {
+                    let start = page * PAGE_SIZE;
+                    checkpoints.base[start..start + bytes.len()].copy_from_slice(&bytes);
+                }
+            }
+        }
+        self.next_checkpoint = self.instret.saturating_add(checkpoints.every);
+        self.checkpoints = Some(checkpoints);
+    }
+
+    /// Winds the program back to the most recent checkpoint taken at or
+    /// before instruction `instret`, and returns that checkpoint's
+    /// instruction count. Checkpoints after it are dropped, since the
+    /// program will take a new course from there.
+    ///
+    /// ```
+    /// # use rvem::Emulator;
+    /// let mut em = Emulator::load_from("tests/data/crash", None).unwrap();
+    /// em.set_checkpoints(100, 8);
+    /// assert!(em.run().is_err());
+    /// // back to shortly before the crash
+    /// let at = em.rewind(em.instret()).unwrap();
+    /// assert_eq!(em.instret(), at);
+    /// ```
+    pub fn rewind(&mut self, instret: u64) -> Result<u64, EmulatorError> {
+        let checkpoints = (self.checkpoints.as_mut())
+            .ok_or_else(|| EmulatorError::Config("no checkpoints are being taken".into()))?;
+        let i = (checkpoints.ring.iter())
+            .rposition(|checkpoint| checkpoint.instret <= instret)
+            .ok_or_else(|| {
+                EmulatorError::Config(format!(
+                    "no checkpoint at or before instruction {}",
+                    instret
+                ))
+            })?;
+        if checkpoints.base.len() != self.mem.len() {
+            return Err(EmulatorError::Config(
+                "memory has been replaced since the checkpoints were taken".into(),
+            ));
+        }
+        checkpoints.ring.truncate(i + 1);
+
+        self.mem.copy_from_slice(&checkpoints.base);
+        for checkpoint in checkpoints.ring.iter().skip(1) {
+            for (page, bytes) in &checkpoint.pages {
+                let start = page * PAGE_SIZE;
+                self.mem[start..start + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        let checkpoint = &checkpoints.ring[i];
+        self.instret = checkpoint.instret;
+        self.cycles = checkpoint.cycles;
+        self.pc = checkpoint.pc;
+        self.reg = checkpoint.reg;
+        self.brk = checkpoint.brk;
+        self.mmap_top = checkpoint.mmap_top;
+        self.exit_code = checkpoint.exit_code;
+        self.rng = checkpoint.rng;
+        self.next_checkpoint = self.instret.saturating_add(checkpoints.every);
+
+        // NB as far as a snapshot's concerned, all of memory may have changed
+        self.touch(0..self.mem.len());
+        self.checkpoint_dirty.fill(false);
+        self.invalidate_icache(0, self.mem.len());
+        self.calls.clear();
+        Ok(self.instret)
+    }
+
+    /// Winds the program back to exactly instruction `instret`: to the
+    /// checkpoint before it, and then forward again from there, a step at a
+    /// time. This only retraces the program's steps if it runs the same way
+    /// every time - with a seed (see [Emulator::set_seed]), and the same
+    /// input - and anything it writes on the way is written again.
+    pub fn rewind_to(&mut self, instret: u64) -> Result<(), EmulatorError> {
+        self.rewind(instret)?;
+        while self.instret < instret && self.exit_code.is_none() {
+            self.step_with(&mut NoTrace)?;
+            if self.instret >= self.next_checkpoint {
+                self.checkpoint();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, Reg};
+
+    #[test]
+    fn test_checkpoints() {
+        let mut em = Emulator::load_from("tests/data/primes", None).unwrap();
+        em.set_stdout(std::io::sink());
+        em.set_seed(1);
+        em.set_checkpoints(100_000, 4);
+        em.run().unwrap();
+        let checkpoints = em.checkpoints();
+        assert_eq!(checkpoints.len(), 4);
+        for pair in checkpoints.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= 100_000);
+        }
+        let (total, s1) = (em.instret(), em[Reg::s1]);
+
+        // back to a checkpoint...
+        let oldest = checkpoints[0].0;
+        assert_eq!(em.rewind(oldest + 1).unwrap(), oldest);
+        assert_eq!(em.checkpoints().len(), 1);
+        assert_eq!(em.pc(), checkpoints[0].1);
+        assert!(em[Reg::s1] < s1);
+        // ...from which it runs to the same end
+        em.resume_with(&mut crate::NoTrace).unwrap();
+        assert_eq!((em.instret(), em[Reg::s1]), (total, s1));
+
+        // and to any instruction in between
+        for n in [total - 250_000, total - 1] {
+            em.rewind_to(n).unwrap();
+            assert_eq!(em.instret(), n);
+            let mut replay = Emulator::load_from("tests/data/primes", None).unwrap();
+            replay.set_seed(1);
+            replay.init().unwrap();
+            while replay.instret() < n {
+                replay.step_with(&mut crate::NoTrace).unwrap();
+            }
+            assert!(em.diff(&replay).is_empty(), "{}", em.diff(&replay));
+        }
+
+        assert!(em.rewind(0).is_err());
+        em.clear_checkpoints();
+        assert!(em.rewind(total).is_err());
+    }
+}
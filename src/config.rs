@@ -0,0 +1,415 @@
+use crate::{Emulator, EmulatorError, HYPERCALL};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Deserializer};
+use std::fs::File;
+use std::io::BufReader;
+#[cfg(feature = "serialize")]
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Which family of syscalls the emulator provides.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Personality {
+    /// All of them
+    #[default]
+    All,
+    /// Just the Linux ones (write, exit, brk, etc.)
+    Linux,
+    /// Just the SPIM/MARS-style ones (print_int, print_string, read_int,
+    /// and exit)
+    Spim,
+}
+
+impl Personality {
+    /// Returns whether `syscall` belongs to this personality (hypercalls
+    /// always do).
+    pub(crate) fn allows(&self, syscall: u32) -> bool {
+        let spim = matches!(syscall, 1 | 4 | 5 | 10);
+        match self {
+            _ if syscall == HYPERCALL => true,
+            Personality::All => true,
+            Personality::Linux => !spim,
+            Personality::Spim => spim,
+        }
+    }
+}
+
+/// What the emulator does with a syscall it doesn't provide (see
+/// [Personality]).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum UnknownSyscalls {
+    /// Log it and carry on, leaving the registers alone
+    #[default]
+    Ignore,
+    /// Return -ENOSYS in a0, as Linux does
+    Enosys,
+    /// Stop with [crate::EmulatorError::UnknownSyscall]
+    Stop,
+}
+
+impl FromStr for UnknownSyscalls {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(UnknownSyscalls::Ignore),
+            "enosys" => Ok(UnknownSyscalls::Enosys),
+            "stop" => Ok(UnknownSyscalls::Stop),
+            _ => Err(format!("expected ignore, enosys, or stop: {}", s)),
+        }
+    }
+}
+
+/// What the emulator does with a load or store that isn't naturally aligned.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Misaligned {
+    /// Carry it out anyway, as most Linux-class cores do
+    #[default]
+    Emulate,
+    /// Raise an address-misaligned exception, as strict embedded cores do
+    /// (see [crate::EmulatorError::MisalignedAccess])
+    Trap,
+}
+
+/// Caps on the resources a program can use, for running untrusted ones
+/// (see [Emulator::set_limits]); going over one stops the program with
+/// [crate::EmulatorError::LimitExceeded].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct Limits {
+    /// Bytes of memory the program can ask for, with brk and mmap together
+    #[cfg_attr(feature = "serialize", serde(deserialize_with = "size"))]
+    pub heap: Option<usize>,
+    /// Bytes the program can write to its standard output and error
+    #[cfg_attr(feature = "serialize", serde(deserialize_with = "size"))]
+    pub output: Option<usize>,
+    /// Host time the program can run for (in seconds, in a config file);
+    /// it's checked every [CPU_TIME_CHECK] instructions or so
+    #[cfg_attr(feature = "serialize", serde(deserialize_with = "seconds"))]
+    pub cpu_time: Option<Duration>,
+}
+
+/// How many instructions run between checks of [Limits::cpu_time]
+pub const CPU_TIME_CHECK: u64 = 1 << 16;
+
+/// Which of its [Limits] a program went over, and what it was.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Heap(usize),
+    Output(usize),
+    CpuTime(Duration),
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Heap(bytes) => write!(f, "heap of {} bytes", bytes),
+            Limit::Output(bytes) => write!(f, "output of {} bytes", bytes),
+            Limit::CpuTime(time) => write!(f, "CPU time of {:?}", time),
+        }
+    }
+}
+
+/// A description of the machine to emulate, as read from a TOML file by
+/// [MachineConfig::load] (with the `serialize` feature); e.g.:
+///
+/// ```toml
+/// memory = "8M"
+/// isa = "rv32im_zicsr"
+/// strict = true
+/// misaligned = "trap"
+/// syscalls = "linux"
+/// unknown_syscalls = "enosys"
+/// args = ["prog", "--verbose"]
+/// seed = 42
+/// virtual_time = 1000000
+///
+/// [limits]
+/// heap = "1M"
+/// output = "64k"
+/// cpu_time = 2.5
+///
+/// [devices]
+/// tohost = 0x80001000
+///
+/// [io]
+/// stdin = "input.txt"
+/// stdout = "output.txt"
+/// ```
+///
+/// Everything is optional; anything left out is as it would be otherwise.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct MachineConfig {
+    /// Bytes of memory (the top half of which is the stack and anonymous
+    /// mappings), as a number or a string for [parse_size]
+    #[cfg_attr(feature = "serialize", serde(deserialize_with = "size"))]
+    pub memory: Option<usize>,
+    /// ISA string; see [Emulator::set_isa]
+    pub isa: Option<String>,
+    /// Whether to reject reserved and hint instruction encodings
+    pub strict: bool,
+    /// What to do with misaligned loads and stores
+    pub misaligned: Misaligned,
+    /// Which syscalls are provided
+    pub syscalls: Personality,
+    /// What to do with syscalls that aren't provided
+    pub unknown_syscalls: UnknownSyscalls,
+    /// The program's arguments, including its name
+    pub args: Vec<String>,
+    /// Seed for reproducible runs; see [Emulator::set_seed]
+    pub seed: Option<u64>,
+    /// Instructions per second of virtual time; see
+    /// [Emulator::set_virtual_time]
+    pub virtual_time: Option<u64>,
+    /// Caps on the resources the program can use
+    pub limits: Limits,
+    /// Where devices are in memory
+    pub devices: DeviceConfig,
+    /// Where the program's standard I/O comes from and goes
+    pub io: IoConfig,
+}
+
+/// Device placements for a [MachineConfig].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct DeviceConfig {
+    /// Address of the HTIF tohost word (by default, the program's tohost
+    /// symbol, if it has one)
+    pub tohost: Option<usize>,
+}
+
+/// Standard I/O redirections for a [MachineConfig]; each is the path of a
+/// file to use instead of rvem's own.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct IoConfig {
+    pub stdin: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+/// Parses a size in bytes: a number (decimal, or hex with a `0x` prefix),
+/// optionally followed by a `k`, `M`, or `G` suffix (for KiB, MiB, and GiB;
+/// a trailing `B` or `iB` is allowed too).
+///
+/// ```rust
+/// use rvem::parse_size;
+///
+/// assert_eq!(parse_size("4096").unwrap(), 4096);
+/// assert_eq!(parse_size("64k").unwrap(), 64 << 10);
+/// assert_eq!(parse_size("8M").unwrap(), 8 << 20);
+/// assert_eq!(parse_size("1GiB").unwrap(), 1 << 30);
+/// assert_eq!(parse_size("0x100000").unwrap(), 1 << 20);
+/// assert!(parse_size("8Q").is_err());
+/// ```
+pub fn parse_size(s: &str) -> Result<usize, EmulatorError> {
+    let error = |why: &str| EmulatorError::Config(format!("invalid size '{}': {}", s, why));
+    let trimmed = s.trim();
+    let unit = trimmed
+        .strip_suffix("iB")
+        .or_else(|| trimmed.strip_suffix('B'))
+        .unwrap_or(trimmed);
+    let (digits, shift) = match unit.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&unit[..i], 10),
+        Some((i, 'm' | 'M')) => (&unit[..i], 20),
+        Some((i, 'g' | 'G')) => (&unit[..i], 30),
+        _ => (unit, 0),
+    };
+    let n = match digits.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|_| error("expected a number of bytes, optionally with a k, M, or G suffix"))?;
+    n.checked_mul(1 << shift)
+        .filter(|bytes| shift == 0 || bytes >> shift == n)
+        .ok_or_else(|| error("too large"))
+}
+
+/// Formats a size in bytes as [parse_size] would take it, in the largest
+/// unit it's a whole number of.
+pub(crate) fn format_size(bytes: usize) -> String {
+    match bytes {
+        0 => "0".to_string(),
+        n if n.trailing_zeros() >= 30 => format!("{}G", n >> 30),
+        n if n.trailing_zeros() >= 20 => format!("{}M", n >> 20),
+        n if n.trailing_zeros() >= 10 => format!("{}k", n >> 10),
+        n => n.to_string(),
+    }
+}
+
+/// Deserializes an optional duration, given as a number of seconds.
+#[cfg(feature = "serialize")]
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let secs = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Deserializes an optional size, given either as a number of bytes or as a
+/// string for [parse_size].
+#[cfg(feature = "serialize")]
+fn size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(usize),
+        String(String),
+    }
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(n) => Ok(Some(n)),
+        Size::String(s) => parse_size(&s).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+impl MachineConfig {
+    /// Reads a configuration from the TOML file at `path`.
+    #[cfg(feature = "serialize")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<MachineConfig, EmulatorError> {
+        let path = path.as_ref();
+        toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| EmulatorError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Configures `em` (into which a program has been loaded) accordingly;
+    /// the memory size has to be given to [Emulator::new] beforehand.
+    pub fn apply(&self, em: &mut Emulator) -> Result<(), EmulatorError> {
+        if let Some(isa) = &self.isa {
+            em.set_isa(isa)?;
+        }
+        if self.strict {
+            em.set_strict(true);
+        }
+        em.set_misaligned(self.misaligned);
+        em.set_personality(self.syscalls);
+        em.set_unknown_syscalls(self.unknown_syscalls);
+        if !self.args.is_empty() {
+            em.set_args(self.args.iter().map(String::as_str));
+        }
+        if let Some(seed) = self.seed {
+            em.set_seed(seed);
+        }
+        if self.virtual_time.is_some() {
+            em.set_virtual_time(self.virtual_time);
+        }
+        if self.limits != Limits::default() {
+            em.set_limits(self.limits);
+        }
+        if let Some(tohost) = self.devices.tohost {
+            em.set_tohost(Some(tohost));
+        }
+        if let Some(path) = &self.io.stdin {
+            em.set_stdin(BufReader::new(File::open(path)?));
+        }
+        if let Some(path) = &self.io.stdout {
+            em.set_stdout(File::create(path)?);
+        }
+        if let Some(path) = &self.io.stderr {
+            em.set_stderr(File::create(path)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "serialize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config: MachineConfig = toml::from_str(
+            r#"
+            memory = 0x200000
+            isa = "rv32i"
+            syscalls = "spim"
+            misaligned = "trap"
+            unknown_syscalls = "stop"
+
+            [devices]
+            tohost = 0x1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.memory, Some(2 << 20));
+        assert_eq!(config.syscalls, Personality::Spim);
+        assert_eq!(config.misaligned, Misaligned::Trap);
+        assert_eq!(config.unknown_syscalls, UnknownSyscalls::Stop);
+        assert_eq!(config.devices.tohost, Some(0x1000));
+        assert!(!config.strict);
+
+        let mut em = Emulator::load_from("tests/data/fac", config.memory).unwrap();
+        config.apply(&mut em).unwrap();
+        // fac multiplies...
+        assert!(matches!(
+            em.run(),
+            Err(EmulatorError::IllegalInstruction(_))
+        ));
+        em.set_isa("RV32IM_Zicsr").unwrap();
+        assert_eq!(em.run().unwrap(), 0);
+        for isa in ["rv64i", "rv32e", "rv32imf", "rv32im_zba"] {
+            assert!(em.set_isa(isa).is_err(), "{}", isa);
+        }
+
+        // typos aren't silently ignored
+        assert!(toml::from_str::<MachineConfig>("memroy = 1").is_err());
+        assert!(toml::from_str::<MachineConfig>("syscalls = \"bsd\"").is_err());
+        assert!(MachineConfig::load("tests/data/nonesuch.toml").is_err());
+
+        let config: MachineConfig = toml::from_str("virtual_time = 1000").unwrap();
+        assert_eq!(config.virtual_time, Some(1000));
+
+        let config: MachineConfig =
+            toml::from_str("[limits]\nheap = \"1M\"\noutput = 100\ncpu_time = 0.5").unwrap();
+        assert_eq!(
+            config.limits,
+            Limits {
+                heap: Some(1 << 20),
+                output: Some(100),
+                cpu_time: Some(Duration::from_millis(500)),
+            }
+        );
+        assert!(toml::from_str::<MachineConfig>("[limits]\ncpu_time = -1").is_err());
+
+        let config: MachineConfig = toml::from_str("memory = \"64k\"").unwrap();
+        assert_eq!(config.memory, Some(64 << 10));
+        assert!(toml::from_str::<MachineConfig>("memory = \"lots\"").is_err());
+
+        for size in [0, 1000, 64 << 10, 3 << 20, 1 << 30] {
+            assert_eq!(parse_size(&format_size(size)).unwrap(), size);
+        }
+        assert_eq!(format_size(256 << 10), "256k");
+    }
+}
@@ -0,0 +1,152 @@
+use crate::tracediff::parse_record;
+use crate::{Divergence, Emulator, Retired, TraceRecord, TraceSink};
+use std::io::{self, BufRead};
+
+/// A [TraceSink] that runs in lockstep with a reference simulator, comparing
+/// each instruction retired against the next record of the reference's
+/// commit log (in spike `--log-commits` format, or the JSONL format written
+/// by [crate::JsonTrace]), and halting execution at the first divergence.
+///
+/// Reference records before the first instruction executed (e.g., spike's
+/// boot ROM) are skipped, as are any lines that aren't trace records.
+pub struct CoSim<R: BufRead> {
+    reference: R,
+    /// Instructions compared so far, and reference records consumed
+    compared: usize,
+    consumed: usize,
+    /// Whether the reference has caught up to our first instruction
+    synced: bool,
+    divergence: Option<Divergence>,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> CoSim<R> {
+    /// Returns a new co-simulation against the commit log read from
+    /// `reference`.
+    pub fn new(reference: R) -> CoSim<R> {
+        CoSim {
+            reference,
+            compared: 0,
+            consumed: 0,
+            synced: false,
+            divergence: None,
+            error: None,
+        }
+    }
+
+    /// Returns the number of instructions that matched the reference.
+    pub fn matched(&self) -> usize {
+        match self.divergence {
+            Some(_) => self.compared - 1,
+            None => self.compared,
+        }
+    }
+
+    /// Returns where execution diverged from the reference, if it did.
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Returns the first error encountered reading the reference (if any).
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the next record from the reference, or `None` at the end of
+    /// the log.
+    fn next(&mut self) -> io::Result<Option<TraceRecord>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reference.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            match parse_record(&line) {
+                Some(Some(record)) => {
+                    self.consumed += 1;
+                    return Ok(Some(record));
+                }
+                Some(None) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed reference trace record: {}", line.trim()),
+                    ))
+                }
+                None => continue,
+            }
+        }
+    }
+
+    fn compare(&mut self, ours: TraceRecord) -> io::Result<()> {
+        let mut reference = self.next()?;
+        if !self.synced {
+            while reference.is_some_and(|r| r.pc != ours.pc) {
+                reference = self.next()?;
+            }
+            self.synced = true;
+        }
+        self.compared += 1;
+        if !reference.is_some_and(|r| ours.matches(&r)) {
+            self.divergence = Some(Divergence {
+                index: (self.compared - 1, self.consumed.saturating_sub(1)),
+                records: (Some(ours), reference),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> TraceSink for CoSim<R> {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        if self.divergence.is_some() || self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.compare(retired.into()) {
+            self.error = Some(e);
+        }
+    }
+
+    fn halt(&self) -> bool {
+        self.divergence.is_some() || self.error.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmulatorError, SpikeTrace};
+
+    #[test]
+    fn test_cosim() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut buf = Vec::new();
+        let mut spike = SpikeTrace::new(&mut buf);
+        em.run_with(&mut spike).unwrap();
+        spike.finish().unwrap();
+        let mut log = String::from_utf8(buf).unwrap();
+
+        // pretend there's a boot ROM
+        log.insert_str(0, "core   0: 3 0x00001000 (0x00000297) x5  0x00001000\n");
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut cosim = CoSim::new(log.as_bytes());
+        assert_eq!(em.run_with(&mut cosim).unwrap(), 0);
+        assert_eq!(cosim.matched(), 67);
+        assert!(cosim.divergence().is_none());
+
+        // change the value of the first store
+        let log = log.replace("0x0007fff8 0x000100b4", "0x0007fff8 0x000100b5");
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut cosim = CoSim::new(log.as_bytes());
+        assert!(matches!(
+            em.run_with(&mut cosim),
+            Err(EmulatorError::Halted(0x1007c))
+        ));
+        assert_eq!(cosim.matched(), 3);
+        let divergence = cosim.divergence().unwrap();
+        assert_eq!(divergence.index, (3, 4));
+        assert_eq!(divergence.records.0.unwrap().pc, 0x10078);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{Emulator, EmulatorError, Inst, InstClass, Retired, SymbolTable, TraceSink};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A user-supplied table of what each instruction costs, in whatever units
+/// are of interest (cycles, nanojoules, ...).
+///
+/// Tables are written one `key = cost` pair per line, with `#` starting a
+/// comment. Keys are an instruction class (see [InstClass]), a mnemonic
+/// (which takes precedence over its class), `default` (for anything not
+/// otherwise listed; 1 if unspecified), or `taken` (an additional cost for
+/// taken branches and jumps; 0 if unspecified).
+///
+/// ```rust
+/// use rvem::{CostTable, Inst};
+///
+/// let table: CostTable = "load = 2.5\nlbu = 2\ndefault = 1 # everything else"
+///     .parse()
+///     .unwrap();
+/// let lw = Inst::try_from(0x00052503).unwrap(); // lw a0, 0(a0)
+/// let lbu = Inst::try_from(0x00054503).unwrap(); // lbu a0, 0(a0)
+/// let add = Inst::try_from(0x00b50533).unwrap(); // add a0, a0, a1
+/// assert_eq!(table.cost(&lw), 2.5);
+/// assert_eq!(table.cost(&lbu), 2.0);
+/// assert_eq!(table.cost(&add), 1.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostTable {
+    /// Cost of anything without a more specific entry
+    pub default: f64,
+    /// Additional cost of a taken branch or jump
+    pub taken: f64,
+    /// Cost per instruction class
+    pub classes: HashMap<InstClass, f64>,
+    /// Cost per mnemonic
+    pub mnemonics: HashMap<String, f64>,
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        CostTable {
+            default: 1.0,
+            taken: 0.0,
+            classes: HashMap::new(),
+            mnemonics: HashMap::new(),
+        }
+    }
+}
+
+impl CostTable {
+    /// Loads a cost table from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CostTable, EmulatorError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Returns the cost of `inst`, not counting any taken-branch cost.
+    pub fn cost(&self, inst: &Inst) -> f64 {
+        if let Some(cost) = self.mnemonics.get(inst.mnemonic()) {
+            *cost
+        } else if let Some(cost) = self.classes.get(&inst.class()) {
+            *cost
+        } else {
+            self.default
+        }
+    }
+}
+
+impl FromStr for CostTable {
+    type Err = EmulatorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut table = CostTable::default();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| EmulatorError::Config(format!("line {}: {}", n + 1, msg));
+            let (key, cost) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected key = cost, found '{}'", line)))?;
+            let (key, cost) = (key.trim().to_lowercase(), cost.trim());
+            let cost: f64 = cost
+                .parse()
+                .map_err(|e| err(format!("invalid cost '{}': {}", cost, e)))?;
+
+            if key == "default" {
+                table.default = cost;
+            } else if key == "taken" {
+                table.taken = cost;
+            } else if let Ok(class) = key.parse::<InstClass>() {
+                table.classes.insert(class, cost);
+            } else if is_mnemonic(&key) {
+                table.mnemonics.insert(key, cost);
+            } else {
+                return Err(err(format!("unknown instruction or class '{}'", key)));
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// Returns whether `name` is the mnemonic of some instruction we know about.
+fn is_mnemonic(name: &str) -> bool {
+    Inst::mnemonics().any(|m| m == name)
+}
+
+/// A [TraceSink] that tallies up the cost of a program's execution, per a
+/// [CostTable], both in total and by function.
+#[derive(Clone, Debug)]
+pub struct CostProfile {
+    table: CostTable,
+    total: f64,
+    by_pc: HashMap<usize, f64>,
+}
+
+impl CostProfile {
+    /// Returns a new profile that costs instructions according to `table`.
+    pub fn new(table: CostTable) -> CostProfile {
+        CostProfile {
+            table,
+            total: 0.0,
+            by_pc: HashMap::new(),
+        }
+    }
+
+    /// Returns the total cost of everything executed so far.
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    /// Returns the cost incurred by each function (i.e., the nearest symbol
+    /// at or below each instruction executed), most expensive first.
+    pub fn by_function(&self, symtab: &SymbolTable) -> Vec<(String, f64)> {
+        let mut costs: HashMap<String, f64> = HashMap::new();
+        for (pc, cost) in &self.by_pc {
+            let name = symtab.symbolize_function(*pc);
+            *costs.entry(name).or_default() += cost;
+        }
+        let mut costs: Vec<(String, f64)> = costs.into_iter().collect();
+        costs.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        costs
+    }
+}
+
+impl TraceSink for CostProfile {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        let mut cost = self.table.cost(&retired.inst);
+        if retired.next_pc != retired.pc + 4 {
+            cost += self.table.taken;
+        }
+        self.total += cost;
+        *self.by_pc.entry(retired.pc).or_default() += cost;
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{Emulator, Inst, Retired, TraceSink};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+/// Coverage summary for a single function.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionCoverage {
+    /// Function name (or address, if there's no symbol for it)
+    pub name: String,
+    /// Address of the function's first instruction
+    pub addr: usize,
+    /// Number of times the function was entered at its first instruction
+    pub entries: u64,
+    /// Instructions in the function
+    pub instructions: usize,
+    /// Instructions in the function that were executed
+    pub instructions_hit: usize,
+    /// Basic blocks in the function
+    pub blocks: usize,
+    /// Basic blocks in the function that were executed
+    pub blocks_hit: usize,
+}
+
+/// A [TraceSink] that records which instructions were executed (and how many
+/// times), for reporting basic block and source line coverage.
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    counts: HashMap<usize, u64>,
+}
+
+impl Coverage {
+    /// Returns a new, empty set of coverage data.
+    pub fn new() -> Coverage {
+        Default::default()
+    }
+
+    /// Returns the number of times the instruction at `pc` was executed.
+    pub fn count(&self, pc: usize) -> u64 {
+        self.counts.get(&pc).copied().unwrap_or(0)
+    }
+
+    /// Returns coverage for each function in `em`'s .text section, in order
+    /// of address.
+    pub fn functions(&self, em: &Emulator) -> Vec<FunctionCoverage> {
+        let Some(text) = em.section(".text").map(|text| &text.range) else {
+            return Vec::new();
+        };
+
+        // NB functions run from one symbol to the next
+        let mut starts: Vec<(usize, String)> = Vec::new();
+        if em
+            .symbols()
+            .lookup(text.start)
+            .is_none_or(|(_, off)| off != 0)
+        {
+            starts.push((text.start, em.symbols().symbolize_function(text.start)));
+        }
+        for (name, addr) in em.symbols().iter() {
+            if text.contains(&addr) && starts.last().is_none_or(|(a, _)| *a != addr) {
+                starts.push((addr, name.to_string()));
+            }
+        }
+
+        // basic blocks start at function entry points, branch and jump
+        // targets, and wherever the previous block ended
+        let mut leaders: HashSet<usize> = starts.iter().map(|(addr, _)| *addr).collect();
+        for pc in text.clone().step_by(4) {
+            let Ok(inst) = em.inst(pc) else {
+                continue;
+            };
+            if inst.ends_block() {
+                leaders.insert(pc + 4);
+            }
+            if let Inst::JAL { imm, .. }
+            | Inst::BEQ { imm, .. }
+            | Inst::BNE { imm, .. }
+            | Inst::BLT { imm, .. }
+            | Inst::BGE { imm, .. }
+            | Inst::BLTU { imm, .. }
+            | Inst::BGEU { imm, .. } = inst
+            {
+                leaders.insert(pc.wrapping_add(imm as usize));
+            }
+        }
+
+        let mut functions = Vec::new();
+        for (i, (addr, name)) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(text.end, |(a, _)| *a);
+            let mut function = FunctionCoverage {
+                name: name.clone(),
+                addr: *addr,
+                entries: self.count(*addr),
+                ..Default::default()
+            };
+            for pc in (*addr..end).step_by(4) {
+                let hit = self.count(pc) > 0;
+                function.instructions += 1;
+                function.instructions_hit += hit as usize;
+                if leaders.contains(&pc) {
+                    function.blocks += 1;
+                    function.blocks_hit += hit as usize;
+                }
+            }
+            functions.push(function);
+        }
+        functions
+    }
+
+    /// Formats a per-function coverage summary.
+    pub fn report(&self, em: &Emulator) -> String {
+        let functions = self.functions(em);
+        let mut report = format!(
+            "{:>13} {:>7} {:>9} {:>7}  {}\n",
+            "instructions", "%", "blocks", "%", "function"
+        );
+        let (mut instructions, mut instructions_hit, mut blocks, mut blocks_hit) = (0, 0, 0, 0);
+        for f in &functions {
+            let _ = writeln!(
+                report,
+                "{:>13} {:>6.2}% {:>9} {:>6.2}%  {}",
+                format!("{}/{}", f.instructions_hit, f.instructions),
+                percent(f.instructions_hit, f.instructions),
+                format!("{}/{}", f.blocks_hit, f.blocks),
+                percent(f.blocks_hit, f.blocks),
+                f.name
+            );
+            instructions += f.instructions;
+            instructions_hit += f.instructions_hit;
+            blocks += f.blocks;
+            blocks_hit += f.blocks_hit;
+        }
+        let _ = writeln!(
+            report,
+            "{:>13} {:>6.2}% {:>9} {:>6.2}%  (total)",
+            format!("{}/{}", instructions_hit, instructions),
+            percent(instructions_hit, instructions),
+            format!("{}/{}", blocks_hit, blocks),
+            percent(blocks_hit, blocks),
+        );
+        report
+    }
+
+    /// Formats the coverage data as an lcov tracefile. Line coverage comes
+    /// from the program's DWARF line information, if it has any; functions
+    /// without line information are attributed to `program` (the path of the
+    /// program itself).
+    pub fn lcov(&self, em: &Emulator, program: &str) -> String {
+        #[derive(Default)]
+        struct Record {
+            functions: Vec<(u32, String, u64)>,
+            lines: BTreeMap<u32, u64>,
+        }
+        let mut records: BTreeMap<String, Record> = BTreeMap::new();
+
+        for f in self.functions(em) {
+            let (file, line) = em.lines().lookup(f.addr).unwrap_or((program, 0));
+            let record = records.entry(file.to_string()).or_default();
+            record.functions.push((line, f.name, f.entries));
+        }
+        if let Some(text) = em.section(".text").map(|text| &text.range) {
+            for pc in text.clone().step_by(4) {
+                if let Some((file, line)) = em.lines().lookup(pc) {
+                    let record = records.entry(file.to_string()).or_default();
+                    let count = record.lines.entry(line).or_default();
+                    *count = (*count).max(self.count(pc));
+                }
+            }
+        }
+
+        let mut lcov = String::new();
+        for (file, record) in records {
+            let _ = writeln!(lcov, "TN:\nSF:{}", file);
+            for (line, name, _) in &record.functions {
+                let _ = writeln!(lcov, "FN:{},{}", line, name);
+            }
+            for (_, name, count) in &record.functions {
+                let _ = writeln!(lcov, "FNDA:{},{}", count, name);
+            }
+            let _ = writeln!(lcov, "FNF:{}", record.functions.len());
+            let hit = record.functions.iter().filter(|(_, _, c)| *c > 0).count();
+            let _ = writeln!(lcov, "FNH:{}", hit);
+            for (line, count) in &record.lines {
+                let _ = writeln!(lcov, "DA:{},{}", line, count);
+            }
+            let _ = writeln!(lcov, "LF:{}", record.lines.len());
+            let hit = record.lines.values().filter(|c| **c > 0).count();
+            let _ = writeln!(lcov, "LH:{}\nend_of_record", hit);
+        }
+        lcov
+    }
+}
+
+/// Returns `n` as a percentage of `total` (or 0 if `total` is 0).
+fn percent(n: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * n as f64 / total as f64
+    }
+}
+
+impl TraceSink for Coverage {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        *self.counts.entry(retired.pc).or_default() += 1;
+    }
+}
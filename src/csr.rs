@@ -0,0 +1,51 @@
+//! Control and status register numbers, and what little of the CSR file the
+//! emulator implements (the unprivileged counters/timers).
+
+/// Cycle counter for RDCYCLE
+pub const CYCLE: u32 = 0xc00;
+/// Timer for RDTIME
+pub const TIME: u32 = 0xc01;
+/// Instructions-retired counter for RDINSTRET
+pub const INSTRET: u32 = 0xc02;
+/// Upper 32 bits of cycle
+pub const CYCLEH: u32 = 0xc80;
+/// Upper 32 bits of time
+pub const TIMEH: u32 = 0xc81;
+/// Upper 32 bits of instret
+pub const INSTRETH: u32 = 0xc82;
+
+/// Returns the assembler name of `csr`, if it's one we know about.
+pub fn name(csr: u32) -> Option<&'static str> {
+    match csr {
+        CYCLE => Some("cycle"),
+        TIME => Some("time"),
+        INSTRET => Some("instret"),
+        CYCLEH => Some("cycleh"),
+        TIMEH => Some("timeh"),
+        INSTRETH => Some("instreth"),
+        _ => None,
+    }
+}
+
+/// Returns whether `csr` is one of the unprivileged counters/timers, which
+/// have `rdcycle`-style pseudoinstructions.
+pub(crate) fn is_counter(csr: u32) -> bool {
+    matches!(csr, CYCLE | TIME | INSTRET | CYCLEH | TIMEH | INSTRETH)
+}
+
+/// Returns whether `csr` is read-only (csr[11:10] == 0b11).
+pub(crate) fn is_read_only(csr: u32) -> bool {
+    (csr >> 10) & 0b11 == 0b11
+}
+
+/// Displays a CSR by name if it has one, or by number otherwise.
+pub(crate) struct Name(pub(crate) u32);
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match name(self.0) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "0x{:03x}", self.0),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{Emulator, EmulatorError, Inst, Reg};
+use std::path::Path;
+use std::process::Command;
+
+/// Environment variable naming the objdump to run, ahead of the ones
+/// [find_objdump] looks for on the PATH
+pub const OBJDUMP_VAR: &str = "RVEM_OBJDUMP";
+
+/// objdumps that understand RISC-V, in order of preference
+const OBJDUMPS: &[&str] = &[
+    "riscv64-unknown-elf-objdump",
+    "riscv64-linux-gnu-objdump",
+    "riscv64-elf-objdump",
+    "riscv32-unknown-elf-objdump",
+    "llvm-objdump",
+];
+
+/// An instruction that rvem and objdump disassemble differently.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisasmMismatch {
+    pub addr: usize,
+    pub word: u32,
+    /// rvem's disassembly (`<unknown>` if it couldn't decode the word)
+    pub rvem: String,
+    /// objdump's disassembly, less any symbol annotations
+    pub objdump: String,
+}
+
+impl std::fmt::Display for DisasmMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:x}: {:08x} rvem: {} objdump: {}",
+            self.addr, self.word, self.rvem, self.objdump
+        )
+    }
+}
+
+/// An instruction that doesn't survive a decode/encode/decode round trip
+/// unchanged, as found by [verify_decode].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeMismatch {
+    pub addr: usize,
+    pub word: u32,
+    /// What the word decodes to
+    pub inst: Inst,
+    /// What that encodes to
+    pub encoded: u32,
+    /// What that decodes to in turn (`None` if it doesn't)
+    pub redecoded: Option<Inst>,
+}
+
+impl std::fmt::Display for DecodeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:x}: {:08x} decodes as {}, which encodes as {:08x}",
+            self.addr, self.word, self.inst, self.encoded
+        )?;
+        match &self.redecoded {
+            Some(inst) => write!(f, ", which decodes as {}", inst),
+            None => write!(f, ", which doesn't decode"),
+        }
+    }
+}
+
+/// Returns the objdump to cross-check against: the one named by
+/// [OBJDUMP_VAR], if set, or else the first of the usual RISC-V (or LLVM)
+/// objdumps that can be run. Fails with [EmulatorError::Config] if there
+/// isn't one.
+pub fn find_objdump() -> Result<String, EmulatorError> {
+    if let Ok(objdump) = std::env::var(OBJDUMP_VAR) {
+        return Ok(objdump);
+    }
+    OBJDUMPS
+        .iter()
+        .find(|objdump| {
+            Command::new(objdump)
+                .arg("--version")
+                .output()
+                .is_ok_and(|output| output.status.success())
+        })
+        .map(|objdump| objdump.to_string())
+        .ok_or_else(|| {
+            EmulatorError::Config(format!(
+                "no objdump for RISC-V found (set {} to use a particular one)",
+                OBJDUMP_VAR
+            ))
+        })
+}
+
+/// Runs `objdump -d` on the program at `path`, without pseudo-instructions
+/// (`-M no-aliases`), and returns its output.
+pub fn objdump(objdump: &str, path: impl AsRef<Path>) -> Result<String, EmulatorError> {
+    let mut cmd = Command::new(objdump);
+    cmd.args(["-d", "-M", "no-aliases"]);
+    if objdump.contains("llvm") {
+        // NB unlike GNU's, LLVM's objdump leaves the M extension out unless
+        // the program's attributes say it uses it
+        cmd.arg("--mattr=+m");
+    }
+    let output = cmd
+        .arg(path.as_ref())
+        .output()
+        .map_err(|e| EmulatorError::Config(format!("couldn't run {}: {}", objdump, e)))?;
+    if !output.status.success() {
+        return Err(EmulatorError::Config(format!(
+            "{} failed: {}",
+            objdump,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Compares `listing` (the output of `objdump -d -M no-aliases`, GNU or
+/// LLVM) against rvem's disassembly of the same instructions in `em`, and
+/// returns those that differ. Mnemonics are compared exactly; operands are
+/// compared as registers or numbers, so it doesn't matter which base or
+/// register names either side prints them with. Compressed instructions,
+/// which rvem doesn't decode, are skipped.
+pub fn compare_disasm(em: &Emulator, listing: &str) -> Vec<DisasmMismatch> {
+    let mut mismatches = Vec::new();
+    for line in listing.lines() {
+        let Some((addr, rest)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let Ok(addr) = usize::from_str_radix(addr, 16) else {
+            continue;
+        };
+        // NB the encoding comes first: a word in GNU's output, bytes in
+        // LLVM's
+        let mut fields = rest.split('\t').map(str::trim).filter(|s| !s.is_empty());
+        let Some(encoding) = fields.next() else {
+            continue;
+        };
+        if encoding.chars().filter(char::is_ascii_hexdigit).count() != 8 {
+            continue;
+        }
+        let objdump = fields.collect::<Vec<_>>().join(" ");
+        let objdump = match objdump.split_once(" <") {
+            Some((inst, _)) if !objdump.starts_with('<') => inst.to_string(),
+            _ => objdump,
+        };
+
+        let word = em.read_u32(addr).unwrap_or_default();
+        let rvem = match em.inst(addr) {
+            Ok(inst) => inst.at(addr).to_string(),
+            Err(_) => "<unknown>".to_string(),
+        };
+        if canonical(&rvem, true) != canonical(&objdump, false) {
+            mismatches.push(DisasmMismatch {
+                addr,
+                word,
+                rvem,
+                objdump,
+            });
+        }
+    }
+    mismatches
+}
+
+/// Cross-checks rvem's disassembly of the program at `path` against
+/// objdump's (see [find_objdump]), returning the instructions they disagree
+/// on.
+pub fn verify_disasm(path: impl AsRef<Path>) -> Result<Vec<DisasmMismatch>, EmulatorError> {
+    let listing = objdump(&find_objdump()?, &path)?;
+    let em = Emulator::load_from(&path, None)?;
+    Ok(compare_disasm(&em, &listing))
+}
+
+/// An operand, as compared between rvem and objdump
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Reg(u32),
+    Num(i64),
+    Other(String),
+}
+
+/// Breaks an instruction into its mnemonic and operands, expanding the
+/// pseudo-instructions rvem prints into the instructions they stand for.
+/// `rvem` says whose disassembly it is: rvem prints branch and jump
+/// targets in bare hex, and objdump prints them in hex (GNU without a 0x,
+/// LLVM with one), but any other bare number is decimal.
+fn canonical(inst: &str, rvem: bool) -> (String, Vec<Operand>) {
+    let (mnemonic, operands) = inst.split_once(' ').unwrap_or((inst, ""));
+    let operands: Vec<&str> = operands
+        .split([',', ' ', '(', ')'])
+        .filter(|s| !s.is_empty())
+        .collect();
+    let (mnemonic, operands) = match (mnemonic, operands.as_slice()) {
+        ("li", [rd, imm]) if rvem => ("addi", vec![*rd, "zero", imm]),
+        ("j", [target]) if rvem => ("jal", vec!["zero", target]),
+        ("jal", [target]) if rvem => ("jal", vec!["ra", target]),
+        ("csrr", [rd, csr]) if rvem => ("csrrs", vec![*rd, csr, "zero"]),
+        (counter, [rd]) if rvem && counter.starts_with("rd") => {
+            ("csrrs", vec![*rd, &counter[2..], "zero"])
+        }
+        _ => (mnemonic, operands),
+    };
+
+    if !rvem && (mnemonic.starts_with('.') || mnemonic == "<unknown>") {
+        // NB GNU shows words it can't decode as .word or .insn directives
+        return ("<unknown>".to_string(), Vec::new());
+    }
+
+    let target = mnemonic == "jal" || mnemonic.starts_with('b');
+    let last = operands.len().saturating_sub(1);
+    let operands = operands
+        .iter()
+        .enumerate()
+        .map(|(i, operand)| {
+            if let Ok(reg) = operand.parse::<Reg>() {
+                return Operand::Reg(reg.into());
+            }
+            let (negative, digits) = match operand.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, *operand),
+            };
+            let n = match digits.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None if target && i == last => i64::from_str_radix(digits, 16),
+                None => digits.parse(),
+            };
+            match n {
+                Ok(n) if negative => Operand::Num(-n),
+                Ok(n) => Operand::Num(n),
+                Err(_) => Operand::Other(operand.to_string()),
+            }
+        })
+        .collect();
+    (mnemonic.to_string(), operands)
+}
+
+/// Checks that every word in the executable sections of the program loaded
+/// into `em` that decodes (words that don't, like data in `.text`, are
+/// skipped) encodes to a word that decodes to the very same instruction,
+/// and returns those that don't: a self-check of rvem's encoder against
+/// real programs, needing no objdump.
+pub fn verify_decode(em: &Emulator) -> Vec<DecodeMismatch> {
+    let mut mismatches = Vec::new();
+    for section in em.sections().iter().filter(|s| s.executable && s.from_file) {
+        for addr in section.range.clone().step_by(4) {
+            let Ok(inst) = em.inst(addr) else {
+                continue;
+            };
+            let encoded = u32::from(inst);
+            let redecoded = Inst::try_from(encoded).ok();
+            if redecoded != Some(inst) {
+                mismatches.push(DecodeMismatch {
+                    addr,
+                    word: em.read_u32(addr).unwrap_or_default(),
+                    inst,
+                    encoded,
+                    redecoded,
+                });
+            }
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_disasm() {
+        let em = Emulator::load_from("tests/data/fac", None).unwrap();
+
+        // GNU-style, with one mnemonic and one operand wrong
+        let listing = "\
+tests/data/fac:     file format elf32-littleriscv
+
+
+Disassembly of section .text:
+
+00010074 <fact>:
+   10074:\tff810113          \taddi\tsp,sp,-8
+   10078:\t00112023          \tsw\tra,0(sp)
+   1007c:\t00200293          \taddi\tt0,zero,2
+   10080:\t00554e63          \tblt\ta0,t0,1009c <ret_one>
+   10084:\t00a12223          \tsw\ta0,4(sp)
+   10088:\tfff50513          \taddi\ta0,a0,-2
+   1008c:\tfe9ff0ef          \tjal\tra,10074 <fact>
+   10090:\t00412283          \tlw\tt0,4(sp)
+   10094:\t02b285b3          \tmulh\ta1,t0,a1
+   10098:\t0080006f          \tjal\tzero,100a0 <done>
+   1009c:\t8082              \tc.jr\tra
+";
+        let mismatches = compare_disasm(&em, listing);
+        assert_eq!(
+            mismatches
+                .iter()
+                .map(|m| (m.addr, m.rvem.as_str(), m.objdump.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (0x10088, "addi a0, a0, -1", "addi a0,a0,-2"),
+                (0x10094, "mul a1, t0, a1", "mulh a1,t0,a1"),
+            ]
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "10088: fff50513 rvem: addi a0, a0, -1 objdump: addi a0,a0,-2"
+        );
+    }
+
+    #[test]
+    fn test_verify_decode() {
+        for program in ["fac", "funcs", "boot", "random", "primes", "helloc"] {
+            let em = Emulator::load_from(format!("tests/data/{}", program), None).unwrap();
+            let mismatches = verify_decode(&em);
+            assert!(mismatches.is_empty(), "{}: {:#?}", program, mismatches);
+        }
+
+        let mismatch = DecodeMismatch {
+            addr: 0x10074,
+            word: 0xff810113,
+            inst: Inst::try_from(0xff810113).unwrap(),
+            encoded: 0xff810193,
+            redecoded: Inst::try_from(0xff810193).ok(),
+        };
+        assert_eq!(
+            mismatch.to_string(),
+            "10074: ff810113 decodes as addi sp, sp, -8, which encodes as ff810193, \
+             which decodes as addi gp, sp, -8"
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a RISC-V objdump (on the PATH, or in RVEM_OBJDUMP)"]
+    fn test_verify_disasm() {
+        for program in ["fac", "funcs", "boot", "random", "primes", "signature"] {
+            let mismatches = verify_disasm(format!("tests/data/{}", program)).unwrap();
+            assert!(mismatches.is_empty(), "{}: {:#?}", program, mismatches);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{Emulator, Inst, Reg, Section};
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+use std::fmt::Write;
+use strum::IntoEnumIterator;
+
+/// A register, as it appears in a [Dump].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct RegDump {
+    pub name: String,
+    pub value: u32,
+}
+
+/// A section, as it appears in a [Dump].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct SectionDump {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    pub writable: bool,
+    pub executable: bool,
+    pub from_file: bool,
+    /// The section's contents, hex-encoded (only for sections loaded from
+    /// the file, other than `.text`, whose contents are in
+    /// [Dump::disassembly] instead)
+    pub data: Option<String>,
+}
+
+/// A symbol, as it appears in a [Dump].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct SymbolDump {
+    pub name: String,
+    pub addr: usize,
+}
+
+/// A disassembled instruction, as it appears in a [Dump].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct InstDump {
+    pub addr: usize,
+    pub word: u32,
+    /// The instruction, or None if the word isn't one
+    pub inst: Option<String>,
+    /// Source file and line, if the program has line information
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// A word on the stack, as it appears in a [Dump].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct StackDump {
+    pub addr: usize,
+    /// The word's offset from the stack pointer
+    pub offset: isize,
+    pub value: u32,
+    /// Where the word points, symbolized, if it looks like a return address
+    pub symbol: Option<String>,
+}
+
+/// A structured dump of a loaded program (the same information as the
+/// alternate [std::fmt::Debug] form of an [Emulator]), as returned by
+/// [Emulator::dump], for tools to consume as JSON (with the `serialize`
+/// feature).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+pub struct Dump {
+    pub pc: usize,
+    pub registers: Vec<RegDump>,
+    pub sections: Vec<SectionDump>,
+    pub symbols: Vec<SymbolDump>,
+    /// The contents of `.text`
+    pub disassembly: Vec<InstDump>,
+    /// The words around the stack pointer
+    pub stack: Vec<StackDump>,
+}
+
+impl Dump {
+    /// Returns a dump of `em`.
+    pub(crate) fn new(em: &Emulator) -> Dump {
+        let registers = Reg::iter()
+            .map(|reg| RegDump {
+                name: reg.to_string(),
+                value: em[reg],
+            })
+            .collect();
+
+        let sections = em
+            .sections()
+            .iter()
+            .map(|section| {
+                let Section {
+                    name,
+                    range,
+                    writable,
+                    executable,
+                    from_file,
+                } = section.clone();
+                let data = (from_file && name != ".text").then(|| {
+                    let mut hex = String::with_capacity(2 * range.len());
+                    for b in &em[range.clone()] {
+                        let _ = write!(hex, "{:02x}", b);
+                    }
+                    hex
+                });
+                SectionDump {
+                    name,
+                    start: range.start,
+                    end: range.end,
+                    writable,
+                    executable,
+                    from_file,
+                    data,
+                }
+            })
+            .collect();
+
+        let symbols = em
+            .symbols()
+            .iter()
+            .map(|(name, addr)| SymbolDump {
+                name: name.to_string(),
+                addr,
+            })
+            .collect();
+
+        let mut disassembly = Vec::new();
+        if let Some(text) = em.section(".text") {
+            for addr in text.range.clone().step_by(4) {
+                let Ok(word) = em.read_u32(addr) else {
+                    break;
+                };
+                let source = em.lines().lookup(addr);
+                disassembly.push(InstDump {
+                    addr,
+                    word,
+                    inst: Inst::try_from(word)
+                        .ok()
+                        .map(|inst| inst.at(addr).to_string()),
+                    file: source.map(|(file, _)| file.to_string()),
+                    line: source.map(|(_, line)| line),
+                });
+            }
+        }
+
+        let sp = em[Reg::sp] as usize;
+        let stack = em
+            .stack_window()
+            .into_iter()
+            .map(|(addr, value)| StackDump {
+                addr,
+                offset: addr as isize - sp as isize,
+                value,
+                symbol: em
+                    .is_return_address(value as usize)
+                    .then(|| em.symbols().symbolize(value as usize)),
+            })
+            .collect();
+
+        Dump {
+            pc: em.pc(),
+            registers,
+            sections,
+            symbols,
+            disassembly,
+            stack,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, Reg};
+
+    #[test]
+    fn test_dump() {
+        let em = Emulator::load_from("tests/data/hello", None).unwrap();
+        let dump = em.dump();
+        assert_eq!(dump.registers.len(), 32);
+        assert_eq!(dump.registers[2].name, "sp");
+
+        let data = dump.sections.iter().find(|s| s.name == ".data").unwrap();
+        let hello = hex(b"Hello World!\n");
+        assert!(data.data.as_ref().unwrap().starts_with(&hello));
+        let text = dump.sections.iter().find(|s| s.name == ".text").unwrap();
+        assert_eq!((text.executable, &text.data), (true, &None));
+        assert_eq!(dump.disassembly.len(), (text.end - text.start) / 4);
+        assert!(dump.disassembly.iter().all(|inst| inst.inst.is_some()));
+        assert!(dump
+            .symbols
+            .iter()
+            .any(|s| s.name == "_start" && s.addr == text.start));
+
+        #[cfg(feature = "serialize")]
+        {
+            let json = serde_json::to_value(&dump).unwrap();
+            assert_eq!(json["disassembly"][0]["addr"], text.start);
+        }
+        // nothing's been pushed yet
+        assert!(dump.stack.is_empty());
+        assert!(dump.disassembly.iter().all(|inst| inst.line.is_none()));
+
+        let em = Emulator::load_from("tests/data/cover", None).unwrap();
+        let dump = em.dump();
+        let sign = &dump.disassembly[0];
+        assert!(sign.file.as_ref().unwrap().ends_with("cover.s"));
+        assert_eq!(sign.line, Some(4));
+    }
+
+    #[test]
+    fn test_dump_stack() {
+        let mut em = Emulator::load_from("tests/data/crash", None).unwrap();
+        assert!(em.run().is_err());
+        let dump = em.dump();
+        let sp = em[Reg::sp] as usize;
+        assert_eq!(dump.stack[0].offset, -16);
+        assert_eq!(dump.stack.last().unwrap().addr, em.stack_top() - 4);
+        // inner's frame, then outer's
+        let saved = |offset| {
+            dump.stack
+                .iter()
+                .find(|word| word.offset == offset)
+                .unwrap()
+        };
+        assert_eq!(saved(12).symbol.as_deref(), Some("outer+0x14"));
+        assert_eq!(saved(8).value as usize, sp + 32);
+        assert_eq!(saved(8).symbol, None);
+        assert_eq!(saved(28).symbol.as_deref(), Some("_start+0x4"));
+
+        let debug = format!("{:#?}", em);
+        assert!(debug.contains(&format!("\nStack:\n  {:08x} sp-16 : 00000000\n", sp - 16)));
+        assert!(debug.contains(&format!(
+            "\n  {:08x} sp+12 : {:08x} <outer+0x14>\n",
+            sp + 12,
+            saved(12).value
+        )));
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
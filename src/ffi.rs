@@ -0,0 +1,258 @@
+//! C bindings, for embedding rvem in C/C++ test rigs and the like (see
+//! `include/rvem.h`). Build the shared library with `make ffi`.
+//!
+//! Functions that can fail return 0 on success and -1 on failure, with a
+//! description of the error available from [rvem_last_error].
+use crate::{Emulator, EmulatorError, NoTrace};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+thread_local! {
+    /// The most recent error on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Converts `result` to a status code, stashing any error.
+fn status<T>(result: Result<T, EmulatorError>) -> c_int {
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            let msg = e.to_string().replace('\0', "");
+            LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(msg).ok());
+            -1
+        }
+    }
+}
+
+/// Returns the emulator `em` points to, or an error if it's null.
+unsafe fn emulator<'a>(em: *mut Emulator) -> Result<&'a mut Emulator, EmulatorError> {
+    em.as_mut()
+        .ok_or_else(|| EmulatorError::Config("null emulator".to_string()))
+}
+
+/// Sets up the program to run, if it hasn't been already.
+fn start(em: &mut Emulator) -> Result<(), EmulatorError> {
+    if em.icache.is_empty() {
+        em.init()?;
+    }
+    Ok(())
+}
+
+/// Returns a description of the last error on this thread, or null if there
+/// hasn't been one. The string is valid until the next error.
+#[no_mangle]
+pub extern "C" fn rvem_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+/// Returns a new emulator with `memory` bytes of memory (or the default
+/// amount, if 0), to be freed with [rvem_free].
+#[no_mangle]
+pub extern "C" fn rvem_new(memory: usize) -> *mut Emulator {
+    let em = Emulator::new((memory > 0).then_some(memory));
+    Box::into_raw(Box::new(em))
+}
+
+/// Frees an emulator returned by [rvem_new].
+///
+/// # Safety
+/// `em` must be null or have come from [rvem_new], and not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rvem_free(em: *mut Emulator) {
+    if !em.is_null() {
+        drop(Box::from_raw(em));
+    }
+}
+
+/// Loads the program at `path` (a NUL-terminated string) into `em`.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new], and `path` a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn rvem_load(em: *mut Emulator, path: *const c_char) -> c_int {
+    status(emulator(em).and_then(|em| {
+        if path.is_null() {
+            return Err(EmulatorError::Config("null path".to_string()));
+        }
+        let path = CStr::from_ptr(path).to_string_lossy();
+        em.load(&*path)
+    }))
+}
+
+/// Loads the program in the `len` bytes at `buf` into `em`.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new], and `buf` must point to
+/// `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rvem_load_bytes(em: *mut Emulator, buf: *const u8, len: usize) -> c_int {
+    status(emulator(em).and_then(|em| {
+        if buf.is_null() {
+            return Err(EmulatorError::Config("null buffer".to_string()));
+        }
+        em.load_bytes(std::slice::from_raw_parts(buf, len))
+    }))
+}
+
+/// Executes a single instruction, starting the program first if need be.
+/// Returns 1 (without executing anything) once the program has exited.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new].
+#[no_mangle]
+pub unsafe extern "C" fn rvem_step(em: *mut Emulator) -> c_int {
+    let em = match emulator(em) {
+        Ok(em) => em,
+        Err(e) => return status::<()>(Err(e)),
+    };
+    if em.exit_code.is_some() {
+        return 1;
+    }
+    status(start(em).and_then(|_| em.step_with(&mut NoTrace)))
+}
+
+/// Runs the program until it exits (starting it first if need be, or
+/// otherwise from wherever it left off), storing its exit code in
+/// `exit_code` if that isn't null.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new], and `exit_code` null or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rvem_run(em: *mut Emulator, exit_code: *mut c_int) -> c_int {
+    status(emulator(em).and_then(|em| {
+        start(em)?;
+        let code = em.resume_with(&mut NoTrace)?;
+        if let Some(exit_code) = exit_code.as_mut() {
+            *exit_code = code;
+        }
+        Ok(())
+    }))
+}
+
+/// Returns the program counter.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new].
+#[no_mangle]
+pub unsafe extern "C" fn rvem_pc(em: *const Emulator) -> u32 {
+    em.as_ref().map_or(0, |em| em.pc() as u32)
+}
+
+/// Returns the value of register x`n` (0 if there's no such register).
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new].
+#[no_mangle]
+pub unsafe extern "C" fn rvem_get_reg(em: *const Emulator, n: u32) -> u32 {
+    em.as_ref()
+        .and_then(|em| em.registers().get(n as usize).copied())
+        .unwrap_or(0)
+}
+
+/// Sets register x`n` to `value`.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new].
+#[no_mangle]
+pub unsafe extern "C" fn rvem_set_reg(em: *mut Emulator, n: u32, value: u32) -> c_int {
+    status(emulator(em).and_then(|em| em.set_reg(&format!("x{}", n), value)))
+}
+
+/// Copies the `len` bytes of guest memory at `addr` into `buf`.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new], and `buf` must point to
+/// `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rvem_read_mem(
+    em: *const Emulator,
+    addr: usize,
+    buf: *mut u8,
+    len: usize,
+) -> c_int {
+    status(
+        em.as_ref()
+            .ok_or_else(|| EmulatorError::Config("null emulator".to_string()))
+            .and_then(|em| em.memory(addr, len))
+            .map(|mem| ptr::copy_nonoverlapping(mem.as_ptr(), buf, len)),
+    )
+}
+
+/// Copies the `len` bytes at `buf` into guest memory at `addr`.
+///
+/// # Safety
+/// `em` must be a live emulator from [rvem_new], and `buf` must point to
+/// `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rvem_write_mem(
+    em: *mut Emulator,
+    addr: usize,
+    buf: *const u8,
+    len: usize,
+) -> c_int {
+    status(
+        emulator(em)
+            .and_then(|em| em.memory_mut(addr, len))
+            .map(|mem| ptr::copy_nonoverlapping(buf, mem.as_mut_ptr(), len)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            let em = rvem_new(0);
+            let path = CString::new("tests/data/funcs").unwrap();
+            assert_eq!(rvem_load(em, path.as_ptr()), 0);
+
+            // a few instructions in, a0 has been set up for sum
+            for _ in 0..2 {
+                assert_eq!(rvem_step(em), 0);
+            }
+            assert_eq!(rvem_get_reg(em, 10), 10);
+            assert_eq!(rvem_pc(em) as usize, (*em).addr_of("sum").unwrap());
+            assert_eq!(rvem_set_reg(em, 10, 4), 0);
+
+            let mut code = 0;
+            assert_eq!(rvem_run(em, &mut code), 0);
+            assert_eq!(code, 1 + 2 + 3 + 4);
+            assert_eq!(rvem_step(em), 1);
+
+            let total = (*em).addr_of("total").unwrap();
+            let mut buf = [0u8; 4];
+            assert_eq!(rvem_write_mem(em, total, [1, 2, 3, 4].as_ptr(), 4), 0);
+            assert_eq!(rvem_read_mem(em, total, buf.as_mut_ptr(), 4), 0);
+            assert_eq!(buf, [1, 2, 3, 4]);
+
+            assert_eq!(rvem_read_mem(em, usize::MAX, buf.as_mut_ptr(), 4), -1);
+            let error = CStr::from_ptr(rvem_last_error()).to_str().unwrap();
+            assert!(error.starts_with("memory access out of bounds"));
+            assert_eq!(rvem_set_reg(em, 32, 0), -1);
+
+            // a program loaded over one that's run starts from the beginning
+            let path = CString::new("tests/data/fac").unwrap();
+            assert_eq!(rvem_load(em, path.as_ptr()), 0);
+            (*em).set_stdout(std::io::sink());
+            assert_eq!(rvem_run(em, &mut code), 0);
+            assert_eq!((code, rvem_get_reg(em, 10)), (0, 120));
+            // and has only its own sections
+            let fac = Emulator::load_from("tests/data/fac", None).unwrap();
+            assert_eq!((*em).sections(), fac.sections());
+            assert_eq!((*em).brk_start(), fac.brk_start());
+            rvem_free(em);
+
+            let missing = CString::new("tests/data/nonesuch").unwrap();
+            assert_eq!(rvem_load(ptr::null_mut(), missing.as_ptr()), -1);
+            assert_eq!(rvem_run(ptr::null_mut(), ptr::null_mut()), -1);
+        }
+    }
+}
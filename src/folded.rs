@@ -0,0 +1,92 @@
+use crate::callgraph::{is_call, is_return};
+use crate::{Emulator, Retired, SymbolTable, TraceSink};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A [TraceSink] that maintains a shadow call stack and samples it every so
+/// many instructions, producing "folded" stacks (`main;foo;bar 42`) suitable
+/// for feeding to inferno or flamegraph.pl.
+#[derive(Clone, Debug)]
+pub struct FoldedStacks {
+    /// Instructions per sample
+    period: u64,
+    /// Instructions until the next sample
+    countdown: u64,
+    /// Entry addresses of the functions on the (shadow) call stack
+    stack: Vec<usize>,
+    /// Scratch space for building sample keys
+    key: Vec<usize>,
+    /// Sample counts by stack
+    samples: HashMap<Vec<usize>, u64>,
+}
+
+impl FoldedStacks {
+    /// Returns a new set of stacks that samples once every `period`
+    /// instructions (or every instruction, if `period` is 0).
+    pub fn new(period: u64) -> FoldedStacks {
+        let period = period.max(1);
+        FoldedStacks {
+            period,
+            countdown: period,
+            stack: Vec::new(),
+            key: Vec::new(),
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Formats the samples in folded-stack format, one stack per line.
+    pub fn folded(&self, symtab: &SymbolTable) -> String {
+        let mut lines: Vec<String> = self
+            .samples
+            .iter()
+            .map(|(stack, count)| {
+                let frames: Vec<String> = stack
+                    .iter()
+                    .map(|addr| symtab.symbolize_function(*addr))
+                    .collect();
+                format!("{} {}", frames.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+
+        let mut folded = String::new();
+        for line in lines {
+            let _ = writeln!(folded, "{}", line);
+        }
+        folded
+    }
+}
+
+impl TraceSink for FoldedStacks {
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        // the function we're in right now, which may not be the one on top of
+        // the stack if we got here by way of a tail call
+        let function = match em.symbols().lookup(retired.pc) {
+            Some((_, offset)) => retired.pc - offset,
+            None => retired.pc,
+        };
+        if self.stack.is_empty() {
+            self.stack.push(function);
+        }
+
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.period;
+            self.key.clear();
+            self.key
+                .extend_from_slice(&self.stack[..self.stack.len() - 1]);
+            self.key.push(function);
+            if let Some(count) = self.samples.get_mut(self.key.as_slice()) {
+                *count += 1;
+            } else {
+                self.samples.insert(self.key.clone(), 1);
+            }
+        }
+
+        if is_call(&retired.inst) {
+            self.stack.push(retired.next_pc);
+        } else if is_return(&retired.inst) && self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Tests of a program's functions, called one at a time (see
+//! [Emulator::call]) with the arguments given in a manifest, and checked
+//! against the return values and memory contents expected of them.
+//!
+//! A manifest is a TOML file of `[[test]]` tables:
+//!
+//! ```toml
+//! [[test]]
+//! function = "max"
+//! args = [-3, -7]
+//! returns = -3
+//!
+//! [[test]]
+//! name = "fill a buffer"
+//! function = "fill"
+//! args = ["buf", 3, 0x2a]      # symbols stand for their addresses
+//! memory = [{ at = "buf", bytes = [42, 42, 42, 0] }]
+//! ```
+use crate::{Emulator, EmulatorError};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A list of function tests, as read from a manifest
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestManifest {
+    #[serde(rename = "test", default)]
+    pub tests: Vec<FunctionTest>,
+}
+
+/// A call to make of a guest function, and what to expect of it
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FunctionTest {
+    /// What to call the test, if not the call itself
+    pub name: Option<String>,
+    /// The function to call: a symbol or address
+    pub function: String,
+    /// Its arguments, passed in a0-a7
+    #[serde(default)]
+    pub args: Vec<Value>,
+    /// The value it should return in a0, if any in particular
+    pub returns: Option<Value>,
+    /// What memory should hold afterwards
+    #[serde(default)]
+    pub memory: Vec<MemoryCheck>,
+}
+
+/// A word-sized value: a number (negative ones in two's complement), or the
+/// address of a symbol
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i64),
+    Symbol(String),
+}
+
+/// The bytes (or little-endian words) expected in memory at a location, a
+/// symbol or address
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryCheck {
+    pub at: String,
+    #[serde(default)]
+    pub bytes: Vec<u8>,
+    #[serde(default)]
+    pub words: Vec<i64>,
+}
+
+/// How a function test went
+#[derive(Debug)]
+pub enum TestOutcome {
+    Pass,
+    /// The function returned, but not what was expected of it
+    Fail(String),
+    /// The function couldn't be called, or didn't return
+    Error(EmulatorError),
+}
+
+impl TestManifest {
+    /// Reads a manifest from the TOML file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TestManifest, EmulatorError> {
+        let path = path.as_ref();
+        toml::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| EmulatorError::Config(format!("{}: {}", path.display(), e)))
+    }
+}
+
+impl Value {
+    /// Returns the value as a word, looking up a symbol's address in `em`.
+    pub fn resolve(&self, em: &Emulator) -> Result<u32, EmulatorError> {
+        match self {
+            Value::Int(n) => Ok(*n as u32),
+            Value::Symbol(s) => Ok(em.lookup(s)? as u32),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Symbol(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl FunctionTest {
+    /// Returns the test's name: the one given, or else the call it makes,
+    /// e.g., `max(3, 7)`.
+    pub fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let args: Vec<String> = self.args.iter().map(Value::to_string).collect();
+        format!("{}({})", self.function, args.join(", "))
+    }
+
+    /// Calls the function in `em` (into which the program has been loaded,
+    /// and which is left as the call leaves it) and checks the result.
+    ///
+    /// ```
+    /// # use rvem::{Emulator, TestManifest, TestOutcome};
+    /// let manifest = TestManifest::load("tests/data/homework.toml").unwrap();
+    /// for test in &manifest.tests {
+    ///     let mut em = Emulator::load_from("tests/data/homework", None).unwrap();
+    ///     assert!(matches!(test.run(&mut em), TestOutcome::Pass));
+    /// }
+    /// ```
+    pub fn run(&self, em: &mut Emulator) -> TestOutcome {
+        match self.check(em) {
+            Ok(None) => TestOutcome::Pass,
+            Ok(Some(why)) => TestOutcome::Fail(why),
+            Err(e) => TestOutcome::Error(e),
+        }
+    }
+
+    /// Returns why the test failed, if it did.
+    fn check(&self, em: &mut Emulator) -> Result<Option<String>, EmulatorError> {
+        let args = (self.args.iter())
+            .map(|arg| arg.resolve(em))
+            .collect::<Result<Vec<_>, _>>()?;
+        let returned = em.call(&self.function, &args)?;
+
+        if let Some(expected) = &self.returns {
+            let value = expected.resolve(em)?;
+            if returned != value {
+                return Ok(Some(format!(
+                    "returned {} ({:#x}), expected {} ({:#x})",
+                    returned as i32, returned, expected, value
+                )));
+            }
+        }
+        for check in &self.memory {
+            let expected: Vec<u8> = if check.words.is_empty() {
+                check.bytes.clone()
+            } else if check.bytes.is_empty() {
+                (check.words.iter())
+                    .flat_map(|word| (*word as u32).to_le_bytes())
+                    .collect()
+            } else {
+                return Err(EmulatorError::Config(format!(
+                    "memory at {}: give either bytes or words, not both",
+                    check.at
+                )));
+            };
+            let addr = em.lookup(&check.at)?;
+            let actual = em.memory(addr, expected.len())?;
+            if let Some(i) = (0..expected.len()).find(|&i| actual[i] != expected[i]) {
+                return Ok(Some(format!(
+                    "memory at {}+{} is {:#04x}, expected {:#04x}",
+                    check.at, i, actual[i], expected[i]
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOutcome::Pass => write!(f, "pass"),
+            TestOutcome::Fail(why) => write!(f, "FAIL ({})", why),
+            TestOutcome::Error(e) => write!(f, "ERROR ({})", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TestManifest, TestOutcome};
+    use crate::Emulator;
+
+    #[test]
+    fn test_function_tests() {
+        let manifest: TestManifest = toml::from_str(
+            r#"
+            [[test]]
+            function = "max"
+            args = [-3, -7]
+            returns = -7
+
+            [[test]]
+            function = "fill"
+            args = ["buf", 2, 1]
+            memory = [{ at = "buf", words = [0x0101] }, { at = "buf", bytes = [1, 1, 1] }]
+
+            [[test]]
+            function = "nonesuch"
+            "#,
+        )
+        .unwrap();
+        let outcomes: Vec<String> = (manifest.tests.iter())
+            .map(|test| {
+                let mut em = Emulator::load_from("tests/data/homework", None).unwrap();
+                test.run(&mut em).to_string()
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                "FAIL (returned -3 (0xfffffffd), expected -7 (0xfffffff9))",
+                "FAIL (memory at buf+2 is 0x00, expected 0x01)",
+                "ERROR (configuration error: unknown symbol or bad address: nonesuch)",
+            ]
+        );
+        assert_eq!(manifest.tests[0].name(), "max(-3, -7)");
+
+        let test = &TestManifest::load("tests/data/homework.toml")
+            .unwrap()
+            .tests[2];
+        assert_eq!(test.name(), "fill(buf, 3, 42)");
+        let mut em = Emulator::load_from("tests/data/homework", None).unwrap();
+        assert!(matches!(test.run(&mut em), TestOutcome::Pass));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{Emulator, Retired, Section, SymbolTable, TraceSink};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of the pages memory is tracked in for the purposes of
+/// [Emulator::restore]
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+/// Source of unique snapshot ids, so an emulator can tell whether its dirty
+/// pages are relative to the snapshot it's being restored to
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The complete architectural state of an [Emulator] (registers, memory, and
+/// so on, along with the program's sections and symbols) at some point in a
+/// program's execution, as taken by [Emulator::snapshot].
+///
+/// Restoring the snapshot the emulator most recently took (or was restored
+/// to) is cheap: only the pages of memory written since are copied back.
+/// This makes it possible to run a program up to some interesting point
+/// once, then run the rest of it over and over again against different
+/// inputs, as fuzzers do.
+///
+/// With the `serialize` feature (on by default), snapshots can also be
+/// serialized, to be saved to disk or sent to another process and restored
+/// into a fresh emulator there. Memory is serialized sparsely, as hex-encoded
+/// pages, skipping pages that are all zeroes.
+#[derive(Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    /// NB a deserialized snapshot is a different snapshot, as far as
+    /// [Emulator::restore] is concerned
+    #[cfg_attr(feature = "serialize", serde(skip, default = "next_id"))]
+    pub(crate) id: u64,
+    pub(crate) pc: usize,
+    pub(crate) reg: [u32; 32],
+    #[cfg_attr(feature = "serialize", serde(with = "pages"))]
+    pub(crate) mem: Vec<u8>,
+    pub(crate) sections: Vec<Section>,
+    /// Executable ranges of memory (if left out, the executable sections)
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub(crate) code: Vec<Range<usize>>,
+    pub(crate) symtab: SymbolTable,
+    pub(crate) functions: SymbolTable,
+    pub(crate) brk: usize,
+    pub(crate) brk_start: usize,
+    pub(crate) mmap_top: usize,
+    pub(crate) tohost: Option<usize>,
+    pub(crate) instret: u64,
+    pub(crate) cycles: u64,
+    pub(crate) exit_code: Option<i32>,
+    /// State of the getrandom generator
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub(crate) rng: u64,
+}
+
+impl Snapshot {
+    /// Returns a snapshot of `em`.
+    pub(crate) fn new(em: &Emulator) -> Snapshot {
+        Snapshot {
+            id: next_id(),
+            pc: em.pc,
+            reg: em.reg,
+            mem: em.mem.clone(),
+            sections: em.sections.clone(),
+            code: em.code.clone(),
+            symtab: em.symtab.clone(),
+            functions: em.functions.clone(),
+            brk: em.brk,
+            brk_start: em.brk_start,
+            mmap_top: em.mmap_top,
+            tohost: em.tohost,
+            instret: em.instret,
+            cycles: em.cycles,
+            exit_code: em.exit_code,
+            rng: em.rng,
+        }
+    }
+
+    /// Returns the program counter at the time of the snapshot.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+}
+
+/// (De)serializes memory as its length plus the pages that aren't all
+/// zeroes, hex-encoded.
+#[cfg(feature = "serialize")]
+mod pages {
+    use super::PAGE_SIZE;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt::Write;
+
+    #[derive(Serialize, Deserialize)]
+    struct Pages {
+        len: usize,
+        pages: Vec<(usize, String)>,
+    }
+
+    pub fn serialize<S: Serializer>(mem: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let pages = mem
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .filter(|(_, page)| page.iter().any(|b| *b != 0))
+            .map(|(i, page)| {
+                let mut hex = String::with_capacity(2 * page.len());
+                for b in page {
+                    let _ = write!(hex, "{:02x}", b);
+                }
+                (i * PAGE_SIZE, hex)
+            })
+            .collect();
+        Pages {
+            len: mem.len(),
+            pages,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let Pages { len, pages } = Pages::deserialize(deserializer)?;
+        let mut mem = vec![0; len];
+        for (addr, hex) in pages {
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| {
+                    hex.get(i..i + 2)
+                        .and_then(|b| u8::from_str_radix(b, 16).ok())
+                        .ok_or_else(|| D::Error::custom(format!("bad hex at {:x}", addr + i / 2)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            mem.get_mut(addr..addr + bytes.len())
+                .ok_or_else(|| D::Error::custom(format!("page at {:x} out of bounds", addr)))?
+                .copy_from_slice(&bytes);
+        }
+        Ok(mem)
+    }
+}
+
+/// A [TraceSink] that records the control flow edges a program takes in an
+/// AFL-style coverage bitmap: each edge (from a branch, jump, or other
+/// instruction ending a basic block, to wherever execution went next) is
+/// hashed to a byte in the map, which counts how many times it was taken
+/// (wrapping around at 256).
+///
+/// The map is meant to be handed to a coverage-guided fuzzer (e.g., as a
+/// LibAFL map observer) after each run, and [CoverageMap::reset] before the
+/// next.
+#[derive(Clone, Debug)]
+pub struct CoverageMap {
+    map: Vec<u8>,
+}
+
+impl CoverageMap {
+    /// Returns a new (empty) map of `size` bytes.
+    pub fn new(size: usize) -> CoverageMap {
+        CoverageMap {
+            map: vec![0; size.max(1)],
+        }
+    }
+
+    /// Returns the map.
+    pub fn map(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Returns the map, mutably (e.g., for a fuzzer that classifies hit
+    /// counts in place).
+    pub fn map_mut(&mut self) -> &mut [u8] {
+        &mut self.map
+    }
+
+    /// Returns the number of entries in the map that have been hit.
+    pub fn edges(&self) -> usize {
+        self.map.iter().filter(|n| **n != 0).count()
+    }
+
+    /// Clears the map, ready for another run.
+    pub fn reset(&mut self) {
+        self.map.fill(0);
+    }
+}
+
+impl TraceSink for CoverageMap {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        if retired.inst.ends_block() {
+            let i = ((retired.pc >> 1) ^ retired.next_pc) % self.map.len();
+            self.map[i] = self.map[i].wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmulatorError, Reg};
+
+    /// Halts execution after a fixed number of instructions.
+    struct Stop(u64);
+
+    impl TraceSink for Stop {
+        fn retire(&mut self, _em: &Emulator, _retired: &Retired) {
+            self.0 -= 1;
+        }
+
+        fn halt(&self) -> bool {
+            self.0 == 0
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let pc = match em.run_with(&mut Stop(20)) {
+            Err(EmulatorError::Halted(pc)) => pc,
+            result => panic!("unexpected result: {:?}", result),
+        };
+        let snapshot = em.snapshot();
+        assert_eq!(snapshot.pc(), pc);
+
+        let mut coverage = CoverageMap::new(1024);
+        assert_eq!(em.resume_with(&mut coverage).unwrap(), 0);
+        assert_eq!((em[Reg::a0], em.instret()), (120, 67));
+        let map = coverage.map().to_vec();
+        assert!(coverage.edges() > 0);
+
+        for _ in 0..3 {
+            em.restore(&snapshot);
+            assert_eq!((em.pc, em.instret(), em.exit_code()), (pc, 20, None));
+            assert_eq!(em.mem, snapshot.mem);
+
+            coverage.reset();
+            assert_eq!(em.resume_with(&mut coverage).unwrap(), 0);
+            assert_eq!((em[Reg::a0], em.instret()), (120, 67));
+            assert_eq!(coverage.map(), map);
+        }
+
+        // host writes count as dirty too
+        em.restore(&snapshot);
+        em[0x100] = 0xff;
+        em.restore(&snapshot);
+        assert_eq!(em[0x100], 0);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn test_serialize() {
+        use crate::NoTrace;
+
+        let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        assert!(em.run_with(&mut Stop(5)).is_err());
+        let json = serde_json::to_string(&em.snapshot()).unwrap();
+        assert!(json.len() < 4 * PAGE_SIZE);
+
+        // restore into a brand new emulator
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+        let mut copy = Emulator::default();
+        copy.restore(&snapshot);
+        assert_eq!(copy.mem, em.mem);
+        assert_eq!(copy.addr_of("sum"), em.addr_of("sum"));
+        assert_eq!(copy.sections(), em.sections());
+        assert_eq!(copy.resume_with(&mut NoTrace).unwrap(), 55);
+        assert_eq!(em.resume_with(&mut NoTrace).unwrap(), 55);
+        assert_eq!(copy.instret(), em.instret());
+
+        // an instruction count that can't go any higher fails, rather than
+        // panicking
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["instret"] = u64::MAX.into();
+        copy.restore(&serde_json::from_value(value).unwrap());
+        assert!(matches!(
+            copy.resume_with(&mut NoTrace),
+            Err(EmulatorError::InstructionLimit { .. })
+        ));
+
+        // memory that doesn't fit
+        assert!(
+            serde_json::from_str::<Snapshot>(&json.replace("\"len\":1048576", "\"len\":16"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_set_stdin() {
+        let mut em = Emulator::new(Some(0x1000));
+        em.set_stdin(&b"42\n"[..]);
+        em[Reg::a7] = 5;
+        em.ecall().unwrap();
+        assert_eq!(em[Reg::a0], 42);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{Emulator, EmulatorError, Retired, TraceSink};
+use std::io;
+
+/// FNV-1a parameters; the hash has to be stable across builds, so
+/// [std::hash::DefaultHasher] won't do.
+pub(crate) const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+pub(crate) const FNV_PRIME: u64 = 0x100000001b3;
+
+/// A [TraceSink] that condenses a run into a series of checkpoints, each a
+/// hash of every instruction retired so far (its PC and raw instruction, and
+/// any register writeback or memory access). Comparing the checkpoints of
+/// two runs - e.g., before and after a change to the interpreter - shows
+/// whether they behaved identically, and if not, roughly where they
+/// diverged.
+///
+/// Its [Display](std::fmt::Display) implementation writes the checkpoints
+/// one per line, as `instret hash`, in the format [GoldenTrace::parse]
+/// reads.
+#[derive(Clone, Debug)]
+pub struct GoldenTrace {
+    /// Instructions between checkpoints
+    period: u64,
+    /// Instructions retired so far
+    count: u64,
+    /// Running hash of everything retired so far
+    hash: u64,
+    /// (instret, hash) pairs
+    checkpoints: Vec<(u64, u64)>,
+}
+
+/// Where a run first differed from a golden trace.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GoldenMismatch {
+    /// Last checkpoint at which the run still matched (0 if none did)
+    pub last_match: u64,
+    /// The first checkpoint at which it didn't (or at which one of the two
+    /// ended early)
+    pub first_mismatch: u64,
+}
+
+impl std::fmt::Display for GoldenMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "run diverges from golden trace between instructions {} and {}",
+            self.last_match, self.first_mismatch
+        )
+    }
+}
+
+impl GoldenTrace {
+    /// Returns a new trace that checkpoints every `period` instructions
+    /// (and once more when [GoldenTrace::finish] is called).
+    pub fn new(period: u64) -> GoldenTrace {
+        GoldenTrace {
+            period: period.max(1),
+            count: 0,
+            hash: FNV_OFFSET,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Records a final checkpoint (unless one was just taken).
+    pub fn finish(&mut self) {
+        if self.checkpoints.last().map(|(n, _)| *n) != Some(self.count) {
+            self.checkpoints.push((self.count, self.hash));
+        }
+    }
+
+    /// Returns the checkpoints recorded so far, as (instret, hash) pairs.
+    pub fn checkpoints(&self) -> &[(u64, u64)] {
+        &self.checkpoints
+    }
+
+    /// Parses checkpoints in the format written by this type's
+    /// [Display](std::fmt::Display) implementation.
+    pub fn parse(golden: &str) -> Result<Vec<(u64, u64)>, EmulatorError> {
+        golden
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(n, line)| {
+                let (count, hash) = line.trim().split_once(' ').unwrap_or((line, ""));
+                match (count.parse(), u64::from_str_radix(hash, 16)) {
+                    (Ok(count), Ok(hash)) => Ok((count, hash)),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: malformed golden checkpoint: {}", n + 1, line),
+                    )
+                    .into()),
+                }
+            })
+            .collect()
+    }
+
+    /// Compares this trace against the checkpoints in `golden`, returning
+    /// where they first differ (if anywhere).
+    pub fn check(&self, golden: &[(u64, u64)]) -> Result<(), GoldenMismatch> {
+        let mut last_match = 0;
+        for i in 0..self.checkpoints.len().max(golden.len()) {
+            match (self.checkpoints.get(i), golden.get(i)) {
+                (Some(a), Some(b)) if a == b => last_match = a.0,
+                (a, b) => {
+                    let first_mismatch = match (a, b) {
+                        (Some(a), Some(b)) => a.0.min(b.0),
+                        (Some(x), None) | (None, Some(x)) => x.0,
+                        (None, None) => unreachable!(),
+                    };
+                    return Err(GoldenMismatch {
+                        last_match,
+                        first_mismatch,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn mix(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.hash = (self.hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl TraceSink for GoldenTrace {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        self.mix(retired.pc as u64);
+        self.mix(retired.word as u64);
+        if let Some((rd, value)) = retired.rd {
+            self.mix(rd as u64);
+            self.mix(value as u64);
+        }
+        if let Some(mem) = retired.mem {
+            self.mix(mem.addr as u64);
+            self.mix(mem.value as u64);
+        }
+        self.count += 1;
+        if self.count.is_multiple_of(self.period) {
+            self.checkpoints.push((self.count, self.hash));
+        }
+    }
+}
+
+impl std::fmt::Display for GoldenTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (count, hash) in &self.checkpoints {
+            writeln!(f, "{} {:016x}", count, hash)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str) -> GoldenTrace {
+        let mut em = Emulator::load_from(path, None).unwrap();
+        let mut golden = GoldenTrace::new(10);
+        em.run_with(&mut golden).unwrap();
+        golden.finish();
+        golden
+    }
+
+    #[test]
+    fn test_golden_trace() {
+        let golden = record("tests/data/fac");
+        assert_eq!(golden.checkpoints().len(), 7);
+        assert_eq!(golden.checkpoints().last().unwrap().0, 67);
+
+        let parsed = GoldenTrace::parse(&golden.to_string()).unwrap();
+        assert_eq!(record("tests/data/fac").check(&parsed), Ok(()));
+
+        let mut tampered = parsed.clone();
+        tampered[3].1 ^= 1;
+        assert_eq!(
+            golden.check(&tampered),
+            Err(GoldenMismatch {
+                last_match: 30,
+                first_mismatch: 40
+            })
+        );
+        assert_eq!(
+            golden.check(&parsed[..5]),
+            Err(GoldenMismatch {
+                last_match: 50,
+                first_mismatch: 60
+            })
+        );
+        assert!(record("tests/data/fib").check(&parsed).is_err());
+    }
+}
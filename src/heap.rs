@@ -0,0 +1,258 @@
+use crate::callgraph::is_call;
+use crate::{Emulator, Inst, Reg, Retired, TraceSink};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A block of memory handed out by the guest's allocator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Allocation {
+    /// Address of the block
+    pub addr: usize,
+    /// Size of the block, in bytes
+    pub size: usize,
+    /// Address of the call that allocated it
+    pub site: usize,
+}
+
+/// Allocator entry points, by address.
+#[derive(Clone, Copy, Debug, Default)]
+struct Hooks {
+    malloc: Option<usize>,
+    calloc: Option<usize>,
+    realloc: Option<usize>,
+    free: Option<usize>,
+}
+
+/// An allocator call that hasn't returned yet.
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+    /// Where the call will return to
+    ret: usize,
+    /// Address of the call
+    site: usize,
+    /// Bytes requested
+    size: usize,
+    /// Block being resized (for realloc)
+    old: Option<usize>,
+    /// Whether this is a call to free (whose return value is meaningless)
+    free: bool,
+}
+
+/// A [TraceSink] that profiles the guest's heap usage.
+///
+/// Growth of the heap itself is tracked through the brk and mmap syscalls.
+/// If the program has `malloc`, `calloc`, `realloc`, and/or `free` symbols,
+/// calls to them are intercepted as well, to keep track of individual blocks;
+/// any still allocated when the program exits are reported as potential
+/// leaks. Calls the allocator makes to itself (e.g., calloc calling malloc)
+/// are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct HeapProfile {
+    hooks: Hooks,
+    /// Initial program break
+    brk_start: usize,
+    /// Highest program break seen
+    brk_peak: usize,
+    /// Anonymous mappings made, and their total size
+    mmaps: u64,
+    mmap_bytes: usize,
+    /// Allocator calls in progress (innermost last)
+    pending: Vec<Pending>,
+    /// Blocks currently allocated, by address
+    live: BTreeMap<usize, Allocation>,
+    live_bytes: usize,
+    live_peak: usize,
+    /// Number of blocks allocated and freed
+    allocations: u64,
+    frees: u64,
+}
+
+impl HeapProfile {
+    /// Returns a new profiler for `em`, whose program should already have
+    /// been loaded (so that its allocator can be found).
+    pub fn new(em: &Emulator) -> HeapProfile {
+        let symbols = em.symbols();
+        HeapProfile {
+            hooks: Hooks {
+                malloc: symbols.get("malloc"),
+                calloc: symbols.get("calloc"),
+                realloc: symbols.get("realloc"),
+                free: symbols.get("free"),
+            },
+            brk_start: em.brk_start(),
+            brk_peak: em.brk(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the most the heap grew (via brk), in bytes.
+    pub fn peak_brk(&self) -> usize {
+        self.brk_peak - self.brk_start
+    }
+
+    /// Returns the most memory that was allocated at once (via the
+    /// allocator), in bytes.
+    pub fn peak_live(&self) -> usize {
+        self.live_peak
+    }
+
+    /// Returns the number of blocks allocated.
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+
+    /// Returns the number of blocks freed.
+    pub fn frees(&self) -> u64 {
+        self.frees
+    }
+
+    /// Returns the blocks that are still allocated, in order of address.
+    pub fn live(&self) -> impl Iterator<Item = &Allocation> {
+        self.live.values()
+    }
+
+    /// Formats a summary of heap usage, including any blocks still
+    /// allocated (i.e., potential leaks, if the program has exited).
+    pub fn report(&self, em: &Emulator) -> String {
+        let mut report = format!("heap: {} bytes peak (brk)", self.peak_brk());
+        if self.mmaps > 0 {
+            let _ = write!(
+                report,
+                ", {} bytes mapped (mmap x{})",
+                self.mmap_bytes, self.mmaps
+            );
+        }
+        report.push('\n');
+        if self.hooks.malloc.is_none() {
+            return report;
+        }
+        let _ = writeln!(
+            report,
+            "allocations: {} ({} freed), {} bytes peak",
+            self.allocations, self.frees, self.live_peak
+        );
+        if !self.live.is_empty() {
+            let _ = writeln!(
+                report,
+                "leaks: {} blocks, {} bytes",
+                self.live.len(),
+                self.live_bytes
+            );
+            for block in self.live.values() {
+                let _ = writeln!(
+                    report,
+                    "  {} bytes at {:08x}, allocated at {:08x} <{}>",
+                    block.size,
+                    block.addr,
+                    block.site,
+                    em.symbols().symbolize(block.site)
+                );
+            }
+        }
+        report
+    }
+
+    fn call(&mut self, em: &Emulator, retired: &Retired) {
+        let target = Some(retired.next_pc);
+        let mut pending = Pending {
+            ret: retired.pc + 4,
+            site: retired.pc,
+            size: 0,
+            old: None,
+            free: false,
+        };
+        if target == self.hooks.malloc {
+            pending.size = em[Reg::a0] as usize;
+        } else if target == self.hooks.calloc {
+            pending.size = (em[Reg::a0] as usize).saturating_mul(em[Reg::a1] as usize);
+        } else if target == self.hooks.realloc {
+            pending.old = Some(em[Reg::a0] as usize).filter(|addr| *addr != 0);
+            pending.size = em[Reg::a1] as usize;
+        } else if target == self.hooks.free {
+            self.release(em[Reg::a0] as usize);
+            pending.free = true;
+        } else {
+            return;
+        }
+        self.pending.push(pending);
+    }
+
+    fn release(&mut self, addr: usize) {
+        if let Some(block) = self.live.remove(&addr) {
+            self.live_bytes -= block.size;
+            self.frees += 1;
+        }
+    }
+}
+
+impl TraceSink for HeapProfile {
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        if matches!(retired.inst, Inst::ECALL) {
+            match em[Reg::a7] {
+                214 => self.brk_peak = self.brk_peak.max(em.brk()),
+                222 if (em[Reg::a0] as i32) >= 0 => {
+                    self.mmaps += 1;
+                    self.mmap_bytes += em[Reg::a1] as usize;
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        if is_call(&retired.inst) {
+            // NB only the outermost allocator call counts
+            if self.pending.is_empty() {
+                self.call(em, retired);
+            }
+            return;
+        }
+
+        let Some(pending) = self.pending.last().copied() else {
+            return;
+        };
+        if !matches!(retired.inst, Inst::JALR { .. }) || retired.next_pc != pending.ret {
+            return;
+        }
+        self.pending.pop();
+        if pending.free {
+            return;
+        }
+        let addr = em[Reg::a0] as usize;
+        if addr == 0 {
+            return;
+        }
+        if let Some(old) = pending.old {
+            self.release(old);
+        }
+        self.live.insert(
+            addr,
+            Allocation {
+                addr,
+                size: pending.size,
+                site: pending.site,
+            },
+        );
+        self.allocations += 1;
+        self.live_bytes += pending.size;
+        self.live_peak = self.live_peak.max(self.live_bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_profile() {
+        let mut em = Emulator::load_from("tests/data/heap", None).unwrap();
+        let mut heap = HeapProfile::new(&em);
+        assert_eq!(em.run_with(&mut heap).unwrap(), 0);
+        assert_eq!(em.brk(), em.brk_start() + 56);
+        assert_eq!(heap.peak_brk(), 56);
+        assert_eq!((heap.allocations(), heap.frees()), (3, 1));
+        assert_eq!(heap.peak_live(), 56);
+
+        let live: Vec<_> = heap.live().map(|block| block.size).collect();
+        assert_eq!(live, vec![32, 8]);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{Emulator, Retired, TraceSink};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A [TraceSink] that counts how many times each instruction address is
+/// executed, to find out where a program spends its time.
+#[derive(Clone, Debug, Default)]
+pub struct Hotspots {
+    counts: HashMap<usize, u64>,
+}
+
+impl Hotspots {
+    /// Returns a new, empty set of counts.
+    pub fn new() -> Hotspots {
+        Default::default()
+    }
+
+    /// Returns the number of times the instruction at `pc` was executed.
+    pub fn count(&self, pc: usize) -> u64 {
+        self.counts.get(&pc).copied().unwrap_or(0)
+    }
+
+    /// Returns the `n` most-executed addresses along with their counts,
+    /// hottest first.
+    pub fn top(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut counts: Vec<(usize, u64)> = self.counts.iter().map(|(a, c)| (*a, *c)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Formats a table of the `n` hottest addresses, with the function each
+    /// belongs to and its disassembly.
+    pub fn report(&self, em: &Emulator, n: usize) -> String {
+        let total: u64 = self.counts.values().sum();
+        let mut report = format!(
+            "{:>12} {:>7} {:>8}  {:<24} {}\n",
+            "count", "%", "address", "function", "instruction"
+        );
+        for (pc, count) in self.top(n) {
+            let inst = match em.inst(pc) {
+                Ok(inst) => inst.at(pc).to_string(),
+                Err(_) => "???".to_string(),
+            };
+            let _ = writeln!(
+                report,
+                "{:>12} {:>6.2}% {:>8x}  {:<24} {}",
+                count,
+                100.0 * count as f64 / total as f64,
+                pc,
+                em.symbols().symbolize(pc),
+                inst
+            );
+        }
+        report
+    }
+}
+
+impl TraceSink for Hotspots {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        *self.counts.entry(retired.pc).or_default() += 1;
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{Emulator, Retired, TraceSink};
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// A register writeback, as written to a JSONL trace.
+#[derive(Serialize)]
+struct RegWrite {
+    reg: String,
+    value: u32,
+}
+
+/// A memory access, as written to a JSONL trace.
+#[derive(Serialize)]
+struct Mem {
+    addr: usize,
+    len: usize,
+    store: bool,
+    value: u32,
+}
+
+/// A retired instruction, as written to a JSONL trace.
+#[derive(Serialize)]
+struct Record {
+    pc: usize,
+    word: u32,
+    inst: String,
+    rd: Option<RegWrite>,
+    mem: Option<Mem>,
+    /// Source file and line, if the program has line information
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+}
+
+/// A [TraceSink] that writes one JSON object per retired instruction to `W`,
+/// e.g.:
+///
+/// ```json
+/// {"pc":65652,"word":4286644499,"inst":"addi sp, sp, -8","rd":{"reg":"sp","value":524280},"mem":null}
+/// ```
+///
+/// Records for programs with source line information (see [crate::LineTable])
+/// also have `"file"` and `"line"` fields.
+///
+/// Since sinks can't fail, the first write error (if any) is held onto and
+/// reported by [JsonTrace::finish].
+pub struct JsonTrace<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonTrace<W> {
+    /// Returns a new trace that writes to `out`.
+    pub fn new(out: W) -> JsonTrace<W> {
+        JsonTrace { out, error: None }
+    }
+
+    /// Flushes the trace, returning the first error encountered while
+    /// writing it (if any).
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+}
+
+impl<W: Write> TraceSink for JsonTrace<W> {
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        if self.error.is_some() {
+            return;
+        }
+        let source = em.lines().lookup(retired.pc);
+        let record = Record {
+            pc: retired.pc,
+            word: retired.word,
+            inst: retired.inst.at(retired.pc).to_string(),
+            rd: retired.rd.map(|(reg, value)| RegWrite {
+                reg: reg.to_string(),
+                value,
+            }),
+            mem: retired.mem.map(|mem| Mem {
+                addr: mem.addr,
+                len: mem.len,
+                store: mem.store,
+                value: mem.value,
+            }),
+            file: source.map(|(file, _)| file.to_string()),
+            line: source.map(|(_, line)| line),
+        };
+        let result = serde_json::to_writer(&mut self.out, &record)
+            .map_err(io::Error::from)
+            .and_then(|_| self.out.write_all(b"\n"));
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
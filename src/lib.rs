@@ -1,28 +1,206 @@
+use goblin::elf::program_header::PT_LOAD;
+use goblin::elf::sym::{STT_FILE, STT_FUNC, STT_NOTYPE, STT_OBJECT, STT_SECTION};
 use goblin::elf::Elf;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Write};
 use std::ops::{Index, IndexMut, Range};
-use std::os::fd::FromRawFd;
 use std::path::Path;
-use std::process;
+use std::time::{Duration, Instant, SystemTime};
 use strum::IntoEnumIterator;
 use thiserror::Error;
 
+pub mod csr;
 pub(crate) mod reg;
 pub use reg::Reg;
 pub(crate) mod inst;
-pub use inst::Inst;
+use inst::Op;
+pub use inst::{Inst, InstAt, InstClass};
+pub(crate) mod symbols;
+pub use symbols::{demangle, SymbolInfo, SymbolKind, SymbolTable};
+pub(crate) mod trace;
+pub use trace::{LogTrace, MemAccess, NoTrace, Retired, TraceSink};
+pub(crate) mod timing;
+pub use timing::Timing;
+pub(crate) mod cost;
+pub use cost::{CostProfile, CostTable};
+pub(crate) mod stats;
+pub use stats::Stats;
+pub(crate) mod hotspots;
+pub use hotspots::Hotspots;
+pub(crate) mod profile;
+pub use profile::{FunctionProfile, Profile};
+pub(crate) mod callgraph;
+pub use callgraph::CallGraph;
+pub(crate) mod folded;
+pub use folded::FoldedStacks;
+pub(crate) mod lines;
+pub use lines::LineTable;
+pub(crate) mod coverage;
+pub use coverage::{Coverage, FunctionCoverage};
+#[cfg(feature = "serialize")]
+pub(crate) mod jsonl;
+#[cfg(feature = "serialize")]
+pub use jsonl::JsonTrace;
+pub(crate) mod spike;
+pub use spike::SpikeTrace;
+pub(crate) mod statediff;
+pub use statediff::{MemDiff, StateDiff};
+pub(crate) mod archstate;
+pub use archstate::{ArchDiff, ArchState};
+pub(crate) mod tracediff;
+pub use tracediff::{diff_traces, parse_trace, Divergence, TraceRecord};
+pub(crate) mod taint;
+pub use taint::{Taint, TaintHit, TaintReport};
+pub(crate) mod memcheck;
+pub use memcheck::{MemCheck, UninitRead};
+pub(crate) mod heap;
+pub use heap::{Allocation, HeapProfile};
+pub(crate) mod memusage;
+pub use memusage::MemUsage;
+pub(crate) mod golden;
+pub use golden::{GoldenMismatch, GoldenTrace};
+pub(crate) mod cosim;
+pub use cosim::CoSim;
+pub(crate) mod breakpoints;
+pub use breakpoints::Breakpoints;
+pub(crate) mod checkpoints;
+#[cfg(feature = "serialize")]
+pub(crate) mod functest;
+#[cfg(feature = "serialize")]
+pub use functest::{FunctionTest, MemoryCheck, TestManifest, TestOutcome, Value};
+pub(crate) mod fuzz;
+pub use fuzz::{CoverageMap, Snapshot};
+pub(crate) mod memmap;
+pub use memmap::{Region, Section};
+pub(crate) mod metrics;
+pub use metrics::Metrics;
+pub(crate) mod config;
+pub use config::{
+    parse_size, DeviceConfig, IoConfig, Limit, Limits, MachineConfig, Misaligned, Personality,
+    UnknownSyscalls, CPU_TIME_CHECK,
+};
+pub(crate) mod disasm;
+pub use disasm::{
+    compare_disasm, find_objdump, objdump, verify_decode, verify_disasm, DecodeMismatch,
+    DisasmMismatch, OBJDUMP_VAR,
+};
+pub(crate) mod dump;
+pub use dump::{Dump, InstDump, RegDump, SectionDump, StackDump, SymbolDump};
+#[cfg(feature = "async")]
+pub(crate) mod run_async;
+#[cfg(feature = "async")]
+pub use run_async::DEFAULT_SLICE;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 /// Default amount of memory to allocate if not specified
 pub const DEFAULT_MEMORY_SIZE: usize = 1 << 20;
+/// Words of the stack below and above the stack pointer to show in dumps
+const STACK_BELOW: usize = 4;
+const STACK_ABOVE: usize = 16;
 /// Symbol name for the program entrypoint
 const ENTRYPOINT_SYM: &str = "_start";
 /// Symbol name for the global pointer
 const GLOBAL_POINTER_SYM: &str = "__global_pointer$";
 /// Symbol names for the start/end of the BSS region
-const BSS_START_SYM: &str = "__bss_start";
-const BSS_END_SYM: &str = "__BSS_END__";
+pub(crate) const BSS_START_SYM: &str = "__bss_start";
+pub(crate) const BSS_END_SYM: &str = "__BSS_END__";
+/// Symbol name for the HTIF tohost word (see [Emulator::load])
+const TOHOST_SYM: &str = "tohost";
+/// Symbol names for the start/end of the compliance test signature
+const BEGIN_SIGNATURE_SYM: &str = "begin_signature";
+const END_SIGNATURE_SYM: &str = "end_signature";
+
+/// Page size, for the purposes of mmap
+const MMAP_ALIGN: usize = 4096;
+/// mmap flag for mappings that aren't backed by a file
+const MAP_ANONYMOUS: u32 = 0x20;
+/// Error numbers for syscalls: bad file descriptor, out of memory, bad
+/// address, and no such syscall
+const EBADF: u32 = 9;
+const ENOMEM: u32 = 12;
+const EFAULT: u32 = 14;
+const ENOSYS: u32 = 38;
+/// clock_gettime's clock for wall-clock time (the others count from when the
+/// program started)
+const CLOCK_REALTIME: u32 = 0;
+
+/// Syscall number (in a7) through which guest code calls back into the host,
+/// to callbacks registered with [Emulator::register_hypercall]; the number of
+/// the hypercall goes in a0, its arguments in a1-a6, and its result comes
+/// back in a0 (or -ENOSYS if there's no such hypercall).
+pub const HYPERCALL: u32 = 0x7276_656d; // "rvem"
+
+/// A host callback invoked by guest code through the [HYPERCALL] syscall,
+/// with the emulator (to get at the guest's memory and so on) and the
+/// hypercall's arguments; see [Emulator::register_hypercall].
+pub type Hypercall = Box<dyn FnMut(&mut Emulator, [u32; 6]) -> Result<u32, EmulatorError> + Send>;
+
+/// What a [YieldCallback] wants to happen next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Yield {
+    /// Carry on running the program
+    Continue,
+    /// Stop running it, with [EmulatorError::Halted] (it can be resumed
+    /// with [Emulator::resume_with])
+    Pause,
+}
+
+/// A host callback invoked every so often while a program runs, with the
+/// emulator (to do whatever work it likes on it in the meantime); see
+/// [Emulator::set_yield].
+pub type YieldCallback = Box<dyn FnMut(&mut Emulator) -> Yield + Send>;
+
+/// Returns the current time on the host clock, if there is one (there isn't
+/// on wasm32-unknown-unknown, e.g., in the browser).
+fn now() -> Option<Instant> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+/// Returns a seed for the getrandom generator, for runs that aren't meant to
+/// be deterministic.
+fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    if let Some(now) = now() {
+        std::hash::Hash::hash(&now, &mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Returns the amount to shift by for a shift by `n`: the low 5 bits of it,
+/// as the spec says for RV32 (RV64 would take the low 6).
+fn shift_amount(n: u32) -> u32 {
+    n & 0x1f
+}
+
+/// Returns how much memory a program whose image ends at `end` needs: twice
+/// that (rounded up to a power of two), since the image has to fit below the
+/// stack, which starts in the middle (see [Emulator::stack_top]).
+fn memory_for(end: usize) -> usize {
+    end.saturating_mul(2)
+        .checked_next_power_of_two()
+        .unwrap_or(usize::MAX)
+}
+
+// NB so that emulators can be handed off to worker threads, and snapshots
+// shared between them
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    const fn assert_sync<T: Sync>() {}
+    assert_send::<Emulator>();
+    assert_send::<Snapshot>();
+    assert_sync::<Snapshot>();
+};
 
 /// Sign-extend `$value` from `$bits` to 32 bits.
 pub(crate) fn sext(value: u32, bits: usize) -> u32 {
@@ -30,6 +208,12 @@ pub(crate) fn sext(value: u32, bits: usize) -> u32 {
 }
 
 /// Representation of a RISC-V machine.
+///
+/// An Emulator owns everything the program it runs can get at - its memory,
+/// its standard I/O (see [Emulator::set_stdout] and so on), its clock and
+/// random numbers - and never exits the process, so any number of them can
+/// run at once, each on its own thread (an Emulator is [Send]). Only the
+/// default standard I/O is shared: that of the host process.
 pub struct Emulator {
     /// Program counter
     pc: usize,
@@ -37,12 +221,107 @@ pub struct Emulator {
     reg: [u32; 32],
     /// Memory
     mem: Vec<u8>,
-    /// Map of section names to their corresponding memory ranges
-    sections: HashMap<String, Range<usize>>,
+    /// Allocatable sections, in order of address
+    sections: Vec<Section>,
+    /// Ranges of memory holding executable code (from the program's segment
+    /// flags), in order of address and with adjacent ranges merged
+    code: Vec<Range<usize>>,
     /// Symbol table
-    symtab: HashMap<String, usize>,
+    symtab: SymbolTable,
+    /// Just the symbols that are marked as functions, if any are
+    functions: SymbolTable,
+    /// Source line information
+    lines: LineTable,
     /// The Great Bit-Bucket in the Sky
     dev_null: u32,
+    /// Reject reserved/hint encodings when decoding (see [Inst::decode_strict])
+    strict: bool,
+    /// Whether the M extension is enabled (see [Emulator::set_isa])
+    mul_div: bool,
+    /// Which syscalls are provided
+    personality: Personality,
+    /// What to do with syscalls that aren't provided
+    unknown_syscalls: UnknownSyscalls,
+    /// What to do with misaligned loads and stores
+    misaligned: Misaligned,
+    /// Whether stores to read-only sections fault (see
+    /// [Emulator::set_write_protect])
+    write_protect: bool,
+    /// Decoded instruction cache, one slot per word of executable code
+    icache: Vec<Option<Op>>,
+    /// Address of the first slot in `icache`
+    icache_base: usize,
+    /// Decoded instruction cache statistics
+    icache_stats: CacheStats,
+    /// Length (in instructions) of the basic block starting at each slot in
+    /// `icache`, or 0 if it hasn't been determined yet
+    blocks: Vec<usize>,
+    /// Number of instructions retired
+    instret: u64,
+    /// Number of cycles elapsed (per `timing`, or one per instruction)
+    cycles: u64,
+    /// Timing model, if any
+    timing: Option<Timing>,
+    /// When the program started running (for the time CSR), if there's a
+    /// host clock
+    started: Option<Instant>,
+    /// The seed for deterministic runs, if one was given (see
+    /// [Emulator::set_seed])
+    seed: Option<u64>,
+    /// Instructions per second of virtual time, if time is virtual (see
+    /// [Emulator::set_virtual_time])
+    virtual_time: Option<u64>,
+    /// Caps on the resources the program can use
+    limits: Limits,
+    /// Bytes the program has written to its standard output and error
+    output_bytes: usize,
+    /// [metrics::Counters::cpu_time] as of when the program started
+    cpu_time_base: Duration,
+    /// State of the generator behind the getrandom syscall
+    rng: u64,
+    /// Current program break, i.e., the end of the heap
+    brk: usize,
+    /// Initial program break, just past the end of the loaded program
+    brk_start: usize,
+    /// Where the next anonymous mapping will go
+    mmap_top: usize,
+    /// Address of the HTIF tohost word, if the program has one
+    tohost: Option<usize>,
+    /// Exit code, once the program has exited
+    exit_code: Option<i32>,
+    /// Which pages of memory have been written since the last snapshot was
+    /// taken or restored (empty if there hasn't been one)
+    dirty: Vec<bool>,
+    /// Id of that snapshot
+    dirty_base: Option<u64>,
+    /// Recent checkpoints, if they're being taken (see
+    /// [Emulator::set_checkpoints])
+    checkpoints: Option<checkpoints::Checkpoints>,
+    /// Which pages of memory have been written since the last checkpoint
+    checkpoint_dirty: Vec<bool>,
+    /// Instruction count at which to take the next one
+    next_checkpoint: u64,
+    /// Where the program's standard input comes from
+    stdin: Box<dyn BufRead + Send>,
+    /// ...and where its standard output and error go
+    stdout: Box<dyn Write + Send>,
+    stderr: Box<dyn Write + Send>,
+    /// The program's arguments (including its name), if it has any
+    args: Vec<String>,
+    /// Registers to seed when the program starts, after the stack and so on
+    /// have been set up
+    initial_regs: Vec<(Reg, u32)>,
+    /// Running totals for [Emulator::metrics]
+    counters: metrics::Counters,
+    /// Host callbacks the guest can invoke, by number
+    hypercalls: HashMap<u32, Hypercall>,
+    /// Callback to yield to every so many instructions, if there is one
+    yielder: Option<(u64, YieldCallback)>,
+    /// Instruction count at which to yield to it next
+    next_yield: u64,
+    /// Shadow call stack: the return addresses of the calls in progress,
+    /// innermost last (kept only while tracing; see [Emulator::backtrace])
+    calls: Vec<usize>,
 }
 
 impl Emulator {
@@ -60,9 +339,52 @@ impl Emulator {
                     DEFAULT_MEMORY_SIZE
                 }
             ],
-            sections: HashMap::new(),
-            symtab: HashMap::new(),
+            sections: Vec::new(),
+            code: Vec::new(),
+            symtab: SymbolTable::new(),
+            functions: SymbolTable::new(),
+            lines: LineTable::new(),
             dev_null: 0x0,
+            strict: false,
+            mul_div: cfg!(feature = "rv32m"),
+            personality: Personality::All,
+            unknown_syscalls: UnknownSyscalls::Ignore,
+            misaligned: Misaligned::Emulate,
+            write_protect: true,
+            icache: Vec::new(),
+            icache_base: 0x0,
+            icache_stats: CacheStats::default(),
+            blocks: Vec::new(),
+            instret: 0,
+            cycles: 0,
+            timing: None,
+            started: now(),
+            virtual_time: None,
+            limits: Limits::default(),
+            output_bytes: 0,
+            cpu_time_base: Duration::ZERO,
+            seed: None,
+            rng: random_seed(),
+            brk: 0x0,
+            brk_start: 0x0,
+            mmap_top: 0x0,
+            tohost: None,
+            exit_code: None,
+            dirty: Vec::new(),
+            dirty_base: None,
+            checkpoints: None,
+            checkpoint_dirty: Vec::new(),
+            next_checkpoint: u64::MAX,
+            stdin: Box::new(io::BufReader::new(io::stdin())),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            args: Vec::new(),
+            initial_regs: Vec::new(),
+            counters: Default::default(),
+            hypercalls: HashMap::new(),
+            yielder: None,
+            next_yield: u64::MAX,
+            calls: Vec::new(),
         }
     }
 
@@ -81,18 +403,71 @@ impl Emulator {
     /// Loads a RISC-V program from the ELF file at `path` into `self`.
     /// Returns the unit type, or an [EmulatorError] if an error occurred
     /// (e.g., the file doesn't exist, isn't formatted correctly, etc.).
+    ///
+    /// If the program has a `tohost` symbol, it's taken to be the HTIF
+    /// tohost word used by riscv-tests and the architectural compliance
+    /// tests: storing an odd value `(code << 1) | 1` there exits the program
+    /// with the given code.
     pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), EmulatorError> {
         let mut file = File::open(path)?;
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
+        self.load_bytes(&buf)
+    }
 
-        let elf = Elf::parse(&buf)?;
+    /// Loads a RISC-V program from the ELF image in `buf` into `self`; see
+    /// [Emulator::load]. Malformed images are reported as an [EmulatorError]
+    /// rather than panicking, however hostile.
+    pub fn load_bytes(&mut self, buf: &[u8]) -> Result<(), EmulatorError> {
+        let elf = Elf::parse(buf)?;
+        // NB any snapshot taken is of some other program now
+        self.dirty_base = None;
+        // and the new one hasn't been started (see Emulator::call): nothing
+        // of the old one's execution carries over
+        self.icache = Vec::new();
+        self.blocks = Vec::new();
+        self.reg = [0; 32];
+        self.exit_code = None;
+        // nor do its sections, or the heap after them
+        self.sections.clear();
+        self.brk_start = 0;
+
+        // make sure the whole image fits before loading any of it
+        let segments = (elf.program_headers.iter().enumerate())
+            .filter(|(_, ph)| ph.p_type == PT_LOAD)
+            .map(|(i, ph)| (format!("segment {}", i), ph.vm_range()));
+        let sections = (elf.section_headers.iter())
+            .filter(|sh| sh.is_alloc())
+            .map(|sh| {
+                let name = elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("?");
+                (format!("section {}", name), sh.vm_range())
+            });
+        if let Some((what, range)) = segments.chain(sections).max_by_key(|(_, r)| r.end) {
+            if range.end > self.mem.len() {
+                return Err(EmulatorError::ImageTooLarge {
+                    what,
+                    start: range.start,
+                    end: range.end,
+                    available: self.mem.len(),
+                });
+            }
+        }
 
         // load allocatable sections
         for section in &elf.section_headers {
             if section.is_alloc() {
-                let name = elf.shdr_strtab.get_at(section.sh_name).unwrap().to_string();
+                let name = elf
+                    .shdr_strtab
+                    .get_at(section.sh_name)
+                    .ok_or_else(|| {
+                        goblin::error::Error::Malformed(format!(
+                            "bad section name offset: {}",
+                            section.sh_name
+                        ))
+                    })?
+                    .to_string();
                 log::debug!(
+                    target: "rvem::loader",
                     "found section: {}; address: 0x{:x}, length: {} bytes",
                     name,
                     section.sh_addr,
@@ -100,26 +475,96 @@ impl Emulator {
                 );
 
                 if let Some(range) = section.file_range() {
-                    self[section.vm_range()].copy_from_slice(&buf[range]);
-                    self.sections.insert(name, section.vm_range());
+                    let data = buf.get(range).ok_or_else(|| {
+                        goblin::error::Error::Malformed(format!(
+                            "section {} extends past the end of the file",
+                            name
+                        ))
+                    })?;
+                    let size = self.mem.len();
+                    self.mem
+                        .get_mut(section.vm_range())
+                        .ok_or_else(|| {
+                            EmulatorError::Execution(format!(
+                                "section {} ({:x}-{:x}) doesn't fit in memory ({} bytes)",
+                                name,
+                                section.vm_range().start,
+                                section.vm_range().end,
+                                size
+                            ))
+                        })?
+                        .copy_from_slice(data);
                 } // TODO if SHT_NOBITS initialize the memory (e.g., .tbss)
+                self.sections.push(Section {
+                    name,
+                    range: section.vm_range(),
+                    writable: section.is_writable(),
+                    executable: section.is_executable(),
+                    from_file: section.file_range().is_some(),
+                });
+
+                // the heap starts wherever the program (including BSS) ends
+                self.brk_start = self
+                    .brk_start
+                    .max(section.vm_range().end.next_multiple_of(8));
             }
         }
+        self.brk = self.brk_start;
+        self.sections.sort_by_key(|section| section.range.start);
+
+        // find the executable code
+        self.set_code(
+            elf.program_headers
+                .iter()
+                .filter(|ph| ph.p_type == PT_LOAD && ph.is_executable())
+                .map(|ph| ph.vm_range())
+                .collect(),
+        );
 
         // load the symbol table
+        let named = |sym: &goblin::elf::Sym| {
+            let name = elf.strtab.get_at(sym.st_name)?;
+            // NB skip mapping symbols ($x, $d, ...), which only mark code vs. data
+            (!name.is_empty() && !name.starts_with('$')).then_some((name, sym.st_value as usize))
+        };
+        let demangle = self.symtab.demangles();
+        self.symtab = elf.syms.iter().filter_map(|sym| named(&sym)).collect();
+        self.functions = elf
+            .syms
+            .iter()
+            .filter(|sym| sym.is_function())
+            .filter_map(|sym| named(&sym))
+            .collect();
         for sym in elf.syms.iter() {
-            if let Some(name) = elf.strtab.get_at(sym.st_name) {
-                if name.len() > 0 {
-                    self.symtab.insert(name.into(), sym.st_value as usize);
-                }
+            if let Some((name, _)) = named(&sym) {
+                let kind = match sym.st_type() {
+                    STT_NOTYPE => SymbolKind::NoType,
+                    STT_OBJECT => SymbolKind::Object,
+                    STT_FUNC => SymbolKind::Func,
+                    STT_SECTION => SymbolKind::Section,
+                    STT_FILE => SymbolKind::File,
+                    _ => SymbolKind::Other,
+                };
+                let size = sym.st_size as usize;
+                self.symtab.set_info(name, SymbolInfo { kind, size });
+                self.functions.set_info(name, SymbolInfo { kind, size });
             }
         }
+        self.set_demangle(demangle);
+
+        self.tohost = self.symtab.get(TOHOST_SYM);
+
+        // load source line information, if there is any
+        self.lines = LineTable::parse(&elf, buf).unwrap_or_else(|e| {
+            log::warn!(target: "rvem::loader", "error parsing DWARF line information: {}", e);
+            LineTable::new()
+        });
 
         // zero the Block Started by Symbol (BSS) region
         if let Some(bss_start) = self.symtab.get(BSS_START_SYM) {
             if let Some(bss_end) = self.symtab.get(BSS_END_SYM) {
-                for i in *bss_start..*bss_end {
-                    self[i] = 0u8;
+                if let Some(bss) = self.mem.get_mut(bss_start..bss_end) {
+                    bss.fill(0);
                 }
             }
         }
@@ -127,318 +572,2111 @@ impl Emulator {
         Ok(())
     }
 
-    /// Runs a loaded program, returning the unit type or an [EmulatorError].
-    pub fn run(&mut self) -> Result<(), EmulatorError> {
-        // TODO refactor the initialization code into an init() function?
-        // find the range for our executable code
-        let text_range = self
-            .sections
-            .get(".text")
-            .ok_or_else(|| EmulatorError::Execution("no .text section found".into()))?
-            .clone();
+    /// Runs a loaded program, returning its exit code or an [EmulatorError].
+    /// The program ends when it makes an exit syscall or returns from its
+    /// entrypoint (in which case a0 is the exit code).
+    /// If debug logging is enabled (for the `rvem::exec` target) each
+    /// instruction is logged as it executes (see [LogTrace]); otherwise the
+    /// program runs at full speed.
+    pub fn run(&mut self) -> Result<i32, EmulatorError> {
+        if log::log_enabled!(target: "rvem::exec", log::Level::Debug) {
+            self.run_with(&mut LogTrace)
+        } else {
+            self.run_with(&mut NoTrace)
+        }
+    }
+
+    /// Runs a loaded program, reporting on its execution to `sink`. Returns
+    /// the program's exit code or an [EmulatorError] ([EmulatorError::Halted]
+    /// if the sink asked for execution to stop).
+    pub fn run_with<T: TraceSink>(&mut self, sink: &mut T) -> Result<i32, EmulatorError> {
+        self.init()?;
+        self.resume_with(sink)
+    }
+
+    /// Sets up the registers, stack, and so on for a loaded program to start
+    /// running from its entrypoint.
+    fn init(&mut self) -> Result<(), EmulatorError> {
+        let code = self.code_range()?;
 
         // set the global pointer address
         if let Some(gp) = self.symtab.get(GLOBAL_POINTER_SYM) {
-            log::debug!("global pointer address: 0x{:x}", gp);
-            self[Reg::gp] = *gp as u32;
+            log::debug!(target: "rvem::loader", "global pointer address: 0x{:x}", gp);
+            self[Reg::gp] = gp as u32;
         } else {
-            log::warn!("global pointer address not found");
+            log::warn!(target: "rvem::loader", "global pointer address not found");
         }
 
         // determine where we should start executing code
         if let Some(pc) = self.symtab.get(ENTRYPOINT_SYM) {
-            log::debug!("program entrypoint: 0x{:x}", pc);
-            self.pc = *pc;
+            log::debug!(target: "rvem::loader", "program entrypoint: 0x{:x}", pc);
+            self.pc = pc;
         } else {
             log::warn!(
-                "program entrypoint {} not found; falling back to beginning of .text section: {:x}",
+                target: "rvem::loader",
+                "program entrypoint {} not found; falling back to beginning of executable code: {:x}",
                 ENTRYPOINT_SYM,
-                text_range.start
+                code.start
             );
-            self.pc = text_range.start;
+            self.pc = code.start;
         }
 
+        // returning from the entrypoint ends the program, as from a C main
+        self[Reg::ra] = self.return_address()? as u32;
+
         // stack pointer in the middle?
-        self[Reg::sp] = (self.mem.len() / 2) as u32;
+        self[Reg::sp] = self.stack_top() as u32;
+        // ...which leaves the top half of memory for anonymous mappings
+        self.mmap_top = self.stack_top();
+        if !self.args.is_empty() {
+            self.push_args()?;
+        }
+        for (reg, value) in self.initial_regs.clone() {
+            self[reg] = value;
+        }
 
-        while text_range.contains(&self.pc) {
-            if log::log_enabled!(log::Level::Trace) {
-                // dump registers
-                log::trace!("{self:?}");
-            }
+        // cache decoded instructions so tight loops don't re-decode every cycle
+        self.reset_icache();
 
-            let inst = self.curr()?;
+        self.calls.clear();
+        self.started = now();
+        self.output_bytes = 0;
+        self.cpu_time_base = self.counters.cpu_time;
+        self.reset_checkpoints();
+        Ok(())
+    }
 
-            if log::log_enabled!(log::Level::Debug) {
-                let word = self[self.pc];
-                log::debug!("{:x}: {:08x} {:.*}", self.pc, word, self.pc, inst);
-            }
+    /// Calls the guest function `func` (a symbol or address, as for
+    /// [Emulator::lookup]) with up to 8 `args` in a0-a7, runs it until it
+    /// returns, and returns what it left in a0.
+    ///
+    /// If the program hasn't been started, the stack and so on are set up as
+    /// [Emulator::run_with] would; otherwise the function runs on the
+    /// program's stack, below the current stack pointer. Either way, the
+    /// registers and PC are put back as they were afterwards (memory isn't),
+    /// so calls can be made before, after, or in the middle of a run.
+    ///
+    /// ```
+    /// # use rvem::Emulator;
+    /// let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+    /// assert_eq!(em.call("sum", &[4]).unwrap(), 1 + 2 + 3 + 4);
+    /// ```
+    pub fn call(&mut self, func: &str, args: &[u32]) -> Result<u32, EmulatorError> {
+        let addr = self.lookup(func)?;
+        if args.len() > 8 {
+            return Err(EmulatorError::Config(format!(
+                "too many arguments for {}: {} (at most 8)",
+                func,
+                args.len()
+            )));
+        }
+        if self.icache.is_empty() {
+            self.init()?;
+        }
 
-            inst.execute(self);
+        let (pc, reg, exit_code) = (self.pc, self.reg, self.exit_code.take());
+        let sentinel = self.return_address()?;
+        for (i, arg) in args.iter().enumerate() {
+            self.reg[Reg::a0 as usize + i] = *arg;
+        }
+        self[Reg::ra] = sentinel as u32;
+        self.pc = addr;
 
-            self.pc += 4;
+        let result = self.resume_with(&mut NoTrace);
+        let (a0, returned) = (self[Reg::a0], self.pc == sentinel);
+        (self.pc, self.reg, self.exit_code) = (pc, reg, exit_code);
+        match result {
+            Ok(_) if returned => Ok(a0),
+            Err(e) => Err(e),
+            Ok(code) => Err(EmulatorError::Execution(format!(
+                "program exited with code {} during call to {}",
+                code, func
+            ))),
         }
+    }
 
-        if text_range.contains(&self.pc) {
-            Ok(())
-        } else {
-            Err(EmulatorError::Execution(format!(
-                "program counter outside bounds of .text section: {:08x}",
-                self.pc
-            )))
+    /// Lays out the program's arguments at the top of the stack as Linux
+    /// does: argc, then argv, then an empty environment and auxiliary vector,
+    /// with the strings themselves above them.
+    fn push_args(&mut self) -> Result<(), EmulatorError> {
+        let overflow = || EmulatorError::Config("program arguments don't fit on the stack".into());
+        let mut sp = self.stack_top();
+        let mut words = vec![self.args.len() as u32];
+        for i in 0..self.args.len() {
+            let mut arg = self.args[i].clone().into_bytes();
+            arg.push(0);
+            sp = sp.checked_sub(arg.len()).ok_or_else(overflow)?;
+            self.bytes_mut(sp, arg.len())
+                .map_err(|_| overflow())?
+                .copy_from_slice(&arg);
+            words.push(sp as u32);
+        }
+        // NB argv, envp, and auxv (AT_NULL) terminators
+        words.extend([0, 0, 0, 0]);
+
+        sp = sp.checked_sub(4 * words.len()).ok_or_else(overflow)? & !15;
+        for (i, word) in words.iter().enumerate() {
+            self.write_u32(sp + 4 * i, *word).map_err(|_| overflow())?;
         }
+        self[Reg::sp] = sp as u32;
+        Ok(())
     }
 
-    /// Returns the current instruction - i.e., the instruction the program
-    /// counter is currently pointing at.
-    pub fn curr(&self) -> Result<Inst, EmulatorError> {
-        self.inst(self.pc)
+    /// Sets up an empty decoded instruction cache, covering the program's
+    /// executable code.
+    fn reset_icache(&mut self) {
+        let code = self.code_range().unwrap_or_default();
+        self.icache_base = code.start;
+        self.icache = vec![None; code.len() / 4];
+        self.blocks = vec![0; self.icache.len()];
     }
 
-    /// Returns the instruction at memory address `addr`.
-    pub fn inst(&self, addr: usize) -> Result<Inst, EmulatorError> {
-        let word: u32 = *bytemuck::from_bytes(&self[addr..addr + 4]);
-        Inst::try_from(word)
+    /// Continues running a program from wherever it left off - e.g., after a
+    /// sink halted it, or after restoring a [Snapshot] - reporting on its
+    /// execution to `sink`. Returns as [Emulator::run_with] does.
+    pub fn resume_with<T: TraceSink>(&mut self, sink: &mut T) -> Result<i32, EmulatorError> {
+        // NB the instruction count can't get anywhere near this - unless it
+        // was restored from a (deserialized) snapshot that says otherwise
+        let code = self.resume_until(sink, u64::MAX)?;
+        code.ok_or(EmulatorError::InstructionLimit {
+            limit: u64::MAX,
+            pc: self.pc,
+        })
     }
-}
 
-impl Default for Emulator {
-    fn default() -> Self {
-        Self {
-            pc: Default::default(),
-            reg: Default::default(),
-            mem: vec![0u8; DEFAULT_MEMORY_SIZE],
-            sections: Default::default(),
-            symtab: Default::default(),
-            dev_null: Default::default(),
+    /// Continues running a program until it's retired `until` instructions
+    /// in all, or ends. Returns its exit code if it ended, or `None` if it
+    /// didn't.
+    fn resume_until<T: TraceSink>(
+        &mut self,
+        sink: &mut T,
+        until: u64,
+    ) -> Result<Option<i32>, EmulatorError> {
+        let started = now();
+        let result = self.run_loop(sink, until);
+        if let Some(started) = started {
+            self.counters.cpu_time += started.elapsed();
+        }
+        if let Err(EmulatorError::MemoryFault { .. }) = result {
+            self.counters.memory_faults += 1;
         }
+        result
     }
-}
 
-impl Index<Reg> for Emulator {
-    type Output = u32;
+    /// Does the work of [Emulator::resume_with].
+    fn run_loop<T: TraceSink>(
+        &mut self,
+        sink: &mut T,
+        until: u64,
+    ) -> Result<Option<i32>, EmulatorError> {
+        // NB fail early if there's nothing to execute
+        self.code_range()?;
 
-    fn index(&self, index: Reg) -> &Self::Output {
-        if index == Reg::zero {
-            &0u32
+        // executing a block at a time skips the per-instruction reporting, so
+        // only do that when it won't be missed
+        let single_step = sink.enabled();
+
+        // NB checking the CPU time means asking the host clock, which is too
+        // slow to do every time round
+        let started = now().filter(|_| self.limits.cpu_time.is_some());
+        let mut check_at = match started {
+            Some(_) => self.instret + CPU_TIME_CHECK,
+            None => u64::MAX,
+        };
+
+        // NB a count restored from a snapshot may be there already
+        if self.instret >= until && self.exit_code.is_none() {
+            return Ok(None);
+        }
+        while self.exit_code.is_none() && self.code_containing(self.pc).is_some() {
+            if single_step {
+                self.step_with(sink)?;
+                if sink.halt() {
+                    return Err(EmulatorError::Halted(self.pc));
+                }
+            } else {
+                self.run_block()?;
+            }
+            if self.instret >= self.next_yield && self.yield_now() == Yield::Pause {
+                return Err(EmulatorError::Halted(self.pc));
+            }
+            if self.instret >= self.next_checkpoint {
+                self.checkpoint();
+            }
+            if self.instret >= until {
+                return Ok(None);
+            }
+            if let Some(started) = started.filter(|_| self.instret >= check_at) {
+                self.check_cpu_time(started)?;
+                check_at = self.instret + CPU_TIME_CHECK;
+            }
+        }
+
+        log::info!(target: "rvem::exec", "instruction cache: {:?}", self.icache_stats);
+        log::info!(target: "rvem::exec", "cycles: {}, instructions: {}", self.cycles, self.instret);
+
+        if self.exit_code.is_none() && self.pc == self.return_address()? {
+            log::debug!(target: "rvem::exec", "returned from {}", ENTRYPOINT_SYM);
+            self.exit_code = Some(self[Reg::a0] as i32);
+        }
+        if let Some(code) = self.exit_code {
+            Ok(Some(code))
         } else {
-            &self.reg[index as usize]
+            Err(EmulatorError::Execution(format!(
+                "program counter outside executable code: {:08x}",
+                self.pc
+            )))
         }
     }
-}
 
-impl IndexMut<Reg> for Emulator {
-    fn index_mut(&mut self, index: Reg) -> &mut Self::Output {
-        if index == Reg::zero {
-            self.dev_null = 0u32;
-            &mut self.dev_null
-        } else {
-            &mut self.reg[index as usize]
+    /// Sets the ranges of memory holding executable code (going by the
+    /// sections if there aren't any, as when the segments don't say).
+    fn set_code(&mut self, mut code: Vec<Range<usize>>) {
+        if code.is_empty() {
+            code = (self.sections.iter())
+                .filter(|section| section.executable)
+                .map(|section| section.range.clone())
+                .collect();
+        }
+        code.sort_by_key(|range| range.start);
+        self.code.clear();
+        for range in code {
+            let range = range.start.min(self.mem.len())..range.end.min(self.mem.len());
+            match self.code.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ if range.is_empty() => {}
+                _ => self.code.push(range),
+            }
         }
     }
-}
 
-impl Index<usize> for Emulator {
-    type Output = u8;
+    /// Returns the range of memory spanning all of the program's executable
+    /// code (which may have gaps in it).
+    fn code_range(&self) -> Result<Range<usize>, EmulatorError> {
+        match (self.code.first(), self.code.last()) {
+            (Some(first), Some(last)) => Ok(first.start..last.end),
+            _ => Err(EmulatorError::Execution("no executable code found".into())),
+        }
+    }
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.mem[index]
+    /// Returns the address that returning from the entrypoint (or from a
+    /// function run by [Emulator::call]) jumps to, which ends execution: the
+    /// first word past the program's executable code.
+    fn return_address(&self) -> Result<usize, EmulatorError> {
+        Ok(self.code_range()?.end.next_multiple_of(4))
     }
-}
 
-impl IndexMut<usize> for Emulator {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.mem[index]
+    /// Returns the range of executable code containing `addr`, if any.
+    fn code_containing(&self, addr: usize) -> Option<&Range<usize>> {
+        self.code.iter().find(|range| range.contains(&addr))
     }
-}
 
-impl Index<Range<usize>> for Emulator {
-    type Output = [u8];
+    /// Executes the instruction at the current PC, reporting it to `sink`.
+    fn step_with<T: TraceSink>(&mut self, sink: &mut T) -> Result<(), EmulatorError> {
+        let pc = self.pc;
+        let op = self.op(pc)?;
 
-    fn index(&self, index: Range<usize>) -> &Self::Output {
-        &self.mem[index]
-    }
-}
+        if !sink.enabled() {
+            self.retire(op, pc)?;
+            return Ok(());
+        }
 
-impl IndexMut<Range<usize>> for Emulator {
-    fn index_mut(&mut self, index: Range<usize>) -> &mut Self::Output {
-        &mut self.mem[index]
+        let word = self.read_u32(pc)?;
+        let inst = self.curr()?;
+        sink.before(self, pc, word, &inst);
+
+        let mem = trace::mem_access(self, &inst);
+        let a0 = self[Reg::a0];
+
+        self.retire(op, pc)?;
+
+        if callgraph::is_call(&inst) {
+            self.calls.push(pc + 4);
+        } else if let Some(i) = self.calls.iter().rposition(|ra| *ra == self.pc) {
+            // NB a return may unwind several frames at once, if some of
+            // the calls in between were really tail calls
+            self.calls.truncate(i);
+        }
+
+        let rd = match inst.dest() {
+            Some(Reg::zero) => None,
+            Some(rd) => Some((rd, self[rd])),
+            // syscalls report back via a0
+            None if matches!(inst, Inst::ECALL) && self[Reg::a0] != a0 => {
+                Some((Reg::a0, self[Reg::a0]))
+            }
+            None => None,
+        };
+        sink.retire(
+            self,
+            &Retired {
+                pc,
+                word,
+                inst,
+                rd,
+                mem,
+                next_pc: self.pc,
+            },
+        );
+        Ok(())
     }
-}
 
-impl std::fmt::Debug for Emulator {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // default behavior: dump PC and registers
-        write!(f, "PC: 0x{:x} ", self.pc)?;
-        for reg in Reg::iter() {
-            write!(f, " {}: 0x{:x}", reg, self[reg])?;
+    /// Executes the basic block starting at the current PC - i.e., straight-line
+    /// code up to and including the next control transfer - without going back
+    /// through the instruction cache for each instruction.
+    fn run_block(&mut self) -> Result<(), EmulatorError> {
+        let offset = self.pc.wrapping_sub(self.icache_base);
+        if !offset.is_multiple_of(4) || offset / 4 >= self.icache.len() {
+            return self.step_with(&mut NoTrace);
         }
 
-        // alternate behavior: also dump all sections in memory
-        if f.alternate() {
-            if let Some(range) = self.sections.get(".text") {
-                write!(f, "\n.text:")?;
-                let mut i = range.start;
-                while i < range.end {
-                    let word: u32 = *bytemuck::from_bytes(&self[i..i + 4]);
-                    let inst = Inst::try_from(word).unwrap();
-                    write!(f, "\n  {:x}: {:08x} {:.*}", i, word, i, inst)?;
+        let start = offset / 4;
+        let len = match self.blocks[start] {
+            0 => self.build_block(start)?,
+            len => len,
+        };
 
-                    i += 4;
-                }
-            }
-            for (name, range) in &self.sections {
-                if name != ".text" {
-                    write!(f, "\n{name}")?;
-                    let mut i = range.start;
-                    while i < range.end {
-                        write!(f, "\n  {:x}: {:02x}", i, self[i])?;
-                        i += 1;
-                    }
-                }
-            }
-            write!(f, "\nSymbols:")?;
-            let mut sorted: Vec<(&String, &usize)> = self.symtab.iter().collect();
-            sorted.sort_by(|a, b| a.1.cmp(b.1));
-            for (sym, addr) in sorted {
-                write!(f, "\n  {:08x}: {}", addr, sym)?;
+        for i in start..start + len {
+            // NB the block may have been invalidated out from under us by a
+            // store into executable memory; if so, bail and rebuild it
+            let Some(op) = self.icache[i] else {
+                break;
+            };
+            self.icache_stats.hits += 1;
+            self.retire(op, self.pc)?;
+            // NB a store to tohost can end the program partway through a
+            // block, as it would a traced run
+            if self.exit_code.is_some() {
+                break;
             }
         }
         Ok(())
     }
-}
-
-/// Errors encountered while loading or emulating a program.
-#[derive(Error, Debug)]
-pub enum EmulatorError {
-    #[error("{0}")]
-    IO(#[from] io::Error),
 
-    #[error("error parsing ELF data: {0}")]
-    ELF(#[from] goblin::error::Error),
+    /// Executes `op` (fetched from `pc`, the current PC), advances the PC, and
+    /// updates the performance counters.
+    #[inline(always)]
+    fn retire(&mut self, op: Op, pc: usize) -> Result<(), EmulatorError> {
+        op.execute(self)?;
+        self.pc = self.pc.wrapping_add(4);
+        self.instret += 1;
+        self.cycles += op.cost as u64;
+        if self.pc != pc.wrapping_add(4) {
+            if let Some(timing) = &self.timing {
+                self.cycles += timing.taken as u64;
+            }
+        }
+        Ok(())
+    }
 
-    #[error("program entrypoint could not be located")]
-    EntryPoint,
+    /// Decodes and caches the basic block starting at `start` (a slot in the
+    /// instruction cache), returning its length.
+    fn build_block(&mut self, start: usize) -> Result<usize, EmulatorError> {
+        // NB blocks don't run on past the end of the code they start in
+        let end = self
+            .code_containing(self.icache_base + 4 * start)
+            .map_or(0, |range| (range.end - self.icache_base) / 4);
+        let mut len = 0;
+        for i in start..end.min(self.icache.len()) {
+            let addr = self.icache_base + 4 * i;
+            let inst = match self.inst(addr) {
+                Ok(inst) => inst,
+                // only report the error if we'd execute it straight away;
+                // otherwise end the block and let it surface if it's reached
+                Err(e) if i == start => return Err(self.decode_error(addr, e)),
+                Err(_) => break,
+            };
+            if self.icache[i].is_none() {
+                self.icache[i] = Some(self.resolve(&inst));
+                self.icache_stats.misses += 1;
+            }
+            len += 1;
+            if inst.ends_block() {
+                break;
+            }
+        }
+        self.blocks[start] = len;
+        Ok(len)
+    }
 
-    #[error("instruction could not be decoded: {0}")]
-    InstructionDecode(String),
+    /// Returns the current instruction - i.e., the instruction the program
+    /// counter is currently pointing at.
+    pub fn curr(&self) -> Result<Inst, EmulatorError> {
+        self.inst(self.pc)
+    }
 
-    #[error("execution error: {0}")]
-    Execution(String),
-}
+    /// Returns the instruction at memory address `addr`.
+    pub fn inst(&self, addr: usize) -> Result<Inst, EmulatorError> {
+        self.decode(self.read_u32(addr)?)
+    }
 
-// rv32i
-impl Emulator {
-    fn nop(&mut self) {
-        log::warn!("nop called");
+    /// Executes `word` as though it were the instruction at the current PC
+    /// (without fetching anything from memory), advancing the PC and the
+    /// performance counters. Any instruction word - whatever it decodes to,
+    /// and whatever state the emulator is in - either executes or returns
+    /// an [EmulatorError]; none can panic, which makes this a suitable entry
+    /// point for fuzzing the interpreter.
+    pub fn execute_word(&mut self, word: u32) -> Result<(), EmulatorError> {
+        let op = self.resolve(&self.decode(word)?);
+        self.retire(op, self.pc)
     }
 
-    /* B-Type (branches) */
-    fn beq(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        if self[rs1] == self[rs2] {
-            self.pc = (self.pc as i32 + imm - 4) as usize; // NB subtract 4 since we're auto-incrementing
+    /// Executes `inst` as though it were the instruction at the current PC,
+    /// as [Emulator::execute_word] does, but without decoding anything, so
+    /// that tests of an instruction's semantics can set up registers and
+    /// memory, construct the instruction, execute it, and check the result
+    /// without having to assemble a program. Operands are used as given,
+    /// even ones too big for any encoding of the instruction.
+    ///
+    /// ```
+    /// # use rvem::{Emulator, Inst, Reg};
+    /// let mut em = Emulator::new(Some(0x1000));
+    /// em[Reg::a1] = 40;
+    /// em.execute_inst(Inst::ADDI { rd: Reg::a0, rs1: Reg::a1, imm: 2 }).unwrap();
+    /// assert_eq!((em[Reg::a0], em.pc()), (42, 4));
+    /// ```
+    pub fn execute_inst(&mut self, inst: Inst) -> Result<(), EmulatorError> {
+        if !self.mul_div && matches!(inst.class(), InstClass::Mul | InstClass::Div) {
+            return Err(EmulatorError::IllegalInstruction(format!(
+                "{:08x}: {} requires the M extension",
+                self.pc,
+                inst.mnemonic()
+            )));
         }
+        let op = self.resolve(&inst);
+        self.retire(op, self.pc)
     }
-    fn bne(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        if self[rs1] != self[rs2] {
-            self.pc = (self.pc as i32 + imm - 4) as usize; // NB subtract 4 since we're auto-incrementing
+
+    /// Decodes `word`, strictly or otherwise (see [Emulator::set_strict]).
+    fn decode(&self, word: u32) -> Result<Inst, EmulatorError> {
+        let inst = if self.strict {
+            Inst::decode_strict(word)
+        } else {
+            Inst::try_from(word)
+        }?;
+        if !self.mul_div && matches!(inst.class(), InstClass::Mul | InstClass::Div) {
+            return Err(EmulatorError::IllegalInstruction(format!(
+                "{:08x}: {} requires the M extension",
+                word,
+                inst.mnemonic()
+            )));
         }
+        Ok(inst)
     }
-    fn blt(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        if (self[rs1] as i32) < (self[rs2] as i32) {
-            self.pc = (self.pc as i32 + imm - 4) as usize; // NB subtract 4 since we're auto-incrementing
+
+    /// Returns the resolved instruction at `addr` from the decoded instruction
+    /// cache, decoding (and caching) it on a miss.
+    fn op(&mut self, addr: usize) -> Result<Op, EmulatorError> {
+        // NB jumps can't get here misaligned, but the PC can be set directly
+        if !addr.is_multiple_of(4) {
+            return Err(EmulatorError::MisalignedFetch { target: addr });
         }
+        let offset = addr.wrapping_sub(self.icache_base);
+        if offset.is_multiple_of(4) {
+            if let Some(slot) = self.icache.get(offset / 4) {
+                if let Some(op) = slot {
+                    self.icache_stats.hits += 1;
+                    return Ok(*op);
+                }
+                let inst = self.inst(addr).map_err(|e| self.decode_error(addr, e))?;
+                let op = self.resolve(&inst);
+                self.icache[offset / 4] = Some(op);
+                self.icache_stats.misses += 1;
+                return Ok(op);
+            }
+        }
+        // not cacheable
+        self.icache_stats.misses += 1;
+        let inst = self.inst(addr).map_err(|e| self.decode_error(addr, e))?;
+        Ok(self.resolve(&inst))
     }
-    fn bge(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        if (self[rs1] as i32) >= (self[rs2] as i32) {
-            self.pc = (self.pc as i32 + imm - 4) as usize; // NB subtract 4 since we're auto-incrementing
+
+    /// Adds where it happened to an error decoding the instruction at `pc`:
+    /// the function it's in, the instruction word, and the disassembly of
+    /// the instructions around it.
+    fn decode_error(&self, pc: usize, e: EmulatorError) -> EmulatorError {
+        let context = |msg: String| {
+            let mut msg = format!("{} at {:08x} <{}>", msg, pc, self.symtab.symbolize(pc));
+            for addr in (pc.saturating_sub(8)..=pc.saturating_add(8)).step_by(4) {
+                let Ok(word) = self.read_u32(addr) else {
+                    continue;
+                };
+                let marker = if addr == pc { '>' } else { ' ' };
+                msg.push_str(&match Inst::try_from(word) {
+                    Ok(inst) => {
+                        format!("\n{} {:08x}: {:08x} {}", marker, addr, word, inst.at(addr))
+                    }
+                    Err(_) => format!("\n{} {:08x}: {:08x} <unknown>", marker, addr, word),
+                });
+            }
+            msg
+        };
+        match e {
+            EmulatorError::InstructionDecode(msg) => EmulatorError::InstructionDecode(context(msg)),
+            EmulatorError::IllegalInstruction(msg) => {
+                EmulatorError::IllegalInstruction(context(msg))
+            }
+            e => e,
         }
     }
-    fn bltu(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        if self[rs1] < self[rs2] {
-            self.pc = (self.pc as i32 + imm - 4) as usize; // NB subtract 4 since we're auto-incrementing
+
+    /// Resolves `inst`, costing it according to the timing model.
+    fn resolve(&self, inst: &Inst) -> Op {
+        let mut op = inst.resolve();
+        if let Some(timing) = &self.timing {
+            op.cost = timing.latency(inst);
         }
+        op
     }
-    fn bgeu(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        if self[rs1] >= self[rs2] {
-            self.pc = (self.pc as i32 + imm - 4) as usize; // NB subtract 4 since we're auto-incrementing
+
+    /// Invalidates any cached instructions overlapped by a store of `len`
+    /// bytes at `addr`, so that self-modifying code behaves.
+    fn invalidate_icache(&mut self, addr: usize, len: usize) {
+        let end = self.icache_base + 4 * self.icache.len();
+        if addr + len > self.icache_base && addr < end {
+            let first = (addr.max(self.icache_base) - self.icache_base) / 4;
+            let last = ((addr + len).min(end) - self.icache_base - 1) / 4;
+            for slot in &mut self.icache[first..=last] {
+                if slot.take().is_some() {
+                    self.icache_stats.invalidations += 1;
+                }
+            }
+            // NB blocks can span the modified range from anywhere before it
+            self.blocks.fill(0);
         }
     }
 
-    /* I-Type */
+    /// Invalidates the entire decoded instruction cache.
+    fn flush_icache(&mut self) {
+        for slot in &mut self.icache {
+            if slot.take().is_some() {
+                self.icache_stats.invalidations += 1;
+            }
+        }
+        self.icache_stats.flushes += 1;
+        self.blocks.fill(0);
+    }
 
-    // integer operations
-    fn addi(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        self[rd] = ((self[rs1] as i32) + imm) as u32;
+    /// Returns the number of instructions retired so far.
+    pub fn instret(&self) -> u64 {
+        self.instret
     }
-    fn andi(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        self[rd] = self[rs1] & (imm as u32);
+
+    /// Returns the number of cycles elapsed so far; this is the same as
+    /// [Emulator::instret] unless a timing model is in use.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
     }
-    fn ori(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        self[rd] = self[rs1] | (imm as u32);
+
+    /// Sets the timing model used to count cycles (or `None` for one cycle
+    /// per instruction).
+    pub fn set_timing(&mut self, timing: Option<Timing>) {
+        self.timing = timing;
+        // NB costs are baked into cached instructions
+        self.icache.fill(None);
+        self.blocks.fill(0);
     }
-    fn slti(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        self[rd] = if (self[rs1] as i32) < imm { 1 } else { 0 };
+
+    /// Returns [Metrics] for the emulator so far, for monitoring
+    /// long-running emulations.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            instret: self.instret,
+            cycles: self.cycles,
+            syscalls: self.counters.syscalls.clone(),
+            memory_faults: self.counters.memory_faults,
+            cpu_time: self.counters.cpu_time,
+        }
     }
-    fn sltiu(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        self[rd] = if self[rs1] < (imm as u32) { 1 } else { 0 };
+
+    /// Returns the program's exit code, if it has exited.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
     }
-    fn xori(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        self[rd] = self[rs1] ^ (imm as u32)
+
+    /// Returns a structured [Dump] of the loaded program: registers,
+    /// sections, symbols, and the disassembly of `.text`.
+    pub fn dump(&self) -> Dump {
+        Dump::new(self)
     }
 
-    // loads
-    fn lb(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        let addr = ((self[rs1] as i32) + imm) as usize;
-        let val = self[addr] as u32;
-        self[rd] = sext(val, 8);
+    /// Compares the state of this emulator with `other`'s, returning the
+    /// registers and ranges of memory that differ (see [StateDiff]).
+    pub fn diff(&self, other: &Emulator) -> StateDiff {
+        StateDiff::new(self, other)
     }
-    fn lh(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        let addr = ((self[rs1] as i32) + imm) as usize;
-        let val = (self[addr] as u32) | ((self[addr + 1] as u32) << 8);
-        self[rd] = sext(val, 16);
+
+    /// Takes a [Snapshot] of the emulator's current state, and starts
+    /// keeping track of which pages of memory are written from here on so
+    /// that restoring it is cheap.
+    pub fn snapshot(&mut self) -> Snapshot {
+        let snapshot = Snapshot::new(self);
+        self.dirty = vec![false; self.mem.len().div_ceil(fuzz::PAGE_SIZE)];
+        self.dirty_base = Some(snapshot.id);
+        snapshot
     }
-    fn lw(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        let addr = ((self[rs1] as i32) + imm) as usize;
-        self[rd] = *bytemuck::from_bytes(&self[addr..addr + 4]);
+
+    /// Restores the emulator to the state captured in `snapshot`. If that's
+    /// the snapshot most recently taken or restored, only the pages of
+    /// memory written since are copied back; otherwise all of memory is
+    /// (along with the program's sections and symbols, so a snapshot can be
+    /// restored into a freshly-created emulator).
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        if self.dirty_base == Some(snapshot.id) && self.mem.len() == snapshot.mem.len() {
+            for page in 0..self.dirty.len() {
+                if self.dirty[page] {
+                    let start = page * fuzz::PAGE_SIZE;
+                    let end = (start + fuzz::PAGE_SIZE).min(self.mem.len());
+                    self.mem[start..end].copy_from_slice(&snapshot.mem[start..end]);
+                    self.invalidate_icache(start, end - start);
+                    self.dirty[page] = false;
+                }
+            }
+        } else {
+            self.mem.clone_from(&snapshot.mem);
+            self.sections.clone_from(&snapshot.sections);
+            self.set_code(snapshot.code.clone());
+            let demangle = self.symtab.demangles();
+            self.symtab.clone_from(&snapshot.symtab);
+            self.functions.clone_from(&snapshot.functions);
+            self.set_demangle(demangle);
+            self.brk_start = snapshot.brk_start;
+            self.tohost = snapshot.tohost;
+            self.reset_icache();
+            self.dirty = vec![false; self.mem.len().div_ceil(fuzz::PAGE_SIZE)];
+            self.dirty_base = Some(snapshot.id);
+        }
+        self.pc = snapshot.pc;
+        self.reg = snapshot.reg;
+        self.brk = snapshot.brk;
+        self.mmap_top = snapshot.mmap_top;
+        self.instret = snapshot.instret;
+        self.cycles = snapshot.cycles;
+        self.exit_code = snapshot.exit_code;
+        self.rng = snapshot.rng;
+        if let Some((every, _)) = self.yielder {
+            self.next_yield = self.instret + every;
+        }
+        self.calls.clear();
+        self.reset_checkpoints();
     }
-    fn lbu(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        let addr = ((self[rs1] as i32) + imm) as usize;
-        let val = self[addr] as u32;
-        self[rd] = val;
+
+    /// Returns a view of the `len` bytes of guest memory at `addr`, for
+    /// exchanging data with the guest without copying it a byte at a time;
+    /// e.g., `em.memory(em.addr_of("buf").unwrap(), 4096)`.
+    pub fn memory(&self, addr: usize, len: usize) -> Result<&[u8], EmulatorError> {
+        self.bytes(addr, len)
     }
-    fn lhu(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        let addr = ((self[rs1] as i32) + imm) as usize;
-        let val = (self[addr] as u32) | ((self[addr + 1] as u32) << 8);
-        self[rd] = val;
+
+    /// Mutable version of [Emulator::memory]. Unlike indexing the emulator
+    /// directly, writes through the view are safe even if they overwrite
+    /// the program's code.
+    pub fn memory_mut(&mut self, addr: usize, len: usize) -> Result<&mut [u8], EmulatorError> {
+        // NB check before invalidating, so a bad range can't get that far
+        self.bytes(addr, len)?;
+        if len > 0 {
+            self.invalidate_icache(addr, len);
+        }
+        self.bytes_mut(addr, len)
     }
 
-    // jump
-    fn jalr(&mut self, rd: Reg, rs1: Reg, imm: i32) {
-        let addr = ((self[rs1] as i32) + imm) as usize;
-        self[rd] = self.pc as u32 + 4;
-        self.pc = (addr - 4) as usize; // NB subtract 4 since we're auto-incrementing
+    /// Places the contents of the host file at `path` in guest memory at
+    /// `addr`, so the guest can get at it without any file syscalls, and
+    /// returns its length. The file shows up as a section named after its
+    /// path, which is read-only if `read_only` is set (see
+    /// [Emulator::set_write_protect]). It mustn't overlap any of the
+    /// program's sections (or another file); where else it goes - clear of
+    /// the heap and stack, presumably - is up to the caller.
+    ///
+    /// NB the contents are copied in, since guest memory is one host buffer
+    /// (rather than being backed by the file), so nothing the guest writes
+    /// goes back to the file.
+    ///
+    /// ```rust
+    /// use rvem::Emulator;
+    ///
+    /// let mut em = Emulator::load_from("tests/data/hello", None).unwrap();
+    /// let len = em.map_file(0x40000, "tests/data/mapped.txt", true).unwrap();
+    /// assert_eq!(em.memory(0x40000, len).unwrap(), b"mapped from a file\n");
+    /// assert_eq!(em.region_of(0x40000).to_string(), "tests/data/mapped.txt");
+    /// ```
+    pub fn map_file(
+        &mut self,
+        addr: usize,
+        path: impl AsRef<Path>,
+        read_only: bool,
+    ) -> Result<usize, EmulatorError> {
+        let path = path.as_ref();
+        let contents = fs::read(path)?;
+        let name = path.display().to_string();
+        let range = addr..addr + contents.len();
+        if range.end > self.mem.len() {
+            return Err(EmulatorError::ImageTooLarge {
+                what: name,
+                start: range.start,
+                end: range.end,
+                available: self.mem.len(),
+            });
+        }
+        if let Some(section) = (self.sections.iter())
+            .find(|section| section.range.start < range.end && range.start < section.range.end)
+        {
+            return Err(EmulatorError::Config(format!(
+                "{} ({:x}-{:x}) would overlap {}",
+                name, range.start, range.end, section.name
+            )));
+        }
+        self.memory_mut(addr, contents.len())?
+            .copy_from_slice(&contents);
+        let pos = (self.sections).partition_point(|section| section.range.start < addr);
+        self.sections.insert(
+            pos,
+            Section {
+                name,
+                range,
+                writable: !read_only,
+                executable: false,
+                from_file: true,
+            },
+        );
+        Ok(contents.len())
     }
 
-    /* J-Type */
-    fn jal(&mut self, rd: Reg, imm: i32) {
-        self[rd] = (self.pc + 4) as u32;
-        let addr = self.pc as i32 + imm - 4; // NB subtract 4 since we're auto-incrementing
-        self.pc = addr as usize;
+    /// Registers `f` as hypercall number `n`, to be invoked whenever the
+    /// guest makes a [HYPERCALL] syscall with `n` in a0 (replacing whatever
+    /// was registered as `n` before). Whatever `f` returns is passed back to
+    /// the guest in a0; an error stops execution.
+    ///
+    /// ```
+    /// # use rvem::{Emulator, Reg, HYPERCALL};
+    /// let mut em = Emulator::new(None);
+    /// em.register_hypercall(1, |_, args| Ok(args[0] + args[1]));
+    /// // what the guest would do
+    /// em[Reg::a7] = HYPERCALL;
+    /// (em[Reg::a0], em[Reg::a1], em[Reg::a2]) = (1, 20, 22);
+    /// em.execute_word(0x00000073).unwrap(); // ecall
+    /// assert_eq!(em[Reg::a0], 42);
+    /// ```
+    pub fn register_hypercall<F>(&mut self, n: u32, f: F)
+    where
+        F: FnMut(&mut Emulator, [u32; 6]) -> Result<u32, EmulatorError> + Send + 'static,
+    {
+        self.hypercalls.insert(n, Box::new(f));
     }
 
-    /* R-Type */
-    fn add(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1].wrapping_add(self[rs2]);
+    /// Has `f` called every `every` instructions (or so: at the end of the
+    /// basic block in which the count comes due) while the program runs, so
+    /// that an embedder running it on, say, a GUI's thread can get other
+    /// work done meanwhile, or stop it (by returning [Yield::Pause]).
+    ///
+    /// ```
+    /// # use rvem::{Emulator, EmulatorError, Yield};
+    /// let mut em = Emulator::load_from("tests/data/primes", None).unwrap();
+    /// em.set_stdout(std::io::sink());
+    /// let mut yields = 0;
+    /// em.set_yield(100, move |_| {
+    ///     yields += 1;
+    ///     if yields < 3 { Yield::Continue } else { Yield::Pause }
+    /// });
+    /// assert!(matches!(em.run(), Err(EmulatorError::Halted(_))));
+    /// assert!(em.instret() >= 300);
+    /// ```
+    pub fn set_yield<F>(&mut self, every: u64, f: F)
+    where
+        F: FnMut(&mut Emulator) -> Yield + Send + 'static,
+    {
+        let every = every.max(1);
+        self.next_yield = self.instret + every;
+        self.yielder = Some((every, Box::new(f)));
     }
-    fn and(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1] & self[rs2];
+
+    /// Stops yielding to the callback set with [Emulator::set_yield].
+    pub fn clear_yield(&mut self) {
+        self.yielder = None;
+        self.next_yield = u64::MAX;
     }
-    fn or(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1] | self[rs2];
+
+    /// Calls the yield callback, if there is one, and returns what it wants.
+    fn yield_now(&mut self) -> Yield {
+        // NB as with hypercalls, the callback can't stay in the emulator
+        // while it has it
+        let Some((every, mut f)) = self.yielder.take() else {
+            self.next_yield = u64::MAX;
+            return Yield::Continue;
+        };
+        let result = f(self);
+        // NB keep to multiples of `every` from when it was set, rather than
+        // drifting by however late each yield was
+        self.next_yield = self.next_yield.saturating_add(every);
+        if self.next_yield <= self.instret {
+            self.next_yield = self.instret + every;
+        }
+        // ...unless it set a replacement for itself
+        self.yielder.get_or_insert((every, f));
+        result
     }
-    fn sll(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1] << self[rs2];
+
+    /// Invokes the hypercall the guest asked for.
+    fn hypercall(&mut self) -> Result<(), EmulatorError> {
+        let n = self[Reg::a0];
+        log::trace!(target: "rvem::syscalls", "hypercall: {}", n);
+        // NB the callback gets the emulator, so it can't stay in it meanwhile
+        let Some(mut f) = self.hypercalls.remove(&n) else {
+            log::warn!(target: "rvem::syscalls", "unknown hypercall: {}", n);
+            self[Reg::a0] = -(ENOSYS as i32) as u32;
+            return Ok(());
+        };
+        let mut args = [0; 6];
+        for (i, arg) in args.iter_mut().enumerate() {
+            *arg = self.reg[Reg::a1 as usize + i];
+        }
+        let result = f(self, args);
+        // ...unless it registered a replacement for itself
+        self.hypercalls.entry(n).or_insert(f);
+        self[Reg::a0] = result?;
+        Ok(())
+    }
+
+    /// Sets where the program's standard input comes from (by default, the
+    /// host's standard input) - e.g., a fuzzer's input.
+    ///
+    /// If a read from it fails with [io::ErrorKind::WouldBlock], the syscall
+    /// doing the reading stops the program with [EmulatorError::IO] without
+    /// completing, leaving the PC at its ecall, so that it's made again when
+    /// the program is resumed (once there's input, presumably); see
+    /// `testing::Harness`, with the `testing` feature.
+    pub fn set_stdin<R: BufRead + Send + 'static>(&mut self, stdin: R) {
+        self.stdin = Box::new(stdin);
+    }
+
+    /// Sets the program's arguments, the first of which is conventionally
+    /// its name. If there are any, they're laid out on the stack (along with
+    /// argc and an empty environment) as Linux does when the program starts;
+    /// otherwise the stack starts out empty.
+    pub fn set_args<I, S>(&mut self, args: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+    }
+
+    /// Sets the register called `name` (see [Emulator::get_reg]) to `value`
+    /// when the program starts - after the stack and so on have been set up,
+    /// so even `sp` can be overridden - e.g., to call a routine with
+    /// particular arguments without writing a wrapper program for it.
+    ///
+    /// ```rust
+    /// use rvem::Emulator;
+    ///
+    /// let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+    /// em.set_initial_reg("s1", 7).unwrap();
+    /// assert_eq!(em.run().unwrap(), 55);
+    /// assert_eq!(em.get_reg("s1").unwrap(), 7);
+    /// ```
+    pub fn set_initial_reg(&mut self, name: &str, value: u32) -> Result<(), EmulatorError> {
+        let reg: Reg = name.parse().map_err(EmulatorError::Config)?;
+        self.initial_regs.retain(|(r, _)| *r != reg);
+        self.initial_regs.push((reg, value));
+        Ok(())
+    }
+
+    /// Makes runs reproducible: time (the time CSR, gettimeofday, and
+    /// clock_gettime) advances a microsecond per instruction retired instead
+    /// of following the host clock (unless [Emulator::set_virtual_time] says
+    /// otherwise), and the getrandom syscall returns a sequence of bytes
+    /// determined by `seed`.
+    /// (These are the only sources of nondeterminism the emulator has; with
+    /// a single hart, there's no scheduling to speak of.)
+    ///
+    /// ```rust
+    /// use rvem::{Emulator, Reg};
+    ///
+    /// let random = |seed| {
+    ///     let mut em = Emulator::load_from("tests/data/random", None).unwrap();
+    ///     em.set_seed(seed);
+    ///     em.set_stdout(std::io::sink());
+    ///     em.run().unwrap();
+    ///     em.memory(em.addr_of("buf").unwrap(), 8).unwrap().to_vec()
+    /// };
+    /// assert_eq!(random(1), random(1));
+    /// assert_ne!(random(1), random(2));
+    /// ```
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = seed;
+    }
+
+    /// Makes time virtual: the time CSR and the gettimeofday and
+    /// clock_gettime syscalls advance by a second for every `rate`
+    /// instructions retired, instead of following the host clock, so that
+    /// timed behavior is the same from one run (or machine) to the next.
+    /// Virtual wall-clock time starts at the Unix epoch. `None` puts time
+    /// back on the host clock (unless there's a seed; see
+    /// [Emulator::set_seed]).
+    ///
+    /// ```rust
+    /// use rvem::{Emulator, Reg};
+    ///
+    /// let mut em = Emulator::load_from("tests/data/random", None).unwrap();
+    /// em.set_virtual_time(Some(1000));
+    /// em.set_stdout(std::io::sink());
+    /// em.run().unwrap();
+    /// // 1000 instructions a second: a millisecond (1,000,000 ns) per instruction
+    /// assert_eq!(em.time_ns(), em.instret() * 1_000_000);
+    /// ```
+    pub fn set_virtual_time(&mut self, rate: Option<u64>) {
+        self.virtual_time = rate.map(|rate| rate.max(1));
+    }
+
+    /// Returns the nanoseconds elapsed since the program started, in
+    /// virtual time if time is virtual (see [Emulator::set_virtual_time]).
+    /// Without a host clock (or with a seed, but no rate given), an
+    /// instruction takes a microsecond.
+    pub fn time_ns(&self) -> u64 {
+        match (self.virtual_time, self.started, self.seed) {
+            (Some(rate), _, _) => (self.instret as u128 * 1_000_000_000 / rate as u128) as u64,
+            (None, Some(started), None) => started.elapsed().as_nanos() as u64,
+            _ => self.instret * 1000,
+        }
+    }
+
+    /// Returns the wall-clock time, in nanoseconds since the Unix epoch: the
+    /// host's, if time follows the host clock, or otherwise
+    /// [Emulator::time_ns].
+    fn wall_time_ns(&self) -> u64 {
+        match (self.virtual_time, self.started, self.seed) {
+            (None, Some(_), None) => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos() as u64),
+            _ => self.time_ns(),
+        }
+    }
+
+    /// Returns the next 64 bits from the getrandom generator (SplitMix64).
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Sets where the program's standard output goes (by default, the host's
+    /// standard output) - e.g., a buffer, in the browser.
+    pub fn set_stdout<W: Write + Send + 'static>(&mut self, stdout: W) {
+        self.stdout = Box::new(stdout);
+    }
+
+    /// Sets where the program's standard error goes (by default, the host's
+    /// standard error).
+    pub fn set_stderr<W: Write + Send + 'static>(&mut self, stderr: W) {
+        self.stderr = Box::new(stderr);
+    }
+
+    /// Writes the compliance test signature - memory from the
+    /// `begin_signature` symbol up to `end_signature` - to `out`, as hex
+    /// with `granularity` bytes per line (most significant first), in the
+    /// format expected by RISCOF.
+    pub fn write_signature<W: Write>(
+        &self,
+        mut out: W,
+        granularity: usize,
+    ) -> Result<(), EmulatorError> {
+        let (Some(begin), Some(end)) = (
+            self.symtab.get(BEGIN_SIGNATURE_SYM),
+            self.symtab.get(END_SIGNATURE_SYM),
+        ) else {
+            return Err(EmulatorError::Execution(format!(
+                "program has no {} and {} symbols",
+                BEGIN_SIGNATURE_SYM, END_SIGNATURE_SYM
+            )));
+        };
+        let signature = self.mem.get(begin..end).ok_or_else(|| {
+            EmulatorError::Execution(format!("bad signature range: {:x}-{:x}", begin, end))
+        })?;
+        for chunk in signature.chunks(granularity.max(1)) {
+            for byte in chunk.iter().rev() {
+                write!(out, "{:02x}", byte)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the program break, i.e., the current end of the heap.
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+
+    /// Returns the initial program break, i.e., where the heap starts.
+    pub fn brk_start(&self) -> usize {
+        self.brk_start
+    }
+
+    /// Returns the initial stack pointer, i.e., the top of the stack (which
+    /// grows down from there).
+    pub fn stack_top(&self) -> usize {
+        self.mem.len() / 2
+    }
+
+    /// Returns where the next anonymous mapping will go; mappings are made
+    /// upwards from [Emulator::stack_top].
+    pub fn mmap_top(&self) -> usize {
+        self.mmap_top
+    }
+
+    /// Returns the read-only section (if any) that the `len` bytes at `addr`
+    /// overlap.
+    fn read_only_section(&self, addr: usize, len: usize) -> Option<&Section> {
+        let end = addr + len;
+        let idx = self
+            .sections
+            .partition_point(|section| section.range.start < end);
+        self.sections[..idx]
+            .iter()
+            .rev()
+            .find(|section| section.range.end > addr && !section.writable)
+    }
+
+    /// Sets whether mangled C++ and Rust symbol names are demangled wherever
+    /// the emulator shows them (as they are by default; see [demangle]).
+    pub fn set_demangle(&mut self, demangle: bool) {
+        self.symtab.set_demangle(demangle);
+        self.functions.set_demangle(demangle);
+    }
+
+    /// Returns the program's symbol table.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symtab
+    }
+
+    /// Returns the address of the symbol `name`, if the program has one.
+    pub fn addr_of(&self, name: &str) -> Option<usize> {
+        self.symtab.get(name)
+    }
+
+    /// Resolves `s` - the name of a symbol, a hex (`0x`-prefixed) or
+    /// decimal address, or (for a program with source line information) a
+    /// `file:line` - to an address.
+    pub fn lookup(&self, s: &str) -> Result<usize, EmulatorError> {
+        if let Some(addr) = self.symtab.get(s) {
+            return Ok(addr);
+        }
+        if let Some((file, line)) = s.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return self
+                    .lines
+                    .addr_of(file, line)
+                    .ok_or_else(|| EmulatorError::Config(format!("no code at or after {}", s)));
+            }
+        }
+        let addr = match s.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        addr.map_err(|_| EmulatorError::Config(format!("unknown symbol or bad address: {}", s)))
+    }
+
+    /// Returns the name of the symbol at exactly `addr`, if there is one
+    /// (the first alphabetically, if there are several).
+    pub fn symbol_at(&self, addr: usize) -> Option<&str> {
+        match self.symtab.lookup(addr) {
+            Some((name, 0)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the function containing `addr`, which must be in
+    /// executable code. If the program's symbol table says which symbols are
+    /// functions (as a compiler's would), that's the function at or below
+    /// `addr`, as long as `addr` is within its size (if known; see
+    /// [SymbolTable::lookup_function]); otherwise it's the nearest symbol of
+    /// any kind, which for hand-written assembly may well be a label within
+    /// a function.
+    pub fn function_containing(&self, addr: usize) -> Option<&str> {
+        let code = self.code_containing(addr)?;
+        let symtab = if self.functions.is_empty() {
+            &self.symtab
+        } else {
+            &self.functions
+        };
+        match symtab.lookup_function(addr) {
+            Some((name, offset)) if addr - offset >= code.start => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the guest's call stack, innermost first: the program counter,
+    /// followed by the return address of each call in progress.
+    ///
+    /// While a [TraceSink] is tracing execution, calls and returns are
+    /// tracked as they happen, so the stack is exact. Otherwise it's
+    /// reconstructed from the registers and memory: the frame pointer chain
+    /// (`ra` at `fp-4`, the caller's `fp` at `fp-8`, as compilers lay frames
+    /// out) if `fp` points into the stack, or else `ra` and any words on the
+    /// stack that look like return addresses (i.e., that follow a call),
+    /// which may include some stale ones.
+    pub fn backtrace(&self) -> Vec<usize> {
+        let mut frames = vec![self.pc];
+        if !self.calls.is_empty() {
+            frames.extend(self.calls.iter().rev());
+            return frames;
+        }
+
+        let sp = self[Reg::sp] as usize;
+        let stack = sp..self.stack_top();
+        let push = |frames: &mut Vec<usize>, addr: usize| {
+            if self.is_return_address(addr) && frames.last() != Some(&addr) {
+                frames.push(addr);
+            }
+        };
+        push(&mut frames, self[Reg::ra] as usize);
+
+        let mut fp = self[Reg::fp] as usize;
+        let mut chained = false;
+        while fp > stack.start && fp <= stack.end && fp.is_multiple_of(4) {
+            let (Ok(ra), Ok(next)) = (self.read_u32(fp - 4), self.read_u32(fp - 8)) else {
+                break;
+            };
+            if !self.is_return_address(ra as usize) {
+                break;
+            }
+            push(&mut frames, ra as usize);
+            chained = true;
+            if next as usize <= fp {
+                break;
+            }
+            fp = next as usize;
+        }
+        if !chained {
+            for addr in stack.step_by(4) {
+                if let Ok(word) = self.read_u32(addr) {
+                    push(&mut frames, word as usize);
+                }
+            }
+        }
+        frames
+    }
+
+    /// Returns the words of memory around the stack pointer, as (address,
+    /// word) pairs, for dumps: a few below it (where the next push would
+    /// go), and those above it up to the top of the stack, or as many as
+    /// are interesting. Empty if the stack hasn't been set up.
+    pub(crate) fn stack_window(&self) -> Vec<(usize, u32)> {
+        let sp = self[Reg::sp] as usize;
+        if sp == 0 || sp > self.mem.len() {
+            return Vec::new();
+        }
+        let start = sp.saturating_sub(4 * STACK_BELOW) & !3;
+        let end = sp
+            .saturating_add(4 * STACK_ABOVE)
+            .min(self.stack_top().max(sp));
+        (start..end)
+            .step_by(4)
+            .map_while(|addr| Some((addr, self.read_u32(addr).ok()?)))
+            .collect()
+    }
+
+    /// Returns whether `addr` could be a return address: one that follows
+    /// a call.
+    fn is_return_address(&self, addr: usize) -> bool {
+        addr.is_multiple_of(4)
+            && self.code_containing(addr.wrapping_sub(4)).is_some()
+            && self
+                .inst(addr - 4)
+                .is_ok_and(|inst| callgraph::is_call(&inst))
+    }
+
+    /// Returns the program's allocatable sections (loaded from the program
+    /// file or otherwise), in order of address.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Returns the section called `name`, if the program has one.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Returns a listing of the contents of the section called `name`:
+    /// disassembled if `disassemble` is set, or in hex otherwise (the same
+    /// as in the alternate [std::fmt::Debug] form of the emulator, but for
+    /// just the one section).
+    pub fn dump_section(&self, name: &str, disassemble: bool) -> Result<String, EmulatorError> {
+        let section = self
+            .section(name)
+            .ok_or_else(|| EmulatorError::Config(format!("no such section: {}", name)))?;
+        let mut listing = format!("{}:", section.name);
+        // NB writing to a String can't fail
+        let _ = self.fmt_section(&mut listing, section, disassemble);
+        listing.push('\n');
+        Ok(listing)
+    }
+
+    /// Returns the strings of at least `min_len` printable characters
+    /// (including tabs and newlines), terminated by a NUL (or the end of the
+    /// section, as with `.ascii`), in the program's data - i.e., the
+    /// sections loaded from the file that aren't executable, such as
+    /// `.rodata` and `.data` - along with their addresses, in order of
+    /// address.
+    pub fn strings(&self, min_len: usize) -> Vec<(usize, String)> {
+        let mut strings = Vec::new();
+        for section in &self.sections {
+            if !section.from_file || section.executable {
+                continue;
+            }
+            let Range { start: first, end } = section.range;
+            let mut start = first;
+            for addr in first..=end {
+                match self.mem.get(addr).filter(|_| addr < end) {
+                    Some(b'\t' | b'\n' | b' '..=b'~') => continue,
+                    Some(0) | None if addr - start >= min_len.max(1) => strings.push((
+                        start,
+                        String::from_utf8_lossy(&self[start..addr]).into_owned(),
+                    )),
+                    _ => (),
+                }
+                start = addr + 1;
+            }
+        }
+        strings
+    }
+
+    /// Returns the region of memory `addr` is in: one of the program's
+    /// sections, the heap, the stack, an anonymous mapping, or none of
+    /// these.
+    ///
+    /// ```rust
+    /// use rvem::{Emulator, Region};
+    ///
+    /// let em = Emulator::load_from("tests/data/fac", None).unwrap();
+    /// let start = em.addr_of("_start").unwrap();
+    /// assert_eq!(em.region_of(start).to_string(), ".text");
+    /// assert_eq!(em.region_of(0), Region::Unmapped);
+    /// ```
+    pub fn region_of(&self, addr: usize) -> Region<'_> {
+        if let Some(section) = self
+            .sections
+            .iter()
+            .find(|section| section.range.contains(&addr))
+        {
+            Region::Section(section)
+        } else if (self.brk_start..self.brk).contains(&addr) {
+            Region::Heap
+        } else if (self[Reg::sp] as usize..self.stack_top()).contains(&addr)
+            && self[Reg::sp] as usize >= self.brk
+        {
+            Region::Stack
+        } else if (self.stack_top()..self.mmap_top).contains(&addr) {
+            Region::Mmap
+        } else {
+            Region::Unmapped
+        }
+    }
+
+    /// Returns the program's source line information.
+    pub fn lines(&self) -> &LineTable {
+        &self.lines
+    }
+
+    /// Returns the program counter, i.e., the address of the next
+    /// instruction to be executed.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Sets the program counter, so that execution continues from `pc`
+    /// (e.g., with [Emulator::resume_with]).
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// Returns the value of the register called `name` (by its ABI name,
+    /// e.g., `a0`, or its architectural name, e.g., `x10`).
+    ///
+    /// ```rust
+    /// use rvem::Emulator;
+    ///
+    /// let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+    /// em.run().unwrap();
+    /// assert_eq!(em.get_reg("a0").unwrap(), 120); // 5!
+    /// assert_eq!(em.get_reg("x10").unwrap(), 120);
+    /// assert!(em.get_reg("a8").is_err());
+    /// ```
+    pub fn get_reg(&self, name: &str) -> Result<u32, EmulatorError> {
+        let reg: Reg = name.parse().map_err(EmulatorError::Config)?;
+        Ok(self[reg])
+    }
+
+    /// Sets the register called `name` (see [Emulator::get_reg]) to `value`.
+    /// Writes to `zero` are ignored, as they would be by an instruction.
+    pub fn set_reg(&mut self, name: &str, value: u32) -> Result<(), EmulatorError> {
+        let reg: Reg = name.parse().map_err(EmulatorError::Config)?;
+        self[reg] = value;
+        Ok(())
+    }
+
+    /// Returns the values of all 32 registers, indexed by register number.
+    pub fn registers(&self) -> [u32; 32] {
+        self.reg
+    }
+
+    /// Sets all 32 registers from `values`, indexed by register number (the
+    /// value for `zero` is ignored).
+    pub fn set_registers(&mut self, values: [u32; 32]) {
+        self.reg = values;
+        self.reg[0] = 0;
+    }
+
+    /// Returns statistics for the decoded instruction cache.
+    pub fn icache_stats(&self) -> CacheStats {
+        self.icache_stats
+    }
+
+    /// Returns the `len` bytes of memory at `addr`, or an
+    /// [EmulatorError::MemoryFault] if any of them are out of bounds.
+    fn bytes(&self, addr: usize, len: usize) -> Result<&[u8], EmulatorError> {
+        addr.checked_add(len)
+            .and_then(|end| self.mem.get(addr..end))
+            .ok_or(EmulatorError::MemoryFault {
+                addr,
+                len,
+                access: None,
+            })
+    }
+
+    /// Mutable version of [Emulator::bytes].
+    fn bytes_mut(&mut self, addr: usize, len: usize) -> Result<&mut [u8], EmulatorError> {
+        let end = addr
+            .checked_add(len)
+            .filter(|end| *end <= self.mem.len())
+            .ok_or(EmulatorError::MemoryFault {
+                addr,
+                len,
+                access: None,
+            })?;
+        self.touch(addr..end);
+        Ok(&mut self.mem[addr..end])
+    }
+
+    /// Marks the pages of memory overlapping `range` as dirty (if a snapshot
+    /// has been taken, or checkpoints are; see [Emulator::restore] and
+    /// [Emulator::set_checkpoints]).
+    #[inline(always)]
+    fn touch(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let first = range.start / fuzz::PAGE_SIZE;
+        let last = (range.end - 1) / fuzz::PAGE_SIZE;
+        for dirty in [&mut self.dirty, &mut self.checkpoint_dirty] {
+            if !dirty.is_empty() {
+                for page in first..=last.min(dirty.len() - 1) {
+                    dirty[page] = true;
+                }
+            }
+        }
+    }
+
+    /// Reads `N` bytes from memory at `addr`.
+    fn read<const N: usize>(&self, addr: usize) -> Result<[u8; N], EmulatorError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.bytes(addr, N)?);
+        Ok(bytes)
+    }
+
+    /// Reads a byte from memory at `addr`.
+    fn read_u8(&self, addr: usize) -> Result<u8, EmulatorError> {
+        Ok(self.read::<1>(addr)?[0])
+    }
+
+    /// Reads a little-endian halfword from memory at `addr`, which needn't be
+    /// aligned.
+    fn read_u16(&self, addr: usize) -> Result<u16, EmulatorError> {
+        Ok(u16::from_le_bytes(self.read(addr)?))
+    }
+
+    /// Reads a little-endian word from memory at `addr`, which needn't be
+    /// aligned.
+    fn read_u32(&self, addr: usize) -> Result<u32, EmulatorError> {
+        Ok(u32::from_le_bytes(self.read(addr)?))
+    }
+
+    /// Writes a byte to memory at `addr`.
+    fn write_u8(&mut self, addr: usize, value: u8) -> Result<(), EmulatorError> {
+        self.bytes_mut(addr, 1)?[0] = value;
+        Ok(())
+    }
+
+    /// Writes a little-endian halfword to memory at `addr`, which needn't be
+    /// aligned.
+    fn write_u16(&mut self, addr: usize, value: u16) -> Result<(), EmulatorError> {
+        self.bytes_mut(addr, 2)?
+            .copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    /// Writes a little-endian word to memory at `addr`, which needn't be
+    /// aligned.
+    fn write_u32(&mut self, addr: usize, value: u32) -> Result<(), EmulatorError> {
+        self.bytes_mut(addr, 4)?
+            .copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    /// Returns the effective address of a jump: `rs1` plus the
+    /// (sign-extended) offset `imm`, wrapping around the address space.
+    fn effective_addr(&self, rs1: Reg, imm: i32) -> usize {
+        self[rs1].wrapping_add(imm as u32) as usize
+    }
+
+    /// Returns the effective address of a `len`-byte load or store, as for
+    /// [Emulator::effective_addr], unless it's misaligned and misaligned
+    /// accesses trap, or it's out of bounds - including when it wraps
+    /// around the address space, which (with a negative offset from a small
+    /// base) is a bug far more often than it's intended.
+    fn load_store_addr(&self, rs1: Reg, imm: i32, len: usize) -> Result<usize, EmulatorError> {
+        let addr = self.effective_addr(rs1, imm);
+        if self.misaligned == Misaligned::Trap && !addr.is_multiple_of(len) {
+            return Err(EmulatorError::MisalignedAccess { addr, len });
+        }
+        let access = LoadStore {
+            pc: self.pc,
+            base: self[rs1],
+            imm,
+        };
+        if access.wrapped() || addr.checked_add(len).is_none_or(|end| end > self.mem.len()) {
+            return Err(EmulatorError::MemoryFault {
+                addr,
+                len,
+                access: Some(access),
+            });
+        }
+        Ok(addr)
+    }
+
+    /// Returns the address that a store of `len` bytes through `rs1` (plus
+    /// `imm`) writes to, as [Emulator::load_store_addr] does, unless it's
+    /// (partly) in a read-only section and writes to those are off limits.
+    fn store_addr(&self, rs1: Reg, imm: i32, len: usize) -> Result<usize, EmulatorError> {
+        let addr = self.load_store_addr(rs1, imm, len)?;
+        if self.write_protect {
+            if let Some(section) = self.read_only_section(addr, len) {
+                return Err(EmulatorError::ReadOnlyWrite {
+                    addr,
+                    len,
+                    section: section.name.clone(),
+                    pc: self.pc,
+                });
+            }
+        }
+        Ok(addr)
+    }
+
+    /// Enables or disables strict decoding; when enabled, reserved encodings,
+    /// nonzero fields that must be zero, and hint encodings are reported as
+    /// illegal instructions rather than silently accepted.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Sets the instruction set to emulate from an ISA string such as
+    /// `rv32im` or `rv32i_zicsr`: the base integer instruction set, and
+    /// optionally the M extension (if rvem was built with it) and the
+    /// Zicsr, Zicntr, and Zifencei extensions (which are always there).
+    /// Instructions from extensions left out are illegal.
+    pub fn set_isa(&mut self, isa: &str) -> Result<(), EmulatorError> {
+        let unsupported =
+            |what: &str| EmulatorError::Config(format!("unsupported ISA {}: {}", isa, what));
+        let lower = isa.to_ascii_lowercase();
+        let mut parts = lower.split('_');
+        let base = parts.next().unwrap_or_default();
+        let Some(letters) = base.strip_prefix("rv32i") else {
+            return Err(unsupported("must start with rv32i"));
+        };
+        let mut mul_div = false;
+        for letter in letters.chars() {
+            match letter {
+                'm' if cfg!(feature = "rv32m") => mul_div = true,
+                _ => return Err(unsupported(&format!("no {} extension", letter))),
+            }
+        }
+        for ext in parts {
+            if !matches!(ext, "zicsr" | "zicntr" | "zifencei") {
+                return Err(unsupported(&format!("no {} extension", ext)));
+            }
+        }
+        self.mul_div = mul_div;
+        // NB instructions already decoded may not be legal anymore
+        self.icache.fill(None);
+        self.blocks.fill(0);
+        Ok(())
+    }
+
+    /// Sets what happens when a halfword or word load or store isn't
+    /// naturally aligned: by default it's carried out anyway, byte by byte,
+    /// but it can raise [EmulatorError::MisalignedAccess] instead.
+    pub fn set_misaligned(&mut self, misaligned: Misaligned) {
+        self.misaligned = misaligned;
+    }
+
+    /// Sets whether guest stores to the program's read-only sections (.text,
+    /// .rodata, and so on) raise [EmulatorError::ReadOnlyWrite], as they do
+    /// by default; turn it off for programs that modify their own code.
+    /// (The host can write anywhere regardless.)
+    pub fn set_write_protect(&mut self, write_protect: bool) {
+        self.write_protect = write_protect;
+    }
+
+    /// Caps the resources the program can use - memory from brk and mmap,
+    /// bytes of output, and host CPU time - so that untrusted programs can
+    /// be run safely; going over a limit stops the program with
+    /// [EmulatorError::LimitExceeded]. Usage counts from when the program
+    /// starts.
+    ///
+    /// ```rust
+    /// use rvem::{Emulator, EmulatorError, Limit, Limits};
+    ///
+    /// let mut em = Emulator::load_from("tests/data/hello", None).unwrap();
+    /// em.set_stdout(std::io::sink());
+    /// em.set_limits(Limits {
+    ///     output: Some(4),
+    ///     ..Default::default()
+    /// });
+    /// assert!(matches!(
+    ///     em.run(),
+    ///     Err(EmulatorError::LimitExceeded(Limit::Output(4)))
+    /// ));
+    /// ```
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Returns the limits set with [Emulator::set_limits].
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Checks a new program break and top of the anonymous mappings against
+    /// the heap limit.
+    fn check_heap(&self, brk: usize, mmap_top: usize) -> Result<(), EmulatorError> {
+        let Some(max) = self.limits.heap else {
+            return Ok(());
+        };
+        let used = brk.saturating_sub(self.brk_start) + mmap_top.saturating_sub(self.stack_top());
+        if used > max {
+            return Err(EmulatorError::LimitExceeded(Limit::Heap(max)));
+        }
+        Ok(())
+    }
+
+    /// Counts `len` more bytes of output against the output limit.
+    fn count_output(&mut self, len: usize) -> Result<(), EmulatorError> {
+        self.output_bytes += len;
+        match self.limits.output {
+            Some(max) if self.output_bytes > max => {
+                Err(EmulatorError::LimitExceeded(Limit::Output(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the host time spent running the program - before this run
+    /// (since it started) and during it (since `started`) - against the CPU
+    /// time limit.
+    fn check_cpu_time(&self, started: Instant) -> Result<(), EmulatorError> {
+        let Some(max) = self.limits.cpu_time else {
+            return Ok(());
+        };
+        let used = self.counters.cpu_time.saturating_sub(self.cpu_time_base) + started.elapsed();
+        if used > max {
+            return Err(EmulatorError::LimitExceeded(Limit::CpuTime(max)));
+        }
+        Ok(())
+    }
+
+    /// Sets which syscalls the emulator provides; others are treated as
+    /// unknown.
+    pub fn set_personality(&mut self, personality: Personality) {
+        self.personality = personality;
+    }
+
+    /// Sets what happens when the program makes a syscall the emulator
+    /// doesn't provide: by default it's logged and ignored, but it can
+    /// return -ENOSYS (which is what Linux does) or stop execution with
+    /// [EmulatorError::UnknownSyscall].
+    pub fn set_unknown_syscalls(&mut self, unknown_syscalls: UnknownSyscalls) {
+        self.unknown_syscalls = unknown_syscalls;
+    }
+
+    /// Sets the address of the HTIF tohost word (or `None` for there not to
+    /// be one), overriding the program's tohost symbol.
+    pub fn set_tohost(&mut self, tohost: Option<usize>) {
+        self.tohost = tohost;
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new(None)
+    }
+}
+
+impl Index<Reg> for Emulator {
+    type Output = u32;
+
+    fn index(&self, index: Reg) -> &Self::Output {
+        if index == Reg::zero {
+            &0u32
+        } else {
+            &self.reg[index as usize]
+        }
+    }
+}
+
+impl IndexMut<Reg> for Emulator {
+    fn index_mut(&mut self, index: Reg) -> &mut Self::Output {
+        if index == Reg::zero {
+            self.dev_null = 0u32;
+            &mut self.dev_null
+        } else {
+            &mut self.reg[index as usize]
+        }
+    }
+}
+
+impl Index<usize> for Emulator {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.mem[index]
+    }
+}
+
+impl IndexMut<usize> for Emulator {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.touch(index..index + 1);
+        self.invalidate_icache(index, 1);
+        &mut self.mem[index]
+    }
+}
+
+impl Index<Range<usize>> for Emulator {
+    type Output = [u8];
+
+    fn index(&self, index: Range<usize>) -> &Self::Output {
+        &self.mem[index]
+    }
+}
+
+impl IndexMut<Range<usize>> for Emulator {
+    fn index_mut(&mut self, index: Range<usize>) -> &mut Self::Output {
+        self.touch(index.clone());
+        if !index.is_empty() {
+            self.invalidate_icache(index.start, index.len());
+        }
+        &mut self.mem[index]
+    }
+}
+
+impl Emulator {
+    /// Writes the contents of `section`, one line per instruction (with a
+    /// `symbol:` line for each symbol, as a label, and a `# file:line`
+    /// comment where the program has source line information) if
+    /// `disassemble` is set,
+    /// or otherwise as a hexdump: 16 bytes per row, followed by the same as
+    /// ASCII, with repeated rows collapsed into a `*`.
+    fn fmt_section(
+        &self,
+        f: &mut dyn std::fmt::Write,
+        section: &Section,
+        disassemble: bool,
+    ) -> std::fmt::Result {
+        let range = section.range.clone();
+        if disassemble {
+            // labels go before the instructions at (or, for any that aren't
+            // aligned, just after) their addresses, as in assembly source
+            let mut labels = (self.symtab.iter())
+                .skip_while(|(_, addr)| *addr < section.range.start)
+                .peekable();
+            for i in range.step_by(4) {
+                while let Some((name, _)) = labels.next_if(|(_, addr)| *addr <= i) {
+                    write!(f, "\n{}:", name)?;
+                }
+                let Ok(word) = self.read_u32(i) else {
+                    break;
+                };
+                match Inst::try_from(word) {
+                    Ok(inst) => write!(f, "\n  {:x}: {:08x} {}", i, word, inst.at(i))?,
+                    Err(_) => write!(f, "\n  {:x}: {:08x} <unknown>", i, word)?,
+                }
+                if let Some((file, line)) = self.lines.lookup(i) {
+                    write!(f, "  # {}:{}", file, line)?;
+                }
+            }
+        } else {
+            // NB a run of rows just like the one before is shown as a *,
+            // except for the last row, so it's clear where the section ends
+            let rows: Vec<(usize, &[u8])> = (range.clone().step_by(16))
+                .zip(self[range].chunks(16))
+                .collect();
+            for (i, (addr, row)) in rows.iter().enumerate() {
+                let repeat = i > 0 && rows[i - 1].1 == *row;
+                if repeat && i + 1 < rows.len() {
+                    if i < 2 || rows[i - 2].1 != rows[i - 1].1 {
+                        write!(f, "\n  *")?;
+                    }
+                    continue;
+                }
+                use std::fmt::Write as _;
+                let mut hex = String::with_capacity(49);
+                for (j, b) in row.iter().enumerate() {
+                    let _ = write!(hex, "{}{:02x}", if j == 8 { "  " } else { " " }, b);
+                }
+                let ascii: String = (row.iter())
+                    .map(|b| {
+                        if b.is_ascii_graphic() || *b == b' ' {
+                            *b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                write!(f, "\n  {:08x} {:<49}  |{}|", addr, hex, ascii)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Emulator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // default behavior: dump PC and registers
+        write!(f, "PC: 0x{:x} ", self.pc)?;
+        for reg in Reg::iter() {
+            write!(f, " {}: 0x{:x}", reg, self[reg])?;
+        }
+
+        // alternate behavior: also dump all sections in memory
+        if f.alternate() {
+            if let Some(text) = self.section(".text") {
+                write!(f, "\n.text:")?;
+                self.fmt_section(f, text, true)?;
+            }
+            for section in &self.sections {
+                if section.name != ".text" && section.from_file {
+                    write!(f, "\n{}", section.name)?;
+                    self.fmt_section(f, section, false)?;
+                }
+            }
+            let stack = self.stack_window();
+            if !stack.is_empty() {
+                write!(f, "\nStack:")?;
+            }
+            for (addr, word) in stack {
+                let offset = addr as isize - self[Reg::sp] as isize;
+                write!(f, "\n  {:08x} sp{:<+4}: {:08x}", addr, offset, word)?;
+                if self.is_return_address(word as usize) {
+                    write!(f, " <{}>", self.symtab.symbolize(word as usize))?;
+                }
+            }
+            write!(f, "\nSymbols:")?;
+            for (sym, addr) in self.symtab.iter() {
+                write!(f, "\n  {:08x}: {}", addr, sym)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decoded instruction cache statistics.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    /// Instructions fetched from the cache
+    pub hits: u64,
+    /// Instructions that had to be decoded
+    pub misses: u64,
+    /// Cache entries discarded due to stores into executable memory
+    pub invalidations: u64,
+    /// Number of times the entire cache was discarded (FENCE.I)
+    pub flushes: u64,
+}
+
+/// The load or store behind an [EmulatorError::MemoryFault]: the PC it was
+/// at, and the base address (the value of rs1) and offset its effective
+/// address came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadStore {
+    pub pc: usize,
+    pub base: u32,
+    pub imm: i32,
+}
+
+impl LoadStore {
+    /// Returns whether the effective address wrapped around the address
+    /// space (e.g., a negative offset from a small base).
+    pub fn wrapped(&self) -> bool {
+        self.base.checked_add_signed(self.imm).is_none()
+    }
+}
+
+impl std::fmt::Display for LoadStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rs1 = {:08x}, imm = {}, pc = {:08x}",
+            self.base, self.imm, self.pc
+        )?;
+        if self.wrapped() {
+            write!(f, ", wrapped around")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors encountered while loading or emulating a program.
+#[derive(Error, Debug)]
+pub enum EmulatorError {
+    #[error("{0}")]
+    IO(#[from] io::Error),
+
+    #[error("error parsing ELF data: {0}")]
+    ELF(#[from] goblin::error::Error),
+
+    #[error("program entrypoint could not be located")]
+    EntryPoint,
+
+    #[error("instruction could not be decoded: {0}")]
+    InstructionDecode(String),
+
+    #[error("illegal instruction: {0}")]
+    IllegalInstruction(String),
+
+    #[error("execution error: {0}")]
+    Execution(String),
+
+    #[error("configuration error: {0}")]
+    Config(String),
+
+    /// Execution was stopped by a [TraceSink] or [YieldCallback] asking for
+    /// it, at the given PC
+    #[error("execution halted at {0:08x}")]
+    Halted(usize),
+
+    /// The program went over one of the [Limits] set for it
+    #[error("resource limit exceeded: {0}")]
+    LimitExceeded(Limit),
+
+    /// An access to memory that isn't there; `access` says which load or
+    /// store made it, if one did
+    #[error(
+        "memory access out of bounds: {len} byte(s) at {addr:08x}{}",
+        .access.map_or(String::new(), |access| format!(" ({})", access))
+    )]
+    MemoryFault {
+        addr: usize,
+        len: usize,
+        access: Option<LoadStore>,
+    },
+
+    /// A syscall (at the PC) that the emulator doesn't provide, when unknown
+    /// syscalls stop execution (see [Emulator::set_unknown_syscalls])
+    #[error("unknown syscall: {0}")]
+    UnknownSyscall(u32),
+
+    /// Part of the program being loaded lies past the end of memory
+    #[error(
+        "{what} ({start:x}-{end:x}) doesn't fit in memory ({available} bytes); try --memory {}",
+        config::format_size(memory_for(*.end))
+    )]
+    ImageTooLarge {
+        what: String,
+        start: usize,
+        end: usize,
+        available: usize,
+    },
+
+    /// A jump (at the given address) to itself, as a bare-metal program
+    /// might end with to park the hart; left alone, it would never end
+    #[error("idle loop at {0:08x}")]
+    IdleLoop(usize),
+
+    /// A load or store (at the PC) that isn't naturally aligned, when
+    /// misaligned accesses trap (see [Emulator::set_misaligned])
+    #[error("load/store address misaligned: {len} byte(s) at {addr:08x}")]
+    MisalignedAccess { addr: usize, len: usize },
+
+    /// The program ran for as many instructions as it was allowed to (at
+    /// the given address) without exiting
+    #[error("instruction budget exceeded ({limit} instructions) at {pc:08x}")]
+    InstructionLimit { limit: u64, pc: usize },
+
+    /// A jump or branch (at the PC) to an address that isn't 4-byte aligned
+    #[error("instruction address misaligned: {target:08x}")]
+    MisalignedFetch { target: usize },
+
+    /// A store (at the PC) to one of the program's read-only sections, when
+    /// those are write-protected (see [Emulator::set_write_protect])
+    #[error("write to read-only section {section}: {len} byte(s) at {addr:08x} (pc = {pc:08x})")]
+    ReadOnlyWrite {
+        addr: usize,
+        len: usize,
+        section: String,
+        pc: usize,
+    },
+}
+
+// rv32i
+impl Emulator {
+    fn nop(&mut self) {
+        log::warn!(target: "rvem::exec", "nop called");
+    }
+
+    /* B-Type (branches) */
+    fn branch(&mut self, imm: i32) -> Result<(), EmulatorError> {
+        // NB a taken branch (or jal) to itself would be taken forever (though
+        // a program that's exited, e.g. via HTIF, may park itself like that)
+        if imm == 0 && self.exit_code.is_none() {
+            return Err(EmulatorError::IdleLoop(self.pc));
+        }
+        self.jump(self.pc.wrapping_add_signed(imm as isize))
+    }
+    /// Sets the PC to `target` (as though it had already been incremented),
+    /// unless `target` is misaligned.
+    fn jump(&mut self, target: usize) -> Result<(), EmulatorError> {
+        if !target.is_multiple_of(4) {
+            return Err(EmulatorError::MisalignedFetch { target });
+        }
+        // NB subtract 4 since we're auto-incrementing
+        self.pc = target.wrapping_sub(4);
+        Ok(())
+    }
+    fn beq(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        if self[rs1] == self[rs2] {
+            self.branch(imm)?;
+        }
+        Ok(())
+    }
+    fn bne(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        if self[rs1] != self[rs2] {
+            self.branch(imm)?;
+        }
+        Ok(())
+    }
+    fn blt(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        if (self[rs1] as i32) < (self[rs2] as i32) {
+            self.branch(imm)?;
+        }
+        Ok(())
+    }
+    fn bge(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        if (self[rs1] as i32) >= (self[rs2] as i32) {
+            self.branch(imm)?;
+        }
+        Ok(())
+    }
+    fn bltu(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        if self[rs1] < self[rs2] {
+            self.branch(imm)?;
+        }
+        Ok(())
+    }
+    fn bgeu(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        if self[rs1] >= self[rs2] {
+            self.branch(imm)?;
+        }
+        Ok(())
+    }
+
+    /* I-Type */
+
+    // integer operations
+    fn addi(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self[rd] = self[rs1].wrapping_add(imm as u32);
+    }
+    fn andi(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self[rd] = self[rs1] & (imm as u32);
+    }
+    fn ori(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self[rd] = self[rs1] | (imm as u32);
+    }
+    fn slti(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self[rd] = if (self[rs1] as i32) < imm { 1 } else { 0 };
+    }
+    fn sltiu(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self[rd] = if self[rs1] < (imm as u32) { 1 } else { 0 };
+    }
+    fn xori(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self[rd] = self[rs1] ^ (imm as u32)
+    }
+
+    // loads
+    fn lb(&mut self, rd: Reg, rs1: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.load_store_addr(rs1, imm, 1)?;
+        let val = self.read_u8(addr)? as u32;
+        self[rd] = sext(val, 8);
+        Ok(())
+    }
+    fn lh(&mut self, rd: Reg, rs1: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.load_store_addr(rs1, imm, 2)?;
+        let val = self.read_u16(addr)? as u32;
+        self[rd] = sext(val, 16);
+        Ok(())
+    }
+    fn lw(&mut self, rd: Reg, rs1: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.load_store_addr(rs1, imm, 4)?;
+        self[rd] = self.read_u32(addr)?;
+        Ok(())
+    }
+    fn lbu(&mut self, rd: Reg, rs1: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.load_store_addr(rs1, imm, 1)?;
+        let val = self.read_u8(addr)? as u32;
+        self[rd] = val;
+        Ok(())
+    }
+    fn lhu(&mut self, rd: Reg, rs1: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.load_store_addr(rs1, imm, 2)?;
+        let val = self.read_u16(addr)? as u32;
+        self[rd] = val;
+        Ok(())
+    }
+
+    // jump
+    fn jalr(&mut self, rd: Reg, rs1: Reg, imm: i32) -> Result<(), EmulatorError> {
+        // NB the spec has the target's low bit cleared, so it can be used as
+        // a flag (e.g., in function pointers)
+        let addr = self.effective_addr(rs1, imm) & !1;
+        // ...as would a jalr to itself, unless it changes its own target
+        if addr == self.pc && (rd != rs1 || rd == Reg::zero) && self.exit_code.is_none() {
+            return Err(EmulatorError::IdleLoop(self.pc));
+        }
+        let link = (self.pc as u32).wrapping_add(4);
+        self.jump(addr)?;
+        self[rd] = link;
+        Ok(())
+    }
+
+    /* J-Type */
+    fn jal(&mut self, rd: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let link = (self.pc as u32).wrapping_add(4);
+        self.branch(imm)?;
+        self[rd] = link;
+        Ok(())
+    }
+
+    /* R-Type */
+    fn add(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
+        self[rd] = self[rs1].wrapping_add(self[rs2]);
+    }
+    fn and(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
+        self[rd] = self[rs1] & self[rs2];
+    }
+    fn or(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
+        self[rd] = self[rs1] | self[rs2];
+    }
+    fn sll(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
+        self[rd] = self[rs1] << shift_amount(self[rs2]);
     }
     fn slt(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
         self[rd] = if (self[rs1] as i32) < (self[rs2] as i32) {
@@ -451,19 +2689,19 @@ impl Emulator {
         self[rd] = if self[rs1] < self[rs2] { 1 } else { 0 };
     }
     fn sra(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = ((self[rs1] as i32) >> (self[rs2] as i32)) as u32;
+        self[rd] = ((self[rs1] as i32) >> shift_amount(self[rs2])) as u32;
     }
     fn srl(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1] >> self[rs2];
+        self[rd] = self[rs1] >> shift_amount(self[rs2]);
     }
     fn slli(&mut self, rd: Reg, rs1: Reg, shamt: u32) {
-        self[rd] = self[rs1] << shamt;
+        self[rd] = self[rs1] << shift_amount(shamt);
     }
     fn srli(&mut self, rd: Reg, rs1: Reg, shamt: u32) {
-        self[rd] = self[rs1] >> shamt;
+        self[rd] = self[rs1] >> shift_amount(shamt);
     }
     fn srai(&mut self, rd: Reg, rs1: Reg, shamt: u32) {
-        self[rd] = ((self[rs1] as i32) >> shamt) as u32;
+        self[rd] = ((self[rs1] as i32) >> shift_amount(shamt)) as u32;
     }
     fn sub(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
         self[rd] = self[rs1].wrapping_sub(self[rs2]);
@@ -473,97 +2711,439 @@ impl Emulator {
     }
 
     /* S-Type */
-    fn sb(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        let addr = (self[rs1] as i32 + imm) as usize;
-        let bytes = self[rs2].to_le_bytes();
-        self[addr] = bytes[0];
-    }
-    fn sh(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        let addr = (self[rs1] as i32 + imm) as usize;
-        let bytes = self[rs2].to_le_bytes();
-        self[addr] = bytes[0];
-        self[addr + 1] = bytes[1];
-    }
-    fn sw(&mut self, rs1: Reg, rs2: Reg, imm: i32) {
-        let addr = (self[rs1] as i32 + imm) as usize;
-        let bytes = self[rs2].to_le_bytes();
-        self[addr] = bytes[0];
-        self[addr + 1] = bytes[1];
-        self[addr + 2] = bytes[2];
-        self[addr + 3] = bytes[3];
+    fn sb(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.store_addr(rs1, imm, 1)?;
+        self.write_u8(addr, self[rs2] as u8)?;
+        self.invalidate_icache(addr, 1);
+        Ok(())
+    }
+    fn sh(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.store_addr(rs1, imm, 2)?;
+        self.write_u16(addr, self[rs2] as u16)?;
+        self.invalidate_icache(addr, 2);
+        Ok(())
+    }
+    fn sw(&mut self, rs1: Reg, rs2: Reg, imm: i32) -> Result<(), EmulatorError> {
+        let addr = self.store_addr(rs1, imm, 4)?;
+        self.write_u32(addr, self[rs2])?;
+        self.invalidate_icache(addr, 4);
+        if Some(addr) == self.tohost {
+            self.htif(self[rs2]);
+        }
+        Ok(())
+    }
+
+    /// Handles a write of `value` to the HTIF tohost word.
+    fn htif(&mut self, value: u32) {
+        if value & 1 == 1 {
+            log::trace!(target: "rvem::devices", "HTIF exit: {}", value >> 1);
+            self.exit_code = Some((value >> 1) as i32);
+        } else if value != 0 {
+            // NB anything else would be a pointer to a syscall block
+            log::warn!(target: "rvem::devices", "unsupported HTIF command: {:08x}", value);
+        }
+    }
+
+    /* fences */
+    fn fence(&mut self) {
+        // NB there's only the one hart, so memory is always coherent
+    }
+    fn fence_i(&mut self) {
+        self.flush_icache();
+    }
+
+    /* CSRs */
+    fn csrrw(&mut self, rd: Reg, rs1: Reg, csr: u32) -> Result<(), EmulatorError> {
+        let value = self[rs1];
+        // NB no read (or read side effects) if rd is x0
+        let old = if rd == Reg::zero {
+            0
+        } else {
+            self.read_csr(csr)?
+        };
+        self.write_csr(csr, value)?;
+        self[rd] = old;
+        Ok(())
+    }
+    fn csrrs(&mut self, rd: Reg, rs1: Reg, csr: u32) -> Result<(), EmulatorError> {
+        let old = self.read_csr(csr)?;
+        // NB no write (or write side effects) if rs1 is x0
+        if rs1 != Reg::zero {
+            self.write_csr(csr, old | self[rs1])?;
+        }
+        self[rd] = old;
+        Ok(())
+    }
+    fn csrrc(&mut self, rd: Reg, rs1: Reg, csr: u32) -> Result<(), EmulatorError> {
+        let old = self.read_csr(csr)?;
+        if rs1 != Reg::zero {
+            self.write_csr(csr, old & !self[rs1])?;
+        }
+        self[rd] = old;
+        Ok(())
+    }
+    fn csrrwi(&mut self, rd: Reg, zimm: u32, csr: u32) -> Result<(), EmulatorError> {
+        let old = if rd == Reg::zero {
+            0
+        } else {
+            self.read_csr(csr)?
+        };
+        self.write_csr(csr, zimm)?;
+        self[rd] = old;
+        Ok(())
+    }
+    fn csrrsi(&mut self, rd: Reg, zimm: u32, csr: u32) -> Result<(), EmulatorError> {
+        let old = self.read_csr(csr)?;
+        if zimm != 0 {
+            self.write_csr(csr, old | zimm)?;
+        }
+        self[rd] = old;
+        Ok(())
+    }
+    fn csrrci(&mut self, rd: Reg, zimm: u32, csr: u32) -> Result<(), EmulatorError> {
+        let old = self.read_csr(csr)?;
+        if zimm != 0 {
+            self.write_csr(csr, old & !zimm)?;
+        }
+        self[rd] = old;
+        Ok(())
+    }
+
+    /// Reads the value of `csr`.
+    fn read_csr(&self, csr: u32) -> Result<u32, EmulatorError> {
+        // NB the time CSR counts microseconds
+        let time = || self.time_ns() / 1000;
+        match csr {
+            csr::CYCLE => Ok(self.cycles as u32),
+            csr::TIME => Ok(time() as u32),
+            csr::INSTRET => Ok(self.instret as u32),
+            csr::CYCLEH => Ok((self.cycles >> 32) as u32),
+            csr::TIMEH => Ok((time() >> 32) as u32),
+            csr::INSTRETH => Ok((self.instret >> 32) as u32),
+            _ => Err(EmulatorError::IllegalInstruction(format!(
+                "{:08x}: unimplemented CSR {}",
+                self.pc,
+                csr::Name(csr)
+            ))),
+        }
+    }
+
+    /// Writes `value` to `csr`.
+    fn write_csr(&mut self, csr: u32, _value: u32) -> Result<(), EmulatorError> {
+        if csr::is_read_only(csr) {
+            Err(EmulatorError::IllegalInstruction(format!(
+                "{:08x}: write to read-only CSR {}",
+                self.pc,
+                csr::Name(csr)
+            )))
+        } else {
+            Err(EmulatorError::IllegalInstruction(format!(
+                "{:08x}: unimplemented CSR {}",
+                self.pc,
+                csr::Name(csr)
+            )))
+        }
     }
 
     /* U-Type */
     fn auipc(&mut self, rd: Reg, imm: i32) {
-        self[rd] = self.pc as u32 + (imm << 12) as u32;
+        self[rd] = (self.pc as u32).wrapping_add((imm << 12) as u32);
     }
     fn lui(&mut self, rd: Reg, imm: i32) {
         self[rd] = (imm << 12) as u32;
     }
 
     /* system calls */
-    fn ecall(&mut self) {
+    fn ecall(&mut self) -> Result<(), EmulatorError> {
         let syscall = self[Reg::a7];
+        *self.counters.syscalls.entry(syscall).or_default() += 1;
+        if !self.personality.allows(syscall) {
+            return self.unknown_syscall(syscall);
+        }
         match syscall {
             1 => {
-                log::trace!("MIPS print_int"); // https://student.cs.uwaterloo.ca/~isg/res/mips/traps
-                print!("{}", (self[Reg::a0] as i32));
-                std::io::stdout().flush().unwrap();
+                log::trace!(target: "rvem::syscalls", "MIPS print_int"); // https://student.cs.uwaterloo.ca/~isg/res/mips/traps
+                let n = (self[Reg::a0] as i32).to_string();
+                self.count_output(n.len())?;
+                write!(self.stdout, "{}", n)?;
+                self.stdout.flush()?;
             }
             4 => {
-                log::trace!("MIPS print_string");
+                log::trace!(target: "rvem::syscalls", "MIPS print_string");
                 let pos = self[Reg::a0] as usize;
-                let mut len = 0usize;
-                while self[pos + len] != 0 {
-                    len += 1;
-                }
-
-                print!(
-                    "{}",
-                    String::from_utf8(self[pos..pos + len].into()).unwrap()
-                );
-                std::io::stdout().flush().unwrap();
+                let tail = self.bytes(pos, self.mem.len().saturating_sub(pos))?;
+                // NB an unterminated string would run off the end of memory
+                let len = tail
+                    .iter()
+                    .position(|b| *b == 0)
+                    .ok_or(EmulatorError::MemoryFault {
+                        addr: self.mem.len(),
+                        len: 1,
+                        access: None,
+                    })?;
+                self.count_output(len)?;
+                // NB the bytes as they are, whether or not they're UTF-8 (and
+                // borrowing just the memory, not the whole emulator)
+                self.stdout.write_all(&self.mem[pos..pos + len])?;
+                self.stdout.flush()?;
             }
             5 => {
-                log::trace!("MIPS read_int");
+                log::trace!(target: "rvem::syscalls", "MIPS read_int");
+                // NB a line that isn't a number is skipped, as though the
+                // user had been asked again
                 let mut buf: String = String::new();
-                // TODO catch error
-                let _ = std::io::stdin().read_line(&mut buf);
-                self[Reg::a0] = buf.trim().parse::<u32>().unwrap(); // TODO get rid of unwrap
+                self[Reg::a0] = loop {
+                    buf.clear();
+                    if self.stdin.read_line(&mut buf)? == 0 {
+                        return Err(EmulatorError::Execution(
+                            "read_int: end of input".to_string(),
+                        ));
+                    }
+                    let line = buf.trim();
+                    match line.parse::<i32>() {
+                        Ok(n) => break n as u32,
+                        // NB the whole unsigned range fits in a register too
+                        Err(_) => match line.parse::<u32>() {
+                            Ok(n) => break n,
+                            Err(e) => log::warn!(
+                                target: "rvem::syscalls",
+                                "read_int: {:?}: {}",
+                                line,
+                                e
+                            ),
+                        },
+                    }
+                };
             }
             10 => {
-                log::trace!("MIPS exit");
-                process::exit(0);
+                log::trace!(target: "rvem::syscalls", "MIPS exit");
+                self.exit_code = Some(0);
+            }
+            63 => {
+                // RISC-V read
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux read syscall: fd: {} addr: {:x} len: {}",
+                    self[Reg::a0],
+                    self[Reg::a1],
+                    self[Reg::a2]
+                );
+
+                let addr = self[Reg::a1] as usize;
+                let len = self[Reg::a2] as usize;
+                // NB the guest only gets at its stdin
+                if self[Reg::a0] != 0 {
+                    self[Reg::a0] = -(EBADF as i32) as u32;
+                    return Ok(());
+                }
+                if !self.syscall_writable(addr, len) {
+                    self[Reg::a0] = -(EFAULT as i32) as u32;
+                    return Ok(());
+                }
+                self.touch(addr..addr + len);
+                self.invalidate_icache(addr, len);
+                match self.stdin.read(&mut self.mem[addr..addr + len]) {
+                    Ok(len) => self[Reg::a0] = len as u32,
+                    // NB see set_stdin
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(e.into()),
+                    Err(_) => self[Reg::a0] = -1i32 as u32,
+                }
             }
             64 => {
                 // RISC-V write
                 log::trace!(
+                    target: "rvem::syscalls",
                     "RISC-V linux write syscall: fp: {} addr: {:x} len: {}",
                     self[Reg::a0],
                     self[Reg::a1],
                     self[Reg::a2]
                 );
 
-                let mut fp = unsafe { File::from_raw_fd(self[Reg::a0] as i32) };
                 let addr = self[Reg::a1] as usize;
                 let len = self[Reg::a2] as usize;
-                if let Ok(len) = fp.write(&self[addr..addr + len]) {
-                    log::trace!("wrote {} bytes", len);
+                // NB the guest only gets at its stdout and stderr
+                let fd = self[Reg::a0];
+                if fd != 1 && fd != 2 {
+                    self[Reg::a0] = -(EBADF as i32) as u32;
+                    return Ok(());
+                }
+                if self.bytes(addr, len).is_err() {
+                    self[Reg::a0] = -(EFAULT as i32) as u32;
+                    return Ok(());
+                }
+                self.count_output(len)?;
+                let buf = &self.mem[addr..addr + len];
+                let fp = if fd == 1 {
+                    &mut self.stdout
+                } else {
+                    &mut self.stderr
+                };
+                if let Ok(len) = fp.write(buf).and_then(|len| fp.flush().map(|_| len)) {
+                    log::trace!(target: "rvem::syscalls", "wrote {} bytes", len);
                     self[Reg::a0] = len as u32;
                 } else {
-                    log::trace!("write error");
+                    log::trace!(target: "rvem::syscalls", "write error");
                     self[Reg::a0] = -1i32 as u32;
                 }
             }
             93 => {
                 // RISC-V exit
-                log::trace!("RISC-V linux exit syscall: rc: {}", self[Reg::a0]);
-                process::exit(self[Reg::a0] as i32);
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux exit syscall: rc: {}",
+                    self[Reg::a0]
+                );
+                self.exit_code = Some(self[Reg::a0] as i32);
+            }
+            214 => {
+                // RISC-V linux brk: a0 is the requested break (or 0 to query
+                // it); the (possibly unchanged) break is returned
+                let addr = self[Reg::a0] as usize;
+                log::trace!(target: "rvem::syscalls", "RISC-V linux brk syscall: addr: {:x}", addr);
+                // NB the heap mustn't grow into the stack (or off the end of
+                // memory)
+                let limit = (self[Reg::sp] as usize).min(self.mem.len());
+                if addr >= self.brk_start && addr < limit {
+                    self.check_heap(addr, self.mmap_top)?;
+                    if addr > self.brk {
+                        let brk = self.brk;
+                        self[brk..addr].fill(0);
+                    }
+                    self.brk = addr;
+                }
+                self[Reg::a0] = self.brk as u32;
+            }
+            215 => {
+                // RISC-V linux munmap: mappings are never reused, so this is
+                // a no-op
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux munmap syscall: addr: {:x}",
+                    self[Reg::a0]
+                );
+                self[Reg::a0] = 0;
+            }
+            222 => {
+                // RISC-V linux mmap: only anonymous mappings are supported,
+                // and the address hint is ignored
+                let len = (self[Reg::a1] as usize).next_multiple_of(MMAP_ALIGN);
+                let flags = self[Reg::a3];
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux mmap syscall: len: {} flags: {:x}",
+                    self[Reg::a1],
+                    flags
+                );
+                let addr = self.mmap_top;
+                if flags & MAP_ANONYMOUS == 0 || len == 0 || addr + len > self.mem.len() {
+                    self[Reg::a0] = -(ENOMEM as i32) as u32;
+                } else {
+                    self.check_heap(self.brk, addr + len)?;
+                    self[addr..addr + len].fill(0);
+                    self.mmap_top += len;
+                    self[Reg::a0] = addr as u32;
+                }
+            }
+            113 | 403 => {
+                // RISC-V linux clock_gettime (403 being clock_gettime64): a0
+                // is the clock, and a1 where to put a timespec, whose fields
+                // are written as 64 bits apiece (time_t always is, on rv32)
+                let (clock, addr) = (self[Reg::a0], self[Reg::a1] as usize);
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux clock_gettime syscall: clock: {} addr: {:x}",
+                    clock,
+                    addr
+                );
+                // NB every clock but CLOCK_REALTIME starts with the program
+                let ns = match clock {
+                    CLOCK_REALTIME => self.wall_time_ns(),
+                    _ => self.time_ns(),
+                };
+                self[Reg::a0] = self.write_time(addr, ns / 1_000_000_000, ns % 1_000_000_000);
+            }
+            169 => {
+                // RISC-V linux gettimeofday: a0 is where to put a timeval
+                // (if anywhere), and the timezone in a1 is ignored
+                let addr = self[Reg::a0] as usize;
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux gettimeofday syscall: addr: {:x}",
+                    addr
+                );
+                let ns = self.wall_time_ns();
+                self[Reg::a0] = match addr {
+                    0 => 0,
+                    _ => self.write_time(addr, ns / 1_000_000_000, ns % 1_000_000_000 / 1000),
+                };
+            }
+            278 => {
+                // RISC-V getrandom
+                log::trace!(
+                    target: "rvem::syscalls",
+                    "RISC-V linux getrandom syscall: addr: {:x} len: {}",
+                    self[Reg::a0],
+                    self[Reg::a1]
+                );
+
+                let addr = self[Reg::a0] as usize;
+                let len = self[Reg::a1] as usize;
+                if !self.syscall_writable(addr, len) {
+                    self[Reg::a0] = -(EFAULT as i32) as u32;
+                    return Ok(());
+                }
+                let mut bytes = Vec::with_capacity(len + 8);
+                while bytes.len() < len {
+                    bytes.extend_from_slice(&self.next_random().to_le_bytes());
+                }
+                self.bytes_mut(addr, len)?.copy_from_slice(&bytes[..len]);
+                self.invalidate_icache(addr, len);
+                self[Reg::a0] = len as u32;
+            }
+            HYPERCALL => self.hypercall()?,
+            _ => self.unknown_syscall(syscall)?,
+        }
+        Ok(())
+    }
+
+    /// Returns whether a syscall may write the `len` bytes at `addr` on the
+    /// guest's behalf: they're in bounds and, if write protection is on (see
+    /// [Emulator::set_write_protect]), not in a read-only section. If not,
+    /// the syscall fails with -EFAULT, as Linux's do.
+    fn syscall_writable(&self, addr: usize, len: usize) -> bool {
+        self.bytes(addr, len).is_ok()
+            && !(self.write_protect && len > 0 && self.read_only_section(addr, len).is_some())
+    }
+
+    /// Writes a timespec or timeval - `secs` and then `frac` (nanoseconds or
+    /// microseconds), 64 bits apiece - to `addr`, returning the syscall's
+    /// result: 0, or -EFAULT if `addr` is out of bounds or read-only.
+    fn write_time(&mut self, addr: usize, secs: u64, frac: u64) -> u32 {
+        if !self.syscall_writable(addr, 16) {
+            return -(EFAULT as i32) as u32;
+        }
+        let buf = &mut self.mem[addr..addr + 16];
+        buf[..8].copy_from_slice(&secs.to_le_bytes());
+        buf[8..].copy_from_slice(&frac.to_le_bytes());
+        self.touch(addr..addr + 16);
+        self.invalidate_icache(addr, 16);
+        0
+    }
+
+    /// Handles a syscall the emulator doesn't provide, as
+    /// [Emulator::set_unknown_syscalls] says to.
+    fn unknown_syscall(&mut self, syscall: u32) -> Result<(), EmulatorError> {
+        match self.unknown_syscalls {
+            UnknownSyscalls::Ignore => {
+                log::error!(target: "rvem::syscalls", "unknown/unimplemented syscall: {}", syscall);
             }
-            _ => {
-                log::error!("unknown/unimplemented syscall: {}", syscall);
+            UnknownSyscalls::Enosys => {
+                log::warn!(target: "rvem::syscalls", "unknown/unimplemented syscall: {}", syscall);
+                self[Reg::a0] = -(ENOSYS as i32) as u32;
             }
+            UnknownSyscalls::Stop => return Err(EmulatorError::UnknownSyscall(syscall)),
         }
+        Ok(())
     }
 }
 
@@ -571,29 +3151,40 @@ impl Emulator {
 impl Emulator {
     // NB all multiplication extensions are R-Type
     fn mul(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = ((self[rs1] as i32) * (self[rs2] as i32)) as u32;
+        self[rd] = self[rs1].wrapping_mul(self[rs2]);
     }
     fn mulh(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = (((self[rs1] as i64) * (self[rs2] as i64)) >> 32) as u32;
+        self[rd] = (((self[rs1] as i32 as i64) * (self[rs2] as i32 as i64)) >> 32) as u32;
     }
     fn mulhu(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
         self[rd] = (((self[rs1] as u64) * (self[rs2] as u64)) >> 32) as u32;
     }
     fn mulhsu(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        // NB I don't think this is quite correct, but I'm fuzzy on what is...
-        self[rd] = (((self[rs1] as u64) * (self[rs2] as u64)) >> 32) as u32;
+        // NB signed rs1 times unsigned rs2, which can't overflow an i64
+        self[rd] = (((self[rs1] as i32 as i64) * (self[rs2] as i64)) >> 32) as u32;
     }
+    // NB division never traps; dividing by zero gives a quotient of all ones
+    // and leaves the dividend as the remainder, and the one signed overflow
+    // (i32::MIN / -1) gives i32::MIN with no remainder, as the spec says
     fn div(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = ((self[rs1] as i32) / (self[rs2] as i32)) as u32;
+        let (a, b) = (self[rs1] as i32, self[rs2] as i32);
+        self[rd] = match b {
+            0 => u32::MAX,
+            _ => a.wrapping_div(b) as u32,
+        };
     }
     fn divu(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1] / self[rs2];
+        self[rd] = self[rs1].checked_div(self[rs2]).unwrap_or(u32::MAX);
     }
     fn rem(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = ((self[rs1] as i32) % (self[rs2] as i32)) as u32;
+        let (a, b) = (self[rs1] as i32, self[rs2] as i32);
+        self[rd] = match b {
+            0 => a as u32,
+            _ => a.wrapping_rem(b) as u32,
+        };
     }
     fn remu(&mut self, rd: Reg, rs1: Reg, rs2: Reg) {
-        self[rd] = self[rs1] % self[rs2];
+        self[rd] = self[rs1].checked_rem(self[rs2]).unwrap_or(self[rs1]);
     }
 }
 
@@ -610,4 +3201,1081 @@ mod tests {
         let value = 0x7f0u32;
         assert_eq!(sext(value, 12), value);
     }
+
+    #[test]
+    fn test_run_with() {
+        struct Counter(u64);
+        impl TraceSink for Counter {
+            fn retire(&mut self, _em: &Emulator, _retired: &Retired) {
+                self.0 += 1;
+            }
+        }
+
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut counter = Counter(0);
+        assert_eq!(em.run_with(&mut counter).unwrap(), 0);
+        assert_eq!(counter.0, em.instret());
+        assert_eq!(em[Reg::a0], 120);
+
+        // an HTIF exit partway through a basic block ends the run there,
+        // traced or not
+        let mut em = Emulator::load_from("tests/data/signature", None).unwrap();
+        em.run().unwrap();
+        let untraced = em.instret();
+        let mut em = Emulator::load_from("tests/data/signature", None).unwrap();
+        let mut counter = Counter(0);
+        em.run_with(&mut counter).unwrap();
+        assert_eq!((em.instret(), counter.0), (untraced, untraced));
+    }
+
+    #[test]
+    fn test_patch_cached_code() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        em.init().unwrap();
+        em.step_with(&mut NoTrace).unwrap();
+        let pc = em.pc();
+        em.curr().unwrap(); // NB cached by now, along with the rest of .text
+
+        // addi a0, zero, 77, written over the next instruction
+        em[pc..pc + 4].copy_from_slice(&0x04d00513u32.to_le_bytes());
+        assert_eq!(em.curr().unwrap().to_string(), "li a0, 77");
+        em.step_with(&mut NoTrace).unwrap();
+        assert_eq!(em[Reg::a0], 77);
+
+        // and a byte at a time
+        em.pc = pc;
+        em[pc + 2] = 0x80; // addi a0, zero, 72
+        em.step_with(&mut NoTrace).unwrap();
+        assert_eq!(em[Reg::a0], 72);
+    }
+
+    #[test]
+    fn test_timing() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        em.set_timing(Some(Timing::default()));
+        assert_eq!(em.run().unwrap(), 0);
+        assert!(em.cycles() > em.instret());
+
+        em.csrrs(Reg::a0, Reg::zero, csr::CYCLE).unwrap();
+        assert_eq!(em[Reg::a0] as u64, em.cycles());
+        em.csrrsi(Reg::a0, 0, csr::INSTRET).unwrap();
+        assert_eq!(em[Reg::a0] as u64, em.instret());
+        assert!(em.csrrw(Reg::a0, Reg::a1, csr::CYCLE).is_err());
+        assert!(em.csrrs(Reg::a0, Reg::zero, 0x7c0).is_err());
+    }
+
+    #[test]
+    fn test_shifts() {
+        let mut em = Emulator::new(Some(64));
+        let cases: [(u32, u32, u32, u32, u32); 6] = [
+            // (rs1, rs2, sll, srl, sra)
+            (0x80000001, 0, 0x80000001, 0x80000001, 0x80000001),
+            (0x80000001, 1, 0x00000002, 0x40000000, 0xc0000000),
+            (0x80000001, 31, 0x80000000, 0x00000001, 0xffffffff),
+            // only the low 5 bits of rs2 count
+            (0x80000001, 32, 0x80000001, 0x80000001, 0x80000001),
+            (0x80000001, 33, 0x00000002, 0x40000000, 0xc0000000),
+            (0x80000001, 0xffffffff, 0x80000000, 0x00000001, 0xffffffff),
+        ];
+        for (a, b, sll, srl, sra) in cases {
+            em[Reg::a0] = a;
+            em[Reg::a1] = b;
+            em.sll(Reg::a2, Reg::a0, Reg::a1);
+            em.srl(Reg::a3, Reg::a0, Reg::a1);
+            em.sra(Reg::a4, Reg::a0, Reg::a1);
+            assert_eq!((em[Reg::a2], em[Reg::a3], em[Reg::a4]), (sll, srl, sra));
+            if b < 32 {
+                em.slli(Reg::a2, Reg::a0, b);
+                em.srli(Reg::a3, Reg::a0, b);
+                em.srai(Reg::a4, Reg::a0, b);
+                assert_eq!((em[Reg::a2], em[Reg::a3], em[Reg::a4]), (sll, srl, sra));
+            }
+        }
+    }
+
+    #[test]
+    fn test_jalr() {
+        let mut em = Emulator::new(Some(0x1000));
+        em.pc = 0x100;
+        for (target, imm) in [(0x201, 0), (0x200, 1), (0x1ff, 2), (0x203, -2)] {
+            em[Reg::a0] = target;
+            // NB jalr returns to the instruction after it
+            em.jalr(Reg::ra, Reg::a0, imm).unwrap();
+            assert_eq!(em.pc + 4, 0x200, "{:x}{:+}", target, imm);
+            assert_eq!(em[Reg::ra], 0x104);
+            em.pc = 0x100;
+        }
+    }
+
+    #[test]
+    fn test_misaligned_fetch() {
+        let mut em = Emulator::new(Some(0x1000));
+        em.pc = 0x100;
+        em[Reg::ra] = 0x42;
+        let misaligned = |r| matches!(r, Err(EmulatorError::MisalignedFetch { target: 0x102 }));
+        assert!(misaligned(em.jal(Reg::ra, 2)));
+        em[Reg::a0] = 0x102;
+        assert!(misaligned(em.jalr(Reg::ra, Reg::a0, 0)));
+        assert!(misaligned(em.beq(Reg::zero, Reg::zero, 2)));
+        // a faulting jump leaves the PC (and the link register) alone
+        assert_eq!((em.pc, em[Reg::ra]), (0x100, 0x42));
+        // an untaken branch can't fault
+        em.bne(Reg::zero, Reg::zero, 2).unwrap();
+
+        em.set_pc(0x102);
+        assert!(matches!(
+            em.step_with(&mut NoTrace),
+            Err(EmulatorError::MisalignedFetch { target: 0x102 })
+        ));
+    }
+
+    #[test]
+    fn test_idle_loop() {
+        let mut em = Emulator::new(Some(0x1000));
+        em.pc = 0x100;
+        // j .
+        assert!(matches!(
+            em.execute_word(0x0000006f),
+            Err(EmulatorError::IdleLoop(0x100))
+        ));
+        assert!(matches!(
+            em.bge(Reg::zero, Reg::zero, 0),
+            Err(EmulatorError::IdleLoop(0x100))
+        ));
+        em[Reg::a0] = 0x100;
+        assert!(matches!(
+            em.jalr(Reg::ra, Reg::a0, 0),
+            Err(EmulatorError::IdleLoop(0x100))
+        ));
+        assert_eq!((em.pc, em.instret), (0x100, 0));
+
+        // an untaken branch falls through, and a jalr that overwrites its
+        // own base register goes somewhere else next time around
+        em.blt(Reg::zero, Reg::zero, 0).unwrap();
+        em.jalr(Reg::a0, Reg::a0, 0).unwrap();
+        assert_eq!(em[Reg::a0], 0x104);
+    }
+
+    #[cfg(feature = "rv32m")]
+    #[test]
+    fn test_multiplication() {
+        let mut em = Emulator::new(Some(64));
+        let cases: [(u32, u32, u32, u32, u32, u32); 7] = [
+            // (rs1, rs2, mul, mulh, mulhsu, mulhu)
+            (3, 5, 15, 0, 0, 0),
+            (u32::MAX, 1, u32::MAX, u32::MAX, u32::MAX, 0),
+            (1, u32::MAX, u32::MAX, u32::MAX, 0, 0),
+            (u32::MAX, u32::MAX, 1, 0, u32::MAX, 0xfffffffe),
+            (-2i32 as u32, 3, -6i32 as u32, u32::MAX, u32::MAX, 2),
+            (
+                0x80000000, 0x80000000, 0, 0x40000000, 0xc0000000, 0x40000000,
+            ),
+            (
+                0x7fffffff, 0x80000000, 0x80000000, 0xc0000000, 0x3fffffff, 0x3fffffff,
+            ),
+        ];
+        for (a, b, mul, mulh, mulhsu, mulhu) in cases {
+            em[Reg::a0] = a;
+            em[Reg::a1] = b;
+            em.mul(Reg::a2, Reg::a0, Reg::a1);
+            em.mulh(Reg::a3, Reg::a0, Reg::a1);
+            em.mulhsu(Reg::a4, Reg::a0, Reg::a1);
+            em.mulhu(Reg::a5, Reg::a0, Reg::a1);
+            assert_eq!(
+                (em[Reg::a2], em[Reg::a3], em[Reg::a4], em[Reg::a5]),
+                (mul, mulh, mulhsu, mulhu),
+                "{:x} * {:x}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[cfg(feature = "rv32m")]
+    #[test]
+    fn test_division() {
+        let mut em = Emulator::new(Some(64));
+        let min = i32::MIN as u32;
+        let cases: [(u32, u32, u32, u32, u32, u32); 6] = [
+            // (rs1, rs2, div, divu, rem, remu)
+            (7, 2, 3, 3, 1, 1),
+            (-7i32 as u32, 2, -3i32 as u32, 0x7ffffffc, -1i32 as u32, 1),
+            // division by zero
+            (7, 0, u32::MAX, u32::MAX, 7, 7),
+            (
+                -7i32 as u32,
+                0,
+                u32::MAX,
+                u32::MAX,
+                -7i32 as u32,
+                -7i32 as u32,
+            ),
+            (0, 0, u32::MAX, u32::MAX, 0, 0),
+            // signed overflow
+            (min, u32::MAX, min, 0, 0, min),
+        ];
+        for (a, b, div, divu, rem, remu) in cases {
+            em[Reg::a0] = a;
+            em[Reg::a1] = b;
+            em.div(Reg::a2, Reg::a0, Reg::a1);
+            em.divu(Reg::a3, Reg::a0, Reg::a1);
+            em.rem(Reg::a4, Reg::a0, Reg::a1);
+            em.remu(Reg::a5, Reg::a0, Reg::a1);
+            assert_eq!(
+                (em[Reg::a2], em[Reg::a3], em[Reg::a4], em[Reg::a5]),
+                (div, divu, rem, remu),
+                "{:x} / {:x}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn test_unaligned_access() {
+        let mut em = Emulator::new(Some(64));
+        em[Reg::a0] = 0x11;
+        em[Reg::a1] = 0xdeadbeef;
+        em.sw(Reg::a0, Reg::a1, 0).unwrap();
+        em.lw(Reg::a2, Reg::a0, 0).unwrap();
+        assert_eq!(em[Reg::a2], 0xdeadbeef);
+        em.sh(Reg::a0, Reg::a1, 7).unwrap();
+        em.lhu(Reg::a3, Reg::a0, 7).unwrap();
+        assert_eq!(em[Reg::a3], 0xbeef);
+        em.lh(Reg::a4, Reg::a0, 7).unwrap();
+        assert_eq!(em[Reg::a4], 0xffffbeef);
+
+        em.set_misaligned(Misaligned::Trap);
+        assert!(matches!(
+            em.lw(Reg::a2, Reg::a0, 0),
+            Err(EmulatorError::MisalignedAccess { addr: 0x11, len: 4 })
+        ));
+        assert!(matches!(
+            em.sh(Reg::a0, Reg::a1, 0),
+            Err(EmulatorError::MisalignedAccess { addr: 0x11, len: 2 })
+        ));
+        // bytes are always aligned, and so is everything else here
+        em.lb(Reg::a2, Reg::a0, 0).unwrap();
+        em.sw(Reg::a0, Reg::a1, 3).unwrap();
+        em.lhu(Reg::a3, Reg::a0, 5).unwrap();
+        assert_eq!(em[Reg::a3], 0xdead);
+    }
+
+    #[test]
+    fn test_execute_word() {
+        let mut em = Emulator::new(Some(0x1000));
+        // lw a0, 0(a1), way out of bounds
+        em[Reg::a1] = 0xfffffffe;
+        assert!(matches!(
+            em.execute_word(0x0005a503),
+            Err(EmulatorError::MemoryFault {
+                addr: 0xfffffffe,
+                len: 4,
+                access: Some(LoadStore {
+                    pc: 0,
+                    base: 0xfffffffe,
+                    imm: 0
+                })
+            })
+        ));
+        // lw a0, -8(a1), wrapping around from near 0
+        em[Reg::a1] = 4;
+        let e = em.execute_word(0xff85a503).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "memory access out of bounds: 4 byte(s) at fffffffc \
+             (rs1 = 00000004, imm = -8, pc = 00000000, wrapped around)"
+        );
+        // divu a0, a0, zero
+        if cfg!(feature = "rv32m") {
+            em.execute_word(0x02055533).unwrap();
+            assert_eq!(em[Reg::a0], u32::MAX);
+        }
+        // jalr zero, 0(zero), to address 0
+        em.pc = 8;
+        em.execute_word(0x00000067).unwrap();
+        assert_eq!(em.pc, 0);
+
+        // NB no word should panic, whatever state it's executed in
+        let mut x = 0x2545f491u32;
+        for _ in 0..200_000 {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            em[Reg::from_bits(x >> 27)] = x.rotate_left(11);
+            if x & 0x7f == 0x73 {
+                continue; // no syscalls, which might block on stdin
+            }
+            let _ = em.execute_word(x);
+        }
+    }
+
+    #[test]
+    fn test_execute_inst() {
+        let mut em = Emulator::new(Some(0x1000));
+        em[Reg::a1] = 0x800;
+        em[Reg::a2] = 0xdeadbeef;
+        em.execute_inst(Inst::SW {
+            rs1: Reg::a1,
+            rs2: Reg::a2,
+            imm: -4,
+        })
+        .unwrap();
+        em.execute_inst(Inst::LBU {
+            rd: Reg::a0,
+            rs1: Reg::a1,
+            imm: -1,
+        })
+        .unwrap();
+        assert_eq!(em[Reg::a0], 0xde);
+        em.execute_inst(Inst::SRAI {
+            rd: Reg::a3,
+            rs1: Reg::a2,
+            shamt: 28,
+        })
+        .unwrap();
+        assert_eq!(em[Reg::a3], 0xfffffffd);
+        assert_eq!((em.pc(), em.instret()), (12, 3));
+
+        // branches are relative to the PC, whatever's at it
+        em.execute_inst(Inst::BNE {
+            rs1: Reg::a0,
+            rs2: Reg::zero,
+            imm: -12,
+        })
+        .unwrap();
+        assert_eq!(em.pc(), 0);
+        // and writes to x0 are dropped
+        em.execute_inst(Inst::LUI {
+            rd: Reg::zero,
+            imm: 1,
+        })
+        .unwrap();
+        assert_eq!(em[Reg::zero], 0);
+
+        // nor can the ISA be got around
+        #[cfg(feature = "rv32m")]
+        {
+            em.set_isa("rv32i").unwrap();
+            let e = (em.execute_inst(Inst::DIVU {
+                rd: Reg::a0,
+                rs1: Reg::a0,
+                rs2: Reg::zero,
+            }))
+            .unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                "illegal instruction: 00000004: divu requires the M extension"
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_hostile() {
+        let elf = std::fs::read("tests/data/fac").unwrap();
+        for len in 0..elf.len() {
+            assert!(Emulator::new(None).load_bytes(&elf[..len]).is_err());
+        }
+        for i in 0..elf.len() {
+            for bits in [0x01, 0x80, 0xff] {
+                let mut bad = elf.clone();
+                bad[i] ^= bits;
+                let _ = Emulator::new(Some(0x1000)).load_bytes(&bad);
+            }
+        }
+        assert!(Emulator::new(Some(0x1000)).load_bytes(&elf).is_err());
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut em = Emulator::new(Some(0x1000));
+        em.set_reg("sp", 0x800).unwrap();
+        em.set_reg("x8", 0x7f0).unwrap();
+        em.set_reg("zero", 42).unwrap();
+        assert!(em.set_reg("pc", 0).is_err());
+        assert_eq!(em[Reg::sp], 0x800);
+        assert_eq!(em.get_reg("fp").unwrap(), 0x7f0);
+        assert_eq!(em.get_reg("x0").unwrap(), 0);
+        assert!(em.get_reg("x32").is_err());
+
+        let mut regs = em.registers();
+        assert_eq!((regs[0], regs[2], regs[8]), (0, 0x800, 0x7f0));
+        regs[0] = 1;
+        regs[10] = 120;
+        em.set_registers(regs);
+        assert_eq!(em[Reg::zero], 0);
+        assert_eq!(em.registers()[0], 0);
+        assert_eq!(em.get_reg("a0").unwrap(), 120);
+    }
+
+    #[test]
+    fn test_symbol_lookup() {
+        let em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        let sum = em.addr_of("sum").unwrap();
+        let done = em.addr_of("done").unwrap();
+        let total = em.addr_of("total").unwrap();
+        assert_eq!(em.addr_of("nope"), None);
+        assert_eq!(em.symbol_at(sum), Some("sum"));
+        assert_eq!(em.symbol_at(sum + 4), None);
+        assert_eq!(em.symbol_at(total), Some("total"));
+        assert_eq!(em.function_containing(done), Some("sum"));
+        assert_eq!(em.function_containing(sum - 4), Some("_start"));
+        assert_eq!(em.function_containing(total), None);
+        // sum's size says where it ends, labels and all
+        let end = sum + em.symbols().info("sum").unwrap().size;
+        assert_eq!(em.symbols().symbolize_function(done), "sum");
+        assert_eq!(em.symbols().symbolize_function(end - 4), "sum");
+        assert_eq!(em.function_containing(end), None);
+        assert_eq!(em.symbols().lookup_function(end), None);
+
+        // no function symbols, so labels will have to do
+        let em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let done = em.addr_of("done").unwrap();
+        assert_eq!(em.function_containing(done + 4), Some("done"));
+        assert_eq!(em.function_containing(done - 4), Some("ret_one"));
+    }
+
+    #[test]
+    fn test_region_of() {
+        let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        assert_eq!(em.run().unwrap(), 55);
+        let names: Vec<_> = em.sections().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec![".text", ".data", ".bss"]);
+        let text = em.section(".text").unwrap();
+        assert!(text.executable && text.from_file && !text.writable);
+        let bss = em.section(".bss").unwrap();
+        assert!(!bss.executable && !bss.from_file && bss.writable);
+        assert_eq!(em.region_of(bss.range.start), Region::Section(bss));
+        assert_eq!(em.region_of(bss.range.end), Region::Unmapped);
+
+        // a program loaded over it has only its own sections
+        let bss = bss.range.clone();
+        em.load("tests/data/rodata").unwrap();
+        let names: Vec<_> = em.sections().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec![".rodata", ".text"]);
+        assert_eq!(em.region_of(bss.start), Region::Unmapped);
+
+        let mut em = Emulator::load_from("tests/data/heap", None).unwrap();
+        assert_eq!(em.run().unwrap(), 0);
+        assert_eq!(em.region_of(em.brk_start()), Region::Heap);
+        assert_eq!(em.region_of(em.brk()), Region::Unmapped);
+        em[Reg::sp] -= 16;
+        assert_eq!(em.region_of(em.stack_top() - 16), Region::Stack);
+        assert_eq!(em.region_of(em.stack_top()), Region::Unmapped);
+        assert_eq!(em.region_of(usize::MAX), Region::Unmapped);
+    }
+
+    #[test]
+    fn test_set_pc() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        assert_eq!(em.run().unwrap(), 0);
+        let fact = em.symbols().get("fact").unwrap();
+
+        // call fact(3) directly, returning straight to the exit syscall
+        em.exit_code = None;
+        em.set_reg("a0", 3).unwrap();
+        em.set_reg("ra", em.pc() as u32 - 8).unwrap();
+        em.set_pc(fact);
+        assert_eq!(em.pc(), fact);
+        assert_eq!(em.resume_with(&mut NoTrace).unwrap(), 0);
+        assert_eq!(em.get_reg("a1").unwrap(), 6);
+    }
+
+    #[test]
+    fn test_call() {
+        let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        assert_eq!(em.call("sum", &[10]).unwrap(), 55);
+        let sum = em.addr_of("sum").unwrap();
+        assert_eq!(em.call(&format!("0x{:x}", sum), &[3]).unwrap(), 6);
+        assert_eq!(em.call(&sum.to_string(), &[0]).unwrap(), 0);
+
+        // in the middle of a run, the program picks up where it left off
+        let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        em.init().unwrap();
+        for _ in 0..3 {
+            em.step_with(&mut NoTrace).unwrap();
+        }
+        let (pc, reg) = (em.pc(), em.registers());
+        assert_eq!(em.call("sum", &[100]).unwrap(), 5050);
+        assert_eq!((em.pc(), em.registers()), (pc, reg));
+        assert_eq!(em.resume_with(&mut NoTrace).unwrap(), 55);
+
+        // calling _start exits rather than returning
+        assert!(em.call("_start", &[]).is_err());
+        assert_eq!(em.exit_code(), Some(55));
+        assert!(em.call("nonesuch", &[]).is_err());
+        assert!(em.call("sum", &[0; 9]).is_err());
+    }
+
+    #[test]
+    fn test_return_from_start() {
+        let mut em = Emulator::load_from("tests/data/ret", None).unwrap();
+        assert_eq!(em.run().unwrap(), 3);
+        assert_eq!(em.pc(), em.return_address().unwrap());
+    }
+
+    /// Output collected in a buffer, for checking what a program printed.
+    #[derive(Clone, Default)]
+    struct Output(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_print_string() {
+        let mut em = Emulator::new(Some(64));
+        let output = Output::default();
+        em.set_stdout(output.clone());
+        em[0x10..0x15].copy_from_slice(b"hi\xff\xfe\0");
+        (em[Reg::a7], em[Reg::a0]) = (4, 0x10);
+        em.ecall().unwrap();
+        assert_eq!(*output.0.lock().unwrap(), b"hi\xff\xfe");
+
+        // with no NUL before the end of memory
+        em[0x10..64].fill(b'x');
+        assert!(matches!(
+            em.ecall(),
+            Err(EmulatorError::MemoryFault {
+                addr: 64,
+                len: 1,
+                access: None
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_error_context() {
+        let mut em = Emulator::new(Some(0x1000));
+        // li a0, 1; <nothing>; li a0, 2
+        em.write_u32(0xfc, 0x00100513).unwrap();
+        em.write_u32(0x104, 0x00200513).unwrap();
+        em.set_pc(0x100);
+        let Err(EmulatorError::InstructionDecode(msg)) = em.step_with(&mut NoTrace) else {
+            panic!("expected a decode error");
+        };
+        assert!(msg.contains(" at 00000100 <0x100>\n"), "{}", msg);
+        assert!(msg.contains("\n  000000fc: 00100513 li a0, 1"), "{}", msg);
+        assert!(msg.contains("\n> 00000100: 00000000 <unknown>"), "{}", msg);
+        assert_eq!(msg.lines().count(), 6);
+    }
+
+    #[test]
+    fn test_strings() {
+        let em = Emulator::load_from("tests/data/hello", None).unwrap();
+        let data = em.section(".data").unwrap().range.start;
+        assert_eq!(em.strings(4), [(data, "Hello World!\n".to_string())]);
+        assert!(em.strings(14).is_empty());
+
+        let em = Emulator::load_from("tests/data/helloc", None).unwrap();
+        let strings = em.strings(4);
+        assert!(strings.iter().any(|(_, s)| s == "Hello, World!"));
+        assert!(strings.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(strings.iter().all(|(addr, s)| em[addr + s.len()] == 0));
+    }
+
+    #[test]
+    fn test_symbol_info() {
+        let em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        let info = |name| em.symbols().info(name).map(|info| (info.kind, info.size));
+        assert_eq!(info("sum"), Some((SymbolKind::Func, 28)));
+        assert_eq!(info("total"), Some((SymbolKind::Object, 4)));
+        assert_eq!(info("loop"), Some((SymbolKind::NoType, 0)));
+    }
+
+    #[test]
+    fn test_write_protect() {
+        let mut em = Emulator::load_from("tests/data/rodata", None).unwrap();
+        em.set_stdout(std::io::sink());
+        let msg = em.addr_of("msg").unwrap();
+        let sb = em.addr_of("_start").unwrap() + 12;
+        match em.run() {
+            Err(EmulatorError::ReadOnlyWrite {
+                addr,
+                len: 1,
+                section,
+                pc,
+            }) => assert_eq!((addr, section.as_str(), pc), (msg, ".rodata", sb)),
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(em[msg], b'H');
+
+        // code's read-only too
+        assert!(em.read_only_section(sb - 2, 4).is_some());
+        assert!(em.read_only_section(msg + 6, 1).is_some());
+        assert!(em.read_only_section(em.stack_top() - 4, 4).is_none());
+
+        // nor can syscalls write there on the guest's behalf
+        let efault = -(EFAULT as i32) as u32;
+        em.set_stdin(io::Cursor::new("XXXX"));
+        for (syscall, args) in [
+            (63, [0, msg as u32, 4]),      // read
+            (278, [msg as u32, 4, 0]),     // getrandom
+            (113, [1, msg as u32, 0]),     // clock_gettime
+            (169, [sb as u32 & !3, 0, 0]), // gettimeofday
+        ] {
+            em[Reg::a7] = syscall;
+            (em[Reg::a0], em[Reg::a1], em[Reg::a2]) = (args[0], args[1], args[2]);
+            em.execute_word(0x00000073).unwrap(); // ecall
+            assert_eq!(em[Reg::a0], efault, "syscall {}", syscall);
+        }
+        assert_eq!(&em[msg..msg + 4], b"Hell");
+
+        let mut em = Emulator::load_from("tests/data/rodata", None).unwrap();
+        em.set_write_protect(false);
+        em.set_stdout(std::io::sink());
+        assert_eq!(em.run().unwrap(), 0);
+        assert_eq!(em[msg], b'h');
+        em.set_stdin(io::Cursor::new("XXXX"));
+        em[Reg::a7] = 63;
+        (em[Reg::a0], em[Reg::a1], em[Reg::a2]) = (0, msg as u32, 4);
+        em.execute_word(0x00000073).unwrap();
+        assert_eq!((em[Reg::a0], &em[msg..msg + 4]), (4, &b"XXXX"[..]));
+    }
+
+    #[test]
+    fn test_yield() {
+        let mut expected = Emulator::load_from("tests/data/primes", None).unwrap();
+        expected.set_stdout(std::io::sink());
+        expected.run().unwrap();
+
+        let mut em = Emulator::load_from("tests/data/primes", None).unwrap();
+        em.set_stdout(std::io::sink());
+        let counts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = counts.clone();
+        em.set_yield(1000, move |em| {
+            let mut seen = seen.lock().unwrap();
+            seen.push(em.instret());
+            if seen.len() % 2 == 0 {
+                Yield::Pause
+            } else {
+                Yield::Continue
+            }
+        });
+        match em.run() {
+            Err(EmulatorError::Halted(pc)) => assert_eq!(pc, em.pc),
+            result => panic!("{:?}", result),
+        }
+        // paused after the second, and then resumable to the end
+        let mut result = Err(EmulatorError::Halted(0));
+        while let Err(EmulatorError::Halted(_)) = result {
+            result = em.resume_with(&mut NoTrace);
+        }
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(em.instret(), expected.instret());
+
+        let counts = counts.lock().unwrap();
+        assert_eq!(counts.len() as u64, em.instret() / 1000);
+        for (i, count) in counts.iter().enumerate() {
+            // NB blocks run to the end, so yields can come a little late
+            let due = 1000 * (i as u64 + 1);
+            assert!((due..due + 100).contains(count), "{} {}", i, count);
+        }
+
+        em.clear_yield();
+        assert_eq!(em.run().unwrap(), 0);
+        assert_eq!(counts.len() as u64, em.instret() / 1000);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let expected = [
+            ("hello", "Hello World!\n"),
+            ("fac", "120"),
+            ("primes", "9592"),
+        ];
+        let threads: Vec<_> = (expected.iter().cycle().take(9))
+            .map(|&(program, _)| {
+                let mut em = Emulator::load_from(format!("tests/data/{}", program), None).unwrap();
+                let output = Output::default();
+                em.set_stdout(output.clone());
+                // NB the emulator moves to the thread
+                std::thread::spawn(move || (em.run(), output))
+            })
+            .collect();
+        for (thread, (program, stdout)) in threads.into_iter().zip(expected.iter().cycle()) {
+            let (result, output) = thread.join().unwrap();
+            assert_eq!(result.unwrap(), 0, "{}", program);
+            assert_eq!(*output.0.lock().unwrap(), stdout.as_bytes(), "{}", program);
+        }
+
+        // ...and snapshots can be shared between them
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        em.set_stdout(std::io::sink());
+        let snapshot = std::sync::Arc::new(em.snapshot());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let snapshot = snapshot.clone();
+                std::thread::spawn(move || {
+                    let mut em = Emulator::new(None);
+                    em.restore(&snapshot);
+                    em.set_stdout(std::io::sink());
+                    em.run().map(|_| em.instret())
+                })
+            })
+            .collect();
+        em.run().unwrap();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().unwrap(), em.instret());
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let heap = |max| {
+            let mut em = Emulator::load_from("tests/data/heap", None).unwrap();
+            em.set_limits(Limits {
+                heap: Some(max),
+                ..Default::default()
+            });
+            em.run()
+        };
+        // 16 + 32 + 8 bytes
+        assert_eq!(heap(56).unwrap(), 0);
+        assert!(matches!(
+            heap(50),
+            Err(EmulatorError::LimitExceeded(Limit::Heap(50)))
+        ));
+
+        // ...and mappings count too
+        let mut em = Emulator::load_from("tests/data/heap", None).unwrap();
+        em.set_limits(Limits {
+            heap: Some(8192),
+            ..Default::default()
+        });
+        em.init().unwrap();
+        let mmap = |em: &mut Emulator, len| {
+            (em[Reg::a7], em[Reg::a1], em[Reg::a3]) = (222, len, MAP_ANONYMOUS);
+            em.execute_word(0x00000073) // ecall
+        };
+        mmap(&mut em, 4096).unwrap();
+        mmap(&mut em, 4096).unwrap();
+        assert!(matches!(
+            mmap(&mut em, 1),
+            Err(EmulatorError::LimitExceeded(Limit::Heap(8192)))
+        ));
+
+        let mut em = Emulator::load_from("tests/data/hello", None).unwrap();
+        em.set_stdout(std::io::sink());
+        em.set_limits(Limits {
+            output: Some(64),
+            ..Default::default()
+        });
+        assert_eq!(em.run().unwrap(), 0);
+        let mut em = Emulator::load_from("tests/data/hello", None).unwrap();
+        em.set_stdout(std::io::sink());
+        em.set_limits(Limits {
+            output: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(
+            em.run().unwrap_err().to_string(),
+            "resource limit exceeded: output of 5 bytes"
+        );
+
+        let mut em = Emulator::load_from("tests/data/primes", None).unwrap();
+        em.set_stdout(std::io::sink());
+        em.set_limits(Limits {
+            cpu_time: Some(Duration::from_millis(1)),
+            ..Default::default()
+        });
+        assert!(matches!(
+            em.run(),
+            Err(EmulatorError::LimitExceeded(Limit::CpuTime(_)))
+        ));
+        // it's checked every so often, not every instruction
+        assert!(em.instret() >= CPU_TIME_CHECK);
+    }
+
+    #[test]
+    fn test_map_file() {
+        let mut em = Emulator::load_from("tests/data/mapped", None).unwrap();
+        em.set_stdout(std::io::sink());
+        assert_eq!(
+            em.map_file(0x40000, "tests/data/mapped.txt", false)
+                .unwrap(),
+            19
+        );
+        assert_eq!(em.run().unwrap(), 0);
+        assert_eq!(em.memory(0x40000, 6).unwrap(), b"Mapped");
+
+        let mut em = Emulator::load_from("tests/data/mapped", None).unwrap();
+        em.map_file(0x40000, "tests/data/mapped.txt", true).unwrap();
+        match em.run() {
+            Err(EmulatorError::ReadOnlyWrite { addr, section, .. }) => {
+                assert_eq!((addr, section.as_str()), (0x40000, "tests/data/mapped.txt"))
+            }
+            result => panic!("{:?}", result),
+        }
+
+        // overlapping, and off the end of memory
+        let text = em.section(".text").unwrap().range.start;
+        assert!(matches!(
+            em.map_file(text, "tests/data/mapped.txt", true),
+            Err(EmulatorError::Config(_))
+        ));
+        assert!(matches!(
+            em.map_file(0x40010, "tests/data/mapped.txt", true),
+            Err(EmulatorError::Config(_))
+        ));
+        let end = em.mem.len() - 4;
+        assert!(matches!(
+            em.map_file(end, "tests/data/mapped.txt", true),
+            Err(EmulatorError::ImageTooLarge { .. })
+        ));
+        assert!(em.map_file(0x40000, "tests/data/missing", true).is_err());
+    }
+
+    #[test]
+    fn test_virtual_time() {
+        let mut em = Emulator::load_from("tests/data/clock", None).unwrap();
+        em.set_virtual_time(Some(1_000_000));
+        assert_eq!(em.run().unwrap(), 206);
+        // the time of day is that of the 215 instructions before the call,
+        // from the epoch
+        let tv = em.memory(em.addr_of("tv").unwrap(), 16).unwrap();
+        assert_eq!(tv, [[0; 8], 215u64.to_le_bytes()].concat());
+
+        em.set_virtual_time(Some(1));
+        assert_eq!(em.time_ns(), em.instret() * 1_000_000_000);
+
+        // a seed makes for a microsecond per instruction too
+        let mut em = Emulator::load_from("tests/data/clock", None).unwrap();
+        em.set_seed(1);
+        assert_eq!(em.run().unwrap(), 206);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut em = Emulator::load_from("tests/data/crash", None).unwrap();
+        let expected = ["inner+0x14", "outer+0x14", "_start+0x4"];
+        let symbolized = |em: &Emulator| {
+            (em.backtrace().into_iter())
+                .map(|addr| em.symbols().symbolize(addr))
+                .collect::<Vec<_>>()
+        };
+
+        // by way of the frame pointers
+        assert!(matches!(em.run(), Err(EmulatorError::MemoryFault { .. })));
+        assert_eq!(symbolized(&em), expected);
+
+        // ...or without them, by scanning the stack
+        em[Reg::fp] = 0;
+        assert_eq!(symbolized(&em), expected);
+
+        // ...or, while tracing, from the shadow call stack
+        let mut coverage = Coverage::new();
+        assert!(em.run_with(&mut coverage).is_err());
+        assert_eq!(em.calls.len(), 2);
+        assert_eq!(symbolized(&em), expected);
+    }
+
+    #[test]
+    fn test_unknown_syscalls() {
+        let mut em = Emulator::new(Some(64));
+        em[Reg::a7] = 1234;
+        em[Reg::a0] = 42;
+        em.ecall().unwrap();
+        assert_eq!(em[Reg::a0], 42);
+
+        em.set_unknown_syscalls(UnknownSyscalls::Enosys);
+        em.ecall().unwrap();
+        assert_eq!(em[Reg::a0], -(ENOSYS as i32) as u32);
+
+        // ...including the ones left out of the personality
+        em.set_unknown_syscalls(UnknownSyscalls::Stop);
+        em.set_personality(Personality::Linux);
+        em[Reg::a7] = 1;
+        assert!(matches!(em.ecall(), Err(EmulatorError::UnknownSyscall(1))));
+    }
+
+    #[test]
+    fn test_read_int() {
+        let mut em = Emulator::new(Some(64));
+        em.set_stdin(io::Cursor::new("-3\n  42 \nabc\n\n4294967295\n"));
+        em[Reg::a7] = 5;
+        for expected in [-3i32 as u32, 42, u32::MAX] {
+            em.ecall().unwrap();
+            assert_eq!(em[Reg::a0], expected);
+        }
+        assert!(matches!(em.ecall(), Err(EmulatorError::Execution(_))));
+    }
+
+    #[test]
+    fn test_image_too_large() {
+        let result = Emulator::load_from("tests/data/fac", Some(0x10000));
+        assert!(matches!(
+            result,
+            Err(EmulatorError::ImageTooLarge { ref what, available: 0x10000, .. })
+                if what == "section .text"
+        ));
+        let message = result.err().unwrap().to_string();
+        assert!(message.ends_with("try --memory 256k"), "{}", message);
+        assert_eq!(memory_for(0x100c8), 256 << 10);
+        assert_eq!(memory_for(usize::MAX), usize::MAX);
+    }
+
+    #[test]
+    fn test_code_outside_text() {
+        let mut em = Emulator::load_from("tests/data/boot", None).unwrap();
+        let start = em.addr_of("_start").unwrap();
+        assert!(!em.section(".text").unwrap().range.contains(&start));
+        assert_eq!(em.function_containing(start), Some("_start"));
+        assert_eq!(em.run().unwrap(), 7);
+
+        // data isn't code, though
+        let mut em = Emulator::load_from("tests/data/hello", None).unwrap();
+        let data = em.section(".data").unwrap().range.start;
+        assert_eq!(em.function_containing(data), None);
+        match em.call(&data.to_string(), &[]) {
+            Err(EmulatorError::Execution(msg)) => assert!(msg.contains("outside executable code")),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        let mut em = Emulator::load_from("tests/data/signature", None).unwrap();
+        assert_eq!(em.run().unwrap(), 0);
+        let mut out = Vec::new();
+        em.write_signature(&mut out, 8).unwrap();
+        assert_eq!(out, b"0000002a12345678\ndeadbeefdeadbe2a\n");
+
+        // odd values other than 1 report failure
+        em.htif(7 << 1 | 1);
+        assert_eq!(em.exit_code(), Some(7));
+
+        let em = Emulator::load_from("tests/data/fac", None).unwrap();
+        assert!(em.write_signature(&mut Vec::new(), 4).is_err());
+    }
+
+    #[test]
+    fn test_mmap() {
+        let mut em = Emulator::new(Some(0x4000));
+        em.mmap_top = 0x2000;
+        for expected in [0x2000, 0x3000, -(ENOMEM as i32) as u32] {
+            em[Reg::a7] = 222;
+            em[Reg::a1] = 100;
+            em[Reg::a3] = MAP_ANONYMOUS;
+            em.ecall().unwrap();
+            assert_eq!(em[Reg::a0], expected);
+        }
+    }
+
+    #[test]
+    fn test_hypercall() {
+        let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        let mut calls = 0;
+        em.register_hypercall(7, move |em, args| {
+            calls += 1;
+            // e.g., peek at the guest's memory
+            let total = em.addr_of("total").unwrap();
+            em[total] = args[0] as u8;
+            Ok(calls)
+        });
+        em.register_hypercall(8, |_, _| {
+            Err(EmulatorError::Execution("host says no".to_string()))
+        });
+
+        for (n, expected) in [(7, 1), (7, 2), (9, -(ENOSYS as i32) as u32)] {
+            em[Reg::a7] = HYPERCALL;
+            em[Reg::a0] = n;
+            em[Reg::a1] = 42;
+            em.ecall().unwrap();
+            assert_eq!(em[Reg::a0], expected);
+        }
+        assert_eq!(em[em.addr_of("total").unwrap()], 42);
+
+        em[Reg::a7] = HYPERCALL;
+        em[Reg::a0] = 8;
+        assert!(em.ecall().is_err());
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        let total = em.addr_of("total").unwrap();
+        em.memory_mut(total, 4)
+            .unwrap()
+            .copy_from_slice(&42u32.to_le_bytes());
+        assert_eq!(em.memory(total, 4).unwrap(), [42, 0, 0, 0]);
+        assert_eq!(em.read_u32(total).unwrap(), 42);
+        assert!(em.memory(em.mem.len() - 2, 4).is_err());
+        assert!(em.memory_mut(usize::MAX, 2).is_err());
+
+        // patch sum's `li a0, 0` into `li a0, 1`, after it's been cached
+        assert_eq!(em.call("sum", &[10]).unwrap(), 55);
+        let sum = em.addr_of("sum").unwrap();
+        let code = em.memory_mut(sum + 4, 4).unwrap();
+        assert_eq!(code, 0x00000513u32.to_le_bytes());
+        code.copy_from_slice(&0x00100513u32.to_le_bytes());
+        assert_eq!(em.call("sum", &[10]).unwrap(), 56);
+    }
+
+    #[test]
+    fn test_dump_section() {
+        let em = Emulator::load_from("tests/data/hello", None).unwrap();
+        let text = em.dump_section(".text", true).unwrap();
+        assert!(text.starts_with(".text:\n_start:\n  "));
+        assert!(text.contains(": 00100513 li a0, 1\n"));
+
+        // with source lines, for a program that has them
+        let cover = Emulator::load_from("tests/data/cover", None).unwrap();
+        let text = cover.dump_section(".text", true).unwrap();
+        assert!(text.contains(": 00054863 blt a0, zero, 110c4  # "));
+        assert!(text.contains("cover.s:4\n"));
+
+        let data = em.dump_section(".data", false).unwrap();
+        let start = em.section(".data").unwrap().range.start;
+        assert_eq!(
+            data,
+            format!(
+                ".data:\n  {:08x}  48 65 6c 6c 6f 20 57 6f  72 6c 64 21 0a           \
+                 |Hello World!.|\n",
+                start
+            )
+        );
+
+        // repeated rows are collapsed, short of the last
+        let mut em = Emulator::new(Some(0x100));
+        em.sections.push(Section {
+            name: ".data".into(),
+            range: 0x10..0x60,
+            writable: true,
+            executable: false,
+            from_file: true,
+        });
+        em[0x10..0x14].copy_from_slice(b"rvem");
+        let data = em.dump_section(".data", false).unwrap();
+        let rows: Vec<_> = data
+            .lines()
+            .map(|line| line.get(..12).unwrap_or(line))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ".data:",
+                "  00000010  ",
+                "  00000020  ",
+                "  *",
+                "  00000050  "
+            ]
+        );
+        assert!(data.contains(" 72 76 65 6d 00 "));
+        assert!(data.contains("|rvem............|"));
+
+        assert!(em.dump_section(".nonesuch", false).is_err());
+
+        // labels, local and otherwise, go where they are in the source
+        let em = Emulator::load_from("tests/data/funcs", None).unwrap();
+        let text = em.dump_section(".text", true).unwrap();
+        let labels: Vec<_> = text.lines().filter(|line| line.ends_with(':')).collect();
+        assert_eq!(labels, [".text:", "_start:", "sum:", "loop:", "done:"]);
+        assert!(text.contains("\nloop:\n  110ec: 00028863 beq t0, zero, 110fc\n"));
+    }
 }
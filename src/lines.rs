@@ -0,0 +1,140 @@
+use gimli::{EndianSlice, LittleEndian};
+use goblin::elf::Elf;
+use std::collections::HashMap;
+
+/// Source line information, as parsed from a program's DWARF `.debug_line`
+/// section.
+#[derive(Clone, Debug, Default)]
+pub struct LineTable {
+    /// Source file paths
+    files: Vec<String>,
+    /// (address, (file index, line)) pairs, sorted by address; `None` marks
+    /// the end of a sequence
+    rows: Vec<(usize, Option<(usize, u32)>)>,
+}
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+impl LineTable {
+    /// Returns a new, empty line table.
+    pub fn new() -> LineTable {
+        Default::default()
+    }
+
+    /// Parses the line table from `elf` (whose raw bytes are `buf`), which
+    /// will be empty if the program doesn't have any debug information.
+    pub(crate) fn parse(elf: &Elf, buf: &[u8]) -> Result<LineTable, gimli::Error> {
+        let section = |id: gimli::SectionId| -> Result<Reader, gimli::Error> {
+            let data = elf
+                .section_headers
+                .iter()
+                .find(|sh| elf.shdr_strtab.get_at(sh.sh_name) == Some(id.name()))
+                .and_then(|sh| sh.file_range())
+                .map(|range| &buf[range])
+                .unwrap_or(&[]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        };
+        let dwarf = gimli::Dwarf::load(section)?;
+
+        let mut table = LineTable::new();
+        let mut file_indices: HashMap<String, usize> = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let addr = row.address() as usize;
+                if row.end_sequence() {
+                    table.rows.push((addr, None));
+                    continue;
+                }
+                let (Some(file), Some(line)) = (row.file(header), row.line()) else {
+                    continue;
+                };
+
+                let mut path = dwarf
+                    .attr_string(&unit, file.path_name())?
+                    .to_string_lossy()
+                    .into_owned();
+                if !path.starts_with('/') {
+                    let dir = match file.directory(header) {
+                        Some(dir) => Some(dwarf.attr_string(&unit, dir)?.to_string_lossy()),
+                        None => None,
+                    };
+                    if let Some(dir) = dir.filter(|dir| !dir.is_empty()) {
+                        path = format!("{}/{}", dir, path);
+                    }
+                }
+
+                let index = *file_indices.entry(path.clone()).or_insert_with(|| {
+                    table.files.push(path);
+                    table.files.len() - 1
+                });
+                table.rows.push((addr, Some((index, line.get() as u32))));
+            }
+        }
+        // NB end-of-sequence markers sort ahead of any sequence that starts
+        // at the same address
+        table.rows.sort_by_key(|(addr, row)| (*addr, row.is_some()));
+        Ok(table)
+    }
+
+    /// Returns the source file and line number for `addr`, if known.
+    ///
+    /// ```rust
+    /// use rvem::Emulator;
+    ///
+    /// let em = Emulator::load_from("tests/data/cover", None).unwrap();
+    /// let (file, line) = em.lines().lookup(em.symbols().get("_start").unwrap()).unwrap();
+    /// assert!(file.ends_with("cover.s"));
+    /// assert_eq!(line, 28);
+    /// ```
+    pub fn lookup(&self, addr: usize) -> Option<(&str, u32)> {
+        let idx = self.rows.partition_point(|(a, _)| *a <= addr);
+        if idx == 0 {
+            return None;
+        }
+        let (file, line) = self.rows[idx - 1].1?;
+        Some((&self.files[file], line))
+    }
+
+    /// Returns the address of the first instruction for `line` of `file`,
+    /// or (if that line has no code of its own) for the next line after it
+    /// that does. `file` needn't be the full path of the source file, just
+    /// enough of the end of it to identify it, e.g. `main.c`.
+    ///
+    /// ```rust
+    /// use rvem::Emulator;
+    ///
+    /// let em = Emulator::load_from("tests/data/cover", None).unwrap();
+    /// let start = em.symbols().get("_start").unwrap();
+    /// assert_eq!(em.lines().addr_of("cover.s", 28), Some(start));
+    /// assert_eq!(em.lines().addr_of("data/cover.s", 27), Some(start));
+    /// assert_eq!(em.lines().addr_of("other.s", 28), None);
+    /// ```
+    pub fn addr_of(&self, file: &str, line: u32) -> Option<usize> {
+        let matches = |path: &str| {
+            path == file
+                || path
+                    .strip_suffix(file)
+                    .is_some_and(|dir| dir.ends_with('/'))
+        };
+        self.rows
+            .iter()
+            .filter_map(|(addr, row)| {
+                let (index, l) = (*row)?;
+                (l >= line && matches(&self.files[index])).then_some((l, *addr))
+            })
+            .min()
+            .map(|(_, addr)| addr)
+    }
+
+    /// Returns whether the table is empty (e.g., because the program was
+    /// built without debug information).
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
@@ -1,54 +1,1359 @@
 use ::rvem::Emulator;
-use clap::Parser;
-use rvem::{EmulatorError, DEFAULT_MEMORY_SIZE};
-use std::{env, process};
+use clap::{Parser, Subcommand, ValueEnum};
+use rvem::{
+    compare_disasm, find_objdump, verify_decode, Breakpoints, CallGraph, CoSim, CostProfile,
+    CostTable, Coverage, EmulatorError, FoldedStacks, GoldenTrace, HeapProfile, Hotspots, LogTrace,
+    MachineConfig, MemCheck, MemUsage, Misaligned, Profile, Reg, SpikeTrace, Stats, Taint, Timing,
+    TraceSink, UnknownSyscalls, DEFAULT_MEMORY_SIZE,
+};
+#[cfg(feature = "serialize")]
+use rvem::{JsonTrace, TestManifest, TestOutcome, Yield};
+#[cfg(feature = "serialize")]
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{self, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+use strum::IntoEnumIterator;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about)]
+#[command(
+    version,
+    about,
+    long_about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 /// A RISC-V emulator.
 ///
 /// rvem is an emulator that supports a subset of the RISC-V instruction set -
 /// specifically, the rv32i base instruction set and the rv32m extensions.
+///
+/// Exit status: the program's own exit code, if it exits (or returns from
+/// its entrypoint); otherwise 121 if it couldn't be loaded, 122 if it ran
+/// into an instruction that couldn't be decoded or is illegal, 123 if it
+/// accessed memory out of bounds or misaligned (or wrote to read-only
+/// memory), 124 if it ran out of instructions (--max-instructions) or some
+/// other resource (--max-heap and so on), or into an idle loop, and 120 for
+/// any other error (bad options, I/O errors, and the like).
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Report instructions executed, elapsed time, and MIPS on exit
+    #[arg(long, default_value_t = false)]
+    bench: bool,
+
+    /// Stop when execution reaches LOC (a symbol, an address, or a
+    /// file:line in the program's source), and report the registers; may be
+    /// given more than once
+    #[arg(long, value_name = "LOC")]
+    break_at: Vec<String>,
+
+    /// Keep going after reporting each --break-at breakpoint, instead of
+    /// stopping at the first one
+    #[arg(long, default_value_t = false, requires = "break_at")]
+    break_continue: bool,
+
+    /// Also report N instructions from each --break-at breakpoint, and N
+    /// words from the top of the stack
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "break_at")]
+    break_context: usize,
+
+    /// Write the program's dynamic call graph to FILE, in graphviz DOT format
+    #[arg(long, value_name = "FILE")]
+    call_graph: Option<String>,
+
+    /// Checkpoint the program's state every N instructions as it runs (see
+    /// --crash-snapshot)
+    #[arg(long, value_name = "N")]
+    checkpoint_every: Option<u64>,
+
+    /// How many of the most recent checkpoints to keep
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 16,
+        requires = "checkpoint_every"
+    )]
+    keep_checkpoints: usize,
+
+    /// Run in lockstep with a reference simulator, halting at the first
+    /// instruction where the two disagree
+    ///
+    /// CMD is run with the shell, and its stderr read as a commit log in
+    /// spike --log-commits format (or the JSONL format of --trace-file); e.g.,
+    /// --cosim 'spike --isa=rv32im --log-commits prog'.
+    #[arg(long, value_name = "CMD", conflicts_with = "cosim_log")]
+    cosim: Option<String>,
+
+    /// Like --cosim, but reads the reference commit log from FILE (which may
+    /// be a named pipe)
+    #[arg(long, value_name = "FILE")]
+    cosim_log: Option<String>,
+
+    /// Report the total and per-function cost of execution, according to the
+    /// cost table in FILE
+    ///
+    /// Each line of the table is `key = cost`, where key is an instruction
+    /// class (alu, branch, jump, load, store, mul, div, fence, system), a
+    /// mnemonic, default, or taken (the extra cost of a taken branch or jump).
+    #[arg(long, value_name = "FILE")]
+    cost_model: Option<String>,
+
+    /// Describe the machine to emulate with the TOML file FILE
+    ///
+    /// The file can give the memory size (memory), ISA string (isa, e.g.,
+    /// rv32im), strict decoding (strict), misaligned loads and stores
+    /// (misaligned: emulate or trap), syscalls provided (syscalls: all,
+    /// linux, or spim), what to do with others (unknown_syscalls: ignore,
+    /// enosys, or stop), program arguments (args), a seed for reproducible
+    /// runs (seed), a rate for virtual time (virtual_time), device placements
+    /// ([devices] tohost), and standard I/O redirections ([io] stdin, stdout,
+    /// and stderr, as paths). Options given on the command line take
+    /// precedence.
+    #[cfg(feature = "serialize")]
+    #[arg(long, value_name = "FILE")]
+    config: Option<String>,
+
+    /// Report instruction and basic block coverage for each function on exit
+    #[arg(long, default_value_t = false)]
+    coverage: bool,
+
+    /// Write the guest memory in RANGE (START..END, where each is a symbol
+    /// or an address) to FILE, when the program exits or stops at a
+    /// --break-at breakpoint; may be given more than once
+    #[arg(long, num_args = 2, value_names = ["FILE", "RANGE"])]
+    dump_memory: Vec<String>,
+
     /// Dump the program and exit
     #[arg(short = 'D', long, default_value_t = false)]
     dump: bool,
 
+    /// Dump just the section NAME (e.g., .data) and exit; may be given more
+    /// than once
+    #[arg(long, value_name = "NAME", conflicts_with = "dump")]
+    dump_section: Vec<String>,
+
+    /// List the program's symbols (address, type, size, and name) and exit;
+    /// with a PATTERN, list just those whose names contain it, or match it
+    /// if it's a glob (using * and ?)
+    #[arg(long, value_name = "PATTERN", num_args = 0..=1, require_equals = true, default_missing_value = "", conflicts_with_all = ["dump", "dump_section"])]
+    symbols: Option<String>,
+
+    /// How to show the contents of each --dump-section
+    #[arg(long, value_enum, default_value_t = SectionFormat::Auto, requires = "dump_section")]
+    section_format: SectionFormat,
+
+    /// Format for --dump
+    #[arg(long, value_enum, default_value_t = DumpFormat::Text, requires = "dump")]
+    format: DumpFormat,
+
+    /// Print the registers (one per line, for diffing) on exit
+    #[arg(long, default_value_t = false)]
+    final_regs: bool,
+
+    /// Also print the word of memory at LOC (a symbol or address) on exit;
+    /// may be given more than once
+    #[arg(long, value_name = "LOC", requires = "final_regs")]
+    final_mem: Vec<String>,
+
+    /// Write folded call stacks (for flamegraphs) to FILE
+    #[arg(long, value_name = "FILE")]
+    folded: Option<String>,
+
+    /// Sample the call stack every N instructions for --folded
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    sample_period: u64,
+
+    /// Check the run against a golden trace previously written by
+    /// --record-golden, exiting with status 1 if it doesn't match
+    ///
+    /// Checkpoints are taken at the same period the golden trace was
+    /// recorded with.
+    #[arg(long, value_name = "FILE", conflicts_with = "record_golden")]
+    check_golden: Option<String>,
+
+    /// Instructions between checkpoints for --record-golden
+    #[arg(long, value_name = "N", default_value_t = 10000)]
+    golden_period: u64,
+
+    /// Report peak heap usage, allocation counts, and blocks still allocated
+    /// on exit (i.e., potential leaks)
+    ///
+    /// Heap growth is tracked through the brk and mmap syscalls; individual
+    /// blocks are tracked by intercepting calls to malloc, calloc, realloc,
+    /// and free, if the program has them.
+    #[arg(long, default_value_t = false)]
+    heap_profile: bool,
+
+    /// Report the N most-executed instructions on exit
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "10")]
+    hotspots: Option<usize>,
+
+    /// Write coverage data to FILE as an lcov tracefile
+    #[arg(long, value_name = "FILE")]
+    lcov: Option<String>,
+
     /// Set log level (overrides RUST_LOG environment variable)
     ///
     /// Available options include: error (default), warn, info, debug,
-    /// trace (most verbose).
-    #[arg(short, long)]
+    /// trace (most verbose). Levels can also be set per subsystem - loader,
+    /// exec (each instruction, at debug level), syscalls, or devices - with
+    /// a comma-separated list of subsystem=level; e.g., warn,syscalls=trace.
+    #[arg(short, long, conflicts_with = "quiet")]
     log_level: Option<String>,
 
-    /// Memory to allocate for the emulator
-    #[arg(short, long, value_name = "BYTES", default_value_t = DEFAULT_MEMORY_SIZE)]
-    memory: usize,
+    /// Don't log anything (not even errors)
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Report the maximum stack depth and heap size reached on exit, for
+    /// sizing RAM
+    #[arg(long, default_value_t = false)]
+    mem_report: bool,
+
+    /// Report loads of memory that has never been written (e.g.,
+    /// uninitialized stack variables)
+    #[arg(long, default_value_t = false)]
+    memcheck: bool,
+
+    /// Stop with an error (and dump the registers) if the program hasn't
+    /// exited after N instructions
+    #[arg(long, value_name = "N")]
+    max_instructions: Option<u64>,
+
+    /// Stop with an error if the program asks for more than BYTES of memory
+    /// with brk and mmap
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_heap: Option<usize>,
+
+    /// Stop with an error if the program writes more than BYTES to its
+    /// standard output and error
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_output: Option<usize>,
+
+    /// Stop with an error if the program runs for more than SECS seconds of
+    /// host CPU time
+    #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+    max_cpu_time: Option<Duration>,
+
+    /// Memory to allocate for the emulator [default: 1M]
+    ///
+    /// BYTES may have a k, M, or G suffix (e.g., 64k or 8M).
+    #[arg(short, long, value_name = "BYTES", value_parser = parse_size)]
+    memory: Option<usize>,
+
+    /// Show mangled C++ and Rust symbol names as they are, instead of
+    /// demangling them
+    #[arg(long, default_value_t = false)]
+    no_demangle: bool,
+
+    /// Let the program write to its read-only sections (.text, .rodata, and
+    /// so on), e.g., to modify its own code, instead of stopping with an
+    /// error
+    #[arg(long, default_value_t = false)]
+    no_write_protect: bool,
+
+    /// Place the contents of a host file in guest memory at ADDR (an
+    /// address or symbol) before running, read-only if followed by ",ro";
+    /// may be repeated
+    #[arg(long, value_name = "ADDR=PATH[,ro]")]
+    map_file: Vec<String>,
+
+    /// Report instructions and cycles spent in each function on exit
+    #[arg(long, default_value_t = false)]
+    profile: bool,
+
+    /// Record a golden trace of the run to FILE, for later use with
+    /// --check-golden
+    ///
+    /// The trace is a series of checkpoints, each a hash of everything the
+    /// program has done up to that point.
+    #[arg(long, value_name = "FILE")]
+    record_golden: Option<String>,
+
+    /// Resume execution from a snapshot previously written by
+    /// --save-snapshot, instead of starting the program from the beginning
+    ///
+    /// The snapshot includes the whole program, so FILE may be omitted.
+    #[cfg(feature = "serialize")]
+    #[arg(long, value_name = "FILE")]
+    restore_snapshot: Option<String>,
+
+    /// Write a snapshot of the emulator's state to FILE when the program
+    /// exits or execution is halted (e.g., by --cosim), for later use with
+    /// --restore-snapshot
+    #[cfg(feature = "serialize")]
+    #[arg(long, value_name = "FILE")]
+    save_snapshot: Option<String>,
+
+    /// If the program fails, write a snapshot of its state as of the last
+    /// checkpoint before the failure to FILE, for re-examining it with
+    /// --restore-snapshot
+    #[cfg(feature = "serialize")]
+    #[arg(long, value_name = "FILE", requires = "checkpoint_every")]
+    crash_snapshot: Option<String>,
+
+    /// Write the compliance test signature (memory between the
+    /// begin_signature and end_signature symbols) to FILE on exit, for
+    /// RISCOF
+    #[arg(long, value_name = "FILE")]
+    signature: Option<String>,
+
+    /// Bytes per line of the --signature file
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    signature_granularity: usize,
+
+    /// Report a summary of the run on exit, in FORMAT: exit code (or error),
+    /// instructions retired, wall time, syscall counts, and peak memory use
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    summary: Option<SummaryFormat>,
+
+    /// Write the --summary to FILE, instead of stderr
+    #[arg(long, value_name = "FILE", requires = "summary")]
+    summary_file: Option<String>,
+
+    /// Make the run reproducible, with SEED determining what getrandom
+    /// returns, and the time CSR counting a microsecond per instruction
+    #[arg(long, value_name = "SEED")]
+    seed: Option<u64>,
+
+    /// Run on virtual time: the time CSR, gettimeofday, and clock_gettime
+    /// advance a second for every RATE instructions retired (by default, a
+    /// million), instead of following the host clock
+    #[arg(long, value_name = "RATE", num_args = 0..=1, require_equals = true, default_missing_value = "1000000", value_parser = clap::value_parser!(u64).range(1..))]
+    virtual_time: Option<u64>,
+
+    /// Set register REG to VALUE (a symbol, an address, or a possibly
+    /// negative number) when the program starts, e.g., --set-reg a0=5; may be
+    /// given more than once
+    #[arg(long, value_name = "REG=VALUE")]
+    set_reg: Vec<String>,
+
+    /// Report instruction counts by mnemonic, branch behavior, and syscalls
+    /// on exit
+    #[arg(long, default_value_t = false)]
+    stats: bool,
+
+    /// Reject reserved and hint instruction encodings
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Track data from SOURCE as tainted, and report where it ends up
+    ///
+    /// SOURCE is either `stdin` or a memory range, given as ADDR:LEN where
+    /// ADDR is a symbol or an address (e.g., buf:16 or 0x11000:4). Tainted
+    /// data is reported when it's jumped through, passed to a syscall, or
+    /// passed to a --taint-sink function. May be given more than once.
+    #[arg(long, value_name = "SOURCE")]
+    taint: Vec<String>,
+
+    /// Report calls to FUNCTION (a symbol or address) with tainted arguments;
+    /// may be given more than once
+    #[arg(long, value_name = "FUNCTION", requires = "taint")]
+    taint_sink: Vec<String>,
+
+    /// Count cycles using a simple timing model, and report them on exit
+    ///
+    /// Latencies can be overridden with a comma-separated list of
+    /// class=cycles, where class is one of base, load, store, mul, div, or
+    /// taken (the extra cost of a taken branch or jump); e.g.,
+    /// --timing=load=3,div=20.
+    #[arg(long, value_name = "LATENCIES", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    timing: Option<Timing>,
+
+    /// Write a record of each instruction executed to FILE
+    #[arg(long, value_name = "FILE")]
+    trace_file: Option<String>,
+
+    /// Format for --trace-file
+    #[cfg_attr(
+        feature = "serialize",
+        arg(long, value_enum, default_value_t = TraceFormat::Jsonl)
+    )]
+    #[cfg_attr(
+        not(feature = "serialize"),
+        arg(long, value_enum, default_value_t = TraceFormat::Spike)
+    )]
+    trace_format: TraceFormat,
+
+    /// What to do with syscalls rvem doesn't provide: ignore them (the
+    /// default), return -ENOSYS (enosys), or stop with an error (stop)
+    #[arg(long, value_name = "MODE")]
+    unknown_syscalls: Option<UnknownSyscalls>,
+
+    /// Trap on misaligned loads and stores, rather than carrying them out
+    #[arg(long, default_value_t = false)]
+    trap_misaligned: bool,
+
+    /// Cross-check rvem's disassembly of the program against objdump's
+    /// (from $RVEM_OBJDUMP, a riscv*-objdump, or llvm-objdump), report the
+    /// instructions they disagree on, and exit
+    #[arg(long, default_value_t = false)]
+    verify_disasm: bool,
+
+    /// Before running the program, check that every instruction in it
+    /// encodes back to one that decodes the same (a self-check of rvem's
+    /// encoder), and report any that don't
+    #[arg(long, default_value_t = false)]
+    verify_decode: bool,
 
     /// RISC-V program to emulate
-    file: String,
+    #[cfg_attr(
+        feature = "serialize",
+        arg(required_unless_present = "restore_snapshot")
+    )]
+    #[cfg_attr(not(feature = "serialize"), arg(required = true))]
+    file: Option<String>,
+
+    /// Arguments to pass to the program
+    ///
+    /// With these, rvem can serve as a Cargo runner (runner = "rvem --"):
+    /// the program gets its arguments and stdio, and rvem exits with the
+    /// program's exit code.
+    #[arg(
+        value_name = "ARGS",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    program_args: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare two instruction traces and report the first point at which
+    /// they diverge
+    ///
+    /// Traces may be in either of the formats written by --trace-file (or
+    /// come from spike --log-commits). They're aligned on the first PC they
+    /// have in common before being compared.
+    TraceDiff {
+        /// First trace
+        a: String,
+        /// Second trace
+        b: String,
+    },
+    /// Compare two snapshots (from --save-snapshot) and report the
+    /// registers and ranges of memory that differ
+    ///
+    /// Exits with status 1 if they differ at all.
+    #[cfg(feature = "serialize")]
+    StateDiff {
+        /// First snapshot
+        a: String,
+        /// Second snapshot
+        b: String,
+    },
+    /// Run the rv32ui and rv32um tests from riscv-tests, and summarize the
+    /// results
+    ///
+    /// Every file in DIR named rv32ui-* or rv32um-* (without an extension,
+    /// so as to skip .dump files and the like) is run as a test binary. A
+    /// test passes if it writes 1 to its HTIF tohost word, and fails if it
+    /// writes any other exit code.
+    TestSuite {
+        /// Directory containing the test binaries (e.g., riscv-tests'
+        /// isa/ build directory)
+        dir: String,
+        /// Give up on a test after this many instructions
+        #[arg(long, value_name = "N", default_value_t = 1_000_000)]
+        timeout: u64,
+    },
+    /// Call a program's functions as a manifest says to, and check what
+    /// they return
+    ///
+    /// The manifest is a TOML file of [[test]] tables, each naming a
+    /// function, its arguments, and the value it should return and/or the
+    /// memory contents it should leave, e.g.:
+    ///
+    ///   [[test]]
+    ///   function = "fill"
+    ///   args = ["buf", 3, 0x2a]
+    ///   memory = [{ at = "buf", bytes = [42, 42, 42] }]
+    ///
+    /// Each test is run on a freshly loaded copy of the program. Exits with
+    /// status 1 if any test fails.
+    #[cfg(feature = "serialize")]
+    Test {
+        /// RISC-V program whose functions to test
+        file: String,
+        /// The tests to run
+        #[arg(long, value_name = "FILE")]
+        manifest: String,
+        /// Give up on a call after this many instructions
+        #[arg(long, value_name = "N", default_value_t = 1_000_000)]
+        timeout: u64,
+    },
+    /// List the strings in a program's data, with their addresses
+    ///
+    /// Like strings(1), but only looks in the sections rvem loads that
+    /// aren't executable (.rodata, .data, and so on), and only at
+    /// NUL-terminated strings.
+    Strings {
+        /// RISC-V program to look in
+        file: String,
+        /// Only list strings at least this long
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 4)]
+        min_len: usize,
+    },
+}
+
+/// Returns the exit status for a run that failed with `e` (see the
+/// description of exit status in [Args]), so that scripts can tell a program
+/// that couldn't be loaded from one that crashed from one that exited with
+/// an error of its own.
+fn exit_status(e: &EmulatorError) -> i32 {
+    match e {
+        EmulatorError::ELF(_) | EmulatorError::EntryPoint | EmulatorError::ImageTooLarge { .. } => {
+            121
+        }
+        EmulatorError::InstructionDecode(_) | EmulatorError::IllegalInstruction(_) => 122,
+        EmulatorError::MemoryFault { .. }
+        | EmulatorError::MisalignedAccess { .. }
+        | EmulatorError::MisalignedFetch { .. }
+        | EmulatorError::ReadOnlyWrite { .. } => 123,
+        EmulatorError::InstructionLimit { .. }
+        | EmulatorError::LimitExceeded(_)
+        | EmulatorError::IdleLoop(_) => 124,
+        _ => 120,
+    }
+}
+
+/// Formats the guest's call stack (see [Emulator::backtrace]), one frame per
+/// line, with source lines where the program has them.
+fn backtrace(em: &Emulator) -> String {
+    let mut backtrace = String::from("backtrace:\n");
+    for (i, addr) in em.backtrace().into_iter().enumerate() {
+        let _ = write!(
+            backtrace,
+            "  #{:<2} {:08x} <{}>",
+            i,
+            addr,
+            em.symbols().symbolize(addr)
+        );
+        // NB a return address is the instruction after the call
+        let site = if i == 0 { addr } else { addr - 4 };
+        if let Some((file, line)) = em.lines().lookup(site) {
+            let _ = write!(backtrace, " at {}:{}", file, line);
+        }
+        backtrace.push('\n');
+    }
+    backtrace
+}
+
+/// A [TraceSink] that halts execution after a fixed number of instructions.
+struct Budget(u64);
+
+impl TraceSink for Budget {
+    fn retire(&mut self, _em: &Emulator, _retired: &rvem::Retired) {
+        self.0 = self.0.saturating_sub(1);
+    }
+
+    fn halt(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DumpFormat {
+    /// Human-readable
+    Text,
+    /// A JSON document with registers, sections, symbols, and disassembly
+    #[cfg(feature = "serialize")]
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum SectionFormat {
+    /// Disassembled if the section is executable, in hex otherwise
+    Auto,
+    /// In hex
+    Hex,
+    /// Disassembled
+    Disasm,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SummaryFormat {
+    /// One `key: value` line per item
+    Text,
+    /// A JSON object
+    #[cfg(feature = "serialize")]
+    Json,
+}
+
+/// Peak memory use, in bytes, for a [Summary].
+#[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+struct PeakMemory {
+    stack: usize,
+    heap: usize,
+    mmap: usize,
+}
+
+/// A summary of a run, for --summary.
+#[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+struct Summary {
+    /// The program's exit code, if it exited
+    exit_code: Option<i32>,
+    /// Why the run ended, if not by the program exiting
+    error: Option<String>,
+    instructions: u64,
+    /// In seconds
+    wall_time: f64,
+    /// By syscall number
+    syscalls: BTreeMap<u32, u64>,
+    peak_memory: PeakMemory,
+}
+
+impl Summary {
+    fn new<T>(
+        em: &Emulator,
+        result: &Result<T, EmulatorError>,
+        wall_time: f64,
+        mem_usage: &MemUsage,
+    ) -> Summary {
+        Summary {
+            exit_code: em.exit_code(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            instructions: em.instret(),
+            wall_time,
+            syscalls: em.metrics().syscalls.into_iter().collect(),
+            peak_memory: PeakMemory {
+                stack: mem_usage.stack_depth(),
+                heap: mem_usage.heap_size(),
+                mmap: mem_usage.mmap_size(),
+            },
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, format: SummaryFormat) -> Result<(), EmulatorError> {
+        match format {
+            SummaryFormat::Text => {
+                match (self.exit_code, &self.error) {
+                    (_, Some(error)) => writeln!(out, "error: {}", error)?,
+                    (Some(code), None) => writeln!(out, "exit code: {}", code)?,
+                    (None, None) => {}
+                }
+                writeln!(out, "instructions: {}", self.instructions)?;
+                writeln!(out, "wall time: {:.6}s", self.wall_time)?;
+                for (n, count) in &self.syscalls {
+                    writeln!(out, "syscall {}: {}", n, count)?;
+                }
+                let PeakMemory { stack, heap, mmap } = self.peak_memory;
+                writeln!(out, "peak memory: {} bytes", stack + heap + mmap)?;
+            }
+            #[cfg(feature = "serialize")]
+            SummaryFormat::Json => {
+                serde_json::to_writer(&mut *out, self).map_err(std::io::Error::from)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TraceFormat {
+    /// One JSON object per line
+    #[cfg(feature = "serialize")]
+    Jsonl,
+    /// Compatible with spike --log-commits
+    Spike,
+}
+
+/// Subsystems that can be given their own log levels with --log-level,
+/// each of which logs to the `rvem::<subsystem>` target
+const SUBSYSTEMS: [&str; 4] = ["loader", "exec", "syscalls", "devices"];
+
+/// Turns a --log-level into an env_logger filter, by expanding subsystem
+/// names into their log targets.
+fn log_filter(log_level: &str) -> String {
+    log_level
+        .split(',')
+        .map(|directive| match directive.split_once('=') {
+            Some((name, level)) if SUBSYSTEMS.contains(&name) => {
+                format!("rvem::{}={}", name, level)
+            }
+            _ => directive.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses a size argument with [rvem::parse_size] (without the error's
+/// "configuration error" prefix, since clap says what's wrong already).
+fn parse_size(s: &str) -> Result<usize, String> {
+    rvem::parse_size(s).map_err(|e| match e {
+        EmulatorError::Config(msg) => msg,
+        e => e.to_string(),
+    })
 }
 
-fn emulate(args: Args) -> Result<(), EmulatorError> {
-    if let Some(log_level) = args.log_level {
-        env::set_var("RUST_LOG", log_level);
+/// Parses a (possibly fractional) number of seconds.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s
+        .parse()
+        .map_err(|_| format!("expected a number of seconds: {}", s))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "serialize")]
+fn load_snapshot(path: &str) -> Result<rvem::Snapshot, EmulatorError> {
+    serde_json::from_reader(BufReader::new(File::open(path)?))
+        .map_err(|e| EmulatorError::Config(format!("bad snapshot {}: {}", path, e)))
+}
+
+#[cfg(feature = "serialize")]
+fn save_snapshot(em: &mut Emulator, path: &str) -> Result<(), EmulatorError> {
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut out, &em.snapshot()).map_err(std::io::Error::from)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn trace_diff(a: &str, b: &str) -> Result<i32, EmulatorError> {
+    let a = rvem::parse_trace(&fs::read_to_string(a)?)?;
+    let b = rvem::parse_trace(&fs::read_to_string(b)?)?;
+    match rvem::diff_traces(&a, &b) {
+        Some(divergence) => {
+            print!("{}", divergence);
+            Ok(1)
+        }
+        None => {
+            println!("traces match ({} instructions)", a.len().min(b.len()));
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+fn state_diff(a: &str, b: &str) -> Result<i32, EmulatorError> {
+    let restore = |path| -> Result<Emulator, EmulatorError> {
+        let mut em = Emulator::new(None);
+        em.restore(&load_snapshot(path)?);
+        Ok(em)
+    };
+    let diff = restore(a)?.diff(&restore(b)?);
+    if diff.is_empty() {
+        println!("snapshots match");
+        return Ok(0);
+    }
+    print!("{}", diff);
+    Ok(1)
+}
+
+fn strings(file: &str, min_len: usize) -> Result<i32, EmulatorError> {
+    let em = Emulator::load_from(file, None)?;
+    let mut out = std::io::stdout().lock();
+    for (addr, s) in em.strings(min_len) {
+        writeln!(out, "{:08x} {:?}", addr, s)?;
+    }
+    Ok(0)
+}
+
+fn test_suite(dir: &str, timeout: u64) -> Result<i32, EmulatorError> {
+    let mut tests: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            (name.starts_with("rv32ui-") || name.starts_with("rv32um-"))
+                && path.extension().is_none()
+                && path.is_file()
+        })
+        .collect();
+    tests.sort();
+
+    let (mut passed, mut failed) = (0, 0);
+    for path in &tests {
+        let result = Emulator::load_from(path, None).and_then(|mut em| {
+            if em.symbols().get("tohost").is_none() {
+                return Err(EmulatorError::Config("no tohost symbol".to_string()));
+            }
+            em.run_with(&mut Budget(timeout))
+        });
+        if matches!(result, Ok(0)) {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+        let outcome = match result {
+            Ok(0) => "pass".to_string(),
+            Ok(n) => format!("FAIL (test {})", n),
+            Err(EmulatorError::Halted(pc)) => format!("TIMEOUT (at {:08x})", pc),
+            Err(e) => format!("ERROR ({})", e),
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!("{:<24} {}", name, outcome);
+    }
+    println!("{} passed, {} failed", passed, failed);
+
+    Ok(if failed > 0 || tests.is_empty() { 1 } else { 0 })
+}
+
+/// Runs the function tests in `manifest` against the program in `file`,
+/// each on a fresh copy of it, and summarizes the results.
+#[cfg(feature = "serialize")]
+fn function_tests(file: &str, manifest: &str, timeout: u64) -> Result<i32, EmulatorError> {
+    let manifest = TestManifest::load(manifest)?;
+
+    let (mut passed, mut failed) = (0, 0);
+    for test in &manifest.tests {
+        let mut em = Emulator::load_from(file, None)?;
+        em.set_stdout(std::io::sink());
+        // NB pausing makes the call fail with Halted
+        em.set_yield(timeout, |_| Yield::Pause);
+        let outcome = test.run(&mut em);
+        if matches!(outcome, TestOutcome::Pass) {
+            passed += 1;
+        } else {
+            failed += 1;
+        }
+        let outcome = match outcome {
+            TestOutcome::Error(EmulatorError::Halted(pc)) => format!("TIMEOUT (at {:08x})", pc),
+            outcome => outcome.to_string(),
+        };
+        println!("{:<24} {}", test.name(), outcome);
+    }
+    println!("{} passed, {} failed", passed, failed);
+
+    Ok(if failed > 0 || manifest.tests.is_empty() {
+        1
+    } else {
+        0
+    })
+}
+
+fn emulate(args: Args) -> Result<i32, EmulatorError> {
+    if args.quiet {
+        env::set_var("RUST_LOG", "off");
+    } else if let Some(log_level) = &args.log_level {
+        env::set_var("RUST_LOG", log_filter(log_level));
     }
 
     env_logger::init();
 
-    let mut em: Emulator = Emulator::load_from(&args.file, Some(args.memory))?;
+    #[cfg(feature = "serialize")]
+    let config = match &args.config {
+        Some(path) => MachineConfig::load(path)?,
+        None => MachineConfig::default(),
+    };
+    #[cfg(not(feature = "serialize"))]
+    let config = MachineConfig::default();
+    let memory = args.memory.or(config.memory).unwrap_or(DEFAULT_MEMORY_SIZE);
+
+    // NB clap guarantees a file when there's no subcommand (or snapshot)
+    let file = args.file.as_deref().unwrap_or_default();
+    let mut em: Emulator = match &args.file {
+        Some(file) => Emulator::load_from(file, Some(memory))?,
+        None => Emulator::new(Some(memory)),
+    };
+    config.apply(&mut em)?;
+    #[cfg(feature = "serialize")]
+    let restored = match &args.restore_snapshot {
+        Some(path) => {
+            em.restore(&load_snapshot(path)?);
+            true
+        }
+        None => false,
+    };
+    #[cfg(not(feature = "serialize"))]
+    let restored = false;
+    if args.no_demangle {
+        em.set_demangle(false);
+    }
+    if !args.program_args.is_empty() {
+        em.set_args(std::iter::once(file).chain(args.program_args.iter().map(String::as_str)));
+    }
+    if let Some(seed) = args.seed {
+        em.set_seed(seed);
+    }
+    if args.virtual_time.is_some() {
+        em.set_virtual_time(args.virtual_time);
+    }
+    for seed in &args.set_reg {
+        let (reg, value) = seed.split_once('=').ok_or_else(|| {
+            EmulatorError::Config(format!("register seed must be REG=VALUE: {}", seed))
+        })?;
+        let value = match value.strip_prefix('-') {
+            Some(n) => (em.lookup(n)? as u32).wrapping_neg(),
+            None => em.lookup(value)? as u32,
+        };
+        em.set_initial_reg(reg, value)?;
+    }
+    if args.strict {
+        em.set_strict(true);
+    }
+    if args.trap_misaligned {
+        em.set_misaligned(Misaligned::Trap);
+    }
+    if args.no_write_protect {
+        em.set_write_protect(false);
+    }
+    for mapping in &args.map_file {
+        let (addr, path) = mapping.split_once('=').ok_or_else(|| {
+            EmulatorError::Config(format!("file mapping must be ADDR=PATH[,ro]: {}", mapping))
+        })?;
+        let (path, read_only) = match path.strip_suffix(",ro") {
+            Some(path) => (path, true),
+            None => (path, false),
+        };
+        em.map_file(em.lookup(addr)?, path, read_only)?;
+    }
+    if let Some(every) = args.checkpoint_every {
+        em.set_checkpoints(every, args.keep_checkpoints);
+    }
+    let mut limits = em.limits();
+    limits.heap = args.max_heap.or(limits.heap);
+    limits.output = args.max_output.or(limits.output);
+    limits.cpu_time = args.max_cpu_time.or(limits.cpu_time);
+    em.set_limits(limits);
+    if let Some(unknown_syscalls) = args.unknown_syscalls {
+        em.set_unknown_syscalls(unknown_syscalls);
+    }
+    em.set_timing(args.timing);
 
     if args.dump {
-        println!("{em:#?}");
+        match args.format {
+            DumpFormat::Text => println!("{em:#?}"),
+            #[cfg(feature = "serialize")]
+            DumpFormat::Json => {
+                let mut out = std::io::stdout().lock();
+                serde_json::to_writer_pretty(&mut out, &em.dump()).map_err(std::io::Error::from)?;
+                writeln!(out)?;
+            }
+        }
         process::exit(0);
-    } else if log::log_enabled!(log::Level::Trace) {
-        log::trace!("{:#?}", em);
+    } else if !args.dump_section.is_empty() {
+        for name in &args.dump_section {
+            let disassemble = match args.section_format {
+                SectionFormat::Auto => em.section(name).is_some_and(|s| s.executable),
+                format => format == SectionFormat::Disasm,
+            };
+            print!("{}", em.dump_section(name, disassemble)?);
+        }
+        process::exit(0);
+    } else if let Some(pattern) = &args.symbols {
+        let symbols = em.symbols();
+        for (name, addr) in symbols.matching(pattern) {
+            let info = symbols.info(name);
+            let kind = info.map(|info| info.kind.to_string()).unwrap_or_default();
+            let size = match info {
+                Some(info) if info.size > 0 => info.size.to_string(),
+                _ => String::new(),
+            };
+            println!("{:08x} {:<7} {:>6} {}", addr, kind, size, name);
+        }
+        process::exit(0);
+    } else if args.verify_disasm {
+        let objdump = find_objdump()?;
+        let mismatches = compare_disasm(&em, &rvem::objdump(&objdump, file)?);
+        for mismatch in &mismatches {
+            println!("{}", mismatch);
+        }
+        eprintln!(
+            "{} instruction(s) disassembled differently by {}",
+            mismatches.len(),
+            objdump
+        );
+        process::exit(if mismatches.is_empty() { 0 } else { 1 });
+    } else if log::log_enabled!(target: "rvem::loader", log::Level::Trace) {
+        log::trace!(target: "rvem::loader", "{:#?}", em);
+    }
+    if args.verify_decode {
+        let mismatches = verify_decode(&em);
+        for mismatch in &mismatches {
+            eprintln!("{}", mismatch);
+        }
+        if !mismatches.is_empty() {
+            eprintln!(
+                "{} instruction(s) didn't survive a decode/encode/decode round trip",
+                mismatches.len()
+            );
+        }
+    }
+
+    let mut cost = match &args.cost_model {
+        Some(path) => Some(CostProfile::new(CostTable::load(path)?)),
+        None => None,
+    };
+
+    let mut stats = args.stats.then(Stats::new);
+    let mut hotspots = args.hotspots.map(|_| Hotspots::new());
+    let mut profile = args.profile.then(Profile::new);
+    let mut call_graph = args.call_graph.as_ref().map(|_| CallGraph::new());
+    #[cfg(feature = "serialize")]
+    let mut json_trace = None;
+    let mut spike_trace = None;
+    if let Some(path) = &args.trace_file {
+        let out = BufWriter::new(File::create(path)?);
+        match args.trace_format {
+            #[cfg(feature = "serialize")]
+            TraceFormat::Jsonl => json_trace = Some(JsonTrace::new(out)),
+            TraceFormat::Spike => spike_trace = Some(SpikeTrace::new(out)),
+        }
+    }
+    let mut coverage = (args.coverage || args.lcov.is_some()).then(Coverage::new);
+    let mut folded = args
+        .folded
+        .as_ref()
+        .map(|_| FoldedStacks::new(args.sample_period));
+
+    let mut taint = None;
+    if !args.taint.is_empty() {
+        let mut t = Taint::new();
+        for source in &args.taint {
+            if source == "stdin" {
+                t.taint_stdin();
+                continue;
+            }
+            let (addr, len) = source.split_once(':').ok_or_else(|| {
+                EmulatorError::Config(format!(
+                    "taint source must be stdin or ADDR:LEN: {}",
+                    source
+                ))
+            })?;
+            let addr = em.lookup(addr)?;
+            let len = em.lookup(len)?;
+            t.taint_range(addr..addr + len);
+        }
+        for sink in &args.taint_sink {
+            t.add_sink(em.lookup(sink)?);
+        }
+        taint = Some(t);
+    }
+
+    let mut memcheck = args.memcheck.then(|| MemCheck::new(&em));
+    let mut heap_profile = args.heap_profile.then(|| HeapProfile::new(&em));
+    let mut mem_usage = (args.mem_report || args.summary.is_some()).then(|| MemUsage::new(&em));
+    // NB when checking, take the period from the golden trace itself
+    let expected = match &args.check_golden {
+        Some(path) => Some(GoldenTrace::parse(&fs::read_to_string(path)?)?),
+        None => None,
+    };
+    let golden_period = match expected.as_deref() {
+        Some([(period, _), ..]) => *period,
+        _ => args.golden_period,
+    };
+    let mut golden = (args.record_golden.is_some() || expected.is_some())
+        .then(|| GoldenTrace::new(golden_period));
+    let mut reference = None;
+    let mut cosim = None;
+    if let Some(cmd) = &args.cosim {
+        let mut child = process::Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let log: Box<dyn BufRead> = Box::new(BufReader::new(child.stderr.take().unwrap()));
+        cosim = Some(CoSim::new(log));
+        reference = Some(child);
+    } else if let Some(path) = &args.cosim_log {
+        let log: Box<dyn BufRead> = Box::new(BufReader::new(File::open(path)?));
+        cosim = Some(CoSim::new(log));
+    }
+    let mut breakpoints = None;
+    if !args.break_at.is_empty() {
+        let addrs = args
+            .break_at
+            .iter()
+            .map(|loc| em.lookup(loc))
+            .collect::<Result<Vec<_>, _>>()?;
+        breakpoints = Some(
+            Breakpoints::new(std::io::stderr(), addrs, !args.break_continue)
+                .context(args.break_context),
+        );
+    }
+    let mut budget = args.max_instructions.map(Budget);
+    let mut log = LogTrace;
+    let mut sinks: Vec<&mut dyn TraceSink> = Vec::new();
+    if log::log_enabled!(target: "rvem::exec", log::Level::Debug) {
+        sinks.push(&mut log);
+    }
+    if let Some(cost) = &mut cost {
+        sinks.push(cost);
+    }
+    if let Some(stats) = &mut stats {
+        sinks.push(stats);
+    }
+    if let Some(hotspots) = &mut hotspots {
+        sinks.push(hotspots);
+    }
+    if let Some(profile) = &mut profile {
+        sinks.push(profile);
+    }
+    if let Some(call_graph) = &mut call_graph {
+        sinks.push(call_graph);
+    }
+    if let Some(folded) = &mut folded {
+        sinks.push(folded);
+    }
+    if let Some(coverage) = &mut coverage {
+        sinks.push(coverage);
+    }
+    #[cfg(feature = "serialize")]
+    if let Some(json_trace) = &mut json_trace {
+        sinks.push(json_trace);
+    }
+    if let Some(spike_trace) = &mut spike_trace {
+        sinks.push(spike_trace);
+    }
+    if let Some(taint) = &mut taint {
+        sinks.push(taint);
+    }
+    if let Some(memcheck) = &mut memcheck {
+        sinks.push(memcheck);
+    }
+    if let Some(heap_profile) = &mut heap_profile {
+        sinks.push(heap_profile);
+    }
+    if let Some(mem_usage) = &mut mem_usage {
+        sinks.push(mem_usage);
+    }
+    if let Some(golden) = &mut golden {
+        sinks.push(golden);
+    }
+    if let Some(cosim) = &mut cosim {
+        sinks.push(cosim);
+    }
+    if let Some(breakpoints) = &mut breakpoints {
+        sinks.push(breakpoints);
+    }
+    if let Some(budget) = &mut budget {
+        sinks.push(budget);
+    }
+
+    let start = Instant::now();
+    let result = if restored {
+        em.resume_with(&mut sinks)
+    } else {
+        em.run_with(&mut sinks)
+    };
+
+    if let Some(mut child) = reference {
+        // NB the reference may well still be running (or blocked writing)
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    #[cfg(feature = "serialize")]
+    let halted = matches!(result, Ok(_) | Err(EmulatorError::Halted(_)));
+    let result = match (result, args.max_instructions) {
+        (Err(EmulatorError::Halted(pc)), Some(n)) if budget.as_ref().is_some_and(|b| b.halt()) => {
+            eprintln!("{:?}", em);
+            Err(EmulatorError::InstructionLimit { limit: n, pc })
+        }
+        // stopping at a breakpoint is what was asked for
+        (Err(EmulatorError::Halted(_)), _) if breakpoints.as_ref().is_some_and(|b| b.halt()) => {
+            Ok(0)
+        }
+        (result, _) => result,
+    };
+    if let Some(breakpoints) = breakpoints {
+        breakpoints.finish()?;
+    }
+    if let Err(e) = &result {
+        if !matches!(e, EmulatorError::Halted(_)) {
+            eprint!("{}", backtrace(&em));
+        }
+    }
+
+    if let (Some(format), Some(mem_usage)) = (args.summary, &mem_usage) {
+        let summary = Summary::new(&em, &result, start.elapsed().as_secs_f64(), mem_usage);
+        match &args.summary_file {
+            Some(path) => {
+                let mut out = BufWriter::new(File::create(path)?);
+                summary.write(&mut out, format)?;
+                out.flush()?;
+            }
+            None => summary.write(&mut std::io::stderr().lock(), format)?,
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    if let (Some(path), true) = (&args.save_snapshot, halted) {
+        save_snapshot(&mut em, path)?;
+    }
+    #[cfg(feature = "serialize")]
+    if let (Some(path), false) = (&args.crash_snapshot, halted) {
+        let at = em.rewind(em.instret())?;
+        save_snapshot(&mut em, path)?;
+        eprintln!("snapshot from instruction {} written to {}", at, path);
+    }
+    if let Some(cosim) = cosim {
+        match cosim.divergence() {
+            Some(divergence) => {
+                eprint!("cosim: {}", divergence);
+                return Ok(1);
+            }
+            None => eprintln!("cosim: {} instructions matched", cosim.matched()),
+        }
+        cosim.finish()?;
+    }
+
+    for pair in args.dump_memory.chunks(2) {
+        let [path, range] = pair else {
+            unreachable!("clap takes --dump-memory values in pairs")
+        };
+        let (start, end) = range.split_once("..").ok_or_else(|| {
+            EmulatorError::Config(format!("memory range must be START..END: {}", range))
+        })?;
+        let (start, end) = (em.lookup(start)?, em.lookup(end)?);
+        if end < start {
+            return Err(EmulatorError::Config(format!(
+                "memory range ends before it starts: {}",
+                range
+            )));
+        }
+        fs::write(path, em.memory(start, end - start)?)?;
+    }
+
+    if args.final_regs {
+        let mut out = std::io::stderr().lock();
+        for reg in Reg::iter() {
+            writeln!(out, "{:<4} 0x{:08x}", reg.to_string(), em[reg])?;
+        }
+        for loc in &args.final_mem {
+            let word = em.memory(em.lookup(loc)?, 4)?;
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            writeln!(out, "{} 0x{:08x}", loc, word)?;
+        }
+    }
+
+    if let Some(path) = &args.signature {
+        let mut out = BufWriter::new(File::create(path)?);
+        em.write_signature(&mut out, args.signature_granularity)?;
+        out.flush()?;
+    }
+
+    #[cfg(feature = "serialize")]
+    if let Some(json_trace) = json_trace {
+        json_trace.finish()?;
+    }
+    if let Some(spike_trace) = spike_trace {
+        spike_trace.finish()?;
     }
 
-    em.run()
+    if args.bench {
+        let elapsed = start.elapsed().as_secs_f64();
+        eprintln!("instructions: {}", em.instret());
+        eprintln!("elapsed: {:.6}s", elapsed);
+        eprintln!("MIPS: {:.2}", em.instret() as f64 / elapsed / 1e6);
+    }
+
+    if args.timing.is_some() {
+        eprintln!("cycles: {}", em.cycles());
+        eprintln!("CPI: {:.2}", em.cycles() as f64 / em.instret() as f64);
+    }
+
+    if let Some(stats) = stats {
+        eprint!("{}", stats);
+    }
+
+    if let (Some(hotspots), Some(n)) = (hotspots, args.hotspots) {
+        eprint!("{}", hotspots.report(&em, n));
+    }
+
+    if let Some(profile) = profile {
+        eprint!("{}", profile.report(em.symbols()));
+    }
+
+    if let (Some(call_graph), Some(path)) = (call_graph, &args.call_graph) {
+        fs::write(path, call_graph.to_string())?;
+    }
+
+    if let (Some(folded), Some(path)) = (folded, &args.folded) {
+        fs::write(path, folded.folded(em.symbols()))?;
+    }
+
+    if let Some(coverage) = coverage {
+        if args.coverage {
+            eprint!("{}", coverage.report(&em));
+        }
+        if let Some(path) = &args.lcov {
+            fs::write(path, coverage.lcov(&em, file))?;
+        }
+    }
+
+    if let Some(taint) = taint {
+        eprint!("{}", taint.report(em.symbols()));
+    }
+
+    if let Some(memcheck) = memcheck {
+        eprint!("{}", memcheck.report(&em));
+    }
+
+    if let Some(heap_profile) = heap_profile {
+        eprint!("{}", heap_profile.report(&em));
+    }
+
+    if let (Some(mem_usage), true) = (mem_usage, args.mem_report) {
+        eprint!("{}", mem_usage.report());
+    }
+
+    if let Some(cost) = cost {
+        eprintln!("cost: {}", cost.total());
+        for (name, cost) in cost.by_function(em.symbols()) {
+            eprintln!("  {}: {}", name, cost);
+        }
+    }
+    // NB everything above is reported even if the program failed - the
+    // registers of one that crashed, say, are just what's wanted
+    let code = result?;
+
+    if let Some(mut golden) = golden {
+        golden.finish();
+        if let Some(path) = &args.record_golden {
+            fs::write(path, golden.to_string())?;
+        }
+        if let Some(expected) = &expected {
+            if let Err(mismatch) = golden.check(expected) {
+                eprintln!("{}", mismatch);
+                return Ok(1);
+            }
+        }
+    }
+
+    Ok(code)
 }
 
-fn main() -> Result<(), EmulatorError> {
+fn main() {
     let args = Args::parse();
-    emulate(args)
+    let result = match &args.command {
+        Some(Command::TraceDiff { a, b }) => trace_diff(a, b),
+        #[cfg(feature = "serialize")]
+        Some(Command::StateDiff { a, b }) => state_diff(a, b),
+        Some(Command::Strings { file, min_len }) => strings(file, *min_len),
+        #[cfg(feature = "serialize")]
+        Some(Command::Test {
+            file,
+            manifest,
+            timeout,
+        }) => {
+            env_logger::init();
+            function_tests(file, manifest, *timeout)
+        }
+        Some(Command::TestSuite { dir, timeout }) => {
+            env_logger::init();
+            test_suite(dir, *timeout)
+        }
+        None => emulate(args),
+    };
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            // NB the message, rather than the Debug form, so any advice
+            // in it (e.g., how much memory to ask for) is legible
+            eprintln!("Error: {}", e);
+            process::exit(exit_status(&e))
+        }
+    }
 }
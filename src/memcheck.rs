@@ -0,0 +1,147 @@
+use crate::{Emulator, Inst, Region, Retired, TraceSink, BSS_END_SYM, BSS_START_SYM};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A load that read memory which had never been written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UninitRead {
+    /// Address of the load
+    pub pc: usize,
+    /// Decoded instruction
+    pub inst: Inst,
+    /// Address read (the first time, if there was more than one)
+    pub addr: usize,
+    /// Number of bytes read
+    pub len: usize,
+    /// Number of times this load read uninitialized memory
+    pub count: u64,
+}
+
+/// A [TraceSink] that keeps track of which bytes of memory have been
+/// initialized, and reports loads of memory that never has been, à la
+/// valgrind's memcheck.
+///
+/// Memory starts out initialized only where it was loaded from the program
+/// file; everything else (the stack, heap, and BSS) becomes initialized as
+/// it's stored to. Note that reading zeroed BSS is perfectly legal C, so
+/// reports of reads from BSS may or may not be interesting.
+#[derive(Clone, Debug)]
+pub struct MemCheck {
+    /// Whether each byte of memory has been initialized
+    valid: Vec<bool>,
+    /// Reads of uninitialized memory, in order of first occurrence
+    reads: Vec<UninitRead>,
+    /// Index into `reads` by PC
+    index: HashMap<usize, usize>,
+}
+
+impl MemCheck {
+    /// Returns a new checker for `em`, whose program should already have
+    /// been loaded.
+    pub fn new(em: &Emulator) -> MemCheck {
+        let mut valid = vec![false; em.mem.len()];
+        for section in em.sections().iter().filter(|section| section.from_file) {
+            valid[section.range.clone()].fill(true);
+        }
+        MemCheck {
+            valid,
+            reads: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the byte at `addr` has been initialized.
+    pub fn is_initialized(&self, addr: usize) -> bool {
+        self.valid.get(addr).copied().unwrap_or(false)
+    }
+
+    /// Returns the loads that read uninitialized memory, in the order they
+    /// first did so.
+    pub fn reads(&self) -> &[UninitRead] {
+        &self.reads
+    }
+
+    /// Formats the uninitialized reads, one per line.
+    pub fn report(&self, em: &Emulator) -> String {
+        let symtab = em.symbols();
+        let bss = symtab.get(BSS_START_SYM).zip(symtab.get(BSS_END_SYM));
+        let mut report = String::new();
+        for r in &self.reads {
+            // NB stack and heap addresses aren't near any symbol worth naming
+            let named = matches!(em.region_of(r.addr), Region::Section(_))
+                || bss.is_some_and(|(start, end)| (start..end).contains(&r.addr));
+            let _ = writeln!(
+                report,
+                "{:08x} <{}>: read of {} uninitialized byte(s) at {:08x}{} ({}){}",
+                r.pc,
+                symtab.symbolize(r.pc),
+                r.len,
+                r.addr,
+                if named {
+                    format!(" <{}>", symtab.symbolize(r.addr))
+                } else {
+                    String::new()
+                },
+                r.inst.at(r.pc),
+                match r.count {
+                    1 => String::new(),
+                    n => format!(" x{}", n),
+                }
+            );
+        }
+        report
+    }
+}
+
+impl TraceSink for MemCheck {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        let Some(mem) = retired.mem else {
+            return;
+        };
+        let end = (mem.addr + mem.len).min(self.valid.len());
+        if mem.store {
+            if mem.addr < end {
+                self.valid[mem.addr..end].fill(true);
+            }
+            return;
+        }
+        if (mem.addr..mem.addr + mem.len).all(|addr| self.is_initialized(addr)) {
+            return;
+        }
+        match self.index.get(&retired.pc) {
+            Some(i) => self.reads[*i].count += 1,
+            None => {
+                self.index.insert(retired.pc, self.reads.len());
+                self.reads.push(UninitRead {
+                    pc: retired.pc,
+                    inst: retired.inst,
+                    addr: mem.addr,
+                    len: mem.len,
+                    count: 1,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memcheck() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut memcheck = MemCheck::new(&em);
+        assert_eq!(em.run_with(&mut memcheck).unwrap(), 0);
+        assert!(memcheck.reads().is_empty());
+
+        let mut em = Emulator::load_from("tests/data/uninit", None).unwrap();
+        let mut memcheck = MemCheck::new(&em);
+        assert_eq!(em.run_with(&mut memcheck).unwrap(), 0);
+        let reads = memcheck.reads();
+        assert_eq!(reads.len(), 2);
+        assert_eq!((reads[0].len, reads[0].count), (4, 1));
+        assert_eq!((reads[1].len, reads[1].count), (1, 3));
+        assert_eq!(reads[1].addr, reads[0].addr + 4);
+    }
+}
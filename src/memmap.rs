@@ -0,0 +1,49 @@
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A section of the program, as loaded into memory.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Section {
+    /// Name of the section (e.g., `.text`)
+    pub name: String,
+    /// Where the section is in memory
+    pub range: Range<usize>,
+    /// Whether the section is writable
+    pub writable: bool,
+    /// Whether the section contains executable code
+    pub executable: bool,
+    /// Whether the section's contents were loaded from the program file (as
+    /// opposed to being zero-initialized, like `.bss`)
+    pub from_file: bool,
+}
+
+/// The region of memory an address is in, as returned by
+/// [crate::Emulator::region_of].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region<'a> {
+    /// One of the program's sections
+    Section(&'a Section),
+    /// The heap, i.e., between the initial and current program break
+    Heap,
+    /// The in-use part of the stack, i.e., between the stack pointer and the
+    /// top of the stack (once the program has started running)
+    Stack,
+    /// An anonymous mapping
+    Mmap,
+    /// None of the above
+    Unmapped,
+}
+
+impl std::fmt::Display for Region<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Region::Section(section) => write!(f, "{}", section.name),
+            Region::Heap => write!(f, "heap"),
+            Region::Stack => write!(f, "stack"),
+            Region::Mmap => write!(f, "mmap"),
+            Region::Unmapped => write!(f, "unmapped"),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{Emulator, Reg, Retired, TraceSink};
+use std::fmt::Write;
+
+/// A [TraceSink] that keeps track of how much memory a program uses: the
+/// deepest the stack gets, and the furthest the heap (and any anonymous
+/// mappings) extend. Useful for sizing RAM for firmware.
+#[derive(Clone, Debug)]
+pub struct MemUsage {
+    /// Top of the stack
+    stack_top: usize,
+    /// Lowest stack pointer seen
+    stack_low: usize,
+    /// Start of the heap
+    heap_start: usize,
+    /// Highest program break seen
+    heap_high: usize,
+    /// Highest end of an anonymous mapping seen
+    mmap_high: usize,
+}
+
+impl MemUsage {
+    /// Returns a new tracker for `em`, whose program should already have
+    /// been loaded.
+    pub fn new(em: &Emulator) -> MemUsage {
+        MemUsage {
+            stack_top: em.stack_top(),
+            stack_low: em.stack_top(),
+            heap_start: em.brk_start(),
+            heap_high: em.brk(),
+            mmap_high: em.stack_top(),
+        }
+    }
+
+    /// Returns the maximum stack depth, in bytes.
+    pub fn stack_depth(&self) -> usize {
+        self.stack_top - self.stack_low
+    }
+
+    /// Returns the highest address the heap reached (i.e., the highest
+    /// program break).
+    pub fn heap_high(&self) -> usize {
+        self.heap_high
+    }
+
+    /// Returns the maximum heap size, in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.heap_high - self.heap_start
+    }
+
+    /// Returns the total size of all anonymous mappings, in bytes.
+    pub fn mmap_size(&self) -> usize {
+        self.mmap_high - self.stack_top
+    }
+
+    /// Formats a summary of memory usage.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let _ = writeln!(
+            report,
+            "stack: {} bytes ({:08x}-{:08x})",
+            self.stack_depth(),
+            self.stack_low,
+            self.stack_top
+        );
+        let _ = writeln!(
+            report,
+            "heap: {} bytes ({:08x}-{:08x})",
+            self.heap_size(),
+            self.heap_start,
+            self.heap_high
+        );
+        if self.mmap_size() > 0 {
+            let _ = writeln!(
+                report,
+                "mmap: {} bytes ({:08x}-{:08x})",
+                self.mmap_size(),
+                self.stack_top,
+                self.mmap_high
+            );
+        }
+        report
+    }
+}
+
+impl TraceSink for MemUsage {
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        if let Some((Reg::sp, sp)) = retired.rd {
+            self.stack_low = self.stack_low.min(sp as usize);
+        }
+        self.heap_high = self.heap_high.max(em.brk());
+        self.mmap_high = self.mmap_high.max(em.mmap_top());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mem_usage() {
+        let mut em = Emulator::load_from("tests/data/heap", None).unwrap();
+        let mut usage = MemUsage::new(&em);
+        assert_eq!(em.run_with(&mut usage).unwrap(), 0);
+        assert_eq!(usage.stack_depth(), 0);
+        assert_eq!(usage.heap_size(), 56);
+        assert_eq!(usage.heap_high(), em.brk());
+        assert_eq!(usage.mmap_size(), 0);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Running totals the emulator keeps for [Metrics] (the rest come from its
+/// instruction and cycle counts).
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) syscalls: HashMap<u32, u64>,
+    pub(crate) memory_faults: u64,
+    pub(crate) cpu_time: Duration,
+}
+
+/// Metrics for an emulator, as returned by [crate::Emulator::metrics], for
+/// monitoring long-running emulations (e.g., in a server). Unlike the
+/// [crate::Stats] sink, these are kept as the program runs at full speed.
+///
+/// They can be exported in the Prometheus text format with
+/// [Metrics::prometheus], or (with the `metrics` feature) published to
+/// whatever recorder is installed for the `metrics` crate with
+/// [Metrics::publish].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    /// Instructions retired
+    pub instret: u64,
+    /// Cycles elapsed
+    pub cycles: u64,
+    /// Syscalls made, by number (a7)
+    pub syscalls: HashMap<u32, u64>,
+    /// Runs that ended in an out-of-bounds memory access
+    pub memory_faults: u64,
+    /// Host time spent running the program
+    pub cpu_time: Duration,
+}
+
+impl Metrics {
+    /// Returns the instruction rate, in millions of instructions per second
+    /// of [Metrics::cpu_time].
+    pub fn mips(&self) -> f64 {
+        let secs = self.cpu_time.as_secs_f64();
+        if secs > 0.0 {
+            self.instret as f64 / secs / 1e6
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the syscall counts, in order of syscall number.
+    fn syscalls(&self) -> Vec<(u32, u64)> {
+        let mut syscalls: Vec<_> = self.syscalls.iter().map(|(n, c)| (*n, *c)).collect();
+        syscalls.sort();
+        syscalls
+    }
+
+    /// Returns the metrics in the Prometheus text exposition format, e.g.,
+    /// for serving from a `/metrics` endpoint.
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+        let one = |value: String| [(String::new(), value)];
+        metric(
+            "rvem_instructions_total",
+            "counter",
+            "Instructions retired.",
+            &one(self.instret.to_string()),
+        );
+        metric(
+            "rvem_cycles_total",
+            "counter",
+            "Cycles elapsed.",
+            &one(self.cycles.to_string()),
+        );
+        let syscalls: Vec<_> = self
+            .syscalls()
+            .into_iter()
+            .map(|(n, count)| (format!("{{syscall=\"{}\"}}", n), count.to_string()))
+            .collect();
+        metric(
+            "rvem_syscalls_total",
+            "counter",
+            "Syscalls made, by number.",
+            &syscalls,
+        );
+        metric(
+            "rvem_memory_faults_total",
+            "counter",
+            "Runs that ended in an out-of-bounds memory access.",
+            &one(self.memory_faults.to_string()),
+        );
+        metric(
+            "rvem_cpu_seconds_total",
+            "counter",
+            "Host time spent running the program.",
+            &one(self.cpu_time.as_secs_f64().to_string()),
+        );
+        metric(
+            "rvem_mips",
+            "gauge",
+            "Instruction rate, in millions of instructions per second.",
+            &one(self.mips().to_string()),
+        );
+        out
+    }
+
+    /// Publishes the metrics (under the same names as [Metrics::prometheus])
+    /// to the `metrics` crate's installed recorder.
+    #[cfg(feature = "metrics")]
+    pub fn publish(&self) {
+        ::metrics::counter!("rvem_instructions_total").absolute(self.instret);
+        ::metrics::counter!("rvem_cycles_total").absolute(self.cycles);
+        for (n, count) in self.syscalls() {
+            ::metrics::counter!("rvem_syscalls_total", "syscall" => n.to_string()).absolute(count);
+        }
+        ::metrics::counter!("rvem_memory_faults_total").absolute(self.memory_faults);
+        ::metrics::gauge!("rvem_cpu_seconds_total").set(self.cpu_time.as_secs_f64());
+        ::metrics::gauge!("rvem_mips").set(self.mips());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, EmulatorError, Reg};
+
+    #[test]
+    fn test_metrics() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        assert_eq!(em.run().unwrap(), 0);
+        let metrics = em.metrics();
+        assert_eq!((metrics.instret, metrics.cycles), (67, 67));
+        assert_eq!(metrics.syscalls.get(&1), Some(&1));
+        assert_eq!(metrics.syscalls.get(&10), Some(&1));
+        assert_eq!(metrics.memory_faults, 0);
+
+        let text = metrics.prometheus();
+        assert!(
+            text.contains("# TYPE rvem_instructions_total counter\nrvem_instructions_total 67\n")
+        );
+        assert!(text.contains(
+            "rvem_syscalls_total{syscall=\"1\"} 1\nrvem_syscalls_total{syscall=\"10\"} 1\n"
+        ));
+
+        // a bad stack pointer makes fact fault saving its return address
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        em.run().unwrap();
+        em.exit_code = None;
+        em.set_pc(em.addr_of("fact").unwrap() + 4);
+        em[Reg::sp] = u32::MAX;
+        assert!(matches!(
+            em.resume_with(&mut crate::NoTrace),
+            Err(EmulatorError::MemoryFault { .. })
+        ));
+        assert_eq!(em.metrics().memory_faults, 1);
+    }
+}
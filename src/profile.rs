@@ -0,0 +1,100 @@
+use crate::callgraph::is_call;
+use crate::{Emulator, Inst, Retired, SymbolTable, TraceSink};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Flat profile entry for a single function.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionProfile {
+    /// Function name (or address, if there's no symbol for it)
+    pub name: String,
+    /// Instructions retired in the function itself
+    pub instructions: u64,
+    /// Cycles spent in the function itself
+    pub cycles: u64,
+    /// Number of times the function was called
+    pub calls: u64,
+}
+
+/// A [TraceSink] that attributes retired instructions and cycles to the
+/// function (i.e., the nearest symbol) they belong to, along the lines of a
+/// gprof flat profile.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    /// Cycle count before the current instruction
+    cycles: u64,
+    /// (instructions, cycles, calls) by address
+    by_pc: HashMap<usize, (u64, u64, u64)>,
+}
+
+impl Profile {
+    /// Returns a new, empty profile.
+    pub fn new() -> Profile {
+        Default::default()
+    }
+
+    /// Returns the profile for each function, most expensive first.
+    pub fn functions(&self, symtab: &SymbolTable) -> Vec<FunctionProfile> {
+        let mut functions: HashMap<String, FunctionProfile> = HashMap::new();
+        for (pc, (instructions, cycles, calls)) in &self.by_pc {
+            let name = symtab.symbolize_function(*pc);
+            let function = functions
+                .entry(name.clone())
+                .or_insert_with(|| FunctionProfile {
+                    name,
+                    ..Default::default()
+                });
+            function.instructions += instructions;
+            function.cycles += cycles;
+            function.calls += calls;
+        }
+        let mut functions: Vec<FunctionProfile> = functions.into_values().collect();
+        functions.sort_by(|a, b| {
+            (b.cycles, b.instructions)
+                .cmp(&(a.cycles, a.instructions))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        functions
+    }
+
+    /// Formats a flat profile, most expensive function first.
+    pub fn report(&self, symtab: &SymbolTable) -> String {
+        let functions = self.functions(symtab);
+        let total: u64 = functions.iter().map(|f| f.cycles).sum();
+        let mut report = format!(
+            "{:>7} {:>12} {:>12} {:>12} {:>8}  {}\n",
+            "%", "cumulative", "cycles", "instructions", "calls", "function"
+        );
+        let mut cumulative = 0;
+        for f in functions {
+            cumulative += f.cycles;
+            let _ = writeln!(
+                report,
+                "{:>6.2}% {:>12} {:>12} {:>12} {:>8}  {}",
+                100.0 * f.cycles as f64 / total as f64,
+                cumulative,
+                f.cycles,
+                f.instructions,
+                f.calls,
+                f.name
+            );
+        }
+        report
+    }
+}
+
+impl TraceSink for Profile {
+    fn before(&mut self, em: &Emulator, _pc: usize, _word: u32, _inst: &Inst) {
+        self.cycles = em.cycles();
+    }
+
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        let entry = self.by_pc.entry(retired.pc).or_default();
+        entry.0 += 1;
+        entry.1 += em.cycles() - self.cycles;
+
+        if is_call(&retired.inst) {
+            self.by_pc.entry(retired.next_pc).or_default().2 += 1;
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use strum::{Display, EnumIter};
+use std::str::FromStr;
+use strum::{Display, EnumIter, IntoEnumIterator};
 
 /// Enumeration of all available registers.
 #[allow(non_camel_case_types)]
-#[derive(Clone, Copy, Debug, Display, EnumIter, PartialEq)]
+#[derive(Clone, Copy, Debug, Display, EnumIter, PartialEq, Eq, Hash)]
 #[repr(u32)]
 pub enum Reg {
     /// x0 - hardwired to 0, ignores writes
@@ -108,42 +109,59 @@ impl Reg {
     pub const fp: Reg = Reg::s0;
 }
 
-impl From<u32> for Reg {
-    fn from(value: u32) -> Self {
-        match value {
-            0 => Reg::x0,
-            1 => Reg::x1,
-            2 => Reg::x2,
-            3 => Reg::x3,
-            4 => Reg::x4,
-            5 => Reg::x5,
-            6 => Reg::x6,
-            7 => Reg::x7,
-            8 => Reg::x8,
-            9 => Reg::x9,
-            10 => Reg::x10,
-            11 => Reg::x11,
-            12 => Reg::x12,
-            13 => Reg::x13,
-            14 => Reg::x14,
-            15 => Reg::x15,
-            16 => Reg::x16,
-            17 => Reg::x17,
-            18 => Reg::x18,
-            19 => Reg::x19,
-            20 => Reg::x20,
-            21 => Reg::x21,
-            22 => Reg::x22,
-            23 => Reg::x23,
-            24 => Reg::x24,
-            25 => Reg::x25,
-            26 => Reg::x26,
-            27 => Reg::x27,
-            28 => Reg::x28,
-            29 => Reg::x29,
-            30 => Reg::x30,
-            31 => Reg::x31,
-            _ => unimplemented!("unimplemented register value: {}", value),
+/// The registers, by number.
+const REGS: [Reg; 32] = [
+    Reg::x0,
+    Reg::x1,
+    Reg::x2,
+    Reg::x3,
+    Reg::x4,
+    Reg::x5,
+    Reg::x6,
+    Reg::x7,
+    Reg::x8,
+    Reg::x9,
+    Reg::x10,
+    Reg::x11,
+    Reg::x12,
+    Reg::x13,
+    Reg::x14,
+    Reg::x15,
+    Reg::x16,
+    Reg::x17,
+    Reg::x18,
+    Reg::x19,
+    Reg::x20,
+    Reg::x21,
+    Reg::x22,
+    Reg::x23,
+    Reg::x24,
+    Reg::x25,
+    Reg::x26,
+    Reg::x27,
+    Reg::x28,
+    Reg::x29,
+    Reg::x30,
+    Reg::x31,
+];
+
+impl Reg {
+    /// Returns the register numbered by the low 5 bits of `bits` (as in an
+    /// instruction's register fields), ignoring the rest.
+    pub(crate) fn from_bits(bits: u32) -> Reg {
+        REGS[(bits & 0b1_1111) as usize]
+    }
+}
+
+/// Returns register x`value`, or an error if there's no such register.
+impl TryFrom<u32> for Reg {
+    type Error = String;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        if value < 32 {
+            Ok(Reg::from_bits(value))
+        } else {
+            Err(format!("no such register: x{}", value))
         }
     }
 }
@@ -186,3 +204,41 @@ impl From<Reg> for u32 {
         }
     }
 }
+
+/// Parses a register by its ABI name (e.g., `sp`), its architectural name
+/// (e.g., `x2`), or as `fp`.
+impl FromStr for Reg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "fp" {
+            return Ok(Reg::fp);
+        }
+        if let Some(reg) = (s.strip_prefix('x'))
+            .and_then(|n| n.parse::<u32>().ok())
+            .and_then(|n| Reg::try_from(n).ok())
+        {
+            return Ok(reg);
+        }
+        Reg::iter()
+            .find(|reg| reg.to_string() == s)
+            .ok_or_else(|| format!("unknown register '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from() {
+        for (n, reg) in Reg::iter().enumerate() {
+            assert_eq!(Reg::try_from(n as u32), Ok(reg));
+            assert_eq!(u32::from(reg), n as u32);
+        }
+        assert!(Reg::try_from(32).is_err());
+        assert!(Reg::try_from(u32::MAX).is_err());
+        assert_eq!(Reg::from_bits(32 + 10), Reg::a0);
+        assert!("x32".parse::<Reg>().is_err());
+    }
+}
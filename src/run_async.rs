@@ -0,0 +1,149 @@
+//! Running programs in async tasks, a slice of instructions at a time (see
+//! [Emulator::run_async]).
+use crate::{Emulator, EmulatorError, LogTrace, NoTrace, TraceSink};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Instructions to run between awaits, by default
+pub const DEFAULT_SLICE: u64 = 100_000;
+
+impl Emulator {
+    /// Runs a loaded program as [Emulator::run] does, but as a future that
+    /// returns to the executor every `slice` instructions (or so; see
+    /// [Emulator::set_yield]), so that many programs can share an async
+    /// runtime's threads, e.g., one per connection to a server. Dropping the
+    /// future - e.g., when it loses a `tokio::select!`, or its task is
+    /// aborted - stops the program between slices, where it can be picked up
+    /// again with [Emulator::resume_async].
+    ///
+    /// ```
+    /// # use rvem::Emulator;
+    /// # fn block_on<F: std::future::Future>(f: F) -> F::Output {
+    /// #     let mut f = std::pin::pin!(f);
+    /// #     let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    /// #     loop {
+    /// #         if let std::task::Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+    /// #             return out;
+    /// #         }
+    /// #     }
+    /// # }
+    /// let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+    /// em.set_stdout(std::io::sink());
+    /// assert_eq!(block_on(em.run_async(rvem::DEFAULT_SLICE)).unwrap(), 0);
+    /// ```
+    pub async fn run_async(&mut self, slice: u64) -> Result<i32, EmulatorError> {
+        self.init()?;
+        self.resume_async(slice).await
+    }
+
+    /// Continues running a program from wherever it left off, as
+    /// [Emulator::resume_with] does, but asynchronously as
+    /// [Emulator::run_async] does.
+    pub async fn resume_async(&mut self, slice: u64) -> Result<i32, EmulatorError> {
+        if log::log_enabled!(target: "rvem::exec", log::Level::Debug) {
+            self.resume_async_with(&mut LogTrace, slice).await
+        } else {
+            self.resume_async_with(&mut NoTrace, slice).await
+        }
+    }
+
+    /// Runs a loaded program as [Emulator::run_with] does, reporting on its
+    /// execution to `sink`, but asynchronously as [Emulator::run_async] does.
+    pub async fn run_async_with<T: TraceSink>(
+        &mut self,
+        sink: &mut T,
+        slice: u64,
+    ) -> Result<i32, EmulatorError> {
+        self.init()?;
+        self.resume_async_with(sink, slice).await
+    }
+
+    /// Continues running a program, reporting on its execution to `sink`,
+    /// as [Emulator::resume_with] does, but asynchronously as
+    /// [Emulator::run_async] does.
+    pub async fn resume_async_with<T: TraceSink>(
+        &mut self,
+        sink: &mut T,
+        slice: u64,
+    ) -> Result<i32, EmulatorError> {
+        let slice = slice.max(1);
+        loop {
+            let until = self.instret.saturating_add(slice);
+            if let Some(code) = self.resume_until(sink, until)? {
+                return Ok(code);
+            }
+            YieldNow(false).await;
+        }
+    }
+}
+
+/// A future that returns to the executor once (asking to be polled again
+/// straight away) before it completes.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, EmulatorError};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Polls `f` to completion, returning its output and the number of times
+    /// it returned to the "executor".
+    fn block_on<F: Future>(f: F) -> (F::Output, usize) {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pending = 0;
+        loop {
+            match f.as_mut().poll(&mut cx) {
+                Poll::Ready(out) => return (out, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_async() {
+        let mut em = Emulator::load_from("tests/data/primes", None).unwrap();
+        em.set_stdout(std::io::sink());
+        let (result, pending) = block_on(em.run_async(1_000_000));
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(pending as u64, em.instret() / 1_000_000);
+
+        // dropped after a few slices, then picked up where it left off
+        let mut em = Emulator::load_from("tests/data/primes", None).unwrap();
+        em.set_stdout(std::io::sink());
+        {
+            let mut f = pin!(em.run_async(1_000_000));
+            let mut cx = Context::from_waker(Waker::noop());
+            for _ in 0..3 {
+                assert!(f.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+        assert!((3_000_000..3_000_100).contains(&em.instret()));
+        let (result, pending) = block_on(em.resume_async(1_000_000));
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(pending as u64, em.instret() / 1_000_000 - 3);
+
+        let mut em = Emulator::new(None);
+        assert!(matches!(
+            block_on(em.run_async(1000)).0,
+            Err(EmulatorError::Execution(_))
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{Emulator, Inst, Retired, TraceSink};
+use std::io::{self, Write};
+
+/// A [TraceSink] that writes a commit log in the format of spike's
+/// `--log-commits` option, e.g.:
+///
+/// ```text
+/// core   0: 3 0x00010074 (0xff810113) x2  0x0007fff8
+/// core   0: 3 0x00010078 (0x00112023) mem 0x0007fff8 0x000100b4
+/// core   0: 3 0x00010090 (0x00412283) x5  0x00000002 mem 0x0007ffe4
+/// ```
+///
+/// i.e., the hart, privilege level (always machine mode), PC, and raw
+/// instruction, followed by any register writeback and memory access. As with
+/// [crate::JsonTrace], the first write error (if any) is reported by
+/// [SpikeTrace::finish].
+pub struct SpikeTrace<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> SpikeTrace<W> {
+    /// Returns a new commit log that writes to `out`.
+    pub fn new(out: W) -> SpikeTrace<W> {
+        SpikeTrace { out, error: None }
+    }
+
+    /// Flushes the log, returning the first error encountered while writing
+    /// it (if any).
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    fn write(&mut self, retired: &Retired) -> io::Result<()> {
+        write!(
+            self.out,
+            "core   0: 3 0x{:08x} (0x{:08x})",
+            retired.pc, retired.word
+        )?;
+        // NB spike doesn't see a syscall's return value as a writeback
+        if let (Some((rd, value)), false) = (retired.rd, matches!(retired.inst, Inst::ECALL)) {
+            write!(self.out, " x{:<2} 0x{:08x}", rd as u32, value)?;
+        }
+        if let Some(mem) = retired.mem {
+            write!(self.out, " mem 0x{:08x}", mem.addr)?;
+            if mem.store {
+                write!(self.out, " 0x{:0width$x}", mem.value, width = 2 * mem.len)?;
+            }
+        }
+        writeln!(self.out)
+    }
+}
+
+impl<W: Write> TraceSink for SpikeTrace<W> {
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        if self.error.is_none() {
+            if let Err(e) = self.write(retired) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{Emulator, Reg};
+use std::ops::Range;
+use strum::IntoEnumIterator;
+
+/// Most bytes of a differing memory range to show, when formatting a
+/// [StateDiff]
+const MAX_SHOWN: usize = 16;
+
+/// A range of memory whose contents differ between two emulators.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemDiff {
+    pub range: Range<usize>,
+    /// The first emulator's bytes in `range`
+    pub a: Vec<u8>,
+    /// The second emulator's bytes in `range`
+    pub b: Vec<u8>,
+}
+
+/// The differences between the states of two emulators, as returned by
+/// [Emulator::diff].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDiff {
+    /// The two program counters, if they differ
+    pub pc: Option<(usize, usize)>,
+    /// Registers that differ, with their values in each emulator
+    pub registers: Vec<(Reg, u32, u32)>,
+    /// The two memory sizes, if they differ (in which case memory is only
+    /// compared up to the smaller of the two)
+    pub mem_len: Option<(usize, usize)>,
+    /// Ranges of memory that differ (in whole words), in order of address
+    pub memory: Vec<MemDiff>,
+}
+
+impl StateDiff {
+    /// Returns the differences between `a` and `b`.
+    pub(crate) fn new(a: &Emulator, b: &Emulator) -> StateDiff {
+        let mut diff = StateDiff::default();
+        if a.pc != b.pc {
+            diff.pc = Some((a.pc, b.pc));
+        }
+        for reg in Reg::iter() {
+            if a[reg] != b[reg] {
+                diff.registers.push((reg, a[reg], b[reg]));
+            }
+        }
+        if a.mem.len() != b.mem.len() {
+            diff.mem_len = Some((a.mem.len(), b.mem.len()));
+        }
+
+        // NB memory is compared a word at a time, so that a store that
+        // changes some of a word's bytes shows up as the whole word
+        let mut start = None;
+        let len = a.mem.len().min(b.mem.len());
+        for i in (0..len).step_by(4).chain([len]) {
+            let end = (i + 4).min(len);
+            let differs = a.mem[i..end] != b.mem[i..end];
+            match (start, differs) {
+                (None, true) => start = Some(i),
+                (Some(s), false) => {
+                    diff.memory.push(MemDiff {
+                        range: s..i,
+                        a: a.mem[s..i].to_vec(),
+                        b: b.mem[s..i].to_vec(),
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        diff
+    }
+
+    /// Returns whether the two states are the same.
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+impl std::fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex = |bytes: &[u8]| {
+            let mut hex: Vec<String> = (bytes.iter().take(MAX_SHOWN))
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            if bytes.len() > MAX_SHOWN {
+                hex.push("...".to_string());
+            }
+            hex.join(" ")
+        };
+        if let Some((a, b)) = self.pc {
+            writeln!(f, "pc: {:08x} -> {:08x}", a, b)?;
+        }
+        for (reg, a, b) in &self.registers {
+            writeln!(f, "{}: 0x{:08x} -> 0x{:08x}", reg, a, b)?;
+        }
+        if let Some((a, b)) = self.mem_len {
+            writeln!(f, "memory size: {} -> {}", a, b)?;
+        }
+        for mem in &self.memory {
+            writeln!(
+                f,
+                "memory {:08x}..{:08x}: {} -> {}",
+                mem.range.start,
+                mem.range.end,
+                hex(&mem.a),
+                hex(&mem.b)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, Reg};
+
+    #[test]
+    fn test_diff() {
+        let mut a = Emulator::load_from("tests/data/fac", None).unwrap();
+        let b = Emulator::load_from("tests/data/fac", None).unwrap();
+        assert!(a.diff(&b).is_empty());
+
+        // same program, run differently
+        a.set_reg("a0", 6).unwrap();
+        a[0x7fff8] = 0x78;
+        a[0x7fffa] = 0x01;
+        a[0x80004] = 0xff;
+        let diff = a.diff(&b);
+        assert_eq!(diff.pc, None);
+        assert_eq!(diff.registers, [(Reg::a0, 6, 0)]);
+        assert_eq!(
+            (diff.memory.iter())
+                .map(|mem| mem.range.clone())
+                .collect::<Vec<_>>(),
+            [0x7fff8..0x7fffc, 0x80004..0x80008]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "\
+a0: 0x00000006 -> 0x00000000
+memory 0007fff8..0007fffc: 78 00 01 00 -> 00 00 00 00
+memory 00080004..00080008: ff 00 00 00 -> 00 00 00 00
+"
+        );
+
+        let mut b = Emulator::load_from("tests/data/fac", None).unwrap();
+        b.run().unwrap();
+        let diff = b.diff(&Emulator::load_from("tests/data/fac", None).unwrap());
+        assert!(diff.pc.is_some());
+        assert!(!diff.registers.is_empty());
+        assert!(!diff.memory.is_empty());
+    }
+}
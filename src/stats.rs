@@ -0,0 +1,102 @@
+use crate::{Emulator, Inst, InstClass, Reg, Retired, TraceSink};
+use std::collections::HashMap;
+
+/// A [TraceSink] that gathers execution statistics: instruction counts by
+/// mnemonic, how often branches are taken, and which syscalls were made.
+///
+/// Its [Display](std::fmt::Display) implementation gives a human-readable
+/// summary.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Total number of instructions retired
+    pub instructions: u64,
+    /// Instructions retired, by mnemonic
+    pub mnemonics: HashMap<&'static str, u64>,
+    /// Conditional branches taken
+    pub taken: u64,
+    /// Conditional branches not taken
+    pub not_taken: u64,
+    /// Syscalls made, by number (a7)
+    pub syscalls: HashMap<u32, u64>,
+}
+
+impl Stats {
+    /// Returns a new, empty set of statistics.
+    pub fn new() -> Stats {
+        Default::default()
+    }
+
+    /// Returns the counts per mnemonic, most frequent first.
+    pub fn by_mnemonic(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = self.mnemonics.iter().map(|(m, n)| (*m, *n)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        counts
+    }
+}
+
+impl TraceSink for Stats {
+    fn before(&mut self, em: &Emulator, _pc: usize, _word: u32, inst: &Inst) {
+        if matches!(inst, Inst::ECALL) {
+            *self.syscalls.entry(em[Reg::a7]).or_default() += 1;
+        }
+    }
+
+    fn retire(&mut self, _em: &Emulator, retired: &Retired) {
+        self.instructions += 1;
+        *self.mnemonics.entry(retired.inst.mnemonic()).or_default() += 1;
+        if retired.inst.class() == InstClass::Branch {
+            if retired.next_pc != retired.pc + 4 {
+                self.taken += 1;
+            } else {
+                self.not_taken += 1;
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "instructions: {}", self.instructions)?;
+        let branches = self.taken + self.not_taken;
+        if branches > 0 {
+            writeln!(
+                f,
+                "branches: {} taken, {} not taken ({:.1}% taken)",
+                self.taken,
+                self.not_taken,
+                100.0 * self.taken as f64 / branches as f64
+            )?;
+        } else {
+            writeln!(f, "branches: 0")?;
+        }
+        if !self.syscalls.is_empty() {
+            writeln!(f, "syscalls:")?;
+            let mut syscalls: Vec<_> = self.syscalls.iter().collect();
+            syscalls.sort();
+            for (syscall, count) in syscalls {
+                writeln!(f, "  {}: {}", syscall, count)?;
+            }
+        }
+        writeln!(f, "mnemonics:")?;
+        for (mnemonic, count) in self.by_mnemonic() {
+            writeln!(f, "  {}: {}", mnemonic, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut em = Emulator::load_from("tests/data/fac", None).unwrap();
+        let mut stats = Stats::new();
+        assert_eq!(em.run_with(&mut stats).unwrap(), 0);
+        assert_eq!(stats.instructions, em.instret());
+        assert_eq!(stats.mnemonics["mul"], 4);
+        assert_eq!((stats.taken, stats.not_taken), (1, 4));
+        assert_eq!(stats.syscalls.get(&10), Some(&1));
+    }
+}
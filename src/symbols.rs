@@ -0,0 +1,448 @@
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum::Display;
+
+/// What a symbol names, from its ELF symbol type.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum SymbolKind {
+    /// Unspecified, as for labels in hand-written assembly
+    NoType,
+    /// A data object (a variable, array, etc.)
+    Object,
+    /// A function
+    Func,
+    /// A section
+    Section,
+    /// A source file
+    File,
+    /// Anything else
+    Other,
+}
+
+/// Type and size of a symbol, as given by the program's symbol table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymbolInfo {
+    pub kind: SymbolKind,
+    /// Size in bytes, or 0 if unknown or not applicable
+    pub size: usize,
+}
+
+/// Symbol table supporting lookups both by name and by address.
+///
+/// Mangled C++ and Rust names are demangled (see [demangle]) wherever the
+/// table gives out names, unless [SymbolTable::set_demangle] says not to;
+/// symbols can be looked up by either name.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Serialize, Deserialize),
+    serde(into = "Vec<(String, usize)>", from = "Vec<(String, usize)>")
+)]
+pub struct SymbolTable {
+    /// Map of symbol names to their addresses
+    by_name: HashMap<String, usize>,
+    /// (address, name) pairs, sorted by address
+    by_addr: Vec<(usize, String)>,
+    /// Type and size of each symbol, where known (NB not kept in snapshots)
+    info: HashMap<String, SymbolInfo>,
+    /// Map of mangled names to their demangled forms
+    demangled: HashMap<String, String>,
+    /// Map of demangled names back to the mangled ones
+    mangled: HashMap<String, String>,
+    /// Whether to give out names as they are, without demangling them
+    raw: bool,
+    /// (start, end, name) of each function whose size is known, sorted by
+    /// address
+    extents: Vec<(usize, usize, String)>,
+}
+
+impl SymbolTable {
+    /// Returns a new, empty symbol table.
+    pub fn new() -> SymbolTable {
+        Default::default()
+    }
+
+    /// Adds a symbol to the table, replacing any existing symbol of the same
+    /// name.
+    pub fn insert(&mut self, name: &str, addr: usize) {
+        self.info.remove(name);
+        self.extents.retain(|(_, _, n)| n != name);
+        self.add_demangled(name);
+        if let Some(old) = self.by_name.insert(name.into(), addr) {
+            self.by_addr.retain(|(a, n)| !(*a == old && n == name));
+        }
+        let pos = self
+            .by_addr
+            .partition_point(|(a, n)| (*a, n.as_str()) < (addr, name));
+        self.by_addr.insert(pos, (addr, name.into()));
+    }
+
+    /// Returns the address of the symbol `name` (mangled or not).
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.by_name.get(self.mangled_name(name)).copied()
+    }
+
+    /// Records the type and size of the symbol `name`, if there is one.
+    pub fn set_info(&mut self, name: &str, info: SymbolInfo) {
+        let name = self.mangled_name(name).to_string();
+        let Some(&addr) = self.by_name.get(&name) else {
+            return;
+        };
+        self.extents.retain(|(_, _, n)| *n != name);
+        if info.kind == SymbolKind::Func && info.size > 0 {
+            let pos = self.extents.partition_point(|(a, _, _)| *a < addr);
+            self.extents
+                .insert(pos, (addr, addr + info.size, name.clone()));
+        }
+        self.info.insert(name, info);
+    }
+
+    /// Returns the type and size of the symbol `name`, if they're known.
+    pub fn info(&self, name: &str) -> Option<SymbolInfo> {
+        self.info.get(self.mangled_name(name)).copied()
+    }
+
+    /// Sets whether mangled names are demangled (as they are by default).
+    pub fn set_demangle(&mut self, demangle: bool) {
+        self.raw = !demangle;
+    }
+
+    /// Returns whether mangled names are demangled.
+    pub fn demangles(&self) -> bool {
+        !self.raw
+    }
+
+    /// Returns `name` as the table gives it out: demangled, unless told not
+    /// to.
+    fn display_name<'a>(&'a self, name: &'a str) -> &'a str {
+        match self.demangled.get(name) {
+            Some(demangled) if !self.raw => demangled,
+            _ => name,
+        }
+    }
+
+    /// Returns the mangled name of the symbol `name`, if it's a demangled
+    /// one (and otherwise `name`).
+    fn mangled_name<'a>(&'a self, name: &'a str) -> &'a str {
+        match self.mangled.get(name) {
+            Some(mangled) if !self.by_name.contains_key(name) => mangled,
+            _ => name,
+        }
+    }
+
+    /// Remembers the demangled form of `name`, if it's mangled.
+    fn add_demangled(&mut self, name: &str) {
+        if let Some(demangled) = demangle(name) {
+            self.mangled.insert(demangled.clone(), name.into());
+            self.demangled.insert(name.into(), demangled);
+        }
+    }
+
+    /// Returns the nearest symbol at or below `addr`, along with the offset of
+    /// `addr` from it.
+    ///
+    /// ```rust
+    /// use rvem::SymbolTable;
+    ///
+    /// let mut symtab = SymbolTable::new();
+    /// symtab.insert("fact", 0x10074);
+    /// symtab.insert("_start", 0x100a8);
+    /// assert_eq!(symtab.lookup(0x1007c), Some(("fact", 0x8)));
+    /// assert_eq!(symtab.lookup(0x100a8), Some(("_start", 0x0)));
+    /// assert_eq!(symtab.lookup(0x10000), None);
+    /// ```
+    pub fn lookup(&self, addr: usize) -> Option<(&str, usize)> {
+        let idx = self.by_addr.partition_point(|(a, _)| *a <= addr);
+        if idx == 0 {
+            return None;
+        }
+        // prefer the first (alphabetically) of several symbols at one address
+        let base = self.by_addr[idx - 1].0;
+        let first = self.by_addr[..idx].partition_point(|(a, _)| *a < base);
+        let (a, name) = &self.by_addr[first];
+        Some((self.display_name(name), addr - a))
+    }
+
+    /// Formats `addr` as `symbol+offset` (or just `symbol` if it's exact),
+    /// falling back to hex if there's no symbol at or below it.
+    pub fn symbolize(&self, addr: usize) -> String {
+        match self.lookup(addr) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+0x{:x}", name, offset),
+            None => format!("0x{:x}", addr),
+        }
+    }
+
+    /// Returns the function containing `addr`, along with the offset of
+    /// `addr` from its start. That's the function whose extent (from its
+    /// size, if known) covers `addr`, if there is one; otherwise it's the
+    /// nearest symbol at or below `addr`, as long as `addr` isn't past the
+    /// end of it (or of a function it's inside).
+    ///
+    /// ```rust
+    /// use rvem::{SymbolInfo, SymbolKind, SymbolTable};
+    ///
+    /// let mut symtab: SymbolTable = [("f", 0x100), ("f_loop", 0x108), ("g", 0x120)]
+    ///     .into_iter()
+    ///     .collect();
+    /// let kind = SymbolKind::Func;
+    /// symtab.set_info("f", SymbolInfo { kind, size: 0x10 });
+    /// symtab.set_info("g", SymbolInfo { kind, size: 0x8 });
+    /// assert_eq!(symtab.lookup_function(0x10c), Some(("f", 0xc)));
+    /// // between functions, and past the last one
+    /// assert_eq!(symtab.lookup_function(0x110), None);
+    /// assert_eq!(symtab.lookup_function(0x128), None);
+    /// ```
+    pub fn lookup_function(&self, addr: usize) -> Option<(&str, usize)> {
+        let idx = self.extents.partition_point(|(a, _, _)| *a <= addr);
+        let mut ended = 0;
+        if let Some((start, end, name)) = idx.checked_sub(1).map(|i| &self.extents[i]) {
+            if addr < *end {
+                return Some((self.display_name(name), addr - start));
+            }
+            ended = *end;
+        }
+        let (name, offset) = self.lookup(addr)?;
+        match self.info(name) {
+            _ if addr - offset < ended => None,
+            Some(info) if info.size > 0 && offset >= info.size => None,
+            _ => Some((name, offset)),
+        }
+    }
+
+    /// Returns the name of the function containing `addr` (see
+    /// [SymbolTable::lookup_function]), falling back to hex if there's no
+    /// such function.
+    pub fn symbolize_function(&self, addr: usize) -> String {
+        match self.lookup_function(addr) {
+            Some((name, _)) => name.to_string(),
+            None => format!("0x{:x}", addr),
+        }
+    }
+
+    /// Returns an iterator over all symbols as (name, address) pairs, in
+    /// order of address.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.by_addr.iter().map(|(a, n)| (self.display_name(n), *a))
+    }
+
+    /// Returns an iterator over the symbols whose names match `pattern`, as
+    /// (name, address) pairs in order of address. A pattern containing `*`
+    /// (any run of characters) or `?` (any one character) is a glob that
+    /// must match the whole name; any other pattern matches names that
+    /// contain it.
+    ///
+    /// ```rust
+    /// use rvem::SymbolTable;
+    ///
+    /// let symtab: SymbolTable = [("fact", 0x10074), ("_start", 0x100a8), ("ret_one", 0x1009c)]
+    ///     .into_iter()
+    ///     .collect();
+    /// let names = |pattern| symtab.matching(pattern).map(|(n, _)| n).collect::<Vec<_>>();
+    /// assert_eq!(names("ta"), ["_start"]);
+    /// assert_eq!(names("f*"), ["fact"]);
+    /// assert_eq!(names("*t"), ["fact", "_start"]);
+    /// assert_eq!(names("_st?rt"), ["_start"]);
+    /// ```
+    pub fn matching<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = (&'a str, usize)> {
+        let glob = pattern.contains(['*', '?']);
+        self.iter().filter(move |(name, _)| {
+            if glob {
+                glob_match(pattern.as_bytes(), name.as_bytes())
+            } else {
+                name.contains(pattern)
+            }
+        })
+    }
+
+    /// Returns the number of symbols in the table.
+    pub fn len(&self) -> usize {
+        self.by_addr.len()
+    }
+
+    /// Returns whether the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+}
+
+/// Returns the demangled form of `name`, if it's a mangled C++ (Itanium ABI)
+/// or Rust (legacy or v0) symbol name. Rust's hashes are left out.
+///
+/// ```rust
+/// use rvem::demangle;
+///
+/// assert_eq!(demangle("_ZN3foo3barEv").as_deref(), Some("foo::bar()"));
+/// assert_eq!(
+///     demangle("_ZN4core9panicking5panic17h2bd7b8e32bc2c5b8E").as_deref(),
+///     Some("core::panicking::panic")
+/// );
+/// assert_eq!(
+///     demangle("_RNvCs1234_7mycrate3foo").as_deref(),
+///     Some("mycrate::foo")
+/// );
+/// assert_eq!(demangle("main"), None);
+/// ```
+pub fn demangle(name: &str) -> Option<String> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return Some(format!("{:#}", demangled));
+    }
+    if !name.starts_with("_Z") {
+        return None;
+    }
+    let symbol = cpp_demangle::Symbol::new(name).ok()?;
+    symbol.demangle().ok()
+}
+
+/// Returns whether `name` matches the glob `pattern` (see
+/// [SymbolTable::matching]).
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // where to resume after the most recent `*`, if the match fails
+    let mut retry = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                retry = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => {}
+            Some(&c) if c == name[n] => {}
+            _ => match retry {
+                // let the `*` take one more character
+                Some((star, skipped)) => {
+                    retry = Some((star, skipped + 1));
+                    p = star + 1;
+                    n = skipped + 1;
+                    continue;
+                }
+                None => return false,
+            },
+        }
+        p += 1;
+        n += 1;
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+impl<S: AsRef<str>> FromIterator<(S, usize)> for SymbolTable {
+    fn from_iter<I: IntoIterator<Item = (S, usize)>>(iter: I) -> Self {
+        let mut symtab = SymbolTable::new();
+        for (name, addr) in iter {
+            symtab.by_name.insert(name.as_ref().into(), addr);
+        }
+        symtab.by_addr = symtab
+            .by_name
+            .iter()
+            .map(|(n, a)| (*a, n.clone()))
+            .collect();
+        symtab.by_addr.sort();
+        for (_, name) in symtab.by_addr.clone() {
+            symtab.add_demangled(&name);
+        }
+        symtab
+    }
+}
+
+impl From<SymbolTable> for Vec<(String, usize)> {
+    fn from(symtab: SymbolTable) -> Self {
+        symtab.by_addr.into_iter().map(|(a, n)| (n, a)).collect()
+    }
+}
+
+impl From<Vec<(String, usize)>> for SymbolTable {
+    fn from(symbols: Vec<(String, usize)>) -> Self {
+        symbols.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut symtab = SymbolTable::new();
+        symtab.insert("a", 0x10);
+        symtab.insert("b", 0x20);
+        symtab.insert("a", 0x30);
+        assert_eq!(symtab.get("a"), Some(0x30));
+        assert_eq!(symtab.len(), 2);
+        assert_eq!(symtab.lookup(0x18), None);
+        assert_eq!(symtab.symbolize(0x34), "a+0x4");
+    }
+
+    #[test]
+    fn test_info() {
+        let mut symtab: SymbolTable = [("main", 0x10), ("buf", 0x40)].into_iter().collect();
+        let info = SymbolInfo {
+            kind: SymbolKind::Object,
+            size: 64,
+        };
+        symtab.set_info("buf", info);
+        symtab.set_info("nope", info);
+        assert_eq!(symtab.info("buf"), Some(info));
+        assert_eq!(symtab.info("main"), None);
+        assert_eq!(symtab.info("nope"), None);
+        assert_eq!(info.kind.to_string(), "object");
+
+        // a new definition forgets the old one's info
+        symtab.insert("buf", 0x80);
+        assert_eq!(symtab.info("buf"), None);
+    }
+
+    #[test]
+    fn test_demangle() {
+        let mut symtab: SymbolTable = [("_ZN3foo3barEv", 0x10), ("main", 0x20)]
+            .into_iter()
+            .collect();
+        assert_eq!(symtab.symbolize(0x14), "foo::bar()+0x4");
+        assert_eq!(symtab.get("foo::bar()"), Some(0x10));
+        assert_eq!(symtab.get("_ZN3foo3barEv"), Some(0x10));
+        assert_eq!(symtab.matching("bar").count(), 1);
+
+        symtab.set_demangle(false);
+        assert_eq!(symtab.symbolize(0x14), "_ZN3foo3barEv+0x4");
+        assert_eq!(symtab.get("foo::bar()"), Some(0x10));
+        assert_eq!(
+            symtab.iter().collect::<Vec<_>>(),
+            [("_ZN3foo3barEv", 0x10), ("main", 0x20)]
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        for (pattern, name, matches) in [
+            ("*", "", true),
+            ("*", "anything", true),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("a*c", "abcbc", true),
+            ("??", "ab", true),
+            ("??", "abc", false),
+            ("__*_start", "__bss_start", true),
+            ("__*_start", "__bss_end", false),
+        ] {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), name.as_bytes()),
+                matches,
+                "{} vs. {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_collect() {
+        let symtab: SymbolTable = [("y", 0x8), ("x", 0x8), ("z", 0x0)].into_iter().collect();
+        assert_eq!(symtab.lookup(0xc), Some(("x", 0x4)));
+        assert_eq!(
+            symtab.iter().collect::<Vec<_>>(),
+            vec![("z", 0x0), ("x", 0x8), ("y", 0x8)]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::callgraph::is_call;
+use crate::{Emulator, Inst, Reg, Retired, SymbolTable, TraceSink};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::ops::Range;
+
+/// Where tainted data reached, as reported by [Taint].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TaintHit {
+    /// An indirect jump through a tainted register
+    Jump(Reg),
+    /// A syscall (by number) with a tainted argument register
+    SyscallArg(u32, Reg),
+    /// A syscall (by number) whose buffer argument holds tainted bytes
+    SyscallData(u32),
+    /// A call to a sink function (by address) with a tainted argument
+    /// register
+    Sink(usize, Reg),
+}
+
+/// A single place where tainted data reached a sink.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TaintReport {
+    /// Address of the instruction responsible
+    pub pc: usize,
+    /// Decoded instruction
+    pub inst: Inst,
+    /// What the tainted data reached
+    pub hit: TaintHit,
+}
+
+/// A [TraceSink] that does dynamic taint tracking: bytes from the chosen
+/// sources (stdin, or ranges of memory) are marked as tainted, taint is
+/// propagated from source to destination registers and through loads and
+/// stores, and a report is made whenever tainted data reaches the PC (via an
+/// indirect jump), a syscall argument, or one of the chosen sink functions.
+///
+/// Only data flow is tracked; a branch on a tainted value doesn't taint what
+/// happens as a result.
+#[derive(Clone, Debug, Default)]
+pub struct Taint {
+    /// Whether values read from stdin are tainted
+    stdin: bool,
+    /// Register taint, indexed by register number
+    regs: [bool; 32],
+    /// Tainted memory, by byte address
+    mem: HashSet<usize>,
+    /// Addresses of the sink functions
+    sinks: HashSet<usize>,
+    /// Reports so far, first occurrence only
+    reports: Vec<TaintReport>,
+    seen: HashSet<(usize, TaintHit)>,
+}
+
+/// Argument registers that a syscall (by number) reads.
+fn syscall_args(syscall: u32) -> &'static [Reg] {
+    match syscall {
+        1 | 4 | 93 => &[Reg::a0],
+        63 | 64 => &[Reg::a0, Reg::a1, Reg::a2],
+        _ => &[],
+    }
+}
+
+impl Taint {
+    /// Returns a new taint tracker with no sources or sinks.
+    pub fn new() -> Taint {
+        Default::default()
+    }
+
+    /// Marks values read from stdin as tainted.
+    pub fn taint_stdin(&mut self) {
+        self.stdin = true;
+    }
+
+    /// Marks the bytes in `range` as tainted.
+    pub fn taint_range(&mut self, range: Range<usize>) {
+        self.mem.extend(range);
+    }
+
+    /// Adds the function at `addr` as a sink, i.e., reports calls to it with
+    /// tainted arguments.
+    pub fn add_sink(&mut self, addr: usize) {
+        self.sinks.insert(addr);
+    }
+
+    /// Returns whether `reg` is currently tainted.
+    pub fn is_reg_tainted(&self, reg: Reg) -> bool {
+        self.regs[reg as usize]
+    }
+
+    /// Returns whether the byte at `addr` is currently tainted.
+    pub fn is_mem_tainted(&self, addr: usize) -> bool {
+        self.mem.contains(&addr)
+    }
+
+    /// Returns the places tainted data reached, in the order they were
+    /// first reached.
+    pub fn reports(&self) -> &[TaintReport] {
+        &self.reports
+    }
+
+    /// Formats the reports, one per line.
+    pub fn report(&self, symtab: &SymbolTable) -> String {
+        let mut report = String::new();
+        for r in &self.reports {
+            let what = match r.hit {
+                TaintHit::Jump(reg) => format!("jump through tainted {}", reg),
+                TaintHit::SyscallArg(n, reg) => format!("tainted {} passed to syscall {}", reg, n),
+                TaintHit::SyscallData(n) => format!("tainted data passed to syscall {}", n),
+                TaintHit::Sink(addr, reg) => format!(
+                    "tainted {} passed to {}",
+                    reg,
+                    symtab.symbolize_function(addr)
+                ),
+            };
+            let _ = writeln!(
+                report,
+                "{:08x} <{}>: {} ({})",
+                r.pc,
+                symtab.symbolize(r.pc),
+                what,
+                r.inst.at(r.pc)
+            );
+        }
+        report
+    }
+
+    fn hit(&mut self, pc: usize, inst: Inst, hit: TaintHit) {
+        if self.seen.insert((pc, hit)) {
+            self.reports.push(TaintReport { pc, inst, hit });
+        }
+    }
+
+    fn range_tainted(&self, addr: usize, len: usize) -> bool {
+        (addr..addr + len).any(|a| self.mem.contains(&a))
+    }
+}
+
+impl TraceSink for Taint {
+    fn before(&mut self, em: &Emulator, pc: usize, _word: u32, inst: &Inst) {
+        if !matches!(inst, Inst::ECALL) {
+            return;
+        }
+        let syscall = em[Reg::a7];
+        if self.is_reg_tainted(Reg::a7) {
+            self.hit(pc, *inst, TaintHit::SyscallArg(syscall, Reg::a7));
+        }
+        for reg in syscall_args(syscall) {
+            if self.is_reg_tainted(*reg) {
+                self.hit(pc, *inst, TaintHit::SyscallArg(syscall, *reg));
+            }
+        }
+        let data = match syscall {
+            // print_string: NUL-terminated string at a0
+            4 => {
+                let addr = em[Reg::a0] as usize;
+                let len = (addr..em.mem.len())
+                    .position(|a| em.mem[a] == 0)
+                    .unwrap_or(0);
+                Some((addr, len))
+            }
+            // write: a2 bytes at a1
+            64 => Some((em[Reg::a1] as usize, em[Reg::a2] as usize)),
+            _ => None,
+        };
+        if let Some((addr, len)) = data {
+            if self.range_tainted(addr, len) {
+                self.hit(pc, *inst, TaintHit::SyscallData(syscall));
+            }
+        }
+    }
+
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        let inst = retired.inst;
+        let (rs1, rs2) = inst.sources();
+        let tainted = |reg: Option<Reg>| reg.is_some_and(|reg| self.regs[reg as usize]);
+        let (t1, t2) = (tainted(rs1), tainted(rs2));
+
+        if let (Inst::JALR { rs1, .. }, true) = (inst, t1) {
+            self.hit(retired.pc, inst, TaintHit::Jump(rs1));
+        }
+        if is_call(&inst) && self.sinks.contains(&retired.next_pc) {
+            for reg in [
+                Reg::a0,
+                Reg::a1,
+                Reg::a2,
+                Reg::a3,
+                Reg::a4,
+                Reg::a5,
+                Reg::a6,
+                Reg::a7,
+            ] {
+                if self.is_reg_tainted(reg) {
+                    self.hit(retired.pc, inst, TaintHit::Sink(retired.next_pc, reg));
+                }
+            }
+        }
+
+        let taint = match (inst, retired.mem) {
+            (_, Some(mem)) if mem.store => {
+                for addr in mem.addr..mem.addr + mem.len {
+                    if t2 {
+                        self.mem.insert(addr);
+                    } else {
+                        self.mem.remove(&addr);
+                    }
+                }
+                return;
+            }
+            (_, Some(mem)) => self.range_tainted(mem.addr, mem.len),
+            // read puts its input in memory (a1), and returns how much of it
+            // there was
+            (Inst::ECALL, None) if self.stdin && em[Reg::a7] == 63 => {
+                let (addr, len) = (em[Reg::a1] as usize, em[Reg::a0] as i32);
+                self.mem.extend(addr..addr + len.max(0) as usize);
+                false
+            }
+            // otherwise only read_int takes input
+            (Inst::ECALL, None) => self.stdin && em[Reg::a7] == 5,
+            // links and CSR reads are never tainted
+            (
+                Inst::JAL { .. }
+                | Inst::JALR { .. }
+                | Inst::CSRRW { .. }
+                | Inst::CSRRS { .. }
+                | Inst::CSRRC { .. },
+                None,
+            ) => false,
+            _ => t1 || t2,
+        };
+        let dest = match inst {
+            Inst::ECALL => Some(Reg::a0),
+            inst => inst.dest(),
+        };
+        if let Some(rd) = dest.filter(|rd| *rd != Reg::zero) {
+            // NB other syscalls only overwrite a0 with their return value
+            if !matches!(inst, Inst::ECALL) || retired.rd.is_some() || taint {
+                self.regs[rd as usize] = taint;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_taint_range() {
+        let mut em = Emulator::load_from("tests/data/fib", None).unwrap();
+        let n = em.symbols().get("n").unwrap();
+        let mut taint = Taint::new();
+        taint.taint_range(n..n + 4);
+        assert_eq!(em.run_with(&mut taint).unwrap(), 0);
+
+        // n itself is loaded into x7, but the result only depends on it
+        // through control flow
+        assert!(taint.is_reg_tainted(Reg::t2));
+        assert!(!taint.is_reg_tainted(Reg::a0));
+        assert!(taint.reports().is_empty());
+    }
+}
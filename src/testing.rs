@@ -0,0 +1,393 @@
+//! Support for integration tests of interactive programs, expect-style:
+//! a [Harness] runs a program, feeding it input a line at a time as the
+//! test supplies it, until its output shows what the test expects (or it
+//! doesn't, in time).
+//!
+//! ```
+//! use rvem::testing::Harness;
+//!
+//! let mut h = Harness::new("tests/data/adder").unwrap();
+//! h.expect("> ").unwrap();
+//! h.send_line("40");
+//! h.expect("total: 40\n> ").unwrap();
+//! h.send_line("2").send_line("0");
+//! assert_eq!(h.expect_exit().unwrap(), 42);
+//! h.assert_reg("s0", 42);
+//! ```
+use crate::{Emulator, EmulatorError, NoTrace};
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a [Harness] waits for an expectation to be met, by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Instructions to run between checks of the program's output
+const SLICE: u64 = 10_000;
+
+/// Why an expectation of a [Harness] wasn't met.
+#[derive(Debug, thiserror::Error)]
+pub enum ExpectError {
+    #[error("timed out after {timeout:?} waiting for {expected:?}; output: {output:?}")]
+    Timeout {
+        expected: String,
+        timeout: Duration,
+        /// The output since the last expectation that was met
+        output: String,
+    },
+
+    #[error("program exited with code {code} while waiting for {expected:?}; output: {output:?}")]
+    Exited {
+        expected: String,
+        code: i32,
+        output: String,
+    },
+
+    #[error("program is waiting for input, not printing {expected:?}; output: {output:?}")]
+    WaitingForInput { expected: String, output: String },
+
+    #[error(transparent)]
+    Emulator(#[from] EmulatorError),
+}
+
+/// Input sent to the program but not yet read, and whether there'll be more.
+#[derive(Default)]
+struct Pipe {
+    pending: Vec<u8>,
+    closed: bool,
+}
+
+/// The program's end of a [Pipe]: it gets input a line at a time, as from a
+/// terminal, and reads "would block" when there isn't a whole line yet.
+struct Input {
+    pipe: Arc<Mutex<Pipe>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl BufRead for Input {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.buf.len() {
+            let mut pipe = (self.pipe.lock()).map_err(|_| io::Error::other("input poisoned"))?;
+            let len = match pipe.closed {
+                true => pipe.pending.len(),
+                false => (pipe.pending.iter().rposition(|b| *b == b'\n')).map_or(0, |i| i + 1),
+            };
+            if len == 0 && !pipe.closed {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.buf = pipe.pending.drain(..len).collect();
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let buf = self.fill_buf()?;
+        let len = buf.len().min(out.len());
+        out[..len].copy_from_slice(&buf[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+/// Output collected in a buffer shared with the [Harness].
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    fn bytes(&self) -> Vec<u8> {
+        self.0.lock().map(|out| out.clone()).unwrap_or_default()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("output poisoned"))?
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Drives a program with scripted input, checking its output as it goes;
+/// see the [module documentation](self).
+///
+/// Expectations ([Harness::expect] and [Harness::expect_exit]) run the
+/// program until they're met, or until it exits, waits for input that
+/// hasn't been sent, or runs out of time ([DEFAULT_TIMEOUT], unless
+/// [Harness::timeout] says otherwise), returning an [ExpectError] that says
+/// which.
+pub struct Harness {
+    em: Emulator,
+    input: Arc<Mutex<Pipe>>,
+    stdout: Output,
+    stderr: Output,
+    /// How far into the output expectations have been met
+    matched: usize,
+    timeout: Duration,
+    exit_code: Option<i32>,
+}
+
+impl Harness {
+    /// Loads the program at `path` to be driven by a new harness.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Harness, EmulatorError> {
+        Harness::with(Emulator::load_from(path, None)?)
+    }
+
+    /// Takes over `em` (into which a program has been loaded, and which has
+    /// been configured as need be), replacing its standard I/O with the
+    /// harness's.
+    pub fn with(mut em: Emulator) -> Result<Harness, EmulatorError> {
+        let input = Arc::new(Mutex::new(Pipe::default()));
+        let (stdout, stderr) = (Output::default(), Output::default());
+        em.set_stdin(Input {
+            pipe: input.clone(),
+            buf: Vec::new(),
+            pos: 0,
+        });
+        em.set_stdout(stdout.clone());
+        em.set_stderr(stderr.clone());
+        em.init()?;
+        Ok(Harness {
+            em,
+            input,
+            stdout,
+            stderr,
+            matched: 0,
+            timeout: DEFAULT_TIMEOUT,
+            exit_code: None,
+        })
+    }
+
+    /// Sets how long to wait for each expectation to be met.
+    pub fn timeout(mut self, timeout: Duration) -> Harness {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends `input` to the program; it gets it a line at a time.
+    pub fn send(&mut self, input: &str) -> &mut Harness {
+        if let Ok(mut pipe) = self.input.lock() {
+            pipe.pending.extend_from_slice(input.as_bytes());
+        }
+        self
+    }
+
+    /// Sends `line` to the program, followed by a newline.
+    pub fn send_line(&mut self, line: &str) -> &mut Harness {
+        self.send(line).send("\n")
+    }
+
+    /// Closes the program's standard input: once it's read what's been sent
+    /// (including any last partial line), it reads end-of-file.
+    pub fn close(&mut self) -> &mut Harness {
+        if let Ok(mut pipe) = self.input.lock() {
+            pipe.closed = true;
+        }
+        self
+    }
+
+    /// Runs the program until it prints `text`, and returns what it printed
+    /// since the last expectation was met, up to and including `text`.
+    pub fn expect(&mut self, text: &str) -> Result<String, ExpectError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let state = self.advance()?;
+            let output = self.stdout.bytes();
+            let unmatched = &output[self.matched.min(output.len())..];
+            let found = match text.len() {
+                0 => Some(0),
+                len => (unmatched.windows(len)).position(|w| w == text.as_bytes()),
+            };
+            if let Some(i) = found {
+                let end = i + text.len();
+                self.matched += end;
+                return Ok(String::from_utf8_lossy(&unmatched[..end]).into_owned());
+            }
+            let output = String::from_utf8_lossy(unmatched).into_owned();
+            let expected = text.to_string();
+            match state {
+                State::Exited(code) => {
+                    return Err(ExpectError::Exited {
+                        expected,
+                        code,
+                        output,
+                    })
+                }
+                State::Blocked => return Err(ExpectError::WaitingForInput { expected, output }),
+                State::Running if Instant::now() >= deadline => {
+                    return Err(ExpectError::Timeout {
+                        expected,
+                        timeout: self.timeout,
+                        output,
+                    })
+                }
+                State::Running => {}
+            }
+        }
+    }
+
+    /// Runs the program until it exits, and returns its exit code.
+    pub fn expect_exit(&mut self) -> Result<i32, ExpectError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let state = self.advance()?;
+            let output = || {
+                let output = self.stdout.bytes();
+                String::from_utf8_lossy(&output[self.matched.min(output.len())..]).into_owned()
+            };
+            let expected = "exit".to_string();
+            match state {
+                State::Exited(code) => return Ok(code),
+                State::Blocked => {
+                    return Err(ExpectError::WaitingForInput {
+                        expected,
+                        output: output(),
+                    })
+                }
+                State::Running if Instant::now() >= deadline => {
+                    return Err(ExpectError::Timeout {
+                        expected,
+                        timeout: self.timeout,
+                        output: output(),
+                    })
+                }
+                State::Running => {}
+            }
+        }
+    }
+
+    /// Runs the program a little further, if it's still running.
+    fn advance(&mut self) -> Result<State, EmulatorError> {
+        if let Some(code) = self.exit_code {
+            return Ok(State::Exited(code));
+        }
+        let until = self.em.instret.saturating_add(SLICE);
+        match self.em.resume_until(&mut NoTrace, until) {
+            Ok(Some(code)) => {
+                self.exit_code = Some(code);
+                Ok(State::Exited(code))
+            }
+            Ok(None) => Ok(State::Running),
+            Err(EmulatorError::IO(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                Ok(State::Blocked)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns everything the program has written to its standard output.
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.stdout.bytes()).into_owned()
+    }
+
+    /// ...and its standard error.
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.stderr.bytes()).into_owned()
+    }
+
+    /// Returns the emulator running the program, e.g., to look at its state.
+    pub fn emulator(&mut self) -> &mut Emulator {
+        &mut self.em
+    }
+
+    /// Asserts that register `reg` (by name, e.g., "a0" or "x10") holds
+    /// `value`.
+    #[track_caller]
+    pub fn assert_reg(&self, reg: &str, value: u32) {
+        let reg: crate::Reg = reg.parse().unwrap_or_else(|e| panic!("{}", e));
+        let actual = self.em[reg];
+        assert!(
+            actual == value,
+            "{} is 0x{:08x} ({}), not 0x{:08x} ({})",
+            reg,
+            actual,
+            actual as i32,
+            value,
+            value as i32
+        );
+    }
+
+    /// Asserts that the memory at `loc` (a symbol or address, as for
+    /// [Emulator::lookup]) holds `bytes`.
+    #[track_caller]
+    pub fn assert_memory(&self, loc: &str, bytes: &[u8]) {
+        let actual = (self.em.lookup(loc))
+            .and_then(|addr| self.em.memory(addr, bytes.len()))
+            .unwrap_or_else(|e| panic!("{}: {}", loc, e));
+        assert!(
+            actual == bytes,
+            "memory at {} is {:02x?}, not {:02x?}",
+            loc,
+            actual,
+            bytes
+        );
+    }
+}
+
+/// Where a program's got to, as far as a [Harness] is concerned.
+enum State {
+    Running,
+    Blocked,
+    Exited(i32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_harness() {
+        let mut h = Harness::new("tests/data/adder").unwrap();
+        assert_eq!(h.expect("> ").unwrap(), "> ");
+        // nothing more until there's a whole line
+        h.send("1");
+        assert!(matches!(
+            h.expect("total"),
+            Err(ExpectError::WaitingForInput { .. })
+        ));
+        h.send("2\n");
+        assert_eq!(h.expect("> ").unwrap(), "total: 12\n> ");
+        h.send_line("-2").send_line("5");
+        h.expect("10").unwrap();
+        h.expect("15\n").unwrap();
+        assert_eq!(
+            h.expect("total: 20").unwrap_err().to_string(),
+            "program is waiting for input, not printing \"total: 20\"; output: \"> \""
+        );
+        h.send("0").close();
+        assert_eq!(h.expect_exit().unwrap(), 15);
+        assert!(matches!(
+            h.expect("more"),
+            Err(ExpectError::Exited { code: 15, .. })
+        ));
+        assert_eq!(h.stdout(), "> total: 12\n> total: 10\n> total: 15\n> ");
+        h.assert_reg("s0", 15);
+        h.assert_memory("prompt", b"> \0");
+
+        let mut h = Harness::new("tests/data/primes")
+            .unwrap()
+            .timeout(Duration::from_millis(1));
+        assert!(matches!(h.expect_exit(), Err(ExpectError::Timeout { .. })));
+    }
+
+    #[test]
+    #[should_panic(expected = "s0 is 0x00000000 (0), not 0x00000001 (1)")]
+    fn test_assert_reg() {
+        Harness::new("tests/data/adder")
+            .unwrap()
+            .assert_reg("s0", 1);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{Inst, InstClass};
+use std::str::FromStr;
+
+/// A simple timing model: a fixed latency (in cycles) per class of
+/// instruction, plus a penalty for taken branches and jumps. It's nowhere near
+/// a real pipeline, but it beats assuming one cycle per instruction.
+///
+/// ```rust
+/// use rvem::Timing;
+///
+/// let timing: Timing = "load=3,div=20".parse().unwrap();
+/// assert_eq!(timing.load, 3);
+/// assert_eq!(timing.div, 20);
+/// assert_eq!(timing.mul, Timing::default().mul);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    /// Everything not covered below
+    pub base: u32,
+    /// Loads
+    pub load: u32,
+    /// Stores
+    pub store: u32,
+    /// Multiplies
+    pub mul: u32,
+    /// Divides and remainders
+    pub div: u32,
+    /// Additional cycles for a taken branch or jump
+    pub taken: u32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            base: 1,
+            load: 2,
+            store: 1,
+            mul: 3,
+            div: 34,
+            taken: 2,
+        }
+    }
+}
+
+impl Timing {
+    /// Returns the latency of `inst`, not counting any taken-branch penalty.
+    pub fn latency(&self, inst: &Inst) -> u32 {
+        match inst.class() {
+            InstClass::Load => self.load,
+            InstClass::Store => self.store,
+            InstClass::Mul => self.mul,
+            InstClass::Div => self.div,
+            _ => self.base,
+        }
+    }
+}
+
+/// Parses a comma-separated list of `class=cycles` overrides (e.g.,
+/// `load=3,mul=4`) on top of the defaults; an empty string gives the defaults.
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut timing = Timing::default();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (class, cycles) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected class=cycles, found '{}'", item))?;
+            let cycles = cycles
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid latency '{}': {}", cycles, e))?;
+            match class.trim() {
+                "base" => timing.base = cycles,
+                "load" => timing.load = cycles,
+                "store" => timing.store = cycles,
+                "mul" => timing.mul = cycles,
+                "div" => timing.div = cycles,
+                "taken" => timing.taken = cycles,
+                class => return Err(format!("unknown instruction class '{}'", class)),
+            }
+        }
+        Ok(timing)
+    }
+}
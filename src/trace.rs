@@ -0,0 +1,192 @@
+use crate::{Emulator, Inst, Reg};
+
+/// A memory access performed by a load or store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemAccess {
+    /// Effective address
+    pub addr: usize,
+    /// Access width in bytes
+    pub len: usize,
+    /// Whether this was a store (as opposed to a load)
+    pub store: bool,
+    /// Value loaded or stored (zero-extended)
+    pub value: u32,
+}
+
+/// Everything a [TraceSink] gets to know about a retired instruction.
+#[derive(Clone, Copy, Debug)]
+pub struct Retired {
+    /// Address the instruction was fetched from
+    pub pc: usize,
+    /// Raw instruction word
+    pub word: u32,
+    /// Decoded instruction
+    pub inst: Inst,
+    /// Register written by the instruction, along with its new value
+    pub rd: Option<(Reg, u32)>,
+    /// Memory accessed by the instruction, if any
+    pub mem: Option<MemAccess>,
+    /// Address of the next instruction to be executed
+    pub next_pc: usize,
+}
+
+/// Works out the memory access `inst` is about to perform (if it's a load or
+/// store), given the current state of `em`.
+pub(crate) fn mem_access(em: &Emulator, inst: &Inst) -> Option<MemAccess> {
+    let (rs1, imm, len, store) = match *inst {
+        Inst::LB { rs1, imm, .. } | Inst::LBU { rs1, imm, .. } => (rs1, imm, 1, false),
+        Inst::LH { rs1, imm, .. } | Inst::LHU { rs1, imm, .. } => (rs1, imm, 2, false),
+        Inst::LW { rs1, imm, .. } => (rs1, imm, 4, false),
+        Inst::SB { rs1, imm, .. } => (rs1, imm, 1, true),
+        Inst::SH { rs1, imm, .. } => (rs1, imm, 2, true),
+        Inst::SW { rs1, imm, .. } => (rs1, imm, 4, true),
+        _ => return None,
+    };
+    let addr = em[rs1].wrapping_add(imm as u32) as usize;
+    let value = match *inst {
+        Inst::SB { rs2, .. } | Inst::SH { rs2, .. } | Inst::SW { rs2, .. } => {
+            em[rs2] & (u32::MAX >> (32 - 8 * len))
+        }
+        _ => em
+            .mem
+            .get(addr..addr + len)
+            .map(|bytes| bytes.iter().rev().fold(0, |v, b| (v << 8) | *b as u32))
+            .unwrap_or(0),
+    };
+    Some(MemAccess {
+        addr,
+        len,
+        store,
+        value,
+    })
+}
+
+/// Observer for program execution, passed to [Emulator::run_with].
+///
+/// All of the hooks default to doing nothing. Since [Emulator::run_with] is
+/// generic over the sink, a sink that reports itself as disabled (such as
+/// [NoTrace]) compiles away entirely, and the emulator is free to execute
+/// whole basic blocks without stopping to report on each instruction.
+pub trait TraceSink {
+    /// Whether this sink wants per-instruction events at all.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    /// Called just before the instruction at `pc` is executed.
+    fn before(&mut self, _em: &Emulator, _pc: usize, _word: u32, _inst: &Inst) {}
+
+    /// Called after each instruction retires.
+    fn retire(&mut self, _em: &Emulator, _retired: &Retired) {}
+
+    /// Whether the sink wants execution stopped (e.g., because it's found
+    /// what it was looking for); checked after each instruction retires.
+    fn halt(&self) -> bool {
+        false
+    }
+}
+
+/// The do-nothing [TraceSink], for running at full speed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoTrace;
+
+impl TraceSink for NoTrace {
+    #[inline(always)]
+    fn enabled(&self) -> bool {
+        false
+    }
+}
+
+/// A [TraceSink] that logs each instruction at debug level (with its source
+/// file and line, if known), and the machine state before each instruction
+/// at trace level.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogTrace;
+
+impl TraceSink for LogTrace {
+    fn before(&mut self, em: &Emulator, pc: usize, word: u32, inst: &Inst) {
+        if log::log_enabled!(target: "rvem::exec", log::Level::Trace) {
+            // dump registers
+            log::trace!(target: "rvem::exec", "{em:?}");
+        }
+        match em.lines().lookup(pc) {
+            Some((file, line)) => log::debug!(
+                target: "rvem::exec",
+                "{:x} <{}>: {:08x} {}  # {}:{}",
+                pc,
+                em.symbols().symbolize(pc),
+                word,
+                inst.at(pc),
+                file,
+                line
+            ),
+            None => log::debug!(
+                target: "rvem::exec",
+                "{:x} <{}>: {:08x} {}",
+                pc,
+                em.symbols().symbolize(pc),
+                word,
+                inst.at(pc)
+            ),
+        }
+    }
+}
+
+impl<T: TraceSink + ?Sized> TraceSink for &mut T {
+    fn enabled(&self) -> bool {
+        (**self).enabled()
+    }
+
+    fn before(&mut self, em: &Emulator, pc: usize, word: u32, inst: &Inst) {
+        (**self).before(em, pc, word, inst)
+    }
+
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        (**self).retire(em, retired)
+    }
+
+    fn halt(&self) -> bool {
+        (**self).halt()
+    }
+}
+
+impl<T: TraceSink + ?Sized> TraceSink for Box<T> {
+    fn enabled(&self) -> bool {
+        (**self).enabled()
+    }
+
+    fn before(&mut self, em: &Emulator, pc: usize, word: u32, inst: &Inst) {
+        (**self).before(em, pc, word, inst)
+    }
+
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        (**self).retire(em, retired)
+    }
+
+    fn halt(&self) -> bool {
+        (**self).halt()
+    }
+}
+
+/// Fans events out to every sink in the list.
+impl<T: TraceSink> TraceSink for Vec<T> {
+    fn enabled(&self) -> bool {
+        self.iter().any(|sink| sink.enabled())
+    }
+
+    fn before(&mut self, em: &Emulator, pc: usize, word: u32, inst: &Inst) {
+        for sink in self.iter_mut() {
+            sink.before(em, pc, word, inst);
+        }
+    }
+
+    fn retire(&mut self, em: &Emulator, retired: &Retired) {
+        for sink in self.iter_mut() {
+            sink.retire(em, retired);
+        }
+    }
+
+    fn halt(&self) -> bool {
+        self.iter().any(|sink| sink.halt())
+    }
+}
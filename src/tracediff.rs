@@ -0,0 +1,302 @@
+use crate::{EmulatorError, Inst, Retired};
+use std::io;
+
+/// A retired instruction, as read back from a trace written by
+/// [crate::JsonTrace] or [crate::SpikeTrace].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceRecord {
+    /// Address of the instruction
+    pub pc: usize,
+    /// Raw instruction word
+    pub word: u32,
+    /// Register written (by number), along with its new value
+    pub rd: Option<(u32, u32)>,
+    /// Memory address accessed, along with the value stored (if it was a
+    /// store)
+    pub mem: Option<(usize, Option<u32>)>,
+}
+
+impl std::fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:08x} (0x{:08x})", self.pc, self.word)?;
+        if let Some((rd, value)) = self.rd {
+            write!(f, " x{:<2} 0x{:08x}", rd, value)?;
+        }
+        if let Some((addr, value)) = self.mem {
+            write!(f, " mem 0x{:08x}", addr)?;
+            if let Some(value) = value {
+                write!(f, " 0x{:08x}", value)?;
+            }
+        }
+        if let Ok(inst) = Inst::try_from(self.word) {
+            write!(f, "  # {}", inst.at(self.pc))?;
+        }
+        Ok(())
+    }
+}
+
+impl TraceRecord {
+    /// Returns whether this record is equivalent to `other`. Spike doesn't log
+    /// a syscall's return value as a writeback, so that's only compared if
+    /// both records have one.
+    pub fn matches(&self, other: &TraceRecord) -> bool {
+        let ecall = matches!(Inst::try_from(self.word), Ok(Inst::ECALL));
+        self.pc == other.pc
+            && self.word == other.word
+            && self.mem == other.mem
+            && (self.rd == other.rd || (ecall && (self.rd.is_none() || other.rd.is_none())))
+    }
+}
+
+impl From<&Retired> for TraceRecord {
+    fn from(retired: &Retired) -> TraceRecord {
+        TraceRecord {
+            pc: retired.pc,
+            word: retired.word,
+            rd: retired.rd.map(|(rd, value)| (rd as u32, value)),
+            mem: retired
+                .mem
+                .map(|mem| (mem.addr, mem.store.then_some(mem.value))),
+        }
+    }
+}
+
+/// Parses a trace in either JSONL or spike commit log format (which may be
+/// mixed, for what it's worth); lines that don't look like either are skipped.
+pub fn parse_trace(trace: &str) -> Result<Vec<TraceRecord>, EmulatorError> {
+    let mut records = Vec::new();
+    for (n, line) in trace.lines().enumerate() {
+        let Some(record) = parse_record(line) else {
+            continue;
+        };
+        records.push(record.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: malformed trace record: {}", n + 1, line),
+            )
+        })?);
+    }
+    Ok(records)
+}
+
+/// Parses a single line of a trace: `None` if it isn't a trace record at
+/// all, or `Some(None)` if it looks like one but is malformed.
+pub(crate) fn parse_record(line: &str) -> Option<Option<TraceRecord>> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        Some(parse_json(line))
+    } else if line.starts_with("core") {
+        Some(parse_spike(line))
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "serialize")]
+fn parse_json(line: &str) -> Option<TraceRecord> {
+    let json: serde_json::Value = serde_json::from_str(line).ok()?;
+    let rd = match &json["rd"] {
+        serde_json::Value::Null => None,
+        rd => {
+            let name = rd["reg"].as_str()?;
+            let reg: crate::Reg = name.parse().ok()?;
+            Some((reg as u32, rd["value"].as_u64()? as u32))
+        }
+    };
+    let mem = match &json["mem"] {
+        serde_json::Value::Null => None,
+        mem => {
+            let addr = mem["addr"].as_u64()? as usize;
+            let value = mem["value"].as_u64()? as u32;
+            Some((addr, mem["store"].as_bool()?.then_some(value)))
+        }
+    };
+    Some(TraceRecord {
+        pc: json["pc"].as_u64()? as usize,
+        word: json["word"].as_u64()? as u32,
+        rd,
+        mem,
+    })
+}
+
+/// JSONL traces can only be read with the `serialize` feature; without it,
+/// every record in one is malformed.
+#[cfg(not(feature = "serialize"))]
+fn parse_json(_line: &str) -> Option<TraceRecord> {
+    None
+}
+
+fn parse_spike(line: &str) -> Option<TraceRecord> {
+    fn hex(s: &str) -> Option<u64> {
+        u64::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+    }
+
+    // core   0: 3 0x00010090 (0x00412283) x5  0x00000002 mem 0x0007ffe4
+    let (_, rest) = line.split_once(':')?;
+    let mut tokens = rest.split_whitespace().skip(1).peekable();
+    let pc = hex(tokens.next()?)? as usize;
+    let word = hex(tokens.next()?.trim_matches(|c| c == '(' || c == ')'))? as u32;
+
+    let mut record = TraceRecord {
+        pc,
+        word,
+        rd: None,
+        mem: None,
+    };
+    while let Some(token) = tokens.next() {
+        if token == "mem" {
+            let addr = hex(tokens.next()?)? as usize;
+            let value = match tokens.peek() {
+                Some(value) if value.starts_with("0x") => Some(hex(tokens.next()?)? as u32),
+                _ => None,
+            };
+            record.mem = Some((addr, value));
+        } else if let Some(rd) = token.strip_prefix('x') {
+            record.rd = Some((rd.parse().ok()?, hex(tokens.next()?)? as u32));
+        } else {
+            // NB e.g., CSR or floating-point writebacks, which we don't model
+            tokens.next();
+        }
+    }
+    Some(record)
+}
+
+/// Where (and how) two traces diverge.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// Index of the first differing record in each trace
+    pub index: (usize, usize),
+    /// The differing records (`None` if one trace ended before the other)
+    pub records: (Option<TraceRecord>, Option<TraceRecord>),
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "traces diverge at record {} (a) / {} (b):",
+            self.index.0 + 1,
+            self.index.1 + 1
+        )?;
+        match self.records {
+            (Some(a), Some(b)) => {
+                writeln!(f, "  a: {}", a)?;
+                writeln!(f, "  b: {}", b)?;
+                if a.pc != b.pc {
+                    writeln!(f, "  pc differs: 0x{:08x} != 0x{:08x}", a.pc, b.pc)?;
+                } else if a.word != b.word {
+                    writeln!(
+                        f,
+                        "  instruction differs: 0x{:08x} != 0x{:08x}",
+                        a.word, b.word
+                    )?;
+                }
+                if a.rd != b.rd {
+                    let fmt = |rd: Option<(u32, u32)>| match rd {
+                        Some((rd, value)) => format!("x{} = 0x{:08x}", rd, value),
+                        None => "none".to_string(),
+                    };
+                    writeln!(
+                        f,
+                        "  register write differs: {} != {}",
+                        fmt(a.rd),
+                        fmt(b.rd)
+                    )?;
+                }
+                if a.mem != b.mem {
+                    let fmt = |mem: Option<(usize, Option<u32>)>| match mem {
+                        Some((addr, Some(value))) => format!("0x{:08x} <- 0x{:08x}", addr, value),
+                        Some((addr, None)) => format!("0x{:08x}", addr),
+                        None => "none".to_string(),
+                    };
+                    writeln!(
+                        f,
+                        "  memory access differs: {} != {}",
+                        fmt(a.mem),
+                        fmt(b.mem)
+                    )?;
+                }
+            }
+            (Some(a), None) => {
+                writeln!(f, "  a: {}", a)?;
+                writeln!(f, "  b: (end of trace)")?;
+            }
+            (None, Some(b)) => {
+                writeln!(f, "  a: (end of trace)")?;
+                writeln!(f, "  b: {}", b)?;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Compares two traces, returning the first point at which they diverge (or
+/// `None` if they're the same).
+///
+/// Before comparing, the traces are aligned on the first PC they have in
+/// common, so that e.g. boot code at the start of one trace is skipped over.
+/// Memory values are only compared for stores, since spike doesn't log the
+/// values of loads.
+pub fn diff_traces(a: &[TraceRecord], b: &[TraceRecord]) -> Option<Divergence> {
+    let (start_a, start_b) = align(a, b);
+    let (a, b) = (&a[start_a..], &b[start_b..]);
+    for i in 0..a.len().max(b.len()) {
+        let (ra, rb) = (a.get(i).copied(), b.get(i).copied());
+        let same = match (ra, rb) {
+            (Some(ra), Some(rb)) => ra.matches(&rb),
+            (ra, rb) => ra == rb,
+        };
+        if !same {
+            return Some(Divergence {
+                index: (start_a + i, start_b + i),
+                records: (ra, rb),
+            });
+        }
+    }
+    None
+}
+
+/// Returns the positions in `a` and `b` of the earliest PC they have in
+/// common, or (0, 0) if there isn't one.
+fn align(a: &[TraceRecord], b: &[TraceRecord]) -> (usize, usize) {
+    let first = |records: &[TraceRecord], pc: usize| records.iter().position(|r| r.pc == pc);
+    let in_b = a.first().and_then(|ra| first(b, ra.pc)).map(|j| (0, j));
+    let in_a = b.first().and_then(|rb| first(a, rb.pc)).map(|i| (i, 0));
+    match (in_b, in_a) {
+        (Some((_, j)), Some((i, _))) if i < j => (i, 0),
+        (Some(pos), _) | (None, Some(pos)) => pos,
+        (None, None) => (0, 0),
+    }
+}
+
+#[cfg(all(test, feature = "serialize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_traces() {
+        let spike = "core   0: 3 0x00001000 (0x00000297) x5  0x00001000\n\
+                     core   0: 3 0x00010074 (0xff810113) x2  0x0007fff8\n\
+                     core   0: 3 0x00010078 (0x00112023) mem 0x0007fff8 0x000100b4\n";
+        let json = "{\"pc\":65652,\"word\":4286644499,\"inst\":\"addi sp, sp, -8\",\"rd\":{\"reg\":\"sp\",\"value\":524280},\"mem\":null}\n\
+                    {\"pc\":65656,\"word\":1122339,\"inst\":\"sw ra, 0(sp)\",\"rd\":null,\"mem\":{\"addr\":524280,\"len\":4,\"store\":true,\"value\":65717}}\n";
+        let a = parse_trace(spike).unwrap();
+        let b = parse_trace(json).unwrap();
+        assert_eq!(a.len(), 3);
+        assert_eq!(a[1], b[0]);
+
+        let divergence = diff_traces(&a, &b).unwrap();
+        assert_eq!(divergence.index, (2, 1));
+        assert_eq!(
+            divergence.records.0.unwrap().mem,
+            Some((0x7fff8, Some(0x100b4)))
+        );
+        assert_eq!(
+            divergence.records.1.unwrap().mem,
+            Some((0x7fff8, Some(0x100b5)))
+        );
+
+        assert_eq!(diff_traces(&a[..2], &b[..1]), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! A small wasm-bindgen API, for running (and visualizing) programs in the
+//! browser: build with `wasm-pack build --target web -- --features wasm`.
+//!
+//! There's no terminal in the browser, so the program's output is collected
+//! in a buffer for the page to [WasmEmulator::take_output] (after each
+//! [WasmEmulator::run], say), and its input comes from whatever the page
+//! gives [WasmEmulator::set_input].
+use crate::{Emulator, EmulatorError, Inst, NoTrace};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::prelude::*;
+
+/// A [Write] that collects output into a buffer shared with the
+/// [WasmEmulator].
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("output buffer poisoned"))?
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn js_error(e: EmulatorError) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+/// An [Emulator], as seen from JavaScript.
+#[wasm_bindgen]
+pub struct WasmEmulator {
+    em: Emulator,
+    output: Output,
+}
+
+#[wasm_bindgen]
+impl WasmEmulator {
+    /// Loads `program` (the contents of an ELF file) into a new emulator
+    /// with `memory` bytes of memory (or the default amount).
+    #[wasm_bindgen(constructor)]
+    pub fn new(program: &[u8], memory: Option<usize>) -> Result<WasmEmulator, JsError> {
+        let mut em = Emulator::new(memory);
+        em.load_bytes(program).map_err(js_error)?;
+        em.init().map_err(js_error)?;
+        let output = Output::default();
+        em.set_stdout(output.clone());
+        em.set_stderr(output.clone());
+        em.set_stdin(io::empty());
+        Ok(WasmEmulator { em, output })
+    }
+
+    /// Runs the program for up to `max_instructions` instructions (so as not
+    /// to block the page for too long), returning its exit code if it
+    /// exited.
+    pub fn run(&mut self, max_instructions: u32) -> Result<Option<i32>, JsError> {
+        for _ in 0..max_instructions {
+            if self.em.exit_code.is_some() {
+                break;
+            }
+            self.em.step_with(&mut NoTrace).map_err(js_error)?;
+        }
+        Ok(self.em.exit_code)
+    }
+
+    /// Executes a single instruction, returning the program's exit code if
+    /// it exited.
+    pub fn step(&mut self) -> Result<Option<i32>, JsError> {
+        self.run(1)
+    }
+
+    /// Returns (and clears) whatever the program has written to its
+    /// standard output and error since the last call.
+    pub fn take_output(&mut self) -> String {
+        let mut buf = self.output.0.lock().unwrap_or_else(|e| e.into_inner());
+        let output = String::from_utf8_lossy(&buf).into_owned();
+        buf.clear();
+        output
+    }
+
+    /// Sets what the program reads from its standard input.
+    pub fn set_input(&mut self, input: String) {
+        self.em.set_stdin(io::Cursor::new(input.into_bytes()));
+    }
+
+    /// Returns the program counter.
+    pub fn pc(&self) -> usize {
+        self.em.pc()
+    }
+
+    /// Returns the values of the 32 registers.
+    pub fn registers(&self) -> Vec<u32> {
+        self.em.registers().to_vec()
+    }
+
+    /// Returns the number of instructions retired so far.
+    pub fn instret(&self) -> u64 {
+        self.em.instret()
+    }
+
+    /// Returns a copy of the `len` bytes of memory at `addr`.
+    pub fn memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, JsError> {
+        self.em
+            .memory(addr, len)
+            .map(<[u8]>::to_vec)
+            .map_err(js_error)
+    }
+
+    /// Returns the disassembly of the instruction at `addr`.
+    pub fn disassemble(&self, addr: usize) -> Result<String, JsError> {
+        let word = self.em.read_u32(addr).map_err(js_error)?;
+        let inst = Inst::try_from(word).map_err(js_error)?;
+        Ok(inst.at(addr).to_string())
+    }
+
+    /// Returns the name of the function containing `addr`, if any.
+    pub fn function_at(&self, addr: usize) -> Option<String> {
+        self.em.function_containing(addr).map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wasm() {
+        let program = std::fs::read("tests/data/hello").unwrap();
+        let mut em = WasmEmulator::new(&program, None).unwrap();
+        assert_eq!(em.function_at(em.pc()).as_deref(), Some("_start"));
+        assert!(em.disassemble(em.pc()).is_ok());
+        assert_eq!(em.step().unwrap(), None);
+        assert_eq!(em.instret(), 1);
+        assert_eq!(em.run(1000).unwrap(), Some(0));
+        assert_eq!(em.take_output(), "Hello World!\n");
+        assert_eq!(em.take_output(), "");
+    }
+}
@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use predicates::prelude::*;
 
 #[test]
 fn test_hello() {
@@ -48,3 +49,1226 @@ fn test_strlen() {
 
     assert.success().code(0).stdout("44");
 }
+
+#[test]
+fn test_hello_strict() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--strict").arg("tests/data/hello").assert();
+
+    assert.success().code(0).stdout("Hello World!\n");
+}
+
+#[test]
+fn test_primes() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("tests/data/primes").assert();
+
+    assert.success().code(0).stdout("9592");
+}
+
+#[test]
+fn test_bench() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--bench").arg("tests/data/fac").assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr(predicates::str::contains("instructions: 67"))
+        .stderr(predicates::str::contains("MIPS: "));
+}
+
+#[test]
+fn test_timing() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--timing=taken=0").arg("tests/data/fac").assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr(predicates::str::contains("cycles: 84"));
+}
+
+#[test]
+fn test_cost_model() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--cost-model")
+        .arg("tests/data/example.cost")
+        .arg("tests/data/fac")
+        .assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr(predicates::str::contains("cost: 127"))
+        .stderr(predicates::str::contains("  fact: 82"));
+}
+
+#[test]
+fn test_stats() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--stats").arg("tests/data/fac").assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr(predicates::str::contains("instructions: 67"))
+        .stderr(predicates::str::contains("1 taken, 4 not taken"))
+        .stderr(predicates::str::contains("  mul: 4"));
+}
+
+#[test]
+fn test_hotspots() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--hotspots=1").arg("tests/data/fac").assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr(predicates::str::contains(
+            "10074  fact                     addi sp, sp, -8",
+        ));
+}
+
+#[test]
+fn test_profile() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--profile").arg("tests/data/fac").assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr(predicates::str::contains(
+            " 65.67%           44           44           44        5  fact",
+        ));
+}
+
+#[test]
+fn test_call_graph() {
+    let dir = std::env::temp_dir().join("rvem-test-call-graph");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fac.dot");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--call-graph")
+        .arg(&path)
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().code(0).stdout("120");
+
+    let dot = std::fs::read_to_string(&path).unwrap();
+    assert!(dot.starts_with("digraph calls {"));
+    assert!(dot.contains("\"_start\" -> \"fact\" [label=\"1\"];"));
+    assert!(dot.contains("\"fact\" -> \"fact\" [label=\"4\"];"));
+}
+
+#[test]
+fn test_folded() {
+    let dir = std::env::temp_dir().join("rvem-test-folded");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fac.folded");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--folded")
+        .arg(&path)
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().code(0).stdout("120");
+
+    let folded = std::fs::read_to_string(&path).unwrap();
+    assert!(folded.starts_with("_start 7\n"));
+    assert!(folded.contains("\n_start;fact;fact;fact;fact;ret_one 1\n"));
+    let samples: u64 = folded
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(samples, 67);
+}
+
+#[test]
+fn test_coverage() {
+    let dir = std::env::temp_dir().join("rvem-test-coverage");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cover.info");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--coverage")
+        .arg("--lcov")
+        .arg(&path)
+        .arg("tests/data/cover")
+        .assert();
+
+    assert
+        .success()
+        .code(0)
+        .stdout("10")
+        .stderr(predicates::str::contains(
+            "0/2   0.00%       0/1   0.00%  unused",
+        ))
+        .stderr(predicates::str::contains(
+            "26/30  86.67%     13/15  86.67%  (total)",
+        ));
+
+    let lcov = std::fs::read_to_string(&path).unwrap();
+    assert!(lcov.contains("cover.s\n"));
+    assert!(lcov.contains("\nFN:15,unused\n"));
+    assert!(lcov.contains("\nFNDA:0,unused\n"));
+    assert!(lcov.contains("\nDA:21,5\n"));
+    assert!(lcov.contains("\nLF:30\nLH:26\nend_of_record\n"));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_trace_file() {
+    let dir = std::env::temp_dir().join("rvem-test-trace-file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fac.jsonl");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--trace-file")
+        .arg(&path)
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().code(0).stdout("120");
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let records: Vec<serde_json::Value> = trace
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 67);
+    assert_eq!(records[0]["pc"], 0x100ac);
+    assert_eq!(records[0]["inst"], "li a0, 5");
+    assert_eq!(records[0]["rd"]["reg"], "a0");
+    assert_eq!(records[0]["rd"]["value"], 5);
+    assert_eq!(records[3]["mem"]["store"], true);
+    assert_eq!(records[3]["mem"]["value"], 0x100b4);
+    assert!(records[0].get("line").is_none());
+
+    // with source lines, for a program that has them
+    let path = dir.join("cover.jsonl");
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--trace-file")
+        .arg(&path)
+        .arg("tests/data/cover")
+        .assert();
+    assert.success();
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let record: serde_json::Value = serde_json::from_str(trace.lines().next().unwrap()).unwrap();
+    assert!(record["file"].as_str().unwrap().ends_with("cover.s"));
+    assert_eq!(record["line"], 28);
+}
+
+#[test]
+fn test_trace_spike() {
+    let dir = std::env::temp_dir().join("rvem-test-trace-spike");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fac.log");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--trace-file")
+        .arg(&path)
+        .arg("--trace-format=spike")
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().code(0).stdout("120");
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.len(), 67);
+    assert_eq!(
+        lines[0],
+        "core   0: 3 0x000100ac (0x00500513) x10 0x00000005"
+    );
+    assert_eq!(
+        lines[3],
+        "core   0: 3 0x00010078 (0x00112023) mem 0x0007fff8 0x000100b4"
+    );
+    assert_eq!(lines[5], "core   0: 3 0x00010080 (0x00554e63)");
+    assert!(trace.contains("\ncore   0: 3 0x00010090 (0x00412283) x5  0x00000002 mem 0x0007ffe4\n"));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_trace_diff() {
+    let dir = std::env::temp_dir().join("rvem-test-trace-diff");
+    std::fs::create_dir_all(&dir).unwrap();
+    let jsonl = dir.join("fac.jsonl");
+    let spike = dir.join("fac.log");
+
+    for (path, format) in [(&jsonl, "jsonl"), (&spike, "spike")] {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        cmd.arg("--trace-file")
+            .arg(path)
+            .arg(format!("--trace-format={}", format))
+            .arg("tests/data/fac")
+            .assert()
+            .success();
+    }
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("trace-diff").arg(&jsonl).arg(&spike).assert();
+    assert
+        .success()
+        .code(0)
+        .stdout("traces match (67 instructions)\n");
+
+    // clobber the value of the first store
+    let trace = std::fs::read_to_string(&spike).unwrap();
+    let bad = dir.join("bad.log");
+    std::fs::write(
+        &bad,
+        trace.replace("0x0007fff8 0x000100b4", "0x0007fff8 0x000100b5"),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("trace-diff").arg(&jsonl).arg(&bad).assert();
+    let output = String::from_utf8(assert.failure().code(1).get_output().stdout.clone()).unwrap();
+    assert!(output.starts_with("traces diverge at record 4 (a) / 4 (b):\n"));
+    assert!(output.contains(
+        "  memory access differs: 0x0007fff8 <- 0x000100b4 != 0x0007fff8 <- 0x000100b5\n"
+    ));
+}
+
+#[test]
+fn test_taint() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--taint")
+        .arg("stdin")
+        .arg("--taint-sink")
+        .arg("show")
+        .arg("tests/data/taint")
+        .write_stdin("7\n")
+        .assert();
+    assert.success().code(0).stdout("15").stderr(
+        "000110f4 <_start+0x20>: tainted a0 passed to show (jal 11100)\n\
+         00011104 <show+0x4>: tainted a0 passed to syscall 1 (ecall)\n",
+    );
+
+    // the input is untainted, and so is everything derived from it
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--taint")
+        .arg("buf:4")
+        .arg("tests/data/taint")
+        .write_stdin("7\n")
+        .assert();
+    assert.success().code(0).stdout("15").stderr("");
+}
+
+#[test]
+fn test_memcheck() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--memcheck").arg("tests/data/uninit").assert();
+    assert.success().code(0).stdout("0").stderr(
+        "000110c0 <_start+0xc>: read of 4 uninitialized byte(s) at 0007fff4 (lw a1, 4(sp))\n\
+         000110c8 <loop>: read of 1 uninitialized byte(s) at 0007fff8 (lbu a1, 8(sp)) x3\n",
+    );
+}
+
+#[test]
+fn test_heap_profile() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--heap-profile").arg("tests/data/heap").assert();
+    assert.success().code(0).stderr(
+        "heap: 56 bytes peak (brk)\n\
+         allocations: 3 (1 freed), 56 bytes peak\n\
+         leaks: 2 blocks, 40 bytes\n  \
+         32 bytes at 00011118, allocated at 000110c4 <_start+0x10>\n  \
+         8 bytes at 00011138, allocated at 000110cc <_start+0x18>\n",
+    );
+}
+
+#[test]
+fn test_mem_report() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--mem-report").arg("tests/data/fac").assert();
+    assert.success().code(0).stdout("120").stderr(
+        "stack: 40 bytes (0007ffd8-00080000)\n\
+         heap: 0 bytes (000100c8-000100c8)\n",
+    );
+}
+
+#[test]
+fn test_golden() {
+    let dir = std::env::temp_dir().join("rvem-test-golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fac.golden");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--record-golden")
+        .arg(&path)
+        .arg("--golden-period=20")
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().code(0).stdout("120");
+    let golden = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(golden.lines().count(), 4);
+    assert!(golden.ends_with("\n67 79e6f8286f8f93df\n"));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--check-golden")
+        .arg(&path)
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().code(0).stdout("120").stderr("");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--check-golden")
+        .arg(&path)
+        .arg("tests/data/fib")
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stderr("run diverges from golden trace between instructions 0 and 20\n");
+}
+
+#[test]
+fn test_cosim() {
+    let dir = std::env::temp_dir().join("rvem-test-cosim");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fac.log");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    cmd.arg("--trace-file")
+        .arg(&path)
+        .arg("--trace-format=spike")
+        .arg("tests/data/fac")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--cosim-log")
+        .arg(&path)
+        .arg("tests/data/fac")
+        .assert();
+    assert
+        .success()
+        .code(0)
+        .stdout("120")
+        .stderr("cosim: 67 instructions matched\n");
+
+    // a reference that disagrees about the value of the first store halts
+    // the run before anything is printed
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--cosim")
+        .arg(format!(
+            "sed 's/0x0007fff8 0x000100b4/0x0007fff8 0x000100b5/' {} >&2",
+            path.display()
+        ))
+        .arg("tests/data/fac")
+        .assert();
+    let output = assert.failure().code(1).stdout("").get_output().clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("cosim: traces diverge at record 4 (a) / 4 (b):\n"));
+}
+
+#[test]
+fn test_signature() {
+    let dir = std::env::temp_dir().join("rvem-test-signature");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("signature.txt");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--signature")
+        .arg(&path)
+        .arg("tests/data/signature")
+        .assert();
+    assert.success().code(0);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "12345678\n0000002a\ndeadbe2a\ndeadbeef\n"
+    );
+}
+
+#[test]
+fn test_test_suite() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("test-suite").arg("tests/data/riscv-tests").assert();
+    assert.failure().code(1).stdout(
+        "rv32ui-p-add             pass\n\
+         rv32um-p-div             FAIL (test 3)\n\
+         1 passed, 1 failed\n",
+    );
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("test-suite")
+        .arg("--timeout=3")
+        .arg("tests/data/riscv-tests")
+        .assert();
+    assert.failure().stdout(predicates::str::contains(
+        "rv32ui-p-add             TIMEOUT",
+    ));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_snapshot() {
+    let dir = std::env::temp_dir().join("rvem-test-snapshot");
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("fac.log");
+    let snapshot = dir.join("fac.json");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    cmd.arg("--trace-file")
+        .arg(&log)
+        .arg("--trace-format=spike")
+        .arg("tests/data/fac")
+        .assert()
+        .success();
+    let trace = std::fs::read_to_string(&log).unwrap();
+    let trace: Vec<_> = trace.lines().take(20).collect();
+    std::fs::write(&log, trace.join("\n") + "\n").unwrap();
+
+    // the reference running out halts the run partway through
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    cmd.arg("--cosim-log")
+        .arg(&log)
+        .arg("--save-snapshot")
+        .arg(&snapshot)
+        .arg("tests/data/fac")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("");
+
+    // ...and picks up where it left off, without the program
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--restore-snapshot")
+        .arg(&snapshot)
+        .arg("--stats")
+        .assert();
+    let output = assert.success().code(0).stdout("120").get_output().clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("instructions: 46\n"), "{}", stderr);
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--restore-snapshot").arg(&log).assert();
+    assert
+        .failure()
+        .stderr(predicates::str::contains("bad snapshot"));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_crash_snapshot() {
+    let snapshot = std::env::temp_dir().join("rvem-test-crash-snapshot.json");
+    let error = "memory access out of bounds: 4 byte(s) at fffffffc";
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    cmd.args(["--checkpoint-every", "4", "--crash-snapshot"])
+        .arg(&snapshot)
+        .arg("tests/data/crash")
+        .assert()
+        .failure()
+        .code(123)
+        .stderr(predicate::str::contains(format!(
+            "snapshot from instruction 6 written to {}",
+            snapshot.display()
+        )));
+
+    // ...from which it crashes all over again
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("--restore-snapshot").arg(&snapshot).assert();
+    assert
+        .failure()
+        .code(123)
+        .stderr(predicate::str::contains(error));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--crash-snapshot", "x.json", "tests/data/crash"])
+        .assert();
+    assert.failure().code(2);
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_state_diff() {
+    let dir = std::env::temp_dir().join("rvem-test-state-diff");
+    std::fs::create_dir_all(&dir).unwrap();
+    let snapshots = [dir.join("fac-10.json"), dir.join("fac-11.json")];
+    for (n, snapshot) in ["10", "11"].iter().zip(&snapshots) {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        cmd.args(["--max-instructions", n, "--save-snapshot"])
+            .arg(snapshot)
+            .arg("tests/data/fac")
+            .assert()
+            .failure();
+    }
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("state-diff")
+        .args([&snapshots[0], &snapshots[0]])
+        .assert();
+    assert.success().stdout("snapshots match\n");
+
+    // the 11th instruction (sw ra, 0(sp)) is all that's different
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("state-diff").args(&snapshots).assert();
+    assert.failure().code(1).stdout(
+        "pc: 00010078 -> 0001007c\nmemory 0007fff0..0007fff4: 00 00 00 00 -> 90 00 01 00\n",
+    );
+}
+
+#[test]
+fn test_args() {
+    // as a cargo runner would run it
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--", "tests/data/args", "foo", "-x", "--stats"])
+        .write_stdin("hello from stdin, which is longer than the buffer\n")
+        .assert();
+    assert
+        .failure()
+        .code(4)
+        .stdout("foo\n-x\n--stats\nhello from stdin, which is longer than the buffer\n")
+        .stderr("");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("tests/data/args").write_stdin("").assert();
+    assert.success().code(0).stdout("");
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_config() {
+    let dir = std::env::temp_dir().join("rvem-test-config");
+    std::fs::create_dir_all(&dir).unwrap();
+    let (config, input, output) = (
+        dir.join("machine.toml"),
+        dir.join("input.txt"),
+        dir.join("output.txt"),
+    );
+    std::fs::write(&input, "from a file\n").unwrap();
+    std::fs::write(
+        &config,
+        format!(
+            "memory = 0x200000\n\
+             syscalls = \"linux\"\n\
+             args = [\"args\", \"configured\"]\n\
+             [io]\n\
+             stdin = {:?}\n\
+             stdout = {:?}\n",
+            input, output
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--config")
+        .arg(&config)
+        .arg("tests/data/args")
+        .assert();
+    assert.failure().code(2).stdout("");
+    assert_eq!(
+        std::fs::read_to_string(&output).unwrap(),
+        "configured\nfrom a file\n"
+    );
+
+    // command-line arguments win
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--config")
+        .arg(&config)
+        .args(["tests/data/args", "a", "b"])
+        .assert();
+    assert.failure().code(3);
+
+    std::fs::write(&config, "memory = \"lots\"\n").unwrap();
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--config")
+        .arg(&config)
+        .arg("tests/data/args")
+        .assert();
+    assert
+        .failure()
+        .stderr(predicates::str::contains("machine.toml"));
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_dump_json() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--dump", "--format", "json", "tests/data/hello"])
+        .assert();
+    let stdout = assert.success().get_output().stdout.clone();
+    let dump: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+    assert_eq!(dump["registers"].as_array().unwrap().len(), 32);
+    assert_eq!(dump["disassembly"][0]["inst"], "li a0, 1");
+    assert!(dump["symbols"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|sym| sym["name"] == "_start" && sym["addr"] == dump["disassembly"][0]["addr"]));
+
+    // --format only goes with --dump
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    cmd.args(["--format", "json", "tests/data/hello"])
+        .assert()
+        .failure();
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_summary() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--summary", "json", "tests/data/fac"]).assert();
+    let stderr = assert.success().stdout("120").get_output().stderr.clone();
+    let summary: serde_json::Value = serde_json::from_slice(&stderr).unwrap();
+    assert_eq!(summary["exit_code"], 0);
+    assert_eq!(summary["error"], serde_json::Value::Null);
+    assert_eq!(summary["instructions"], 67);
+    assert_eq!(summary["syscalls"]["1"], 1);
+    assert_eq!(summary["syscalls"]["10"], 1);
+    assert!(summary["peak_memory"]["stack"].as_u64().unwrap() > 0);
+    assert!(summary["wall_time"].as_f64().is_some());
+
+    let path = std::env::temp_dir().join("rvem-test-summary.txt");
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--summary", "text", "--summary-file"])
+        .arg(&path)
+        .arg("tests/data/fac")
+        .assert();
+    assert.success().stderr("");
+    let summary = std::fs::read_to_string(&path).unwrap();
+    assert!(summary.starts_with("exit code: 0\ninstructions: 67\n"));
+    assert!(summary.contains("syscall 10: 1\n"));
+}
+
+#[test]
+fn test_max_instructions() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--max-instructions", "30", "tests/data/fac"])
+        .assert();
+    assert
+        .failure()
+        .stdout("")
+        .stderr(predicates::str::contains("PC: 0x"))
+        .stderr(predicates::str::contains(
+            "instruction budget exceeded (30 instructions)",
+        ));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--max-instructions", "1000", "tests/data/fac"])
+        .assert();
+    assert.success().stdout("120");
+}
+
+#[test]
+fn test_break_at() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args([
+            "--break-at",
+            "fact",
+            "--break-context",
+            "2",
+            "tests/data/fac",
+        ])
+        .assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr(predicates::str::starts_with(
+            "breakpoint at 00010074 <fact>\nPC: 0x10074 ",
+        ))
+        .stderr(predicates::str::contains(
+            "  10074: ff810113 addi sp, sp, -8\n  10078: 00112023 sw ra, 0(sp)\nstack:\n",
+        ));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--break-at", "fact", "--break-continue", "tests/data/fac"])
+        .assert();
+    let output = assert.success().stdout("120").get_output().stderr.clone();
+    assert_eq!(
+        String::from_utf8(output)
+            .unwrap()
+            .matches("breakpoint at")
+            .count(),
+        5
+    );
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--break-at", "nonesuch", "tests/data/fac"])
+        .assert();
+    assert.failure();
+}
+
+#[test]
+fn test_break_at_line() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--break-at", "cover.s:20", "tests/data/cover"])
+        .assert();
+    assert
+        .success()
+        .stderr(predicates::str::starts_with(
+            "breakpoint at 000110e4 <loop> at ",
+        ))
+        .stderr(predicates::str::contains("cover.s:21\n"));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--break-at", "cover.s:999", "tests/data/cover"])
+        .assert();
+    assert
+        .failure()
+        .stderr(predicates::str::contains("no code at or after cover.s:999"));
+}
+
+#[test]
+fn test_dump_section() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--dump-section", ".text", "--dump-section", ".data"])
+        .arg("tests/data/hello")
+        .assert();
+    let stdout = assert.success().get_output().stdout.clone();
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.starts_with(".text:\n"));
+    assert!(stdout.contains(" li a0, 1\n"));
+    assert!(stdout.contains("\n.data:\n"));
+    assert!(!stdout.contains("Symbols:"));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--dump-section", ".text", "--section-format", "hex"])
+        .arg("tests/data/hello")
+        .assert();
+    assert
+        .success()
+        .stdout(predicates::str::contains("  00010094  13 05 10 00 "));
+}
+
+#[test]
+fn test_dump_memory() {
+    let dir = std::env::temp_dir().join("rvem-test-dump-memory");
+    std::fs::create_dir_all(&dir).unwrap();
+    let (code, data) = (dir.join("code.bin"), dir.join("data.bin"));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--dump-memory")
+        .arg(&code)
+        .arg("_start..sum")
+        .arg("--dump-memory")
+        .arg(&data)
+        .arg("total..scratch")
+        .args(["--break-at", "done", "tests/data/funcs"])
+        .assert();
+    assert.success();
+    let code = std::fs::read(&code).unwrap();
+    assert_eq!(code.len(), 4 * 4);
+    // li a0, 10
+    assert_eq!(code[..4], 0x00a00513u32.to_le_bytes());
+    assert_eq!(std::fs::read(&data).unwrap(), [0; 4]);
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .arg("--dump-memory")
+        .arg(dir.join("backwards.bin"))
+        .arg("sum.._start")
+        .arg("tests/data/funcs")
+        .assert();
+    assert
+        .failure()
+        .stderr(predicates::str::contains("ends before it starts"));
+}
+
+#[test]
+fn test_memory_size() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--memory", "2M", "tests/data/fac"]).assert();
+    assert.success().stdout("120");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--memory", "8Q", "tests/data/fac"]).assert();
+    assert.failure().stderr(predicates::str::contains(
+        "invalid size '8Q': expected a number of bytes, optionally with a k, M, or G suffix",
+    ));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--memory", "64k", "tests/data/fac"]).assert();
+    assert.failure().code(121).stderr(predicates::str::contains(
+        "Error: section .text (10074-100c8) doesn't fit in memory (65536 bytes); try --memory 256k",
+    ));
+}
+
+#[test]
+fn test_log_filtering() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--log-level", "warn,syscalls=trace", "tests/data/fac"])
+        .assert();
+    assert
+        .success()
+        .stdout("120")
+        .stderr(predicates::str::contains("MIPS print_int"))
+        .stderr(predicates::str::contains("found section").not())
+        .stderr(predicates::str::contains("fact").not());
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .env("RUST_LOG", "trace")
+        .args(["--quiet", "tests/data/fac"])
+        .assert();
+    assert.success().stdout("120").stderr("");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["-q", "-l", "trace", "tests/data/fac"]).assert();
+    assert.failure();
+}
+
+#[test]
+fn test_final_regs() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--final-regs", "--final-mem", "total", "tests/data/funcs"])
+        .assert();
+    let stderr = assert.code(55).get_output().stderr.clone();
+    let stderr = String::from_utf8(stderr).unwrap();
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines.len(), 33);
+    assert_eq!(lines[0], "zero 0x00000000");
+    assert_eq!(lines[10], "a0   0x00000037");
+    assert_eq!(lines[32], "total 0x00000000");
+
+    // they're most wanted of a program that crashes
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--final-regs", "tests/data/crash"]).assert();
+    assert
+        .code(123)
+        .stderr(predicates::str::contains("\nt0   0xfffffffc\n"))
+        .stderr(predicates::str::contains(
+            "\nError: memory access out of bounds",
+        ));
+}
+
+#[test]
+fn test_set_reg() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--set-reg", "s1=-1", "--set-reg", "sp=0x40000"])
+        .args(["--final-regs", "tests/data/funcs"])
+        .assert();
+    assert
+        .code(55)
+        .stderr(predicates::str::contains("sp   0x00040000\n"))
+        .stderr(predicates::str::contains("s1   0xffffffff\n"));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--set-reg", "a8=1", "tests/data/funcs"]).assert();
+    assert.failure();
+}
+
+#[test]
+fn test_seed() {
+    let run = |seed: &str| {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        let assert = cmd.args(["--seed", seed, "tests/data/random"]).assert();
+        assert.success().get_output().stdout.clone()
+    };
+    let output = String::from_utf8(run("42")).unwrap();
+    assert_eq!(run("42"), output.as_bytes());
+    assert_ne!(run("43"), output.as_bytes());
+    // the time is that of the 30 instructions before rdtime
+    assert!(output.ends_with(" 30"), "{}", output);
+}
+
+#[test]
+fn test_write_protect() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("tests/data/rodata").assert();
+    assert
+        .failure()
+        .code(123)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "write to read-only section .rodata: 1 byte(s) at 000100b4 (pc = 000110c8)",
+        ));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--no-write-protect", "tests/data/rodata"])
+        .assert();
+    assert.success().stdout("hello\n");
+}
+
+#[test]
+fn test_limits() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--max-heap", "50", "tests/data/heap"]).assert();
+    assert.failure().code(124).stderr(predicate::str::contains(
+        "resource limit exceeded: heap of 50 bytes",
+    ));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--max-output=1k", "tests/data/hello"]).assert();
+    assert.success().stdout("Hello World!\n");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--max-cpu-time", "0.001", "tests/data/primes"])
+        .assert();
+    assert.failure().code(124).stderr(predicate::str::contains(
+        "resource limit exceeded: CPU time of 1ms",
+    ));
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--max-cpu-time", "soon", "tests/data/primes"])
+        .assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn test_map_file() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args([
+            "--map-file",
+            "0x40000=tests/data/mapped.txt",
+            "tests/data/mapped",
+        ])
+        .assert();
+    assert.success().stdout("Mapped from a file\n");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args([
+            "--map-file=0x40000=tests/data/mapped.txt,ro",
+            "tests/data/mapped",
+        ])
+        .assert();
+    assert.failure().code(123).stderr(predicate::str::contains(
+        "write to read-only section tests/data/mapped.txt: 1 byte(s) at 00040000",
+    ));
+}
+
+#[test]
+fn test_virtual_time() {
+    // 206 instructions retire between the two clock_gettime calls, and the
+    // program exits with the microseconds between them (mod 256)
+    for (rate, micros) in [
+        (None, 206),
+        (Some("2000000"), 103),
+        (Some("100000"), 2060 % 256),
+    ] {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        match rate {
+            Some(rate) => cmd.arg(format!("--virtual-time={}", rate)),
+            None => cmd.arg("--virtual-time"),
+        };
+        let assert = cmd.arg("tests/data/clock").assert();
+        assert.code(micros);
+    }
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--virtual-time=0", "tests/data/clock"]).assert();
+    assert.failure().code(2);
+}
+
+#[test]
+fn test_verify_disasm() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .env("RVEM_OBJDUMP", "no-such-objdump")
+        .args(["--verify-disasm", "tests/data/fac"])
+        .assert();
+
+    assert
+        .failure()
+        .stderr(predicate::str::contains("couldn't run no-such-objdump"));
+
+    if rvem::find_objdump().is_ok() {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        let assert = cmd.args(["--verify-disasm", "tests/data/primes"]).assert();
+
+        assert
+            .success()
+            .stdout("")
+            .stderr(predicate::str::starts_with("0 instruction(s)"));
+    }
+}
+
+#[test]
+fn test_backtrace() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.arg("tests/data/crash").assert();
+
+    assert.failure().stderr(predicate::str::starts_with(
+        "backtrace:\n  #0  000110f8 <inner+0x14>\n  #1  000110d4 <outer+0x14>\n  #2  000110b8 <_start+0x4>\nError: memory access out of bounds",
+    ));
+}
+
+#[test]
+fn test_exit_status() {
+    for (args, code) in [
+        // the program's own
+        (&["tests/data/ret"][..], 3),
+        // not a program
+        (&["Cargo.toml"], 121),
+        // a memory fault
+        (&["tests/data/crash"], 123),
+        // a timeout
+        (&["--max-instructions", "30", "tests/data/fac"], 124),
+        // anything else
+        (&["tests/data/no-such-program"], 120),
+    ] {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        cmd.args(args).assert().code(code);
+    }
+}
+
+#[test]
+fn test_symbols() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--symbols=s", "tests/data/funcs"]).assert();
+    assert.success().stdout(
+        "\
+000110d4 func        16 _start
+000110e4 func        28 sum
+00012104 notype         scratch
+",
+    );
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--symbols=__*_start", "tests/data/hello"])
+        .assert();
+    assert
+        .success()
+        .stdout("000110c5 notype         __bss_start\n");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--symbols", "tests/data/funcs"]).assert();
+    assert
+        .success()
+        .stdout(predicate::str::contains("00012100 object       4 total\n"));
+}
+
+#[test]
+fn test_demangle() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--symbols=::", "tests/data/mangled"]).assert();
+    assert.success().stdout(
+        "\
+000110c8 func         8 foo::double(int)
+000110d0 func         8 demo::inc
+",
+    );
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["--no-demangle", "--symbols=_ZN", "tests/data/mangled"])
+        .assert();
+    assert.success().stdout(
+        "\
+000110c8 func         8 _ZN3foo6doubleEi
+000110d0 func         8 _ZN4demo3inc17h0123456789abcdefE
+",
+    );
+
+    // breakpoints can be set by either name
+    for name in ["demo::inc", "_ZN4demo3inc17h0123456789abcdefE"] {
+        let mut cmd = Command::cargo_bin("rvem").unwrap();
+        let assert = cmd
+            .args(["--break-at", name, "tests/data/mangled"])
+            .assert();
+        assert.success().stderr(predicates::str::starts_with(
+            "breakpoint at 000110d0 <demo::inc>\n",
+        ));
+    }
+}
+
+#[test]
+fn test_strings() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["strings", "tests/data/hello"]).assert();
+    assert.success().stdout("000110b8 \"Hello World!\\n\"\n");
+
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["strings", "-n", "20", "tests/data/hello"])
+        .assert();
+    assert.success().stdout("");
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_function_tests() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["test", "tests/data/homework"])
+        .args(["--manifest", "tests/data/homework.toml"])
+        .assert();
+    assert.success().stdout(
+        "max(3, 7)                pass\n\
+         max of negatives         pass\n\
+         fill(buf, 3, 42)         pass\n\
+         fill words               pass\n\
+         4 passed, 0 failed\n",
+    );
+
+    let dir = std::env::temp_dir().join("rvem-test-function-tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    let manifest = dir.join("tests.toml");
+    std::fs::write(
+        &manifest,
+        "[[test]]\nfunction = \"max\"\nargs = [1, 2]\nreturns = 1\n\n\
+         [[test]]\nfunction = \"spin\"\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd
+        .args(["test", "tests/data/homework", "--timeout=100", "--manifest"])
+        .arg(&manifest)
+        .assert();
+    assert
+        .failure()
+        .code(1)
+        .stdout(predicates::str::starts_with(
+            "max(1, 2)                FAIL (returned 2 (0x2), expected 1 (0x1))\n\
+         spin()                   TIMEOUT (at ",
+        ));
+}
+
+#[test]
+fn test_verify_decode() {
+    let mut cmd = Command::cargo_bin("rvem").unwrap();
+    let assert = cmd.args(["--verify-decode", "tests/data/hello"]).assert();
+    assert.success().stdout("Hello World!\n").stderr("");
+}
@@ -0,0 +1,36 @@
+# Prompts for numbers and keeps a running total of them until it reads a 0,
+# then exits with the total; for exercising the testing harness.
+        .data
+prompt:
+        .string "> "
+total:
+        .string "total: "
+newline:
+        .string "\n"
+        .text
+        .globl _start
+
+_start:
+        li     s0, 0             # the total
+1:
+        la     a0, prompt
+        li     a7, 4
+        ecall                    # print_string(prompt)
+        li     a7, 5
+        ecall                    # read_int
+        beqz   a0, 2f
+        add    s0, s0, a0
+        la     a0, total
+        li     a7, 4
+        ecall                    # print_string(total)
+        mv     a0, s0
+        li     a7, 1
+        ecall                    # print_int(s0)
+        la     a0, newline
+        li     a7, 4
+        ecall                    # print_string(newline)
+        j      1b
+2:
+        mv     a0, s0
+        li     a7, 93
+        ecall                    # exit(s0)
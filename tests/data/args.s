@@ -0,0 +1,56 @@
+# Echoes its arguments (one per line), then whatever it reads from stdin, and
+# exits with its argument count; for exercising passing arguments through.
+        .text
+        .globl _start
+_start:
+        lw     s0, 0(sp)         # argc
+        addi   s1, sp, 8         # &argv[1]
+        li     s2, 1
+args:
+        bge    s2, s0, input
+        lw     a1, 0(s1)         # argv[i]
+        mv     a2, a1
+len:
+        lbu    t0, 0(a2)
+        beqz   t0, print
+        addi   a2, a2, 1
+        j      len
+print:
+        sub    a2, a2, a1
+        li     a0, 1
+        li     a7, 64
+        ecall                    # write(1, argv[i], strlen(argv[i]))
+        li     a0, 1
+        lui    a1, %hi(newline)
+        addi   a1, a1, %lo(newline)
+        li     a2, 1
+        li     a7, 64
+        ecall
+        addi   s1, s1, 4
+        addi   s2, s2, 1
+        j      args
+input:
+        li     a0, 0
+        lui    a1, %hi(buf)
+        addi   a1, a1, %lo(buf)
+        li     a2, 16
+        li     a7, 63
+        ecall                    # n = read(0, buf, 16)
+        blez   a0, done
+        mv     a2, a0
+        li     a0, 1
+        li     a7, 64
+        ecall                    # write(1, buf, n)
+        j      input
+done:
+        mv     a0, s0
+        li     a7, 93
+        ecall                    # exit(argc)
+
+        .data
+newline:
+        .ascii "\n"
+
+        .bss
+buf:
+        .zero  16
@@ -0,0 +1,13 @@
+# Starts up from a section of its own (as bare-metal code often does) and
+# calls into .text; for exercising execution outside .text.
+        .section .boot, "ax"
+        .globl _start
+_start:
+        jal    main
+        li     a7, 93
+        ecall                # exit(main())
+
+        .text
+main:
+        li     a0, 7
+        ret
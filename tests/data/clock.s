@@ -0,0 +1,42 @@
+# Reads the monotonic clock before and after a loop of 200 instructions and
+# exits with the microseconds in between, having also read the time of day
+# into tv; for exercising virtual time (--virtual-time).
+        .text
+        .globl _start
+_start:
+        li     a0, 1
+        lui    a1, %hi(before)
+        addi   a1, a1, %lo(before)
+        li     a7, 403
+        ecall                    # clock_gettime(CLOCK_MONOTONIC, &before)
+        li     t0, 100
+loop:
+        addi   t0, t0, -1
+        bnez   t0, loop
+        li     a0, 1
+        lui    a1, %hi(after)
+        addi   a1, a1, %lo(after)
+        li     a7, 403
+        ecall                    # clock_gettime(CLOCK_MONOTONIC, &after)
+        lui    a0, %hi(tv)
+        addi   a0, a0, %lo(tv)
+        li     a1, 0
+        li     a7, 169
+        ecall                    # gettimeofday(&tv, NULL)
+        lui    s0, %hi(before)
+        addi   s0, s0, %lo(before)
+        lw     t0, 8(s0)         # before.tv_nsec
+        lw     t1, 24(s0)        # after.tv_nsec
+        sub    a0, t1, t0
+        li     t0, 1000
+        divu   a0, a0, t0
+        li     a7, 93
+        ecall                    # exit((after - before) / 1000)
+
+        .data
+before:
+        .zero  16
+after:
+        .zero  16
+tv:
+        .zero  16
@@ -0,0 +1,40 @@
+    .text                  # exercise for coverage reporting (built with -g)
+    .globl _start
+sign: # arg: n in a0, returns sign(n) in a0
+    blt    a0, zero, negative
+    beq    a0, zero, zero_
+    li     a0, 1
+    ret
+negative:
+    li     a0, -1
+    ret
+zero_:
+    li     a0, 0
+    ret
+unused: # never called
+    li     a0, 42
+    ret
+sum: # args: n in a0, returns 1 + 2 + ... + n in a0
+    mv     t0, a0
+    li     a0, 0
+loop:
+    beq    t0, zero, out
+    add    a0, a0, t0
+    addi   t0, t0, -1
+    j      loop
+out:
+    ret
+_start:
+    li     a0, 7
+    jal    sign
+    mv     s0, a0
+    li     a0, -3
+    jal    sign
+    add    s0, s0, a0      # 1 + -1
+    li     a0, 4
+    jal    sum
+    add    a0, s0, a0      # 0 + 10
+    li     a7, 1           # print it
+    ecall
+    li     a7, 10
+    ecall                  # and exit
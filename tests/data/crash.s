@@ -0,0 +1,37 @@
+# Crashes two calls deep, with frame pointers set up as a compiler would
+# (ra at fp-4, the caller's fp at fp-8); for exercising backtraces.
+        .text
+        .globl _start
+        .type  _start, @function
+_start:
+        jal    outer
+        li     a7, 93
+        ecall
+        .size  _start, .-_start
+
+        .type  outer, @function
+outer:
+        addi   sp, sp, -16
+        sw     ra, 12(sp)
+        sw     s0, 8(sp)
+        addi   s0, sp, 16
+        jal    inner
+        lw     ra, 12(sp)
+        lw     s0, 8(sp)
+        addi   sp, sp, 16
+        ret
+        .size  outer, .-outer
+
+        .type  inner, @function
+inner:
+        addi   sp, sp, -16
+        sw     ra, 12(sp)
+        sw     s0, 8(sp)
+        addi   s0, sp, 16
+        li     t0, -4
+        lw     a0, 0(t0)     # way past the end of memory
+        lw     ra, 12(sp)
+        lw     s0, 8(sp)
+        addi   sp, sp, 16
+        ret
+        .size  inner, .-inner
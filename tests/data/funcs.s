@@ -0,0 +1,35 @@
+# A couple of functions marked up with .type/.size, as a compiler would, and
+# with local labels inside them; for exercising symbol lookups.
+        .text
+        .globl _start
+        .type  _start, @function
+_start:
+        li     a0, 10
+        jal    sum
+        li     a7, 93
+        ecall                # exit(sum(10))
+        .size  _start, .-_start
+
+        .type  sum, @function
+sum:                         # returns 1 + 2 + ... + a0
+        mv     t0, a0
+        li     a0, 0
+loop:
+        beqz   t0, done
+        add    a0, a0, t0
+        addi   t0, t0, -1
+        j      loop
+done:
+        ret
+        .size  sum, .-sum
+
+        .data
+        .globl total
+        .type  total, @object
+total:
+        .word  0
+        .size  total, 4
+
+        .bss
+scratch:
+        .zero  16
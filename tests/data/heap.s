@@ -0,0 +1,35 @@
+# Allocates a few blocks from a toy allocator and frees one of them; for
+# exercising --heap-profile.
+        .text
+        .globl _start
+
+_start:
+        li     a0, 16
+        jal    malloc
+        mv     s0, a0
+        li     a0, 32
+        jal    malloc
+        li     a0, 8
+        jal    malloc
+        mv     a0, s0
+        jal    free
+        li     a7, 10
+        ecall                # exit
+
+# void *malloc(size_t size): a bump allocator on top of brk
+        .globl malloc
+malloc:
+        mv     t0, a0
+        li     a0, 0
+        li     a7, 214
+        ecall                # brk(0), i.e., the current break
+        mv     t1, a0
+        add    a0, a0, t0
+        ecall                # brk(break + size)
+        mv     a0, t1
+        ret
+
+# void free(void *ptr): never gives anything back
+        .globl free
+free:
+        ret
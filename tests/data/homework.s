@@ -0,0 +1,47 @@
+# A few functions of the sort assembly homework asks for, marked up with
+# .type/.size as a compiler would; for exercising `rvem test`.
+        .text
+        .globl _start
+        .type  _start, @function
+_start:
+        li     a0, 0
+        li     a7, 93
+        ecall                # exit(0)
+        .size  _start, .-_start
+
+        .globl max
+        .type  max, @function
+max:                         # returns the larger of a0 and a1 (signed)
+        bge    a0, a1, 1f
+        mv     a0, a1
+1:
+        ret
+        .size  max, .-max
+
+        .globl fill
+        .type  fill, @function
+fill:                        # sets the a1 bytes at a0 to a2; returns a0
+        mv     t0, a0
+        add    t1, a0, a1
+1:
+        bgeu   t0, t1, 2f
+        sb     a2, 0(t0)
+        addi   t0, t0, 1
+        j      1b
+2:
+        ret
+        .size  fill, .-fill
+
+        .globl spin
+        .type  spin, @function
+spin:                        # counts in a0, forever
+        addi   a0, a0, 1
+        j      spin
+        .size  spin, .-spin
+
+        .data
+        .globl buf
+        .type  buf, @object
+buf:
+        .zero  8
+        .size  buf, 8
@@ -0,0 +1,24 @@
+# Functions with mangled C++ and Rust (legacy) names, as a compiler would
+# emit them; for exercising symbol demangling.
+        .text
+        .globl _start
+        .type  _start, @function
+_start:
+        li     a0, 6
+        jal    _ZN3foo6doubleEi
+        jal    _ZN4demo3inc17h0123456789abcdefE
+        li     a7, 93
+        ecall                # exit(demo::inc(foo::double(6)))
+        .size  _start, .-_start
+
+        .type  _ZN3foo6doubleEi, @function
+_ZN3foo6doubleEi:            # int foo::double(int)
+        add    a0, a0, a0
+        ret
+        .size  _ZN3foo6doubleEi, .-_ZN3foo6doubleEi
+
+        .type  _ZN4demo3inc17h0123456789abcdefE, @function
+_ZN4demo3inc17h0123456789abcdefE:  # demo::inc
+        addi   a0, a0, 1
+        ret
+        .size  _ZN4demo3inc17h0123456789abcdefE, .-_ZN4demo3inc17h0123456789abcdefE
@@ -0,0 +1,14 @@
+# Uppercases the first letter of a file mapped at 0x40000 and prints it; for
+# exercising --map-file.
+        .text
+        .globl _start
+_start:
+        lui    a0, 0x40          # a0 = 0x40000
+        lbu    t0, 0(a0)
+        addi   t0, t0, -32
+        sb     t0, 0(a0)         # *a0 = toupper(*a0)
+        li     a7, 4
+        ecall                    # print_string(a0)
+        li     a0, 0
+        li     a7, 93
+        ecall                    # exit(0)
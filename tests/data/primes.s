@@ -0,0 +1,31 @@
+# Counts the primes below 100000 by trial division; a (comparatively)
+# long-running workload for measuring emulator performance.
+
+    .text
+    .globl _start
+
+_start:
+    li       s0, 100000      # limit
+    li       s1, 0           # number of primes found
+    li       s2, 2           # n = 2
+1: # for each candidate n
+    bge      s2, s0, 4f
+    li       t0, 2           # d = 2
+2: # trial division
+    mul      t1, t0, t0
+    bgt      t1, s2, 3f      # d * d > n: n is prime
+    rem      t2, s2, t0
+    beqz     t2, 5f          # n % d == 0: n is composite
+    addi     t0, t0, 1
+    j        2b
+3: # prime
+    addi     s1, s1, 1
+5: # next candidate
+    addi     s2, s2, 1
+    j        1b
+4: # done; print the count and exit
+    mv       a0, s1
+    li       a7, 1
+    ecall
+    li       a7, 10
+    ecall
@@ -0,0 +1,44 @@
+# Prints 8 random bytes from getrandom (as two words), then the time CSR, and
+# exits; for exercising deterministic (--seed) runs.
+        .text
+        .globl _start
+_start:
+        lui    a0, %hi(buf)
+        addi   a0, a0, %lo(buf)
+        li     a1, 8
+        li     a2, 0
+        li     a7, 278
+        ecall                    # getrandom(buf, 8, 0)
+        lui    s0, %hi(buf)
+        addi   s0, s0, %lo(buf)
+        lw     a0, 0(s0)
+        li     a7, 1
+        ecall                    # print_int(buf[0])
+        jal    space
+        lw     a0, 4(s0)
+        li     a7, 1
+        ecall                    # print_int(buf[1])
+        jal    space
+        rdtime a0
+        li     a7, 1
+        ecall                    # print_int(time)
+        li     a0, 0
+        li     a7, 93
+        ecall                    # exit(0)
+
+space:
+        li     a0, 1
+        lui    a1, %hi(sep)
+        addi   a1, a1, %lo(sep)
+        li     a2, 1
+        li     a7, 64
+        ecall                    # write(1, " ", 1)
+        ret
+
+        .data
+sep:
+        .ascii " "
+
+        .bss
+buf:
+        .zero  8
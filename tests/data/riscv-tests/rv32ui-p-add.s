@@ -0,0 +1,25 @@
+# A stand-in for a riscv-tests binary that passes: reports success through
+# the HTIF tohost word, as RVTEST_PASS does; for exercising test-suite.
+        .text
+        .globl _start
+
+_start:
+        li     a0, 2
+        li     a1, 3
+        add    a2, a0, a1
+        li     gp, 2         # TESTNUM
+        li     t0, 5
+        bne    a2, t0, fail
+pass:
+        li     gp, 1
+fail:
+        lui    t0, %hi(tohost)
+        sw     gp, %lo(tohost)(t0)
+halt:
+        j      halt
+
+        .data
+        .align 3
+        .globl tohost
+tohost:
+        .word  0, 0
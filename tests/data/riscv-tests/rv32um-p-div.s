@@ -0,0 +1,30 @@
+# A stand-in for a riscv-tests binary that fails test 3: reports
+# (TESTNUM << 1) | 1 through the HTIF tohost word, as RVTEST_FAIL does; for
+# exercising test-suite.
+        .text
+        .globl _start
+
+_start:
+        li     a0, 7
+        li     a1, 2
+        div    a2, a0, a1
+        li     gp, 3         # TESTNUM
+        li     t0, 4         # wrong!
+        bne    a2, t0, fail
+pass:
+        li     gp, 1
+        j      done
+fail:
+        slli   gp, gp, 1
+        ori    gp, gp, 1
+done:
+        lui    t0, %hi(tohost)
+        sw     gp, %lo(tohost)(t0)
+halt:
+        j      halt
+
+        .data
+        .align 3
+        .globl tohost
+tohost:
+        .word  0, 0
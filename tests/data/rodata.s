@@ -0,0 +1,18 @@
+# Lowercases the first letter of a string in .rodata and prints it; for
+# exercising write protection (--no-write-protect).
+        .text
+        .globl _start
+_start:
+        lui    a0, %hi(msg)
+        addi   a0, a0, %lo(msg)
+        li     t0, 'h'
+        sb     t0, 0(a0)         # *msg = 'h'
+        li     a7, 4
+        ecall                    # print_string(msg)
+        li     a0, 0
+        li     a7, 93
+        ecall                    # exit(0)
+
+        .section .rodata
+msg:
+        .string "Hello\n"
@@ -0,0 +1,31 @@
+# Writes a compliance test signature, then halts through the HTIF tohost
+# word; for exercising --signature.
+        .text
+        .globl _start
+
+_start:
+        lui    t0, %hi(begin_signature)
+        addi   t0, t0, %lo(begin_signature)
+        li     t1, 0x12345678
+        sw     t1, 0(t0)
+        li     t1, 42
+        sw     t1, 4(t0)
+        sb     t1, 8(t0)
+        li     gp, 1         # pass
+        lui    t0, %hi(tohost)
+        sw     gp, %lo(tohost)(t0)
+halt:
+        j      halt
+
+        .data
+        .align 3
+        .globl tohost
+tohost:
+        .word  0, 0
+
+        .align 2
+        .globl begin_signature
+begin_signature:
+        .fill  4, 4, 0xdeadbeef
+        .globl end_signature
+end_signature:
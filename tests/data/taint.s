@@ -0,0 +1,25 @@
+# Reads an int, doubles it (plus one), round-trips it through memory, and
+# prints it; for exercising taint tracking.
+        .data
+buf:
+        .word  0
+        .text
+        .globl _start
+
+_start:
+        li     a7, 5
+        ecall                # read_int
+        slli   t0, a0, 1
+        addi   t0, t0, 1
+        lui    t1, %hi(buf)
+        addi   t1, t1, %lo(buf)
+        sw     t0, 0(t1)
+        lw     a0, 0(t1)
+        jal    show
+        li     a7, 10
+        ecall                # exit
+
+show:
+        li     a7, 1
+        ecall                # print_int
+        ret
@@ -0,0 +1,18 @@
+# Reads stack memory that was never written; for exercising --memcheck.
+        .text
+        .globl _start
+
+_start:
+        addi   sp, sp, -16
+        sw     zero, 0(sp)
+        lw     a0, 0(sp)     # fine
+        lw     a1, 4(sp)     # uninitialized
+        li     t0, 3
+loop:
+        lbu    a1, 8(sp)     # uninitialized, three times over
+        addi   t0, t0, -1
+        bnez   t0, loop
+        li     a7, 1
+        ecall                # print_int
+        li     a7, 10
+        ecall                # exit